@@ -0,0 +1,97 @@
+//! Microbenchmarks of the decoding hot loop. Run them with `cargo bench`.
+
+#![feature(test)]
+
+extern crate test;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use test::{black_box, Bencher};
+
+use frequency_tree_compression::bits::BitVec;
+use frequency_tree_compression::corpus::{self, CorpusOptions, Distribution};
+use frequency_tree_compression::{DecodingTree, EncodingTree, Symbol};
+
+
+/// Number of symbols decoded by each iteration
+const LEN: usize = 1 << 20;
+
+
+/// Skewed random bytes, so that the codes have different lengths like in real data
+fn skewed_bytes() -> Vec<u8> {
+
+    let mut rng = StdRng::seed_from_u64(42);
+
+    (0..LEN)
+        .map(|_| (rng.gen_range(0..=u8::MAX) as u32 * rng.gen_range(0..=u8::MAX) as u32 / 255) as u8)
+        .collect()
+}
+
+
+fn encode<U>(data: &[U]) -> (DecodingTree<U>, BitVec)
+where
+    U: Symbol
+{
+    let (encoder, bitcode) = EncodingTree::encode(data.iter().cloned());
+    (encoder.into_decoder().unwrap(), bitcode)
+}
+
+
+#[bench]
+fn decode_skewed_bytes(b: &mut Bencher) {
+
+    let (tree, bitcode) = encode(&skewed_bytes());
+
+    b.bytes = LEN as u64;
+    b.iter(|| tree.decode(black_box(&bitcode.as_bit_view())).unwrap());
+}
+
+
+#[bench]
+fn decode_uniform_bytes(b: &mut Bencher) {
+
+    let mut rng = StdRng::seed_from_u64(42);
+    let data: Vec<u8> = (0..LEN).map(|_| rng.gen()).collect();
+
+    let (tree, bitcode) = encode(&data);
+
+    b.bytes = LEN as u64;
+    b.iter(|| tree.decode(black_box(&bitcode.as_bit_view())).unwrap());
+}
+
+
+#[bench]
+fn decode_zipf_bytes(b: &mut Bencher) {
+
+    let data = corpus::generate_bytes(&CorpusOptions { len: LEN, ..CorpusOptions::new() });
+
+    let (tree, bitcode) = encode(&data);
+
+    b.bytes = LEN as u64;
+    b.iter(|| tree.decode(black_box(&bitcode.as_bit_view())).unwrap());
+}
+
+
+/// Geometric frequencies make the longest codes, so the decoder walks the deepest paths
+#[bench]
+fn decode_geometric_bytes(b: &mut Bencher) {
+
+    let data = corpus::generate_bytes(&CorpusOptions { len: LEN, distribution: Distribution::Geometric { ratio: 0.5 }, ..CorpusOptions::new() });
+
+    let (tree, bitcode) = encode(&data);
+
+    b.bytes = LEN as u64;
+    b.iter(|| tree.decode(black_box(&bitcode.as_bit_view())).unwrap());
+}
+
+
+#[bench]
+fn decode_text_chars(b: &mut Bencher) {
+
+    let text = include_str!("../README.md");
+    let data: Vec<char> = text.chars().cycle().take(LEN).collect();
+
+    let (tree, bitcode) = encode(&data);
+
+    b.bytes = LEN as u64;
+    b.iter(|| tree.decode(black_box(&bitcode.as_bit_view())).unwrap());
+}
@@ -0,0 +1,39 @@
+//! Microbenchmarks of the compression of bytes. Run them with `cargo bench`.
+
+#![feature(test)]
+
+extern crate test;
+
+use test::{black_box, Bencher};
+
+use frequency_tree_compression::corpus::{self, CorpusOptions};
+use frequency_tree_compression::{compress, compress_bytes};
+
+
+/// Number of bytes compressed by each iteration
+const LEN: usize = 1 << 20;
+
+
+fn zipf_bytes() -> Vec<u8> {
+    corpus::generate_bytes(&CorpusOptions { len: LEN, ..CorpusOptions::new() })
+}
+
+
+#[bench]
+fn compress_generic_bytes(b: &mut Bencher) {
+
+    let data = zipf_bytes();
+
+    b.bytes = LEN as u64;
+    b.iter(|| compress(black_box(data.iter().copied())));
+}
+
+
+#[bench]
+fn compress_byte_slice(b: &mut Bencher) {
+
+    let data = zipf_bytes();
+
+    b.bytes = LEN as u64;
+    b.iter(|| compress_bytes(black_box(&data)));
+}
@@ -0,0 +1,135 @@
+//! Compare the compression rate and throughput of this crate with `flate2` and `zstd` over the test corpus.
+//! Run with `cargo run --release --example codec_comparison` for meaningful throughput figures.
+
+use std::fs;
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+use flate2::Compression;
+use frequency_tree_compression::{compress, compress_with_options, decompress, deflate, CompressionOptions};
+
+
+const TEST_DATA_DIR: &str = "test_data";
+
+
+/// A compressor under comparison: its name, and functions to compress and decompress a text
+struct Contender {
+
+    name: &'static str,
+    compress: fn(&str) -> Vec<u8>,
+    decompress: fn(&[u8]) -> String,
+
+}
+
+
+const CONTENDERS: &[Contender] = &[
+    Contender {
+        name: "ftc",
+        compress: |text| compress(text.chars()).into_vec(),
+        decompress: |data| decompress::<char>(data).unwrap().iter().collect(),
+    },
+    Contender {
+        name: "ftc lz77",
+        compress: |text| compress_with_options(text.chars(), &CompressionOptions { lz77: true, ..CompressionOptions::new() }).into_vec(),
+        decompress: |data| decompress::<char>(data).unwrap().iter().collect(),
+    },
+    Contender {
+        name: "ftc raw deflate",
+        compress: |text| deflate::compress_raw(text.as_bytes()).into_vec(),
+        decompress: |data| String::from_utf8(deflate::decompress_raw(data).unwrap().into_vec()).unwrap(),
+    },
+    Contender {
+        name: "flate2 default",
+        compress: |text| deflate_with(text, Compression::default()),
+        decompress: inflate,
+    },
+    Contender {
+        name: "flate2 best",
+        compress: |text| deflate_with(text, Compression::best()),
+        decompress: inflate,
+    },
+    Contender {
+        name: "zstd 3",
+        compress: |text| zstd::encode_all(text.as_bytes(), 3).unwrap(),
+        decompress: |data| String::from_utf8(zstd::decode_all(data).unwrap()).unwrap(),
+    },
+    Contender {
+        name: "zstd 19",
+        compress: |text| zstd::encode_all(text.as_bytes(), 19).unwrap(),
+        decompress: |data| String::from_utf8(zstd::decode_all(data).unwrap()).unwrap(),
+    },
+];
+
+
+fn deflate_with(text: &str, level: Compression) -> Vec<u8> {
+    let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), level);
+    encoder.write_all(text.as_bytes()).unwrap();
+    encoder.finish().unwrap()
+}
+
+
+fn inflate(data: &[u8]) -> String {
+    let mut text = String::new();
+    flate2::read::DeflateDecoder::new(data).read_to_string(&mut text).unwrap();
+    text
+}
+
+
+/// Throughput in MiB/s of processing `bytes` bytes in `time`
+fn throughput(bytes: usize, time: Duration) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0) / time.as_secs_f64()
+}
+
+
+fn main() {
+
+    let mut files: Vec<_> = fs::read_dir(TEST_DATA_DIR)
+        .unwrap_or_else(|err| panic!("Could not read directory {TEST_DATA_DIR}: {err}"))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_file())
+        .collect();
+    files.sort();
+
+    let texts: Vec<(String, String)> = files.iter()
+        .map(|path| (
+            path.file_name().unwrap().to_string_lossy().into_owned(),
+            fs::read_to_string(path).unwrap_or_else(|err| panic!("Could not read file {}: {err}", path.display()))
+        ))
+        .collect();
+
+    let total_size: usize = texts.iter().map(|(_, text)| text.len()).sum();
+
+    println!("Corpus: {} files, {} KiB", texts.len(), total_size / 1024);
+    println!();
+    println!("{:<16} {:>14} {:>8} {:>18} {:>20}", "codec", "size (bytes)", "rate", "compress (MiB/s)", "decompress (MiB/s)");
+
+    for contender in CONTENDERS {
+
+        let mut compressed_size = 0;
+        let mut compress_time = Duration::ZERO;
+        let mut decompress_time = Duration::ZERO;
+
+        for (name, text) in &texts {
+
+            let start = Instant::now();
+            let compressed = (contender.compress)(text);
+            compress_time += start.elapsed();
+
+            let start = Instant::now();
+            let decompressed = (contender.decompress)(&compressed);
+            decompress_time += start.elapsed();
+
+            assert_eq!(decompressed, *text, "{} didn't round-trip {name}", contender.name);
+
+            compressed_size += compressed.len();
+        }
+
+        println!("{:<16} {:>14} {:>8.2} {:>18.2} {:>20.2}",
+            contender.name,
+            compressed_size,
+            total_size as f64 / compressed_size as f64,
+            throughput(total_size, compress_time),
+            throughput(total_size, decompress_time)
+        );
+    }
+}
@@ -0,0 +1,66 @@
+//! Serve a text file over HTTP, compressed for the clients that accept the crate's content coding.
+//!
+//! Run with `cargo run --example http_compression --features http`, then fetch the file with
+//! `curl -H "Accept-Encoding: x-ftc" http://127.0.0.1:3000/ --output lorem.txt.compressed`.
+
+use std::convert::Infallible;
+use std::fs;
+use std::io;
+
+use axum::body::Body;
+use axum::http::header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE, VARY};
+use axum::http::HeaderMap;
+use axum::response::Response;
+use axum::routing::get;
+use axum::Router;
+use frequency_tree_compression::http::{self, CompressStream};
+
+
+/// Size of the body chunks, each of which is compressed into a block
+const CHUNK_SIZE: usize = 16 * 1024;
+
+
+async fn serve_text(headers: HeaderMap) -> Result<Response, Infallible> {
+
+    let text = fs::read("test_data/lorem.txt")
+        .unwrap_or_else(|err| panic!("Could not open file: {}", err));
+
+    let chunks: Vec<io::Result<Vec<u8>>> = text.chunks(CHUNK_SIZE)
+        .map(|chunk| Ok(chunk.to_vec()))
+        .collect();
+    let chunks = futures_util::stream::iter(chunks);
+
+    let accepts_ftc = headers.get_all(ACCEPT_ENCODING).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|coding| coding.split(';').next().unwrap().trim() == http::CONTENT_ENCODING);
+
+    let response = Response::builder()
+        .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+        .header(VARY, ACCEPT_ENCODING.as_str());
+
+    let response = if accepts_ftc {
+        response
+            .header(CONTENT_ENCODING, http::CONTENT_ENCODING)
+            .body(Body::from_stream(CompressStream::new(chunks)))
+    } else {
+        response.body(Body::from_stream(chunks))
+    };
+
+    Ok(response.unwrap())
+}
+
+
+#[tokio::main]
+async fn main() {
+
+    let app = Router::new().route("/", get(serve_text));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await
+        .unwrap_or_else(|err| panic!("Could not bind the address: {}", err));
+
+    println!("Listening on http://{}", listener.local_addr().unwrap());
+
+    axum::serve(listener, app).await
+        .unwrap_or_else(|err| panic!("Server error: {}", err));
+}
@@ -4,7 +4,7 @@ use std::io;
 use std::fs;
 use std::str::Utf8Error;
 
-use frequency_tree_compression::{compress, decompress, DecompressionError};
+use frequency_tree_compression::{compress, decompress, decompress_to_string, DecompressionError};
 
 
 enum OwnedOrBorrowedBytes<'a> {
@@ -21,19 +21,11 @@ impl OwnedOrBorrowedBytes<'_> {
         }
     }
 
-
-    pub fn assume_owned(self) -> Box<[u8]> {
-        if let Self::Owned(bytes) = self {
-            bytes
-        } else {
-            unreachable!()
-        }
-    }
-
 }
 
 
 #[derive(Debug, Clone, Copy)]
+#[allow(dead_code)] // The fields are only read when printing the error
 enum MultipassCompressionError {
 
     MissingCompressionLevelSpecifier,
@@ -85,7 +77,7 @@ impl CompressionLevel<'_> {
 
     pub fn deserialize<'a>(input: &'a [u8]) -> Result<CompressionLevel<'a>, MultipassCompressionError> {
 
-        let level = *input.get(0).ok_or(MultipassCompressionError::MissingCompressionLevelSpecifier)?;
+        let level = *input.first().ok_or(MultipassCompressionError::MissingCompressionLevelSpecifier)?;
 
         if input.len() < 2 {
             return Err(MultipassCompressionError::MissingCompressedData);
@@ -108,7 +100,7 @@ impl CompressionLevel<'_> {
 }
 
 
-fn multipass_compress_string<'a>(text: &'a str, cap: Option<u8>, buf: impl io::Write) -> io::Result<u8> {
+fn multipass_compress_string(text: &str, cap: Option<u8>, buf: impl io::Write) -> io::Result<u8> {
 
     let cap = cap.unwrap_or(u8::MAX);
 
@@ -160,7 +152,7 @@ fn multipass_decompress_string<'a>(input: &'a [u8]) -> Result<Cow<'a, str>, Mult
             => Ok(
                 Cow::Borrowed(
                     str::from_utf8(bytes)
-                        .map_err(|e| MultipassCompressionError::InvalidStringEncoding(e))?
+                        .map_err(MultipassCompressionError::InvalidStringEncoding)?
                 )
             ),
 
@@ -168,16 +160,17 @@ fn multipass_decompress_string<'a>(input: &'a [u8]) -> Result<Cow<'a, str>, Mult
 
             let mut bytes = OwnedOrBorrowedBytes::Borrowed(bytes.bytes());
 
-            for _ in 0..level {
+            // The last level holds the text itself
+            for _ in 1..level {
 
-                let decompressed = decompress::<u8>(bytes.bytes()).map_err(|e| MultipassCompressionError::DecompressionError(e))?;
+                let decompressed = decompress::<u8>(bytes.bytes()).map_err(MultipassCompressionError::DecompressionError)?;
                 bytes = OwnedOrBorrowedBytes::Owned(decompressed);
 
             }
 
             Ok(
                 Cow::Owned(
-                    String::from_utf8(bytes.assume_owned().into()).map_err(|e| MultipassCompressionError::InvalidStringEncoding(e.utf8_error()))?
+                    decompress_to_string(bytes.bytes()).map_err(MultipassCompressionError::DecompressionError)?
                 )
             )
         },
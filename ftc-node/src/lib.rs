@@ -0,0 +1,111 @@
+//! Node.js bindings of `frequency_tree_compression`, built with `napi build` into the `ftc` package.
+//!
+//! ```js
+//! const ftc = require("ftc");
+//!
+//! const data = ftc.decompress(fs.readFileSync("archive.ftc"));
+//! ```
+
+use frequency_tree_compression as compression;
+use frequency_tree_compression::TrainingOptions;
+use napi::bindgen_prelude::Buffer;
+use napi::{Error, Result, Status};
+use napi_derive::napi;
+
+
+/// Convert the Rust error to a JavaScript error, with the messages of the whole error chain
+fn to_js_error(e: &dyn std::error::Error) -> Error {
+
+    let mut message = e.to_string();
+
+    let mut source = e.source();
+    while let Some(e) = source {
+        message.push_str(": ");
+        message.push_str(&e.to_string());
+        source = e.source();
+    }
+
+    Error::new(Status::InvalidArg, message)
+}
+
+
+/// Compress arbitrary bytes, including empty data.
+#[napi]
+pub fn compress(data: Buffer) -> Buffer {
+    compression::compress_bytes(&data).into_vec().into()
+}
+
+
+/// Decompress bytes compressed by `compress`, or by the Rust library and the `ftc` command.
+/// Throw if the data is not valid.
+#[napi]
+pub fn decompress(data: Buffer) -> Result<Buffer> {
+    compression::decompress_bytes(&data)
+        .map(|decompressed| decompressed.into_vec().into())
+        .map_err(|e| to_js_error(&e))
+}
+
+
+/// Byte dictionary trained over a corpus of samples, shared by the compressor and the decompressor.
+#[napi]
+pub struct Dictionary {
+
+    inner: compression::Dictionary<u8>,
+
+}
+
+#[napi]
+impl Dictionary {
+
+    /// Train a dictionary over the byte frequencies of the samples.
+    /// Every byte value gets a code, so that the dictionary can encode any data.
+    #[napi(factory)]
+    pub fn train(samples: Vec<Buffer>) -> Result<Self> {
+        compression::Dictionary::train_with_options(samples.iter().map(|sample| sample.iter().copied()), &TrainingOptions::all_bytes())
+            .map(|inner| Self { inner })
+            .ok_or(Error::new(Status::InvalidArg, "the samples contain no bytes"))
+    }
+
+
+    /// Load a dictionary serialized by `toBuffer`, or by the Rust library and `ftc train`.
+    /// Throw if the data is not a valid dictionary.
+    #[napi(factory)]
+    pub fn from_buffer(data: Buffer) -> Result<Self> {
+        compression::Dictionary::deserialize(&data)
+            .map(|inner| Self { inner })
+            .map_err(|e| to_js_error(&e))
+    }
+
+
+    #[napi]
+    pub fn to_buffer(&self) -> Buffer {
+
+        let mut buf = Vec::new();
+        self.inner.serialize(&mut buf);
+
+        buf.into()
+    }
+
+
+    /// Identifier of the dictionary, recorded in the header of the data compressed with it
+    #[napi(getter)]
+    pub fn id(&self) -> u32 {
+        self.inner.id()
+    }
+
+
+    #[napi]
+    pub fn compress(&self, data: Buffer) -> Buffer {
+        self.inner.compress(data.iter().copied()).into_vec().into()
+    }
+
+
+    /// Throw if the data is not valid, or was compressed with another dictionary.
+    #[napi]
+    pub fn decompress(&self, data: Buffer) -> Result<Buffer> {
+        self.inner.decompress(&data)
+            .map(|decompressed| decompressed.into_vec().into())
+            .map_err(|e| to_js_error(&e))
+    }
+
+}
@@ -0,0 +1,172 @@
+//! Python bindings of `frequency_tree_compression`, built with `maturin` into the `ftc` module.
+//!
+//! ```python
+//! import ftc
+//!
+//! data = ftc.decompress_bytes(open("archive.ftc", "rb").read())
+//! ```
+
+use frequency_tree_compression as compression;
+use frequency_tree_compression::{EncodingTree, FrequencyTable, TrainingOptions};
+use pyo3::create_exception;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+
+
+create_exception!(ftc, DecompressionError, PyValueError, "The data is not valid compressed data.");
+
+create_exception!(ftc, DictionaryError, PyValueError, "The data is not a valid serialized dictionary.");
+
+
+/// Raise the Rust error as the Python exception `E`, with the messages of the whole error chain
+fn raise<E>(e: &dyn std::error::Error) -> PyErr
+where
+    E: pyo3::type_object::PyTypeInfo
+{
+
+    let mut message = e.to_string();
+
+    let mut source = e.source();
+    while let Some(e) = source {
+        message.push_str(": ");
+        message.push_str(&e.to_string());
+        source = e.source();
+    }
+
+    PyErr::new::<E, _>(message)
+}
+
+
+/// Compress arbitrary bytes, including empty data.
+#[pyfunction]
+fn compress_bytes<'py>(py: Python<'py>, data: &[u8]) -> Bound<'py, PyBytes> {
+    PyBytes::new(py, &compression::compress_bytes(data))
+}
+
+
+/// Decompress bytes compressed by `compress_bytes`, or by the Rust library and the `ftc` command.
+/// Raise `DecompressionError` if the data is not valid.
+#[pyfunction]
+fn decompress_bytes<'py>(py: Python<'py>, data: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+
+    let decompressed = compression::decompress_bytes(data)
+        .map_err(|e| raise::<DecompressionError>(&e))?;
+
+    Ok(PyBytes::new(py, &decompressed))
+}
+
+
+/// Byte statistics of `data`, like the `ftc stats` command prints them: the number of bytes, of distinct bytes,
+/// the entropy in bits per byte, the code lengths in bits of the frequency tree of the data, and the count of every byte.
+#[pyfunction]
+fn stats<'py>(py: Python<'py>, data: &[u8]) -> PyResult<Bound<'py, PyDict>> {
+
+    let table = FrequencyTable::from_bytes(data);
+
+    let stats = PyDict::new(py);
+
+    stats.set_item("total", table.total())?;
+    stats.set_item("distinct", table.len())?;
+    stats.set_item("entropy", table.entropy())?;
+
+    let lengths = EncodingTree::from_bytes(data).code_length_stats();
+    stats.set_item("min_code_length", lengths.map(|lengths| lengths.min))?;
+    stats.set_item("max_code_length", lengths.map(|lengths| lengths.max))?;
+    stats.set_item("average_code_length", lengths.map(|lengths| lengths.weighted_average))?;
+
+    let counts = PyDict::new(py);
+    for (byte, count) in table.entries() {
+        counts.set_item(byte, count)?;
+    }
+    stats.set_item("counts", counts)?;
+
+    Ok(stats)
+}
+
+
+/// Byte dictionary trained over a corpus of samples, shared by the compressor and the decompressor.
+#[pyclass(frozen, module = "ftc")]
+struct Dictionary {
+
+    inner: compression::Dictionary<u8>,
+
+}
+
+#[pymethods]
+impl Dictionary {
+
+    /// Train a dictionary over the byte frequencies of the samples.
+    /// With `all_bytes`, every byte value gets a code, so that the dictionary can encode any data.
+    #[staticmethod]
+    #[pyo3(signature = (samples, all_bytes = true))]
+    fn train(samples: Vec<Vec<u8>>, all_bytes: bool) -> PyResult<Self> {
+
+        let options = if all_bytes { TrainingOptions::all_bytes() } else { TrainingOptions::new() };
+
+        compression::Dictionary::train_with_options(samples, &options)
+            .map(|inner| Self { inner })
+            .ok_or(PyValueError::new_err("the samples contain no bytes"))
+    }
+
+
+    /// Read a dictionary serialized by `to_bytes`, or by the Rust library and `ftc train`.
+    /// Raise `DictionaryError` if the data is not a valid dictionary.
+    #[staticmethod]
+    fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        compression::Dictionary::deserialize(data)
+            .map(|inner| Self { inner })
+            .map_err(|e| raise::<DictionaryError>(&e))
+    }
+
+
+    fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+
+        let mut buf = Vec::new();
+        self.inner.serialize(&mut buf);
+
+        PyBytes::new(py, &buf)
+    }
+
+
+    /// Identifier of the dictionary, recorded in the header of the data compressed with it
+    #[getter]
+    fn id(&self) -> u32 {
+        self.inner.id()
+    }
+
+
+    fn compress<'py>(&self, py: Python<'py>, data: &[u8]) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.inner.compress(data.iter().copied()))
+    }
+
+
+    /// Raise `DecompressionError` if the data is not valid, or was compressed with another dictionary.
+    fn decompress<'py>(&self, py: Python<'py>, data: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+
+        let decompressed = self.inner.decompress(data)
+            .map_err(|e| raise::<DecompressionError>(&e))?;
+
+        Ok(PyBytes::new(py, &decompressed))
+    }
+
+
+    fn __repr__(&self) -> String {
+        format!("Dictionary(id={:#010x})", self.inner.id())
+    }
+
+}
+
+
+#[pymodule]
+fn ftc(m: &Bound<'_, PyModule>) -> PyResult<()> {
+
+    m.add_function(wrap_pyfunction!(compress_bytes, m)?)?;
+    m.add_function(wrap_pyfunction!(decompress_bytes, m)?)?;
+    m.add_function(wrap_pyfunction!(stats, m)?)?;
+    m.add_class::<Dictionary>()?;
+    m.add("DecompressionError", m.py().get_type::<DecompressionError>())?;
+    m.add("DictionaryError", m.py().get_type::<DictionaryError>())?;
+
+    Ok(())
+}
@@ -0,0 +1,14 @@
+//! Arbitrary bytes must either decompress or fail with an error, and never panic.
+//!
+//! The only value of a single-leaf tree is encoded with zero bits, so a few bytes can legitimately announce billions of symbols.
+//! Out-of-memory reports on such payloads are expected: the count is only rejected when it can't be allocated at all.
+
+#![no_main]
+
+use frequency_tree_compression::decompress;
+use libfuzzer_sys::fuzz_target;
+
+
+fuzz_target!(|data: &[u8]| {
+    let _ = decompress::<u8>(data);
+});
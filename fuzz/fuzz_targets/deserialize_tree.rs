@@ -0,0 +1,14 @@
+//! Arbitrary bytes must either deserialize into a decoding tree or fail with an error, in every tree layout.
+
+#![no_main]
+
+use frequency_tree_compression::DecodingTree;
+use libfuzzer_sys::fuzz_target;
+
+
+fuzz_target!(|data: &[u8]| {
+    let _ = DecodingTree::<u8>::deserialize(data);
+    let _ = DecodingTree::<u8>::deserialize_compact(data);
+    let _ = DecodingTree::<u8>::deserialize_front_coded(data);
+    let _ = DecodingTree::<[u8; 4]>::deserialize_front_coded(data);
+});
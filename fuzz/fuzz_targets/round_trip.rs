@@ -0,0 +1,34 @@
+//! Arbitrary byte strings must decompress to themselves with every combination of compression options.
+//! The first byte of the input selects the options, the rest is compressed.
+
+#![no_main]
+
+use frequency_tree_compression::bits::BitOrder;
+use frequency_tree_compression::{compress_with_options, decompress, CompressionOptions};
+use libfuzzer_sys::fuzz_target;
+
+
+fuzz_target!(|data: &[u8]| {
+
+    let Some((&selector, symbols)) = data.split_first() else {
+        return;
+    };
+
+    // `compress` doesn't accept empty inputs
+    if symbols.is_empty() {
+        return;
+    }
+
+    let options = CompressionOptions {
+        lz77: selector & 1 != 0,
+        mtf: selector & 2 != 0,
+        compact_tree: selector & 4 != 0,
+        bit_order: if selector & 8 != 0 { BitOrder::LsbFirst } else { BitOrder::MsbFirst },
+        canonical_tree: selector & 16 != 0,
+        ..CompressionOptions::new()
+    };
+
+    let compressed = compress_with_options(symbols.iter().copied(), &options);
+
+    assert_eq!(*decompress::<u8>(&compressed).unwrap(), *symbols);
+});
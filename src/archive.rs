@@ -0,0 +1,435 @@
+//! Archives: files holding a sequence of independently compressed members, followed by an index of the members.
+//!
+//! Appending a member writes it over the index and writes the updated index after it, so the members already
+//! in the archive are never rewritten. Log shippers can accumulate compressed records in a single file this way.
+//!
+//! Layout:
+//! - magic bytes `FTCA` and container format version (1 byte)
+//! - members: member tag (1 byte), compressed length (8 bytes), data compressed with `compress_bytes`
+//! - index: index tag (1 byte), number of members (8 bytes), byte offset of each member (8 bytes each)
+//! - footer: byte offset of the index (8 bytes) and magic bytes `FTCA`
+//!
+//! Integers are little-endian. If an append is interrupted and the index is missing or inconsistent,
+//! opening the archive rebuilds the index by scanning the complete members, and the next append drops the incomplete one.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::{compress_bytes, decompress, DecompressionError, FORMAT_VERSION};
+
+
+/// Magic bytes at the start and at the end of an archive
+const MAGIC: &[u8; 4] = b"FTCA";
+
+/// Size of the magic bytes and the format version at the start of an archive
+const PREAMBLE_SIZE: u64 = MAGIC.len() as u64 + 1;
+
+/// Size of the index offset and the magic bytes at the end of an archive
+const FOOTER_SIZE: u64 = 8 + MAGIC.len() as u64;
+
+/// Size of the tag and the length or count in front of a member or of the index
+const RECORD_HEADER_SIZE: u64 = 1 + 8;
+
+
+/// First byte of each record of an archive
+#[repr(u8)]
+enum RecordTag {
+
+    Member,
+    Index,
+
+}
+
+
+/// An archive file, opened with `open` for reading or with `open_append` for reading and appending
+#[derive(Debug)]
+pub struct Archive {
+
+    file: File,
+    /// Byte offset of the record of each member
+    members: Vec<u64>,
+    /// Byte offset of the index, where the next member is written
+    index_offset: u64,
+
+}
+
+impl Archive {
+
+    /// Open the archive at `path` for reading
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, ArchiveError> {
+        Self::load(File::open(path)?)
+    }
+
+
+    /// Open the archive at `path` for reading and appending, creating an empty archive if the file doesn't exist
+    pub fn open_append(path: impl AsRef<Path>) -> Result<Self, ArchiveError> {
+
+        let mut file = File::options().read(true).write(true).create(true).truncate(false).open(path)?;
+
+        if file.metadata()?.len() == 0 {
+
+            file.write_all(MAGIC)?;
+            file.write_all(&[FORMAT_VERSION])?;
+
+            let mut archive = Self { file, members: Vec::new(), index_offset: PREAMBLE_SIZE };
+            archive.write_index()?;
+
+            return Ok(archive);
+        }
+
+        Self::load(file)
+    }
+
+
+    /// Compress `data` and append it to the archive at `path`, creating the archive if it doesn't exist.
+    /// Return the index of the new member.
+    pub fn append(path: impl AsRef<Path>, data: &[u8]) -> Result<usize, ArchiveError> {
+        Self::open_append(path)?.push(data)
+    }
+
+
+    /// Compress `data` and append it as the last member, then update the index.
+    /// Return the index of the new member. Fails if the archive was opened with `open`.
+    pub fn push(&mut self, data: &[u8]) -> Result<usize, ArchiveError> {
+
+        let compressed = compress_bytes(data);
+
+        let mut record = Vec::with_capacity(RECORD_HEADER_SIZE as usize + compressed.len());
+        record.push(RecordTag::Member as u8);
+        record.extend_from_slice(&(compressed.len() as u64).to_le_bytes());
+        record.extend_from_slice(&compressed);
+
+        self.file.seek(SeekFrom::Start(self.index_offset))?;
+        self.file.write_all(&record)?;
+
+        self.members.push(self.index_offset);
+        self.index_offset += record.len() as u64;
+
+        self.write_index()?;
+
+        Ok(self.members.len() - 1)
+    }
+
+
+    /// Number of members
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+
+    /// Read and decompress the member at `index`
+    pub fn read_member(&mut self, index: usize) -> Result<Box<[u8]>, ArchiveError> {
+
+        let offset = *self.members.get(index)
+            .ok_or(ArchiveError::MemberOutOfRange { index, len: self.members.len() })?;
+
+        // Members are contiguous, so a member ends where the next one or the index starts
+        let end = self.members.get(index + 1).copied().unwrap_or(self.index_offset);
+
+        let (tag, len) = self.read_record_header(offset)?;
+        if tag != RecordTag::Member as u8 || offset + RECORD_HEADER_SIZE + len != end {
+            return Err(ArchiveError::InvalidRecord { offset });
+        }
+
+        let mut compressed = vec![0; len as usize];
+        self.file.read_exact(&mut compressed)?;
+
+        decompress(&compressed)
+            .map_err(|source| ArchiveError::InvalidMember { index, source })
+    }
+
+
+    /// Read the index of an archive, or rebuild it if it's missing or inconsistent
+    fn load(mut file: File) -> Result<Self, ArchiveError> {
+
+        let mut preamble = [0; PREAMBLE_SIZE as usize];
+        file.read_exact(&mut preamble)
+            .map_err(|_| ArchiveError::NotAnArchive)?;
+
+        if preamble[..MAGIC.len()] != *MAGIC {
+            return Err(ArchiveError::NotAnArchive);
+        }
+        if preamble[MAGIC.len()] != FORMAT_VERSION {
+            return Err(ArchiveError::UnsupportedVersion { found: preamble[MAGIC.len()], expected: FORMAT_VERSION });
+        }
+
+        let mut archive = Self { file, members: Vec::new(), index_offset: PREAMBLE_SIZE };
+
+        if !archive.read_index()? {
+            archive.scan_members()?;
+        }
+
+        Ok(archive)
+    }
+
+
+    /// Read the index pointed to by the footer.
+    /// Return whether the index is consistent with the size of the file.
+    fn read_index(&mut self) -> Result<bool, ArchiveError> {
+
+        let file_len = self.file.metadata()?.len();
+
+        if file_len < PREAMBLE_SIZE + RECORD_HEADER_SIZE + FOOTER_SIZE {
+            return Ok(false);
+        }
+
+        let mut footer = [0; FOOTER_SIZE as usize];
+        self.file.seek(SeekFrom::Start(file_len - FOOTER_SIZE))?;
+        self.file.read_exact(&mut footer)?;
+
+        let index_offset = u64::from_le_bytes(footer[..8].try_into().unwrap());
+
+        if footer[8..] != *MAGIC || index_offset < PREAMBLE_SIZE || index_offset > file_len - FOOTER_SIZE - RECORD_HEADER_SIZE {
+            return Ok(false);
+        }
+
+        let (tag, count) = self.read_record_header(index_offset)?;
+
+        let index_len = file_len - FOOTER_SIZE - index_offset - RECORD_HEADER_SIZE;
+        if tag != RecordTag::Index as u8 || count.checked_mul(8) != Some(index_len) {
+            return Ok(false);
+        }
+
+        let mut offsets = vec![0; index_len as usize];
+        self.file.read_exact(&mut offsets)?;
+
+        let members: Vec<u64> = offsets.chunks_exact(8)
+            .map(|offset| u64::from_le_bytes(offset.try_into().unwrap()))
+            .collect();
+
+        // The members are stored in order between the preamble and the index
+        if members.first().is_some_and(|first| *first != PREAMBLE_SIZE)
+            || members.windows(2).any(|pair| pair[0] >= pair[1])
+            || members.last().is_some_and(|last| *last >= index_offset)
+        {
+            return Ok(false);
+        }
+
+        self.members = members;
+        self.index_offset = index_offset;
+
+        Ok(true)
+    }
+
+
+    /// Rebuild the index from the complete members that follow the preamble
+    fn scan_members(&mut self) -> Result<(), ArchiveError> {
+
+        let file_len = self.file.metadata()?.len();
+
+        let mut offset = PREAMBLE_SIZE;
+        self.members.clear();
+
+        while offset + RECORD_HEADER_SIZE <= file_len {
+
+            let (tag, len) = self.read_record_header(offset)?;
+
+            let end = (offset + RECORD_HEADER_SIZE).saturating_add(len);
+            if tag != RecordTag::Member as u8 || end > file_len {
+                break;
+            }
+
+            self.members.push(offset);
+            offset = end;
+        }
+
+        self.index_offset = offset;
+
+        Ok(())
+    }
+
+
+    /// Read the tag and the following length or count of the record at `offset`
+    fn read_record_header(&mut self, offset: u64) -> io::Result<(u8, u64)> {
+
+        let mut header = [0; RECORD_HEADER_SIZE as usize];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut header)?;
+
+        Ok((header[0], u64::from_le_bytes(header[1..].try_into().unwrap())))
+    }
+
+
+    /// Write the index and the footer after the last member, and drop anything after them
+    fn write_index(&mut self) -> io::Result<()> {
+
+        let mut index = Vec::with_capacity(RECORD_HEADER_SIZE as usize + self.members.len() * 8 + FOOTER_SIZE as usize);
+
+        index.push(RecordTag::Index as u8);
+        index.extend_from_slice(&(self.members.len() as u64).to_le_bytes());
+        for offset in &self.members {
+            index.extend_from_slice(&offset.to_le_bytes());
+        }
+
+        index.extend_from_slice(&self.index_offset.to_le_bytes());
+        index.extend_from_slice(MAGIC);
+
+        self.file.seek(SeekFrom::Start(self.index_offset))?;
+        self.file.write_all(&index)?;
+
+        self.file.set_len(self.index_offset + index.len() as u64)?;
+        self.file.flush()
+    }
+
+}
+
+
+/// Error returned when an archive can't be opened or read
+#[derive(Debug)]
+pub enum ArchiveError {
+
+    /// Reading or writing the archive file failed
+    Io (io::Error),
+    /// The file doesn't start with the archive magic bytes
+    NotAnArchive,
+    /// The archive was produced by an unsupported version of the container format
+    UnsupportedVersion { found: u8, expected: u8 },
+    /// The member `index` doesn't exist in the `len` members of the archive
+    MemberOutOfRange { index: usize, len: usize },
+    /// The index points to a record at `offset` that isn't a member, or whose length doesn't match the index
+    InvalidRecord { offset: u64 },
+    /// The compressed data of the member `index` is malformed
+    InvalidMember { index: usize, source: DecompressionError },
+
+}
+
+impl fmt::Display for ArchiveError {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::Io (_)
+                => write!(f, "could not access the archive file"),
+            ArchiveError::NotAnArchive
+                => write!(f, "the file is not an archive"),
+            ArchiveError::UnsupportedVersion { found, expected }
+                => write!(f, "unsupported archive format version {found}, expected {expected}"),
+            ArchiveError::MemberOutOfRange { index, len }
+                => write!(f, "member {index} is out of the {len} members of the archive"),
+            ArchiveError::InvalidRecord { offset }
+                => write!(f, "invalid member record at byte {offset}"),
+            ArchiveError::InvalidMember { index, .. }
+                => write!(f, "could not decompress member {index}"),
+        }
+    }
+}
+
+impl Error for ArchiveError {
+
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ArchiveError::Io (e) => Some(e),
+            ArchiveError::NotAnArchive |
+            ArchiveError::UnsupportedVersion { .. } |
+            ArchiveError::MemberOutOfRange { .. } |
+            ArchiveError::InvalidRecord { .. } => None,
+            ArchiveError::InvalidMember { source, .. } => Some(source),
+        }
+    }
+}
+
+impl From<io::Error> for ArchiveError {
+
+    fn from(e: io::Error) -> Self {
+        ArchiveError::Io(e)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+
+
+    /// Path of a temporary archive, unique to the test
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ftc-archive-{name}-{}.ftca", std::process::id()))
+    }
+
+
+    #[test]
+    fn check_append() {
+
+        let path = temp_path("append");
+        let _ = fs::remove_file(&path);
+
+        let records: [&[u8]; 4] = [b"first log record", b"", b"second log record, a bit longer", b"x"];
+
+        for (i, record) in records.iter().enumerate() {
+            assert_eq!(Archive::append(&path, record).unwrap(), i);
+        }
+
+        let mut archive = Archive::open(&path).unwrap();
+        assert_eq!(archive.len(), 4);
+        for (i, record) in records.iter().enumerate() {
+            assert_eq!(*archive.read_member(i).unwrap(), **record);
+        }
+        assert!(matches!(archive.read_member(4), Err(ArchiveError::MemberOutOfRange { index: 4, len: 4 })));
+
+        // The length of a member must match the offset of the next one
+        let mut corrupted = fs::read(&path).unwrap();
+        corrupted[PREAMBLE_SIZE as usize + 1] += 1;
+        fs::write(&path, &corrupted).unwrap();
+        assert!(matches!(Archive::open(&path).unwrap().read_member(0), Err(ArchiveError::InvalidRecord { offset: PREAMBLE_SIZE })));
+
+        // Reading only archives can't be appended to
+        assert!(matches!(archive.push(b"more"), Err(ArchiveError::Io (_))));
+
+        fs::remove_file(&path).unwrap();
+
+        let empty = Archive::open_append(&path).unwrap();
+        assert!(empty.is_empty());
+        assert!(Archive::open(&path).unwrap().is_empty());
+
+        fs::write(&path, b"not an archive").unwrap();
+        assert!(matches!(Archive::open(&path), Err(ArchiveError::NotAnArchive)));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+
+    #[test]
+    fn check_interrupted_append() {
+
+        let path = temp_path("interrupted");
+        let _ = fs::remove_file(&path);
+
+        Archive::append(&path, b"first").unwrap();
+        Archive::append(&path, b"second").unwrap();
+
+        let complete = fs::read(&path).unwrap();
+        let index_offset = u64::from_le_bytes(complete[complete.len() - 12..complete.len() - 4].try_into().unwrap()) as usize;
+
+        // The index was partially written, or the last member was partially written over the index
+        for len in [complete.len() - 1, index_offset + 3, index_offset - 2] {
+
+            fs::write(&path, &complete[..len]).unwrap();
+
+            let mut archive = Archive::open(&path).unwrap();
+            let expected: &[&[u8]] = if len < index_offset { &[b"first"] } else { &[b"first", b"second"] };
+
+            assert_eq!(archive.len(), expected.len());
+            for (i, record) in expected.iter().enumerate() {
+                assert_eq!(*archive.read_member(i).unwrap(), **record);
+            }
+
+            // Appending drops the incomplete data and writes a new index
+            assert_eq!(Archive::append(&path, b"third").unwrap(), expected.len());
+            assert_eq!(*Archive::open(&path).unwrap().read_member(expected.len()).unwrap(), *b"third");
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+
+}
@@ -0,0 +1,259 @@
+//! ASCII armor: compressed data encoded in base64 between a begin and an end line,
+//! for embedding it in JSON, YAML, emails, or anywhere raw bytes are inconvenient.
+//!
+//! ```text
+//! -----BEGIN FTC DATA-----
+//! BAAAAQMAYWJjCAAAAAAAAAA...
+//! -----END FTC DATA-----
+//! ```
+//!
+//! The base64 body uses the standard alphabet with padding, in lines of 64 characters.
+//! When reading armored text, anything before the begin line and after the end line is ignored,
+//! as is the whitespace in the body, so line breaks changed in transit don't matter.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::{compress, decompress, DecompressionError, Symbol, SymbolSource};
+
+
+/// First line of armored data
+pub const BEGIN_LINE: &str = "-----BEGIN FTC DATA-----";
+
+/// Last line of armored data
+pub const END_LINE: &str = "-----END FTC DATA-----";
+
+/// Number of base64 characters per line of the body
+const LINE_WIDTH: usize = 64;
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+const PADDING: u8 = b'=';
+
+
+/// Compress `input` and armor the compressed data
+pub fn compress_to_armored<U>(input: impl SymbolSource<Symbol = U>) -> String
+where
+    U: Symbol
+{
+    armor(&compress(input))
+}
+
+
+/// Extract the compressed data from the armored `text` and decompress it
+pub fn decompress_armored<U>(text: &str) -> Result<Box<[U]>, ArmorError>
+where
+    U: Symbol
+{
+    decompress(&dearmor(text)?).map_err(ArmorError::InvalidData)
+}
+
+
+/// Encode arbitrary bytes in base64 between the begin and the end line.
+/// The text ends with a line break.
+pub fn armor(data: &[u8]) -> String {
+
+    let mut encoded = Vec::with_capacity(data.len().div_ceil(3) * 4);
+
+    for group in data.chunks(3) {
+
+        let bits = group.iter().enumerate()
+            .fold(0_u32, |bits, (i, byte)| bits | (*byte as u32) << (16 - 8 * i));
+
+        // A group of n bytes is encoded in n + 1 characters, and padded to 4
+        for i in 0..4 {
+            encoded.push(if i <= group.len() {
+                ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize]
+            } else {
+                PADDING
+            });
+        }
+    }
+
+    let mut text = String::with_capacity(BEGIN_LINE.len() + END_LINE.len() + encoded.len() + encoded.len() / LINE_WIDTH + 3);
+
+    text.push_str(BEGIN_LINE);
+    text.push('\n');
+
+    for line in encoded.chunks(LINE_WIDTH) {
+        // The base64 alphabet is ASCII
+        text.push_str(std::str::from_utf8(line).unwrap());
+        text.push('\n');
+    }
+
+    text.push_str(END_LINE);
+    text.push('\n');
+
+    text
+}
+
+
+/// Decode the bytes armored in `text` by `armor`
+pub fn dearmor(text: &str) -> Result<Vec<u8>, ArmorError> {
+
+    let mut lines = text.lines().enumerate().skip_while(|(_, line)| line.trim() != BEGIN_LINE);
+
+    lines.next().ok_or(ArmorError::MissingBegin)?;
+
+    let mut sextets = Vec::new();
+    let mut padding = 0;
+    let mut ended = false;
+
+    for (line_index, line) in lines {
+
+        if line.trim() == END_LINE {
+            ended = true;
+            break;
+        }
+
+        for c in line.chars().filter(|c| !c.is_ascii_whitespace()) {
+
+            let invalid = ArmorError::InvalidCharacter { line: line_index + 1, found: c };
+
+            if c == PADDING as char {
+                padding += 1;
+                continue;
+            }
+
+            // Padding is only valid at the end of the body
+            if padding != 0 {
+                return Err(invalid);
+            }
+
+            let value = u8::try_from(c).ok()
+                .and_then(|byte| ALPHABET.iter().position(|symbol| *symbol == byte))
+                .ok_or(invalid)?;
+
+            sextets.push(value as u32);
+        }
+    }
+
+    if !ended {
+        return Err(ArmorError::MissingEnd);
+    }
+
+    // The last group holds 2 or 3 characters followed by the padding that completes it to 4
+    let remainder = sextets.len() % 4;
+    if padding > 2 || (sextets.len() + padding) % 4 != 0 || remainder == 1 {
+        return Err(ArmorError::InvalidPadding);
+    }
+
+    let mut data = Vec::with_capacity(sextets.len() / 4 * 3 + remainder);
+
+    for group in sextets.chunks(4) {
+
+        let bits = group.iter().enumerate()
+            .fold(0, |bits, (i, sextet)| bits | sextet << (18 - 6 * i));
+
+        // A group of n characters encodes n - 1 bytes
+        for i in 0..group.len() - 1 {
+            data.push((bits >> (16 - 8 * i)) as u8);
+        }
+    }
+
+    Ok(data)
+}
+
+
+/// Error returned when armored text can't be read back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArmorError {
+
+    /// The text doesn't contain the begin line
+    MissingBegin,
+    /// The begin line is not followed by an end line
+    MissingEnd,
+    /// The character `found` on `line`, counting from 1, is not valid base64
+    InvalidCharacter { line: usize, found: char },
+    /// The base64 body is not padded to a multiple of 4 characters
+    InvalidPadding,
+    /// The armored data can't be decompressed
+    InvalidData (DecompressionError),
+
+}
+
+impl fmt::Display for ArmorError {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArmorError::MissingBegin
+                => write!(f, "missing `{BEGIN_LINE}` line"),
+            ArmorError::MissingEnd
+                => write!(f, "missing `{END_LINE}` line"),
+            ArmorError::InvalidCharacter { line, found }
+                => write!(f, "invalid base64 character {found:?} on line {line}"),
+            ArmorError::InvalidPadding
+                => write!(f, "invalid base64 padding"),
+            ArmorError::InvalidData (_)
+                => write!(f, "could not decompress the armored data"),
+        }
+    }
+}
+
+impl Error for ArmorError {
+
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ArmorError::MissingBegin |
+            ArmorError::MissingEnd |
+            ArmorError::InvalidCharacter { .. } |
+            ArmorError::InvalidPadding => None,
+            ArmorError::InvalidData (e) => Some(e),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+
+    #[test]
+    fn check_base64() {
+
+        assert_eq!(armor(b""), format!("{BEGIN_LINE}\n{END_LINE}\n"));
+        assert_eq!(armor(b"f"), format!("{BEGIN_LINE}\nZg==\n{END_LINE}\n"));
+        assert_eq!(armor(b"fo"), format!("{BEGIN_LINE}\nZm8=\n{END_LINE}\n"));
+        assert_eq!(armor(b"foobar"), format!("{BEGIN_LINE}\nZm9vYmFy\n{END_LINE}\n"));
+
+        let bytes: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let armored = armor(&bytes);
+
+        assert!(armored.lines().all(|line| line.len() <= LINE_WIDTH || line == BEGIN_LINE));
+        assert_eq!(dearmor(&armored).unwrap(), bytes);
+
+        // Surrounding text, indentation, and line breaks changed in transit
+        let mangled = format!("Subject: data\r\n\r\n  {}\r\n-- \r\nsignature", armored.replace('\n', "\r\n  "));
+        assert_eq!(dearmor(&mangled).unwrap(), bytes);
+    }
+
+
+    #[test]
+    fn check_armored_round_trip() {
+
+        let text = "the quick brown fox jumps over the lazy dog";
+
+        let armored = compress_to_armored(text.chars());
+        assert!(armored.is_ascii());
+
+        let decompressed: String = decompress_armored::<char>(&armored).unwrap().iter().collect();
+        assert_eq!(decompressed, text);
+    }
+
+
+    #[test]
+    fn check_invalid_armor() {
+
+        assert_eq!(dearmor("Zm9v"), Err(ArmorError::MissingBegin));
+        assert_eq!(dearmor(&format!("{BEGIN_LINE}\nZm9v\n")), Err(ArmorError::MissingEnd));
+        assert_eq!(dearmor(&format!("{BEGIN_LINE}\nZm9v\nZm9*\n{END_LINE}")), Err(ArmorError::InvalidCharacter { line: 3, found: '*' }));
+        assert_eq!(dearmor(&format!("{BEGIN_LINE}\nZg==Zm9v\n{END_LINE}")), Err(ArmorError::InvalidCharacter { line: 2, found: 'Z' }));
+        assert_eq!(dearmor(&format!("{BEGIN_LINE}\nZm9\n{END_LINE}")), Err(ArmorError::InvalidPadding));
+        assert_eq!(dearmor(&format!("{BEGIN_LINE}\nZ===\n{END_LINE}")), Err(ArmorError::InvalidPadding));
+
+        assert!(matches!(decompress_armored::<u8>(&armor(b"not compressed")), Err(ArmorError::InvalidData (_))));
+    }
+
+}
@@ -0,0 +1,823 @@
+//! Command line interface to the frequency tree compressor.
+//! Files are compressed as sequences of bytes, and decompressed as the symbol type recorded in their header.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+use frequency_tree_compression::volume::{SplitReader, SplitWriter, VOLUME_HEADER_SIZE};
+use frequency_tree_compression::{
+    compress, compress_bytes, compress_chunked, compress_file, decompress, decompress_any, decompress_bytes, decompress_chunked, verify,
+    DecompressionError, Dictionary, DynDecodingTree, EncodingTree, FrequencyTable, Header, SymbolType
+};
+
+
+const USAGE: &str = "\
+Usage:
+    ftc compress [--dict <dictionary>] [--split <size>] <input> [-o <output>]
+    ftc decompress [--dict <dictionary>] <input> [-o <output>]
+    ftc test <inputs...>
+    ftc train <samples...> -o <dictionary>
+    ftc dict inspect <dictionary>
+    ftc tree <input>
+    ftc stats [--csv] <input>
+    ftc bench <directory>
+
+Split output is written in volumes <output>.001, <output>.002, and so on.
+Decompress it by passing the first volume. Sizes accept the suffixes K, M, G, KiB, MiB, and GiB.";
+
+
+/// Extension appended to compressed files when no output path is given
+const COMPRESSED_EXTENSION: &str = "ftc";
+
+/// Extension of the first volume of split output
+const FIRST_VOLUME_EXTENSION: &str = "001";
+
+/// Length in characters of the bar of the most frequent byte printed by `stats`
+const HISTOGRAM_WIDTH: usize = 50;
+
+
+#[derive(Debug, PartialEq)]
+enum Command {
+
+    Compress { input: PathBuf, output: PathBuf, dict: Option<PathBuf>, split: Option<u64> },
+    Decompress { input: PathBuf, output: PathBuf, dict: Option<PathBuf> },
+    Test { inputs: Vec<PathBuf> },
+    Train { samples: Vec<PathBuf>, output: PathBuf },
+    InspectDictionary { dict: PathBuf },
+    Tree { input: PathBuf },
+    Stats { input: PathBuf, csv: bool },
+    Bench { dir: PathBuf },
+
+}
+
+
+/// Options and positional arguments of a command line
+#[derive(Default)]
+struct Arguments {
+
+    positional: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    dict: Option<PathBuf>,
+    csv: bool,
+    split: Option<u64>,
+
+}
+
+
+fn parse_arguments(mut args: impl Iterator<Item = String>) -> Result<Arguments, String> {
+
+    let mut parsed = Arguments::default();
+
+    while let Some(arg) = args.next() {
+
+        let mut value = |option: &str| args.next()
+            .ok_or(format!("missing value for option `{option}`"));
+
+        match arg.as_str() {
+            "-o" | "--output" => parsed.output = Some(value(&arg)?.into()),
+            "--dict" => parsed.dict = Some(value(&arg)?.into()),
+            "--csv" => parsed.csv = true,
+            "--split" => parsed.split = Some(parse_size(&value(&arg)?)?),
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{arg}`")),
+            _ => parsed.positional.push(PathBuf::from(arg))
+        }
+    }
+
+    Ok(parsed)
+}
+
+
+/// Parse a size in bytes with an optional decimal (`K`, `M`, `G`, optionally followed by `B`) or binary (`KiB`, `MiB`, `GiB`) unit
+fn parse_size(size: &str) -> Result<u64, String> {
+
+    let digits = size.find(|c: char| !c.is_ascii_digit()).unwrap_or(size.len());
+    let (number, unit) = size.split_at(digits);
+
+    let multiplier = match unit {
+        "" | "B" => 1,
+        "K" | "KB" => 1_000,
+        "M" | "MB" => 1_000_000,
+        "G" | "GB" => 1_000_000_000,
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        _ => return Err(format!("unknown size unit `{unit}`"))
+    };
+
+    number.parse::<u64>().ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .filter(|size| *size > VOLUME_HEADER_SIZE)
+        .ok_or(format!("invalid size `{size}`, it must be larger than {VOLUME_HEADER_SIZE} bytes"))
+}
+
+
+/// Path of the volume `index` of split output, starting from 0
+fn volume_path(output: &Path, index: u32) -> PathBuf {
+    let mut path = output.to_path_buf().into_os_string();
+    path.push(format!(".{:03}", index + 1));
+    path.into()
+}
+
+
+/// Return the only positional argument, which is the input file
+fn single_input(positional: Vec<PathBuf>) -> Result<PathBuf, String> {
+
+    let [input]: [PathBuf; 1] = positional.try_into()
+        .map_err(|_| "expected exactly one input file".to_string())?;
+
+    Ok(input)
+}
+
+
+fn parse_command(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+
+    let command = args.next().ok_or("missing command")?;
+
+    let Arguments { positional, output, dict, csv, split } = parse_arguments(args)?;
+
+    if csv && command != "stats" {
+        return Err(format!("option `--csv` is not valid for `{command}`"));
+    }
+    if split.is_some() && command != "compress" {
+        return Err(format!("option `--split` is not valid for `{command}`"));
+    }
+
+    match command.as_str() {
+
+        "compress" => {
+
+            let input = single_input(positional)?;
+
+            let output = output.unwrap_or_else(|| {
+                let mut path = input.clone().into_os_string();
+                path.push(".");
+                path.push(COMPRESSED_EXTENSION);
+                path.into()
+            });
+
+            Ok(Command::Compress { input, output, dict, split })
+        },
+
+        "decompress" => {
+
+            let input = single_input(positional)?;
+
+            // The first volume of split output is named after the compressed file
+            let compressed = match input.extension() {
+                Some (ext) if ext == FIRST_VOLUME_EXTENSION => input.with_extension(""),
+                _ => input.clone()
+            };
+
+            let output = match output {
+                Some (output) => output,
+                None if compressed.extension().is_some_and(|ext| ext == COMPRESSED_EXTENSION) => compressed.with_extension(""),
+                None => return Err(format!("the input doesn't end with `.{COMPRESSED_EXTENSION}`, specify the output with `-o`"))
+            };
+
+            Ok(Command::Decompress { input, output, dict })
+        },
+
+        "test" => {
+
+            if dict.is_some() {
+                return Err("option `--dict` is not valid for `test`".to_string());
+            }
+            if output.is_some() {
+                return Err("option `-o` is not valid for `test`, nothing is written".to_string());
+            }
+            if positional.is_empty() {
+                return Err("expected at least one input file".to_string());
+            }
+
+            Ok(Command::Test { inputs: positional })
+        },
+
+        "train" => {
+
+            if dict.is_some() {
+                return Err("option `--dict` is not valid for `train`".to_string());
+            }
+            if positional.is_empty() {
+                return Err("expected at least one sample file".to_string());
+            }
+
+            let output = output.ok_or("missing dictionary output path `-o`")?;
+
+            Ok(Command::Train { samples: positional, output })
+        },
+
+        "dict" => {
+
+            if dict.is_some() || output.is_some() {
+                return Err("options `--dict` and `-o` are not valid for `dict`".to_string());
+            }
+
+            let mut positional = positional.into_iter();
+
+            match positional.next() {
+                Some (subcommand) if subcommand.as_os_str() == "inspect" => Ok(Command::InspectDictionary { dict: single_input(positional.collect())? }),
+                Some (subcommand) => Err(format!("unknown dictionary command `{}`", subcommand.display())),
+                None => Err("missing dictionary command".to_string())
+            }
+        },
+
+        "tree" => {
+
+            if dict.is_some() {
+                return Err("option `--dict` is not valid for `tree`".to_string());
+            }
+            if output.is_some() {
+                return Err("option `-o` is not valid for `tree`, the tree is printed".to_string());
+            }
+
+            Ok(Command::Tree { input: single_input(positional)? })
+        },
+
+        "stats" => {
+
+            if dict.is_some() {
+                return Err("option `--dict` is not valid for `stats`".to_string());
+            }
+            if output.is_some() {
+                return Err("option `-o` is not valid for `stats`, the statistics are printed".to_string());
+            }
+
+            Ok(Command::Stats { input: single_input(positional)?, csv })
+        },
+
+        "bench" => {
+
+            if dict.is_some() {
+                return Err("option `--dict` is not valid for `bench`".to_string());
+            }
+            if output.is_some() {
+                return Err("option `-o` is not valid for `bench`, the comparison is printed".to_string());
+            }
+
+            let [dir]: [PathBuf; 1] = positional.try_into()
+                .map_err(|_| "expected exactly one directory".to_string())?;
+
+            Ok(Command::Bench { dir })
+        },
+
+        _ => Err(format!("unknown command `{command}`"))
+    }
+}
+
+
+/// Read the data split in the volumes of `compressed`, up to the last one
+fn read_volumes(compressed: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+
+    let mut paths = Vec::new();
+    while volume_path(compressed, paths.len() as u32).exists() {
+        paths.push(volume_path(compressed, paths.len() as u32));
+    }
+
+    let missing = paths.len() as u32;
+    if volume_path(compressed, missing + 1).exists() {
+        return Err(format!("missing volume {}", volume_path(compressed, missing).display()).into());
+    }
+
+    let volumes = paths.iter().map(File::open).collect::<Result<Vec<File>, _>>()?;
+
+    let mut data = Vec::new();
+    SplitReader::new(volumes).read_to_end(&mut data)?;
+
+    Ok(data)
+}
+
+
+/// A way of compressing files compared by `bench`
+struct Strategy {
+
+    name: &'static str,
+    /// Compress a file, or return `None` if the strategy doesn't apply to it
+    compress: fn(&[u8]) -> Option<Box<[u8]>>,
+    decompress: fn(&[u8]) -> Vec<u8>,
+
+}
+
+
+const STRATEGIES: &[Strategy] = &[
+    Strategy {
+        name: "u8",
+        compress: |data| Some(compress_bytes(data)),
+        decompress: |compressed| decompress_bytes(compressed).unwrap().into_vec(),
+    },
+    Strategy {
+        name: "char",
+        compress: |data| std::str::from_utf8(data).ok().filter(|text| !text.is_empty()).map(|text| compress(text.chars())),
+        decompress: |compressed| decompress::<char>(compressed).unwrap().iter().collect::<String>().into_bytes(),
+    },
+    Strategy {
+        name: "grouped-2",
+        compress: |data| (!data.is_empty()).then(|| compress_chunked::<u8, 2>(data.iter().copied())),
+        decompress: |compressed| decompress_chunked::<u8, 2>(compressed).unwrap().into_vec(),
+    },
+];
+
+
+/// Totals of a row of the `bench` table
+#[derive(Default)]
+struct BenchRow {
+
+    files: usize,
+    input: usize,
+    output: usize,
+    compress_time: Duration,
+    decompress_time: Duration,
+
+}
+
+
+/// Total number of bits of the codes of symbols with the given counts, with optimal Huffman codes
+fn huffman_bits(counts: impl Iterator<Item = usize>) -> usize {
+
+    let mut heap: BinaryHeap<Reverse<usize>> = counts.map(Reverse).collect();
+    let mut bits = 0;
+
+    // Every merge adds a bit to the codes of all the symbols below it
+    while let (Some(Reverse (first)), Some(Reverse (second))) = (heap.pop(), heap.pop()) {
+        bits += first + second;
+        heap.push(Reverse(first + second));
+    }
+
+    bits
+}
+
+
+/// Size of `data` compressed as bytes, if its frequency tree had the optimal Huffman shape instead of the balanced one.
+/// Both trees have the same number of nodes, so only the bitcode changes.
+fn huffman_estimate(data: &[u8], compressed_size: usize) -> usize {
+
+    let table = FrequencyTable::from_bytes(data);
+
+    let mut counts = [0; 256];
+    for (byte, count) in table.entries() {
+        counts[*byte as usize] = *count;
+    }
+
+    let balanced_bits: usize = EncodingTree::from_bytes(data).code_lengths().into_iter()
+        .map(|(byte, length)| counts[byte as usize] * length as usize)
+        .sum();
+
+    compressed_size - balanced_bits.div_ceil(8) + huffman_bits(table.entries().iter().map(|(_, count)| *count)).div_ceil(8)
+}
+
+
+/// Throughput in MiB/s of processing `bytes` bytes in `time`
+fn throughput(bytes: usize, time: Duration) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0) / time.as_secs_f64()
+}
+
+
+/// Compress every file with every strategy, check the round trip, and format the comparison table
+fn bench(files: &[Vec<u8>]) -> Result<String, Box<dyn Error>> {
+
+    let mut rows: Vec<BenchRow> = STRATEGIES.iter().map(|_| BenchRow::default()).collect();
+    let mut huffman = BenchRow::default();
+
+    for data in files {
+
+        for (strategy, row) in STRATEGIES.iter().zip(&mut rows) {
+
+            let start = Instant::now();
+            let Some(compressed) = (strategy.compress)(data) else {
+                continue;
+            };
+            row.compress_time += start.elapsed();
+
+            let start = Instant::now();
+            let decompressed = (strategy.decompress)(&compressed);
+            row.decompress_time += start.elapsed();
+
+            if decompressed != *data {
+                return Err(format!("the `{}` strategy didn't restore a file", strategy.name).into());
+            }
+
+            row.files += 1;
+            row.input += data.len();
+            row.output += compressed.len();
+
+            if strategy.name == "u8" {
+                huffman.files += 1;
+                huffman.input += data.len();
+                huffman.output += huffman_estimate(data, compressed.len());
+            }
+        }
+    }
+
+    let mut table = format!("{:<12} {:>6} {:>12} {:>12} {:>7} {:>14} {:>14}\n", "strategy", "files", "input", "output", "ratio", "compress MiB/s", "decomp. MiB/s");
+
+    let names = STRATEGIES.iter().map(|strategy| strategy.name).chain(["u8-huffman*"]);
+    for (name, row) in names.zip(rows.iter().chain([&huffman])) {
+
+        let ratio = row.input as f64 / row.output.max(1) as f64;
+
+        let (compress, decompress) = if row.compress_time.is_zero() {
+            ("-".to_string(), "-".to_string())
+        } else {
+            (format!("{:.1}", throughput(row.input, row.compress_time)), format!("{:.1}", throughput(row.input, row.decompress_time)))
+        };
+
+        writeln!(table, "{name:<12} {:>6} {:>12} {:>12} {ratio:>7.3} {compress:>14} {decompress:>14}", row.files, row.input, row.output)?;
+    }
+
+    table.push_str("* estimated: u8 with optimal Huffman code lengths instead of the balanced frequency tree\n");
+
+    Ok(table)
+}
+
+
+/// Format the symbols of a dictionary by decreasing count, with their code lengths, after a summary of the code lengths.
+/// Dictionaries serialized without their counts are ranked by code length.
+fn inspect_dictionary(dictionary: &Dictionary<u8>) -> String {
+
+    let codes = dictionary.tree().code_table();
+    let code_length = |byte: &u8| codes[byte].len();
+
+    let mut symbols = dictionary.frequencies();
+    symbols.sort_by_key(|(byte, count)| (Reverse(*count), code_length(byte), *byte));
+
+    let counted = symbols.iter().any(|(_, count)| *count != 0);
+
+    let mut report = format!("dictionary {:#010x}: {} symbols", dictionary.id(), symbols.len());
+    if !counted {
+        report.push_str(", without counts, ranked by code length");
+    }
+    report.push('\n');
+
+    // The tree of a dictionary has at least a leaf
+    let stats = dictionary.tree().clone().into_encoding_tree().code_length_stats().unwrap();
+    if counted {
+        writeln!(
+            report, "entropy: {:.3} bits per symbol, codes: {:.3} bits per symbol on average, {} to {} bits",
+            stats.entropy, stats.weighted_average, stats.min, stats.max
+        ).unwrap();
+    } else {
+        writeln!(report, "codes: {} to {} bits", stats.min, stats.max).unwrap();
+    }
+
+    writeln!(report, "{:>5} {:>8} {:>12} {:>5}", "rank", "symbol", "count", "bits").unwrap();
+
+    for (rank, (byte, count)) in symbols.iter().enumerate() {
+        let symbol = format!("'{}'", byte.escape_ascii());
+        writeln!(report, "{:>5} {symbol:>8} {count:>12} {:>5}", rank + 1, code_length(byte)).unwrap();
+    }
+
+    report
+}
+
+
+/// Format the leaves of the tree of compressed data in pre-order, with their codes, after the number of leaves.
+/// The leaves are the symbols of the type recorded in the header, or bytes if it's not recorded.
+fn inspect_tree(data: &[u8]) -> Result<String, Box<dyn Error>> {
+
+    let (header, header_size) = Header::deserialize(data)?;
+
+    // The other layouts have several trees, or trees of transformed symbols
+    if header.blocks || header.lz77 || header.mtf || header.remap || header.dictionary.is_some() {
+        return Err("only the tree of data compressed without transforms, streaming, or a dictionary can be printed".into());
+    }
+
+    // Chunks don't record their type, and the chunks of the command line are bytes
+    let width = header.symbol_type.map_or(1, SymbolType::width) * header.chunk_size as usize;
+    if width > DynDecodingTree::MAX_WIDTH {
+        return Err(format!("the leaves of {width} bytes are too wide to be printed").into());
+    }
+
+    let input = &data[header_size..];
+    let (tree, _) = if header.compact_tree {
+        DynDecodingTree::deserialize_compact(input, width)
+    } else if header.canonical_tree {
+        DynDecodingTree::deserialize_canonical(input, width)
+    } else {
+        DynDecodingTree::deserialize(input, width)
+    }?;
+
+    let leaves = tree.leaves();
+
+    let mut report = match header.symbol_type {
+        Some (symbol_type) => format!("{} leaves of type {symbol_type}\n", leaves.len()),
+        None => format!("{} leaves of {width} bytes\n", leaves.len()),
+    };
+
+    for (value, code) in leaves {
+
+        let value = match (header.symbol_type, value) {
+            (Some (SymbolType::U8), [byte]) => format!("'{}'", byte.escape_ascii()),
+            (Some (SymbolType::Char), [a, b, c, d]) => match char::from_u32(u32::from_le_bytes([*a, *b, *c, *d])) {
+                Some (c) => format!("{c:?}"),
+                None => return Err("invalid character in the tree".into()),
+            },
+            _ => value.iter().map(|byte| format!("{byte:02x}")).collect(),
+        };
+
+        // Single-leaf trees encode their value with zero bits
+        let code = if code.is_empty() { "(no bits)".to_string() } else { code.to_string() };
+
+        writeln!(report, "{value:>10} {code}").unwrap();
+    }
+
+    Ok(report)
+}
+
+
+/// Read compressed data, from all the volumes if `input` is the first volume of split output
+fn read_compressed(input: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+
+    if input.extension().is_some_and(|ext| ext == FIRST_VOLUME_EXTENSION) {
+        return read_volumes(&input.with_extension(""));
+    }
+
+    Ok(fs::read(input)?)
+}
+
+
+fn load_dictionary(path: &PathBuf) -> Result<Dictionary<u8>, Box<dyn Error>> {
+    Ok(Dictionary::deserialize(&fs::read(path)?)?)
+}
+
+
+fn run(command: Command) -> Result<(), Box<dyn Error>> {
+
+    match command {
+
+        Command::Compress { input, output, dict, split } => {
+
+            // Without a dictionary, the name, modification time, and mode of the input are recorded in the header
+            let compressed = match dict {
+                Some (dict) => load_dictionary(&dict)?.compress(fs::read(input)?.into_iter()),
+                None => compress_file(input)?
+            };
+
+            match split {
+                Some (volume_size) => {
+                    let mut writer = SplitWriter::new(volume_size, |index| Ok(BufWriter::new(File::create(volume_path(&output, index))?)));
+                    writer.write_all(&compressed)?;
+                    writer.finish()?;
+                },
+                None => fs::write(output, compressed)?
+            }
+        },
+
+        Command::Decompress { input, output, dict } => {
+
+            let data = read_compressed(&input)?;
+
+            match dict {
+                Some (dict) => fs::write(output, load_dictionary(&dict)?.decompress(&data)?)?,
+                None => {
+                    let (header, _) = Header::deserialize(&data)?;
+                    // Characters are written as UTF-8 and wider symbols as their little-endian bytes.
+                    // Data without a recorded type is written as bytes.
+                    let decompressed = match decompress_any(&data) {
+                        Err (DecompressionError::UnknownSymbolType) => decompress_bytes(&data)?.into_vec(),
+                        decompressed => decompressed?.to_bytes(),
+                    };
+                    fs::write(&output, decompressed)?;
+                    header.metadata.restore(&output)?;
+                }
+            }
+        },
+
+        Command::Test { inputs } => {
+
+            // Like `gzip -t`, every file is checked even if an earlier one is corrupted
+            let mut failed = 0;
+            for input in &inputs {
+                match read_compressed(input).and_then(|data| Ok(verify(&data)?)) {
+                    Ok (info) => println!("{}: OK, {} bytes, ratio {:.3}, {}", input.display(), info.decompressed_size, info.ratio(), info.describe()),
+                    Err (e) => {
+                        eprintln!("{}: {e}", input.display());
+                        failed += 1;
+                    }
+                }
+            }
+
+            if failed != 0 {
+                return Err(format!("{failed} of {} files failed the test", inputs.len()).into());
+            }
+        },
+
+        Command::Train { samples, output } => {
+
+            let dictionary = Dictionary::train_from_files(&samples)?;
+
+            // The counts let `dict inspect` rank the symbols
+            let mut buf = Vec::new();
+            dictionary.serialize_with_counts(&mut buf);
+
+            fs::write(output, buf)?;
+        },
+
+        Command::InspectDictionary { dict } => {
+            print!("{}", inspect_dictionary(&load_dictionary(&dict)?));
+        },
+
+        Command::Tree { input } => {
+            print!("{}", inspect_tree(&read_compressed(&input)?)?);
+        },
+
+        Command::Stats { input, csv } => {
+
+            let data = fs::read(&input)?;
+            let table = FrequencyTable::from_bytes(&data);
+
+            if csv {
+                print!("{}", table.to_csv());
+                return Ok(());
+            }
+
+            println!("{}: {} bytes, {} distinct", input.display(), table.total(), table.len());
+
+            // The gap between the entropy and the average code length is lost to the tree shape.
+            // A high entropy means that the bytes are nearly uniform, and no frequency tree can compress them much.
+            if let Some(stats) = EncodingTree::from_bytes(&data).code_length_stats() {
+                println!(
+                    "entropy: {:.3} bits per byte, codes: {:.3} bits per byte on average, {} to {} bits",
+                    table.entropy(), stats.weighted_average, stats.min, stats.max
+                );
+            }
+
+            print!("{}", table.to_histogram_string(HISTOGRAM_WIDTH));
+        },
+
+        Command::Bench { dir } => {
+
+            let mut files = Vec::new();
+            for entry in fs::read_dir(&dir)? {
+                let path = entry?.path();
+                if path.is_file() {
+                    files.push(fs::read(path)?);
+                }
+            }
+
+            if files.is_empty() {
+                return Err(format!("no files in {}", dir.display()).into());
+            }
+
+            print!("{}", bench(&files)?);
+        },
+    }
+
+    Ok(())
+}
+
+
+fn main() -> ExitCode {
+
+    let command = match parse_command(std::env::args().skip(1)) {
+        Ok (command) => command,
+        Err (message) => {
+            eprintln!("error: {message}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Err(e) = run(command) {
+        eprintln!("error: {e}");
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+
+    fn parse(args: &str) -> Result<Command, String> {
+        parse_command(args.split_whitespace().map(String::from))
+    }
+
+
+    #[test]
+    fn check_commands() {
+
+        assert_eq!(parse("compress a.txt"), Ok(Command::Compress { input: "a.txt".into(), output: "a.txt.ftc".into(), dict: None, split: None }));
+        assert_eq!(parse("compress --dict d.ftcd a.txt -o b"), Ok(Command::Compress { input: "a.txt".into(), output: "b".into(), dict: Some("d.ftcd".into()), split: None }));
+        assert_eq!(parse("compress --split 100MB a.txt"), Ok(Command::Compress { input: "a.txt".into(), output: "a.txt.ftc".into(), dict: None, split: Some(100_000_000) }));
+        assert_eq!(parse("decompress a.txt.ftc"), Ok(Command::Decompress { input: "a.txt.ftc".into(), output: "a.txt".into(), dict: None }));
+        assert_eq!(parse("decompress a.txt.ftc.001"), Ok(Command::Decompress { input: "a.txt.ftc.001".into(), output: "a.txt".into(), dict: None }));
+        assert_eq!(parse("train a b c -o d.ftcd"), Ok(Command::Train { samples: vec!["a".into(), "b".into(), "c".into()], output: "d.ftcd".into() }));
+        assert_eq!(parse("stats a.txt"), Ok(Command::Stats { input: "a.txt".into(), csv: false }));
+        assert_eq!(parse("stats --csv a.txt"), Ok(Command::Stats { input: "a.txt".into(), csv: true }));
+        assert_eq!(parse("test a.ftc b.ftc.001"), Ok(Command::Test { inputs: vec!["a.ftc".into(), "b.ftc.001".into()] }));
+        assert_eq!(parse("dict inspect a.ftcd"), Ok(Command::InspectDictionary { dict: "a.ftcd".into() }));
+        assert_eq!(parse("tree a.ftc"), Ok(Command::Tree { input: "a.ftc".into() }));
+        assert_eq!(parse("bench test_data"), Ok(Command::Bench { dir: "test_data".into() }));
+    }
+
+
+    #[test]
+    fn check_inspect_dictionary() {
+
+        let dictionary = Dictionary::train([b"aaaab\n".iter().copied()]).unwrap();
+        let lines: Vec<String> = inspect_dictionary(&dictionary).lines().map(String::from).collect();
+
+        assert_eq!(lines[0], format!("dictionary {:#010x}: 3 symbols", dictionary.id()));
+        assert!(lines[1].starts_with("entropy: 1.252 bits per symbol"));
+        assert_eq!(lines[3].split_whitespace().collect::<Vec<_>>(), ["1", "'a'", "4", "2"]);
+        assert_eq!(lines[4].split_whitespace().collect::<Vec<_>>(), ["2", "'\\n'", "1", "1"]);
+        assert_eq!(lines[5].split_whitespace().collect::<Vec<_>>(), ["3", "'b'", "1", "2"]);
+
+        // Without the counts, the symbols are ranked by code length
+        let mut buf = Vec::new();
+        dictionary.serialize(&mut buf);
+        let report = inspect_dictionary(&Dictionary::deserialize(&buf).unwrap());
+        assert!(report.lines().next().unwrap().ends_with("without counts, ranked by code length"));
+        assert!(report.lines().nth(1).unwrap().starts_with("codes: 1 to 2 bits"));
+    }
+
+
+    #[test]
+    fn check_inspect_tree() {
+
+        let report = inspect_tree(&compress_bytes(b"abbcccc")).unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[0], "3 leaves of type u8");
+
+        // The shape of the tree depends on the tie order, but every leaf has a code of 1 or 2 bits
+        let mut leaves: Vec<(&str, &str)> = lines[1..].iter().map(|line| line.trim_start().split_once(' ').unwrap()).collect();
+        leaves.sort_unstable();
+        assert_eq!(leaves.iter().map(|(value, _)| *value).collect::<Vec<_>>(), ["'a'", "'b'", "'c'"]);
+        assert!(leaves.iter().all(|(_, code)| (1..=2).contains(&code.len())));
+
+        let report = inspect_tree(&compress("ééé".chars())).unwrap();
+        assert_eq!(report, "1 leaves of type char\n       'é' (no bits)\n");
+
+        assert!(inspect_tree(&compress_chunked::<u8, 2>(b"abcd".iter().copied())).unwrap().starts_with("2 leaves of 2 bytes"));
+        assert!(inspect_tree(&compress_bytes(&[])).is_err());
+    }
+
+
+    #[test]
+    fn check_bench() {
+
+        assert_eq!(huffman_bits([1, 2, 4, 8].into_iter()), 8 + 2 * 4 + 3 * 2 + 3);
+        assert_eq!(huffman_bits([5].into_iter()), 0);
+
+        // The binary file only applies to the strategies over bytes
+        let files = [b"abbcccddddeeeee".repeat(10), vec![0xff, 0xfe], Vec::new()];
+        let table = bench(&files).unwrap();
+
+        let row = |name: &str| table.lines().find(|line| line.starts_with(&format!("{name} "))).unwrap().split_whitespace().nth(1).unwrap().to_string();
+        assert_eq!(row("u8"), "3");
+        assert_eq!(row("char"), "1");
+        assert_eq!(row("grouped-2"), "2");
+        assert_eq!(row("u8-huffman*"), "3");
+    }
+
+
+    #[test]
+    fn check_sizes() {
+
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("100MB"), Ok(100_000_000));
+        assert_eq!(parse_size("64K"), Ok(64_000));
+        assert_eq!(parse_size("2GiB"), Ok(2 << 30));
+        assert!(parse_size("MB").is_err());
+        assert!(parse_size("1.5M").is_err());
+        assert!(parse_size("99999999999999G").is_err());
+    }
+
+
+    #[test]
+    fn check_invalid_commands() {
+
+        assert!(parse("").is_err());
+        assert!(parse("extract a").is_err());
+        assert!(parse("compress").is_err());
+        assert!(parse("compress a b").is_err());
+        assert!(parse("compress a --dict").is_err());
+        assert!(parse("compress a --level 3").is_err());
+        assert!(parse("decompress a.txt").is_err());
+        assert!(parse("train a").is_err());
+        assert!(parse("train -o d.ftcd").is_err());
+        assert!(parse("stats a b").is_err());
+        assert!(parse("stats a -o b").is_err());
+        assert!(parse("compress --csv a").is_err());
+        assert!(parse("compress --split 8 a").is_err());
+        assert!(parse("compress --split 10TB a").is_err());
+        assert!(parse("decompress --split 1M a.ftc").is_err());
+        assert!(parse("bench").is_err());
+        assert!(parse("test").is_err());
+        assert!(parse("dict").is_err());
+        assert!(parse("dict list a.ftcd").is_err());
+        assert!(parse("dict inspect a.ftcd -o b").is_err());
+        assert!(parse("test a.ftc -o a").is_err());
+        assert!(parse("bench a -o b").is_err());
+    }
+
+}
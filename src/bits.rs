@@ -0,0 +1,1044 @@
+//! Packed bit sequences with a final padding, used to store the encoded data.
+//!
+//! The serialized layout of a bit sequence is the number of padding bits in the last byte (1 byte) followed by the raw bytes.
+//! Bits are stored from the most significant to the least significant bit of each byte,
+//! unless they are serialized with `BitOrder::LsbFirst` for formats like DEFLATE that pack them the other way around.
+//!
+//! `BitWriter` builds bit sequences in memory, and `BitSink` writes them to an `io::Write` as the bytes complete.
+
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
+
+pub mod math;
+
+pub use math::least_bytes_repr_for_bits;
+use math::{bit_in_byte, bit_mask, byte_offset, padding_bits};
+
+
+/// Error returned when a serialized bit sequence is malformed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitsDeserializationError {
+
+    /// The input is empty, so it doesn't even contain the padding byte
+    MissingPadding,
+    /// The padding is not smaller than a byte, or there are padding bits but no bytes to contain them
+    InvalidPadding { padding: u8 },
+
+}
+
+impl fmt::Display for BitsDeserializationError {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BitsDeserializationError::MissingPadding
+                => write!(f, "missing padding byte"),
+            BitsDeserializationError::InvalidPadding { padding }
+                => write!(f, "invalid padding of {padding} bits"),
+        }
+    }
+}
+
+impl Error for BitsDeserializationError {}
+
+
+/// Check the padding of the serialized bits and split it from the raw bytes
+fn split_padding(input: &[u8]) -> Result<(u8, &[u8]), BitsDeserializationError> {
+
+    let (&padding, raw_data) = input.split_first()
+        .ok_or(BitsDeserializationError::MissingPadding)?;
+
+    if padding >= 8 || (padding != 0 && raw_data.is_empty()) {
+        return Err(BitsDeserializationError::InvalidPadding { padding });
+    }
+
+    Ok((padding, raw_data))
+}
+
+
+/// Whether the unused bits of the last byte of the serialized bits in `input` are not all zero.
+/// Serialization always writes them as zero, and deserialization ignores them.
+pub(crate) fn has_dirty_padding(input: &[u8], order: BitOrder) -> bool {
+    match split_padding(input) {
+        Ok ((padding, [.., last])) if padding != 0 => order.convert(*last) & !(0xff << padding) != 0,
+        _ => false
+    }
+}
+
+
+/// Order of the bits inside each serialized byte
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum BitOrder {
+
+    /// The first bit is the most significant bit of the byte, which is how `BitVec` stores bits in memory
+    #[default]
+    MsbFirst,
+    /// The first bit is the least significant bit of the byte, like in DEFLATE streams
+    LsbFirst,
+
+}
+
+impl BitOrder {
+
+    /// Convert a byte between the in-memory order and `self`. The conversion is its own inverse.
+    const fn convert(self, byte: u8) -> u8 {
+        match self {
+            BitOrder::MsbFirst => byte,
+            BitOrder::LsbFirst => byte.reverse_bits(),
+        }
+    }
+
+}
+
+
+/// A vector that stores bits with a final padding.
+/// The final padding is useful when serializing and deserializing the structure.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BitVec {
+
+    /// The actual raw bits
+    raw_data: Vec<u8>,
+    /// How many bits of padding the last byte contains.
+    /// Padding bits have no meaning
+    last_byte_padding: u8
+
+}
+
+impl BitVec {
+
+    /// Construct a new empty `BitVec`
+    pub const fn new() -> Self {
+        Self {
+            raw_data: Vec::new(),
+            last_byte_padding: 0
+        }
+    }
+
+
+    /// Construct a new empty `BitVec` with at least the specified capacity in bits
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            raw_data: Vec::with_capacity(least_bytes_repr_for_bits(capacity)),
+            last_byte_padding: 0
+        }
+    }
+
+
+    /// The number of meaningful bits in the `BitVec`
+    pub fn len_bits(&self) -> usize {
+        self.raw_data.len() * 8 - self.last_byte_padding as usize
+    }
+
+
+    /// The minimum number of bytes needed to store the `BitVec`'s contents
+    pub fn least_len_bytes(&self) -> usize {
+        self.raw_data.len()
+    }
+
+
+    /// Append the specified bit to the `BitVec`
+    pub fn append_bit(&mut self, bit: bool) {
+
+        if self.last_byte_padding == 0 {
+
+            self.raw_data.push((bit as u8) << 7);
+            self.last_byte_padding = 7;
+
+        } else {
+
+            // An empty vec has no padding because it has no bytes
+            let last_byte = self.raw_data.last_mut().unwrap();
+
+            *last_byte |= (bit as u8) << (self.last_byte_padding - 1);
+
+            self.last_byte_padding -= 1;
+        }
+    }
+
+
+    /// Extend the `BitVec` with the specified bits
+    pub fn extend_from_bits(&mut self, bit_view: &BitView) {
+
+        if self.last_byte_padding == 0 && bit_view.first_bit_offset == 0 {
+
+            // The bits are aligned, so the bytes can be copied
+            self.raw_data.extend_from_slice(bit_view.raw_data);
+            self.last_byte_padding = bit_view.last_byte_padding;
+
+            // The padding of a slice holds the bits that follow it
+            self.clear_padding();
+
+        } else {
+
+            for bit in bit_view.iter_bits() {
+                self.append_bit(bit)
+            }
+        }
+    }
+
+
+    /// Construct a `BitView` that borrows `self`
+    pub fn as_bit_view(&self) -> BitView<'_> {
+        BitView {
+            raw_data: &self.raw_data,
+            first_bit_offset: 0,
+            last_byte_padding: self.last_byte_padding
+        }
+    }
+
+
+    /// Iterate over the meaningful bits
+    pub fn iter_bits(&self) -> BitIterator<'_> {
+        BitIterator {
+            bits: self.as_bit_view(),
+            i: 0,
+        }
+    }
+
+
+    /// Return the underlying data as bytes and the padding of the last byte
+    pub fn as_padded_bytes(&self) -> (&[u8], u8) {
+        (
+            &self.raw_data,
+            self.last_byte_padding
+        )
+    }
+
+
+    /// Construct a `BitVec` from a slice of `bool` values
+    pub fn from_bool_slice(bools: &[bool]) -> Self {
+
+        let mut res = Self::with_capacity(bools.len());
+
+        for &b in bools {
+            res.append_bit(b)
+        }
+
+        res
+    }
+
+
+    /// Construct a slice of `bool` values from `self`
+    pub fn to_bool_slice(&self) -> Box<[bool]> {
+        self.iter_bits()
+            .collect()
+    }
+
+
+    /// Append the serialized bits to `buf`
+    pub fn serialize(&self, buf: &mut Vec<u8>) {
+
+        buf.reserve(1 + self.least_len_bytes());
+
+        buf.push(self.last_byte_padding);
+
+        buf.extend_from_slice(&self.raw_data);
+    }
+
+
+    /// Construct a `BitVec` from its serialized representation, which must span the whole input
+    pub fn deserialize(input: &[u8]) -> Result<Self, BitsDeserializationError> {
+        Self::deserialize_with_order(input, BitOrder::MsbFirst)
+    }
+
+
+    /// Append the serialized bits to `buf`, packing the bits of each byte in `order`
+    pub fn serialize_with_order(&self, buf: &mut Vec<u8>, order: BitOrder) {
+
+        buf.reserve(1 + self.least_len_bytes());
+
+        buf.push(self.last_byte_padding);
+
+        buf.extend(self.raw_data.iter().map(|byte| order.convert(*byte)));
+    }
+
+
+    /// Construct a `BitVec` from its serialized representation with the bits of each byte packed in `order`
+    pub fn deserialize_with_order(input: &[u8], order: BitOrder) -> Result<Self, BitsDeserializationError> {
+
+        let (last_byte_padding, raw_data) = split_padding(input)?;
+
+        let mut res = Self {
+            raw_data: raw_data.iter().map(|byte| order.convert(*byte)).collect(),
+            last_byte_padding
+        };
+        res.clear_padding();
+
+        Ok(res)
+    }
+
+
+    /// Read `n_bits` bits from the raw bytes of `reader`, which must contain at least `n_bits.div_ceil(8)` bytes.
+    /// The unused bits of the last byte are ignored.
+    pub fn read_from(mut reader: impl Read, n_bits: usize) -> io::Result<Self> {
+
+        let mut raw_data = vec![0; least_bytes_repr_for_bits(n_bits)];
+        reader.read_exact(&mut raw_data)?;
+
+        let mut res = Self {
+            raw_data,
+            last_byte_padding: padding_bits(n_bits)
+        };
+        res.clear_padding();
+
+        Ok(res)
+    }
+
+
+    /// Write the raw bytes to `writer`, without the padding byte of `serialize`, so that `read_from` reads them back given `len_bits`
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(&self.raw_data)
+    }
+
+
+    /// Number of `0` bits before the first `1` bit, or the length if all the bits are `0`
+    pub fn leading_zeros(&self) -> usize {
+
+        let zeros = self.raw_data.iter()
+            .position(|byte| *byte != 0)
+            .map_or(self.raw_data.len() * 8, |i| i * 8 + self.raw_data[i].leading_zeros() as usize);
+
+        // The padding bits are zeros too
+        zeros.min(self.len_bits())
+    }
+
+
+    /// Set the padding bits to zero, which `append_bit` and the comparisons rely on
+    fn clear_padding(&mut self) {
+        if let Some(last_byte) = self.raw_data.last_mut() {
+            *last_byte &= 0xff << self.last_byte_padding;
+        }
+    }
+
+
+    /// Combine the bytes of `self` with the ones of `other`, which must have the same length
+    fn zip_bytes_with(&mut self, other: &BitVec, op: &str, f: impl Fn(u8, u8) -> u8) {
+
+        assert_eq!(self.len_bits(), other.len_bits(), "bitwise {op} of bit vectors of different lengths");
+
+        for (byte, other) in self.raw_data.iter_mut().zip(&other.raw_data) {
+            *byte = f(*byte, *other);
+        }
+    }
+
+}
+
+
+/// Bit vectors are ordered lexicographically, like strings of `0` and `1`: a prefix comes before the longer vectors that start with it
+impl Ord for BitVec {
+
+    fn cmp(&self, other: &Self) -> Ordering {
+        // The padding bits are zeros, so comparing the bytes compares the common bits first
+        self.raw_data.cmp(&other.raw_data)
+            .then(self.len_bits().cmp(&other.len_bits()))
+    }
+}
+
+impl PartialOrd for BitVec {
+
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+
+/// Implement a bitwise operator for bit vectors of the same length, panicking on different lengths
+macro_rules! impl_bitwise_op {
+    ($op_trait:ident, $op_fn:ident, $assign_trait:ident, $assign_fn:ident, $op:tt) => {
+
+        impl $assign_trait<&BitVec> for BitVec {
+
+            fn $assign_fn(&mut self, rhs: &BitVec) {
+                self.zip_bytes_with(rhs, stringify!($op), |a, b| a $op b);
+            }
+        }
+
+        impl $op_trait<&BitVec> for BitVec {
+            type Output = BitVec;
+
+            fn $op_fn(mut self, rhs: &BitVec) -> BitVec {
+                self.$assign_fn(rhs);
+                self
+            }
+        }
+
+        impl $op_trait<&BitVec> for &BitVec {
+            type Output = BitVec;
+
+            fn $op_fn(self, rhs: &BitVec) -> BitVec {
+                self.clone().$op_fn(rhs)
+            }
+        }
+    };
+}
+
+impl_bitwise_op!(BitAnd, bitand, BitAndAssign, bitand_assign, &);
+impl_bitwise_op!(BitOr, bitor, BitOrAssign, bitor_assign, |);
+impl_bitwise_op!(BitXor, bitxor, BitXorAssign, bitxor_assign, ^);
+
+impl Not for BitVec {
+    type Output = BitVec;
+
+    fn not(mut self) -> BitVec {
+
+        for byte in &mut self.raw_data {
+            *byte = !*byte;
+        }
+        self.clear_padding();
+
+        self
+    }
+}
+
+impl Not for &BitVec {
+    type Output = BitVec;
+
+    fn not(self) -> BitVec {
+        !self.clone()
+    }
+}
+
+
+/// Writer that builds a `BitVec` by accumulating bits into a 64-bit register,
+/// which is flushed to the bytes only when it's full, instead of updating the last byte for every bit.
+#[derive(Debug, Clone, Default)]
+pub struct BitWriter {
+
+    /// Bytes of the full registers flushed so far
+    raw_data: Vec<u8>,
+    /// Pending bits, aligned to the most significant bit
+    register: u64,
+    /// Number of pending bits in the register, always smaller than 64
+    filled: u32
+
+}
+
+impl BitWriter {
+
+    /// Construct a new empty `BitWriter`
+    pub const fn new() -> Self {
+        Self {
+            raw_data: Vec::new(),
+            register: 0,
+            filled: 0
+        }
+    }
+
+
+    /// Construct a new empty `BitWriter` with at least the specified capacity in bits
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            // The last register is flushed whole, so round up to a multiple of 8 bytes
+            raw_data: Vec::with_capacity(least_bytes_repr_for_bits(capacity).next_multiple_of(8)),
+            register: 0,
+            filled: 0
+        }
+    }
+
+
+    /// The number of bits written so far
+    pub fn len_bits(&self) -> usize {
+        self.raw_data.len() * 8 + self.filled as usize
+    }
+
+
+    /// Append the `len` most significant bits of `word`. The other bits of `word` must be zero.
+    ///
+    /// Panics if `len` is greater than 64.
+    pub fn write_bits(&mut self, word: u64, len: u32) {
+
+        assert!(len <= u64::BITS, "cannot write {len} bits from a 64-bit word");
+
+        if len == 0 {
+            return;
+        }
+
+        self.register |= word >> self.filled;
+
+        let filled = self.filled + len;
+
+        if filled < u64::BITS {
+            self.filled = filled;
+            return;
+        }
+
+        self.raw_data.extend_from_slice(&self.register.to_be_bytes());
+
+        // The bits of the word that didn't fit in the register start the next one
+        let spilled = filled - u64::BITS;
+        self.register = if spilled == 0 { 0 } else { word << (len - spilled) };
+        self.filled = spilled;
+    }
+
+
+    /// Append a single bit
+    pub fn write_bit(&mut self, bit: bool) {
+        self.write_bits((bit as u64) << (u64::BITS - 1), 1)
+    }
+
+
+    /// Flush the pending bits and return the written bits
+    pub fn finish(mut self) -> BitVec {
+
+        let pending = least_bytes_repr_for_bits(self.filled as usize);
+        self.raw_data.extend_from_slice(&self.register.to_be_bytes()[..pending]);
+
+        BitVec {
+            raw_data: self.raw_data,
+            last_byte_padding: padding_bits(self.filled as usize)
+        }
+    }
+
+}
+
+
+/// Incremental bit writer that writes the bytes to an `io::Write` as they complete, like `BitWriter` does in memory,
+/// so that long bit sequences can be written to disk without holding them whole.
+/// The bytes are written 8 at a time, so slow writers should be buffered.
+#[derive(Debug)]
+pub struct BitSink<W> {
+
+    writer: W,
+    /// Bits that don't fill a register yet
+    pending: BitWriter,
+    /// Number of bytes written so far
+    written: usize,
+
+}
+
+impl<W> BitSink<W>
+where
+    W: Write
+{
+
+    pub const fn new(writer: W) -> Self {
+        Self {
+            writer,
+            pending: BitWriter::new(),
+            written: 0
+        }
+    }
+
+
+    /// The number of bits written so far, including the ones that don't fill a byte yet
+    pub fn len_bits(&self) -> usize {
+        self.written * 8 + self.pending.len_bits()
+    }
+
+
+    /// Append the `len` most significant bits of `word`. The other bits of `word` must be zero.
+    ///
+    /// Panics if `len` is greater than 64.
+    pub fn write_bits(&mut self, word: u64, len: u32) -> io::Result<()> {
+
+        self.pending.write_bits(word, len);
+
+        if !self.pending.raw_data.is_empty() {
+            self.writer.write_all(&self.pending.raw_data)?;
+            self.written += self.pending.raw_data.len();
+            self.pending.raw_data.clear();
+        }
+
+        Ok(())
+    }
+
+
+    /// Append a single bit
+    pub fn write_bit(&mut self, bit: bool) -> io::Result<()> {
+        self.write_bits((bit as u64) << (u64::BITS - 1), 1)
+    }
+
+
+    /// Append the bits of `bits`
+    pub fn write_view(&mut self, bits: &BitView) -> io::Result<()> {
+        bits.iter_bits().try_for_each(|bit| self.write_bit(bit))
+    }
+
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+
+    /// Write the last bits, padded with zeros to a whole byte, and return the writer.
+    /// The number of padding bits is `len_bits` rounded up to a multiple of 8, minus `len_bits`.
+    pub fn finish(mut self) -> io::Result<W> {
+
+        self.pending.finish().write_to(&mut self.writer)?;
+
+        Ok(self.writer)
+    }
+
+}
+
+
+/// A view into a sequence of bits.
+/// Views returned by `slice` and `split_at_bit` can start in the middle of their first byte.
+#[derive(Debug, Clone)]
+pub struct BitView<'a> {
+
+    raw_data: &'a [u8],
+    /// How many bits of the first byte come before the view
+    first_bit_offset: u8,
+    last_byte_padding: u8
+
+}
+
+impl<'a> BitView<'a> {
+
+    /// Iterate over the bits in the view
+    pub fn iter_bits(&self) -> BitIterator<'a> {
+        BitIterator {
+            bits: self.clone(),
+            i: 0
+        }
+    }
+
+
+    /// Construct a `BitView` from bytes and a final padding value
+    pub const fn from_padded_bytes(bytes: &'a [u8], last_byte_padding: u8) -> BitView<'a> {
+        Self {
+            raw_data: bytes,
+            first_bit_offset: 0,
+            last_byte_padding
+        }
+    }
+
+
+    /// Construct a list of `bool` values from the contents of the view
+    pub fn to_bool_slice(&self) -> Box<[bool]> {
+        self.iter_bits()
+            .collect()
+    }
+
+
+    /// Copy the bits of the view into a new `BitVec`, which starts on a byte boundary
+    pub fn to_bit_vec(&self) -> BitVec {
+        let mut bits = BitVec::with_capacity(self.len_bits());
+        bits.extend_from_bits(self);
+        bits
+    }
+
+
+    /// The number of meaningful bits in the `BitView`
+    pub const fn len_bits(&self) -> usize {
+        self.raw_data.len() * 8 - self.first_bit_offset as usize - self.last_byte_padding as usize
+    }
+
+
+    /// The bit at index `i`, or `None` if it's past the meaningful bits
+    pub fn get(&self, i: usize) -> Option<bool> {
+        (i < self.len_bits()).then(|| {
+            let i = i + self.first_bit_offset as usize;
+            self.raw_data[byte_offset(i)] & bit_mask(i) != 0
+        })
+    }
+
+
+    /// View of the `len_bits` bits starting at bit `start_bit`, without copying them.
+    ///
+    /// Panics if the range is past the end of the view.
+    pub fn slice(&self, start_bit: usize, len_bits: usize) -> BitView<'a> {
+
+        let end_bit = start_bit.checked_add(len_bits)
+            .filter(|end_bit| *end_bit <= self.len_bits())
+            .unwrap_or_else(|| panic!("bit range {start_bit}..{start_bit}+{len_bits} out of range for a view of {} bits", self.len_bits()));
+
+        if len_bits == 0 {
+            return BitView::from_padded_bytes(&[], 0);
+        }
+
+        // Bit indices from the start of the first byte
+        let start = start_bit + self.first_bit_offset as usize;
+        let end = end_bit + self.first_bit_offset as usize;
+
+        let end_byte = least_bytes_repr_for_bits(end);
+
+        Self {
+            raw_data: &self.raw_data[byte_offset(start)..end_byte],
+            first_bit_offset: bit_in_byte(start),
+            last_byte_padding: padding_bits(end)
+        }
+    }
+
+
+    /// Split the view into the first `n` bits and the rest, without copying them.
+    ///
+    /// Panics if `n` is greater than the number of bits.
+    pub fn split_at_bit(&self, n: usize) -> (BitView<'a>, BitView<'a>) {
+
+        assert!(n <= self.len_bits(), "split at bit {n} out of range for a view of {} bits", self.len_bits());
+
+        (self.slice(0, n), self.slice(n, self.len_bits() - n))
+    }
+
+
+    /// The minimum number of bytes needed to store the `BitView`'s contents
+    pub fn least_len_bytes(&self) -> usize {
+        least_bytes_repr_for_bits(self.len_bits())
+    }
+
+
+    /// How many bits of the first byte returned by `as_padded_bytes` come before the view.
+    /// It's 0 unless the view was created by `slice` or `split_at_bit`.
+    pub const fn first_bit_offset(&self) -> u8 {
+        self.first_bit_offset
+    }
+
+
+    /// Return the underlying data as bytes and the padding of the last byte.
+    /// The view starts at bit `first_bit_offset()` of the first byte.
+    pub const fn as_padded_bytes(&self) -> (&[u8], u8) {
+        (
+            self.raw_data,
+            self.last_byte_padding
+        )
+    }
+
+
+    /// Return the serialized representation of the view
+    pub fn serialize(&self) -> Box<[u8]> {
+
+        if self.first_bit_offset != 0 {
+            // The serialized layout starts on a byte boundary
+            let mut buf = Vec::new();
+            self.to_bit_vec().serialize(&mut buf);
+            return buf.into_boxed_slice();
+        }
+
+        let mut buf = Vec::with_capacity(1 + self.least_len_bytes());
+
+        buf.push(self.last_byte_padding);
+
+        buf.extend_from_slice(self.raw_data);
+
+        buf.into_boxed_slice()
+    }
+
+
+    /// Return the serialized representation of the view, with the bits of each byte packed in `order`.
+    /// Views can't borrow bytes packed from the least significant bit, so read them back with `BitVec::deserialize_with_order`.
+    pub fn serialize_with_order(&self, order: BitOrder) -> Box<[u8]> {
+
+        let mut buf = Vec::new();
+        self.to_bit_vec().serialize_with_order(&mut buf, order);
+
+        buf.into_boxed_slice()
+    }
+
+
+    /// Construct a `BitView` from a serialized representation, which must span the whole input
+    pub fn deserialize(input: &'a [u8]) -> Result<BitView<'a>, BitsDeserializationError> {
+
+        let (last_byte_padding, raw_data) = split_padding(input)?;
+
+        Ok(Self {
+            raw_data,
+            first_bit_offset: 0,
+            last_byte_padding
+        })
+    }
+
+}
+
+
+/// An iterator over the bits of a `BitView`
+pub struct BitIterator<'a> {
+
+    bits: BitView<'a>,
+    i: usize
+
+}
+
+impl Iterator for BitIterator<'_> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<Self::Item> {
+
+        if self.i >= self.bits.len_bits() {
+            return None;
+        }
+
+        let bit_i = self.i + self.bits.first_bit_offset as usize;
+        let byte = self.bits.raw_data[bit_i / 8];
+        let bit_in_byte_i = bit_i % 8;
+
+        self.i += 1;
+
+        Some(
+            (byte & (0x80 >> bit_in_byte_i)) != 0
+        )
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+
+    #[test]
+    fn check_view_iter() {
+
+        let expected = [true, true, false, true, false, true, false, true, true, true];
+
+        let v = BitVec::from_bool_slice(&expected);
+        let view = v.as_bit_view();
+
+        assert_eq!(*view.to_bool_slice(), expected);
+        assert_eq!(view.len_bits(), expected.len());
+        assert_eq!(view.clone().to_bool_slice(), view.to_bool_slice());
+
+        assert!(expected.iter().enumerate().all(|(i, bit)| view.get(i) == Some(*bit)));
+        assert_eq!(view.get(expected.len()), None);
+    }
+
+
+    #[test]
+    fn check_slice() {
+
+        let bools = [true, true, false, true, false, true, false, true, true, true, false, false, true, false, true, true, true, false, true];
+
+        let v = BitVec::from_bool_slice(&bools);
+        let view = v.as_bit_view();
+
+        for start in 0..=bools.len() {
+            for len in 0..=bools.len() - start {
+
+                let slice = view.slice(start, len);
+                assert_eq!(*slice.to_bool_slice(), bools[start..start + len]);
+                assert_eq!(slice.len_bits(), len);
+                assert_eq!(slice.least_len_bytes(), least_bytes_repr_for_bits(len));
+                assert_eq!(slice.get(len), None);
+
+                // Slices of slices and copies of unaligned slices
+                if len > 2 {
+                    assert_eq!(*slice.slice(1, len - 2).to_bool_slice(), bools[start + 1..start + len - 1]);
+                }
+                assert_eq!(slice.to_bit_vec(), BitVec::from_bool_slice(&bools[start..start + len]));
+                assert_eq!(BitView::deserialize(&slice.serialize()).unwrap().to_bool_slice(), slice.to_bool_slice());
+            }
+
+            let (head, tail) = view.split_at_bit(start);
+            assert_eq!(*head.to_bool_slice(), bools[..start]);
+            assert_eq!(*tail.to_bool_slice(), bools[start..]);
+
+            let mut extended = BitVec::new();
+            extended.extend_from_bits(&tail);
+            assert_eq!(*extended.to_bool_slice(), bools[start..]);
+        }
+    }
+
+
+    #[test]
+    #[should_panic]
+    fn check_slice_out_of_range() {
+        BitVec::from_bool_slice(&[true; 9]).as_bit_view().slice(4, 6);
+    }
+
+
+    #[test]
+    fn check_extend() {
+
+        let a = [true, false, false, true, false];
+        let b = [true, false, false, false, false, true];
+
+        let mut va = BitVec::from_bool_slice(&a);
+        let vb = BitVec::from_bool_slice(&b);
+
+        va.extend_from_bits(&vb.as_bit_view());
+
+        assert_eq!(*va.to_bool_slice(), [&a[..], &b[..]].concat());
+
+        // Aligned extension copies the bytes
+        let mut empty = BitVec::new();
+        empty.extend_from_bits(&vb.as_bit_view());
+        assert_eq!(empty, vb);
+    }
+
+
+    #[test]
+    fn check_writer() {
+
+        // Codes as (most significant bits, length) pairs, crossing register boundaries at different offsets
+        let codes = [(0b101 << 61, 3), (u64::MAX, 64), (0, 0), (1 << 63, 1), (0xdead_beef << 32, 32), (0, 64), (0b11 << 62, 2), (u64::MAX << 3, 61)];
+
+        for skip in 0..codes.len() {
+
+            let mut writer = BitWriter::with_capacity(100);
+            let mut expected = BitVec::new();
+
+            for (word, len) in codes.iter().cycle().skip(skip).take(codes.len() * 3) {
+
+                writer.write_bits(*word, *len);
+
+                for i in 0..*len {
+                    expected.append_bit(word & (1 << (63 - i)) != 0);
+                }
+
+                assert_eq!(writer.len_bits(), expected.len_bits());
+            }
+
+            assert_eq!(writer.finish(), expected);
+        }
+
+        let mut writer = BitWriter::new();
+        writer.write_bit(true);
+        writer.write_bit(false);
+        writer.write_bit(true);
+        assert_eq!(writer.finish(), BitVec::from_bool_slice(&[true, false, true]));
+
+        assert_eq!(BitWriter::new().finish(), BitVec::new());
+    }
+
+
+    #[test]
+    fn check_io() {
+
+        let codes = [(0b101 << 61, 3), (u64::MAX, 64), (1 << 63, 1), (0xdead_beef << 32, 32), (0b11 << 62, 2)];
+
+        let mut sink = BitSink::new(Vec::new());
+        let mut writer = BitWriter::new();
+
+        for (word, len) in codes.iter().cycle().take(20) {
+            sink.write_bits(*word, *len).unwrap();
+            writer.write_bits(*word, *len);
+
+            // Only complete registers are written before the end
+            assert_eq!(sink.get_ref().len(), sink.len_bits() / 64 * 8);
+        }
+
+        sink.write_view(&BitVec::from_bool_slice(&[true, true, false]).as_bit_view()).unwrap();
+        for bit in [true, true, false] {
+            writer.write_bit(bit);
+        }
+
+        let len_bits = sink.len_bits();
+        let expected = writer.finish();
+        assert_eq!(len_bits, expected.len_bits());
+
+        let written = sink.finish().unwrap();
+        assert_eq!(written, expected.as_padded_bytes().0);
+
+        // Reading back ignores the padding bits
+        let mut dirty = written.clone();
+        *dirty.last_mut().unwrap() |= 1;
+        assert_eq!(BitVec::read_from(&dirty[..], len_bits).unwrap(), expected);
+
+        let mut out = Vec::new();
+        expected.write_to(&mut out).unwrap();
+        assert_eq!(out, written);
+
+        assert_eq!(BitVec::read_from(&written[..written.len() - 1], len_bits).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(BitVec::read_from(&[][..], 0).unwrap(), BitVec::new());
+    }
+
+
+    #[test]
+    fn check_bitwise_ops() {
+
+        let a = BitVec::from_bool_slice(&[true, true, false, false, true, false, true, true, false]);
+        let b = BitVec::from_bool_slice(&[true, false, true, false, false, false, true, false, true]);
+
+        let expected = |f: fn(bool, bool) -> bool| {
+            let bools: Vec<bool> = a.iter_bits().zip(b.iter_bits()).map(|(a, b)| f(a, b)).collect();
+            BitVec::from_bool_slice(&bools)
+        };
+
+        assert_eq!(&a & &b, expected(|a, b| a & b));
+        assert_eq!(&a | &b, expected(|a, b| a | b));
+        assert_eq!(&a ^ &b, expected(|a, b| a ^ b));
+
+        let mut assigned = a.clone();
+        assigned ^= &b;
+        assert_eq!(assigned ^ &b, a);
+
+        // The padding stays zero, so appending after `not` works
+        let mut inverted = !&a;
+        assert_eq!(*inverted.to_bool_slice(), *a.iter_bits().map(|bit| !bit).collect::<Vec<_>>());
+        inverted.append_bit(false);
+        assert_eq!(inverted.len_bits(), a.len_bits() + 1);
+        assert!(!inverted.iter_bits().last().unwrap());
+        assert_eq!(!!a.clone(), a);
+
+        assert_eq!(a.leading_zeros(), 0);
+        assert_eq!(BitVec::from_bool_slice(&[false, false, false, false, false, false, false, false, false, true]).leading_zeros(), 9);
+        assert_eq!(BitVec::from_bool_slice(&[false; 5]).leading_zeros(), 5);
+        assert_eq!(BitVec::new().leading_zeros(), 0);
+    }
+
+
+    #[test]
+    #[should_panic]
+    fn check_bitwise_ops_length_mismatch() {
+        let _ = &BitVec::from_bool_slice(&[true; 3]) & &BitVec::from_bool_slice(&[true; 4]);
+    }
+
+
+    #[test]
+    fn check_ordering() {
+
+        // Strings of `0` and `1` have the same lexicographic order
+        let strings = ["", "0", "00", "0000000001", "01", "1", "10", "100000000", "11", "111111111"];
+
+        let vecs: Vec<BitVec> = strings.iter()
+            .map(|string| BitVec::from_bool_slice(&string.chars().map(|c| c == '1').collect::<Vec<_>>()))
+            .collect();
+
+        for (i, a) in vecs.iter().enumerate() {
+            for (j, b) in vecs.iter().enumerate() {
+                assert_eq!(a.cmp(b), strings[i].cmp(strings[j]), "{} vs {}", strings[i], strings[j]);
+            }
+        }
+
+        // Padding bits of the input don't affect the comparison
+        assert_eq!(BitVec::deserialize(&[7, 0xff]).unwrap(), BitVec::from_bool_slice(&[true]));
+    }
+
+
+    #[test]
+    fn check_bit_order() {
+
+        let bools = [true, false, false, false, false, false, true, true, true, false, true];
+        let v = BitVec::from_bool_slice(&bools);
+
+        let mut msb = Vec::new();
+        v.serialize_with_order(&mut msb, BitOrder::MsbFirst);
+
+        let mut lsb = Vec::new();
+        v.serialize_with_order(&mut lsb, BitOrder::LsbFirst);
+
+        // The first bit is the least significant one, and the padding bits are the most significant ones of the last byte
+        assert_eq!(lsb, [5, 0b1100_0001, 0b0000_0101]);
+        assert_eq!(*v.as_bit_view().serialize_with_order(BitOrder::LsbFirst), *lsb);
+
+        assert_eq!(BitVec::deserialize_with_order(&lsb, BitOrder::LsbFirst), Ok(v.clone()));
+        assert_eq!(BitVec::deserialize_with_order(&msb, BitOrder::MsbFirst), Ok(v));
+        assert_ne!(BitVec::deserialize_with_order(&lsb, BitOrder::MsbFirst).unwrap().to_bool_slice(), bools.into());
+    }
+
+
+    #[test]
+    fn check_serde() {
+
+        for bools in [&[][..], &[true], &[true, false, false, true, false, true, false, false, false, false, true]] {
+
+            let v = BitVec::from_bool_slice(bools);
+
+            let mut ser = Vec::new();
+            v.serialize(&mut ser);
+
+            assert_eq!(ser.len(), 1 + least_bytes_repr_for_bits(bools.len()));
+            assert_eq!(BitVec::deserialize(&ser), Ok(v));
+            assert_eq!(*BitView::deserialize(&ser).unwrap().to_bool_slice(), *bools);
+        }
+
+        assert_eq!(BitVec::deserialize(&[]), Err(BitsDeserializationError::MissingPadding));
+        assert_eq!(BitVec::deserialize(&[8, 0]), Err(BitsDeserializationError::InvalidPadding { padding: 8 }));
+        assert_eq!(BitVec::deserialize(&[3]), Err(BitsDeserializationError::InvalidPadding { padding: 3 }));
+    }
+
+}
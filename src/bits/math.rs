@@ -0,0 +1,73 @@
+//! Arithmetic on bit counts and bit offsets, for bits stored from the most significant to the least significant bit of each byte.
+//!
+//! Bit offsets count from the most significant bit of the first byte. The padding of a bit sequence is the number
+//! of unused bits at the end of its last byte.
+
+
+/// The minimum number of bytes needed to store `bit_count` bits
+pub const fn least_bytes_repr_for_bits(bit_count: usize) -> usize {
+    bit_count.div_ceil(8)
+}
+
+
+/// Number of unused bits in the last byte when storing `bit_count` bits, from 0 to 7
+pub const fn padding_bits(bit_count: usize) -> u8 {
+    (least_bytes_repr_for_bits(bit_count) * 8 - bit_count) as u8
+}
+
+
+/// Index of the byte that contains the bit at `bit_offset`
+pub const fn byte_offset(bit_offset: usize) -> usize {
+    bit_offset / 8
+}
+
+
+/// Position of the bit at `bit_offset` in its byte, from 0 for the most significant bit to 7 for the least significant one
+pub const fn bit_in_byte(bit_offset: usize) -> u8 {
+    (bit_offset % 8) as u8
+}
+
+
+/// Offset of the bit at position `bit` of byte `byte`, the inverse of `byte_offset` and `bit_in_byte`
+pub const fn bit_offset(byte: usize, bit: u8) -> usize {
+    byte * 8 + bit as usize
+}
+
+
+/// Mask that selects the bit at `bit_offset` in its byte
+pub const fn bit_mask(bit_offset: usize) -> u8 {
+    0x80 >> bit_in_byte(bit_offset)
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+
+    #[test]
+    fn check_math() {
+
+        assert_eq!(least_bytes_repr_for_bits(0), 0);
+        assert_eq!(least_bytes_repr_for_bits(1), 1);
+        assert_eq!(least_bytes_repr_for_bits(8), 1);
+        assert_eq!(least_bytes_repr_for_bits(9), 2);
+
+        assert_eq!(padding_bits(0), 0);
+        assert_eq!(padding_bits(1), 7);
+        assert_eq!(padding_bits(8), 0);
+        assert_eq!(padding_bits(13), 3);
+
+        for offset in 0..64 {
+            assert_eq!(bit_offset(byte_offset(offset), bit_in_byte(offset)), offset);
+            assert_eq!(least_bytes_repr_for_bits(offset) * 8, offset + padding_bits(offset) as usize);
+        }
+
+        assert_eq!((byte_offset(11), bit_in_byte(11)), (1, 3));
+        assert_eq!(bit_mask(0), 0b1000_0000);
+        assert_eq!(bit_mask(11), 0b0001_0000);
+        assert_eq!(bit_mask(15), 0b0000_0001);
+    }
+
+}
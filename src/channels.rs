@@ -0,0 +1,321 @@
+//! Interleaved channels: symbol streams that alternate between kinds of data, like the keys and values of a map,
+//! split into a stream per channel and compressed with a tree per channel, so that each tree fits the statistics of its channel.
+//!
+//! The channel of each symbol is given by a repeating pattern: `[0, 1]` alternates two channels, and `[0, 1, 1]` sends
+//! every third symbol to channel 0 and the others to channel 1.
+//!
+//! Layout:
+//! - magic bytes `FTCI` (4 bytes)
+//! - length of the pattern (varint), then the channel of each position of the pattern (varint each)
+//! - for each channel, the size of its compressed data (varint), followed by the symbols of the channel compressed with `compress`.
+//!   The size of a channel without symbols is 0.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::container::{read_varint, write_varint};
+use crate::{compress, decompress, DecompressionError, Symbol};
+
+
+/// Magic bytes at the start of channeled data
+const MAGIC: &[u8; 4] = b"FTCI";
+
+
+/// Repeating sequence of the channels of the symbols of an interleaved stream
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ChannelPattern {
+
+    pattern: Box<[usize]>,
+    channel_count: usize,
+
+}
+
+impl ChannelPattern {
+
+    /// Return `None` if the pattern is empty or skips a channel, since every channel below the highest one must be used.
+    pub fn new(pattern: &[usize]) -> Option<Self> {
+
+        let highest = *pattern.iter().max()?;
+
+        // Checked before allocating, since a pattern without gaps has fewer channels than positions
+        if highest >= pattern.len() {
+            return None;
+        }
+
+        let channel_count = highest + 1;
+
+        let mut used = vec![false; channel_count];
+        for channel in pattern {
+            used[*channel] = true;
+        }
+
+        used.into_iter().all(|used| used).then(|| Self {
+            pattern: pattern.into(),
+            channel_count
+        })
+    }
+
+
+    pub const fn channel_count(&self) -> usize {
+        self.channel_count
+    }
+
+
+    /// Split the symbols into the symbols of each channel, in order
+    pub fn split<U>(&self, symbols: impl IntoIterator<Item = U>) -> Vec<Vec<U>> {
+
+        let mut channels: Vec<Vec<U>> = (0..self.channel_count).map(|_| Vec::new()).collect();
+
+        for (symbol, channel) in symbols.into_iter().zip(self.pattern.iter().cycle()) {
+            channels[*channel].push(symbol);
+        }
+
+        channels
+    }
+
+
+    /// Interleave the symbols of the channels back into a single stream, undoing `split`.
+    /// Return `None` if there isn't a channel per channel of the pattern, or if their lengths don't come from a split.
+    pub fn interleave<U>(&self, channels: Vec<Vec<U>>) -> Option<Vec<U>> {
+
+        if channels.len() != self.channel_count {
+            return None;
+        }
+
+        let total = channels.iter().map(Vec::len).sum();
+        let mut channels: Vec<_> = channels.into_iter().map(Vec::into_iter).collect();
+        let mut symbols = Vec::with_capacity(total);
+
+        // Stop at the first channel that runs out, which is where the split stream ended
+        for channel in self.pattern.iter().cycle() {
+            match channels[*channel].next() {
+                Some (symbol) => symbols.push(symbol),
+                None => break
+            }
+        }
+
+        (symbols.len() == total).then_some(symbols)
+    }
+
+}
+
+
+/// Compress the interleaved symbols with a tree per channel of the pattern
+pub fn compress_channels<U>(input: impl IntoIterator<Item = U>, pattern: &ChannelPattern) -> Box<[u8]>
+where
+    U: Symbol
+{
+
+    let mut buf = Vec::new();
+
+    buf.extend_from_slice(MAGIC);
+
+    write_varint(&mut buf, pattern.pattern.len());
+    for channel in &pattern.pattern {
+        write_varint(&mut buf, *channel);
+    }
+
+    for channel in pattern.split(input) {
+
+        // Trees need at least a symbol, and a channel without symbols has no data
+        if channel.is_empty() {
+            write_varint(&mut buf, 0);
+            continue;
+        }
+
+        let compressed = compress(channel.into_iter());
+        write_varint(&mut buf, compressed.len());
+        buf.extend_from_slice(&compressed);
+    }
+
+    buf.into_boxed_slice()
+}
+
+
+/// Decompress data produced by `compress_channels` and interleave the channels back into a single stream
+pub fn decompress_channels<U>(input: &[u8]) -> Result<Box<[U]>, ChannelError>
+where
+    U: Symbol
+{
+
+    if !input.starts_with(MAGIC) {
+        return Err(ChannelError::NotChanneled);
+    }
+
+    let mut offset = MAGIC.len();
+
+    let read = |offset: usize| read_varint(input, offset)
+        .map_err(|_| ChannelError::InvalidLayout { offset });
+
+    let (pattern_len, size) = read(offset)?;
+
+    // Every position of the pattern takes at least a byte
+    if pattern_len > input.len() - offset - size {
+        return Err(ChannelError::InvalidLayout { offset });
+    }
+
+    let pattern_offset = offset;
+    offset += size;
+
+    let mut pattern = Vec::with_capacity(pattern_len);
+    for _ in 0..pattern_len {
+        let (channel, size) = read(offset)?;
+        pattern.push(channel);
+        offset += size;
+    }
+
+    let pattern = ChannelPattern::new(&pattern)
+        .ok_or(ChannelError::InvalidPattern { offset: pattern_offset })?;
+
+    let mut channels = Vec::with_capacity(pattern.channel_count);
+
+    for channel in 0..pattern.channel_count {
+
+        let (len, size) = read(offset)?;
+        offset += size;
+
+        let data = input.get(offset..offset.saturating_add(len))
+            .ok_or(ChannelError::InvalidLayout { offset })?;
+
+        let symbols = if data.is_empty() {
+            Vec::new()
+        } else {
+            decompress::<U>(data)
+                .map_err(|error| ChannelError::InvalidChannel { channel, error })?
+                .into_vec()
+        };
+
+        channels.push(symbols);
+        offset += len;
+    }
+
+    if offset != input.len() {
+        return Err(ChannelError::InvalidLayout { offset });
+    }
+
+    pattern.interleave(channels)
+        .map(Vec::into_boxed_slice)
+        .ok_or(ChannelError::LengthMismatch)
+}
+
+
+/// Error returned when channeled data can't be decompressed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChannelError {
+
+    /// The data doesn't start with the magic bytes of channeled data
+    NotChanneled,
+    /// A length at byte `offset` is invalid or runs past the end of the data, or data follows the last channel
+    InvalidLayout { offset: usize },
+    /// The pattern at byte `offset` is empty or skips a channel
+    InvalidPattern { offset: usize },
+    /// The compressed data of `channel` can't be decompressed
+    InvalidChannel { channel: usize, error: DecompressionError },
+    /// The numbers of symbols of the channels don't come from splitting a stream with the pattern
+    LengthMismatch,
+
+}
+
+impl fmt::Display for ChannelError {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChannelError::NotChanneled
+                => write!(f, "the data doesn't start with the magic bytes of channeled data"),
+            ChannelError::InvalidLayout { offset }
+                => write!(f, "invalid length at byte {offset}"),
+            ChannelError::InvalidPattern { offset }
+                => write!(f, "the channel pattern at byte {offset} is empty or skips a channel"),
+            ChannelError::InvalidChannel { channel, .. }
+                => write!(f, "could not decompress channel {channel}"),
+            ChannelError::LengthMismatch
+                => write!(f, "the lengths of the channels don't match the channel pattern"),
+        }
+    }
+}
+
+impl Error for ChannelError {
+
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ChannelError::InvalidChannel { error, .. } => Some(error),
+            ChannelError::NotChanneled |
+            ChannelError::InvalidLayout { .. } |
+            ChannelError::InvalidPattern { .. } |
+            ChannelError::LengthMismatch => None,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+
+    #[test]
+    fn check_split_interleave() {
+
+        assert_eq!(ChannelPattern::new(&[]), None);
+        assert_eq!(ChannelPattern::new(&[0, 2]), None);
+        assert_eq!(ChannelPattern::new(&[0, 1, usize::MAX]), None);
+
+        let pattern = ChannelPattern::new(&[0, 1, 1]).unwrap();
+        assert_eq!(pattern.channel_count(), 2);
+
+        let channels = pattern.split(0..8);
+        assert_eq!(channels, [vec![0, 3, 6], vec![1, 2, 4, 5, 7]]);
+        assert_eq!(pattern.interleave(channels), Some((0..8).collect()));
+
+        // Channel 1 can't run out while channel 0 has symbols left
+        assert_eq!(pattern.interleave(vec![vec![0, 3], vec![1]]), None);
+        assert_eq!(pattern.interleave(vec![vec![0]]), None);
+    }
+
+
+    #[test]
+    fn check_channels_round_trip() {
+
+        // Keys and values of a map with very different symbols
+        let pairs: Vec<u8> = (0..200_u8).flat_map(|i| [b'a' + i % 4, i]).collect();
+        let pattern = ChannelPattern::new(&[0, 1]).unwrap();
+
+        let compressed = compress_channels(pairs.iter().copied(), &pattern);
+        assert!(compressed.len() < compress(pairs.iter().copied()).len());
+        assert_eq!(*decompress_channels::<u8>(&compressed).unwrap(), *pairs);
+
+        // An odd number of symbols, and no symbols at all
+        let compressed = compress_channels(pairs[..7].iter().copied(), &pattern);
+        assert_eq!(*decompress_channels::<u8>(&compressed).unwrap(), pairs[..7]);
+        let compressed = compress_channels(std::iter::empty::<u8>(), &pattern);
+        assert!(decompress_channels::<u8>(&compressed).unwrap().is_empty());
+    }
+
+
+    #[test]
+    fn check_channel_errors() {
+
+        let pattern = ChannelPattern::new(&[0, 1]).unwrap();
+        let compressed = compress_channels("key=value".chars(), &pattern);
+
+        assert_eq!(decompress_channels::<char>(b"FTC"), Err(ChannelError::NotChanneled));
+        assert_eq!(decompress_channels::<char>(&compressed[..compressed.len() - 1]), Err(ChannelError::InvalidLayout { offset: 4 + 3 + 1 + compressed[7] as usize + 1 }));
+
+        let mut trailing = compressed.to_vec();
+        trailing.push(0);
+        assert_eq!(decompress_channels::<char>(&trailing), Err(ChannelError::InvalidLayout { offset: compressed.len() }));
+
+        let mut skipping = compressed.to_vec();
+        skipping[6] = 2;
+        assert_eq!(decompress_channels::<char>(&skipping), Err(ChannelError::InvalidPattern { offset: 4 }));
+
+        // The same channels with a pattern that sends more symbols to channel 1
+        let mut mismatched = compressed.to_vec();
+        mismatched.splice(4..7, [3, 0, 1, 1]);
+        assert_eq!(decompress_channels::<char>(&mismatched), Err(ChannelError::LengthMismatch));
+
+        assert!(matches!(decompress_channels::<u8>(&compressed), Err(ChannelError::InvalidChannel { channel: 0, .. })));
+    }
+
+}
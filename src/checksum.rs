@@ -0,0 +1,141 @@
+//! CRC-32 checksums of the symbols of a stream.
+//!
+//! The checksum is the CRC-32 of IEEE 802.3, the one of gzip and zip, computed over the serialized symbols
+//! (`Symbol::to_bytes`) in order. It's updated as the symbols are encoded or decoded, so that streams are still
+//! read and written in a single pass.
+
+use crate::Symbol;
+
+
+/// Reflected polynomial of the IEEE 802.3 CRC-32
+const POLYNOMIAL: u32 = 0xedb8_8320;
+
+
+/// CRC of every byte value, for the byte-at-a-time update
+const TABLE: [u32; 256] = {
+
+    let mut table = [0; 256];
+
+    let mut byte = 0;
+    while byte < 256 {
+
+        let mut crc = byte as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ POLYNOMIAL } else { crc >> 1 };
+            bit += 1;
+        }
+
+        table[byte] = crc;
+        byte += 1;
+    }
+
+    table
+};
+
+
+/// Running CRC-32 of a sequence of bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crc32 {
+
+    /// Inverted CRC of the bytes so far
+    state: u32,
+
+}
+
+impl Crc32 {
+
+    pub const fn new() -> Self {
+        Self { state: u32::MAX }
+    }
+
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.state = TABLE[((self.state ^ *byte as u32) & 0xff) as usize] ^ (self.state >> 8);
+        }
+    }
+
+
+    /// CRC of the bytes so far
+    pub const fn value(&self) -> u32 {
+        !self.state
+    }
+
+
+    /// CRC of `bytes`
+    pub fn checksum(bytes: &[u8]) -> u32 {
+        let mut crc = Self::new();
+        crc.update(bytes);
+        crc.value()
+    }
+
+}
+
+impl Default for Crc32 {
+
+    fn default() -> Self {
+        Self::new()
+    }
+
+}
+
+
+/// Running CRC-32 of the serialized representations of symbols
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SymbolChecksum {
+
+    crc: Crc32,
+    /// Buffer the symbols are serialized into
+    scratch: Vec<u8>,
+
+}
+
+impl SymbolChecksum {
+
+    pub fn update<U>(&mut self, symbols: &[U])
+    where
+        U: Symbol
+    {
+        self.scratch.resize(U::WIDTH, 0);
+
+        for symbol in symbols {
+            symbol.to_bytes(&mut self.scratch);
+            self.crc.update(&self.scratch);
+        }
+    }
+
+
+    pub const fn value(&self) -> u32 {
+        self.crc.value()
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+
+    #[test]
+    fn check_crc32() {
+
+        // Check value of the CRC-32 catalogue
+        assert_eq!(Crc32::checksum(b"123456789"), 0xcbf4_3926);
+        assert_eq!(Crc32::checksum(b""), 0);
+
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.value(), 0xcbf4_3926);
+
+        let mut symbols = SymbolChecksum::default();
+        symbols.update(&[0x3231_u16, 0x3433]);
+        symbols.update(&[0x3635_u16, 0x3837]);
+        symbols.update::<u8>(b"9");
+        assert_eq!(symbols.value(), 0xcbf4_3926);
+    }
+
+}
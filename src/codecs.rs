@@ -0,0 +1,339 @@
+//! Entropy coders that turn symbols into bits using a model of the symbols.
+//!
+//! The container format, the transforms, and the bit infrastructure are shared by every coder,
+//! so a new backend only needs to implement `EntropyCodec` to be usable with `compress_with_codec` and `decompress_with_codec`.
+
+use crate::bits::{BitVec, BitView};
+use crate::tree::{encode_with_table, reserve_single_leaf, try_encode_with_table, value_frequencies, DecodingError, DecodingTree, EncodingTree, UnknownValue};
+use crate::{DecompressionError, Symbol};
+
+
+/// An entropy coding backend.
+/// The codec builds a model from the symbols to encode, which is serialized in front of the bitcode and used to decode it.
+///
+/// The compressed data doesn't record which codec produced it, so it must be decompressed with the same codec.
+pub trait EntropyCodec {
+
+    /// Model of the symbol statistics needed to encode and decode
+    type Model<U: Symbol>;
+
+
+    /// Build a model fitted to `symbols`.
+    /// Return `None` if there are no symbols.
+    fn build_model<U: Symbol>(&self, symbols: impl Iterator<Item = U>) -> Option<Self::Model<U>>;
+
+
+    /// Encode the symbols with `model`. Every symbol must be representable by the model.
+    fn encode<U: Symbol>(&self, symbols: impl Iterator<Item = U>, model: &Self::Model<U>) -> BitVec;
+
+
+    /// Like `encode`, but fail instead of panicking if a symbol can't be represented by the model.
+    /// The default implementation calls `encode`, so codecs whose `encode` can panic should override it.
+    fn try_encode<U: Symbol>(&self, symbols: impl Iterator<Item = U>, model: &Self::Model<U>) -> Result<BitVec, UnknownValue> {
+        Ok(self.encode(symbols, model))
+    }
+
+
+    /// Decode all the symbols encoded in `bits`
+    fn decode<U: Symbol>(&self, bits: &BitView, model: &Self::Model<U>) -> Result<Vec<U>, DecodingError>;
+
+
+    /// Decode `bits`, knowing that they encode `count` symbols.
+    /// Codecs that may encode symbols with zero bits must override this, because `decode` can't count them.
+    fn decode_exact<U: Symbol>(&self, bits: &BitView, model: &Self::Model<U>, count: usize) -> Result<Vec<U>, DecodingError> {
+        let _ = count;
+        self.decode(bits, model)
+    }
+
+
+    /// Minimum number of bits of the code of any symbol, used to reject bitcodes too short for their symbol count.
+    /// The default implementation returns 0, which disables the check.
+    fn min_code_bits<U: Symbol>(&self, model: &Self::Model<U>) -> usize {
+        let _ = model;
+        0
+    }
+
+
+    fn serialize_model<U: Symbol>(&self, model: &Self::Model<U>, buf: &mut Vec<u8>);
+
+
+    /// Deserialize the model at byte `offset` of `input`.
+    /// Return the model and the number of bytes read.
+    fn deserialize_model<U: Symbol>(&self, input: &[u8], offset: usize) -> Result<(Self::Model<U>, usize), DecompressionError>;
+
+}
+
+
+/// The frequency tree coder, used by default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TreeCodec;
+
+impl EntropyCodec for TreeCodec {
+
+    type Model<U: Symbol> = DecodingTree<U>;
+
+
+    fn build_model<U: Symbol>(&self, symbols: impl Iterator<Item = U>) -> Option<Self::Model<U>> {
+        EncodingTree::from_frequencies(&value_frequencies(symbols)).into_decoder()
+    }
+
+
+    /// The bitcode is allocated for the frequencies the model was built from, which are those of `symbols`
+    /// when the model was built by `build_model`
+    fn encode<U: Symbol>(&self, symbols: impl Iterator<Item = U>, model: &Self::Model<U>) -> BitVec {
+        encode_with_table(&model.code_table(), symbols, model.encoded_size_bits() as usize)
+    }
+
+
+    fn try_encode<U: Symbol>(&self, symbols: impl Iterator<Item = U>, model: &Self::Model<U>) -> Result<BitVec, UnknownValue> {
+        try_encode_with_table(&model.code_table(), symbols, model.encoded_size_bits() as usize)
+    }
+
+
+    fn decode<U: Symbol>(&self, bits: &BitView, model: &Self::Model<U>) -> Result<Vec<U>, DecodingError> {
+        model.decode(bits).map(Vec::from)
+    }
+
+
+    fn decode_exact<U: Symbol>(&self, bits: &BitView, model: &Self::Model<U>, count: usize) -> Result<Vec<U>, DecodingError> {
+
+        // The only value of a single-leaf tree is encoded with zero bits
+        if let Some(value) = model.single_value() {
+
+            let mut symbols = Vec::new();
+            reserve_single_leaf(&mut symbols, count)?;
+            symbols.resize(count, value.clone());
+
+            return Ok(symbols);
+        }
+
+        self.decode(bits, model)
+    }
+
+
+    fn min_code_bits<U: Symbol>(&self, model: &Self::Model<U>) -> usize {
+        model.min_code_length()
+    }
+
+
+    fn serialize_model<U: Symbol>(&self, model: &Self::Model<U>, buf: &mut Vec<u8>) {
+        model.serialize(buf);
+    }
+
+
+    fn deserialize_model<U: Symbol>(&self, input: &[u8], offset: usize) -> Result<(Self::Model<U>, usize), DecompressionError> {
+        DecodingTree::deserialize_at(input, offset).map_err(DecompressionError::InvalidDecodingTree)
+    }
+
+}
+
+
+/// The frequency tree coder with the compact tree layout, which stores the node specifiers as bits.
+/// Selected by `CompressionOptions::compact_tree`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CompactTreeCodec;
+
+impl EntropyCodec for CompactTreeCodec {
+
+    type Model<U: Symbol> = DecodingTree<U>;
+
+
+    fn build_model<U: Symbol>(&self, symbols: impl Iterator<Item = U>) -> Option<Self::Model<U>> {
+        TreeCodec.build_model(symbols)
+    }
+
+
+    fn encode<U: Symbol>(&self, symbols: impl Iterator<Item = U>, model: &Self::Model<U>) -> BitVec {
+        TreeCodec.encode(symbols, model)
+    }
+
+
+    fn try_encode<U: Symbol>(&self, symbols: impl Iterator<Item = U>, model: &Self::Model<U>) -> Result<BitVec, UnknownValue> {
+        TreeCodec.try_encode(symbols, model)
+    }
+
+
+    fn decode<U: Symbol>(&self, bits: &BitView, model: &Self::Model<U>) -> Result<Vec<U>, DecodingError> {
+        TreeCodec.decode(bits, model)
+    }
+
+
+    fn decode_exact<U: Symbol>(&self, bits: &BitView, model: &Self::Model<U>, count: usize) -> Result<Vec<U>, DecodingError> {
+        TreeCodec.decode_exact(bits, model, count)
+    }
+
+
+    fn min_code_bits<U: Symbol>(&self, model: &Self::Model<U>) -> usize {
+        TreeCodec.min_code_bits(model)
+    }
+
+
+    fn serialize_model<U: Symbol>(&self, model: &Self::Model<U>, buf: &mut Vec<u8>) {
+        model.serialize_compact(buf);
+    }
+
+
+    fn deserialize_model<U: Symbol>(&self, input: &[u8], offset: usize) -> Result<(Self::Model<U>, usize), DecompressionError> {
+        DecodingTree::deserialize_compact_at(input, offset).map_err(DecompressionError::InvalidDecodingTree)
+    }
+
+}
+
+
+/// The frequency tree coder with the canonical tree layout, which stores the code length of each symbol in the order of the symbols.
+/// The tree is reshaped to canonical codes, with the same code lengths.
+/// Selected by `CompressionOptions::canonical_tree`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CanonicalTreeCodec;
+
+impl EntropyCodec for CanonicalTreeCodec {
+
+    type Model<U: Symbol> = DecodingTree<U>;
+
+
+    fn build_model<U: Symbol>(&self, symbols: impl Iterator<Item = U>) -> Option<Self::Model<U>> {
+        TreeCodec.build_model(symbols).map(|tree| tree.to_canonical())
+    }
+
+
+    fn encode<U: Symbol>(&self, symbols: impl Iterator<Item = U>, model: &Self::Model<U>) -> BitVec {
+        TreeCodec.encode(symbols, model)
+    }
+
+
+    fn try_encode<U: Symbol>(&self, symbols: impl Iterator<Item = U>, model: &Self::Model<U>) -> Result<BitVec, UnknownValue> {
+        TreeCodec.try_encode(symbols, model)
+    }
+
+
+    fn decode<U: Symbol>(&self, bits: &BitView, model: &Self::Model<U>) -> Result<Vec<U>, DecodingError> {
+        TreeCodec.decode(bits, model)
+    }
+
+
+    fn decode_exact<U: Symbol>(&self, bits: &BitView, model: &Self::Model<U>, count: usize) -> Result<Vec<U>, DecodingError> {
+        TreeCodec.decode_exact(bits, model, count)
+    }
+
+
+    fn min_code_bits<U: Symbol>(&self, model: &Self::Model<U>) -> usize {
+        TreeCodec.min_code_bits(model)
+    }
+
+
+    fn serialize_model<U: Symbol>(&self, model: &Self::Model<U>, buf: &mut Vec<u8>) {
+        model.serialize_canonical(buf);
+    }
+
+
+    fn deserialize_model<U: Symbol>(&self, input: &[u8], offset: usize) -> Result<(Self::Model<U>, usize), DecompressionError> {
+        DecodingTree::deserialize_canonical_at(input, offset).map_err(DecompressionError::InvalidDecodingTree)
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use crate::{compress_with_codec, decompress_with_codec, CompressionOptions};
+
+
+    /// Fixed-width coder that stores every symbol with the bits of its index in a sorted alphabet
+    struct FixedWidthCodec;
+
+    impl EntropyCodec for FixedWidthCodec {
+
+        type Model<U: Symbol> = Vec<U>;
+
+
+        fn build_model<U: Symbol>(&self, symbols: impl Iterator<Item = U>) -> Option<Self::Model<U>> {
+            let alphabet: Vec<U> = value_frequencies(symbols).into_vec().into_iter().map(|(value, _)| value).collect();
+            (!alphabet.is_empty()).then_some(alphabet)
+        }
+
+
+        fn encode<U: Symbol>(&self, symbols: impl Iterator<Item = U>, model: &Self::Model<U>) -> BitVec {
+
+            let mut bits = BitVec::new();
+
+            for symbol in symbols {
+                let index = model.iter().position(|value| *value == symbol).unwrap();
+                for i in (0..8).rev() {
+                    bits.append_bit(index & (1 << i) != 0);
+                }
+            }
+
+            bits
+        }
+
+
+        fn decode<U: Symbol>(&self, bits: &BitView, model: &Self::Model<U>) -> Result<Vec<U>, DecodingError> {
+
+            let bits: Vec<bool> = bits.iter_bits().collect();
+
+            bits.chunks(8)
+                .enumerate()
+                .map(|(i, code)| {
+                    let index = code.iter().fold(0, |index, bit| (index << 1) | *bit as usize);
+                    model.get(index).cloned().filter(|_| code.len() == 8)
+                        .ok_or(DecodingError::InvalidEncoding { bit_offset: i * 8 })
+                })
+                .collect()
+        }
+
+
+        fn serialize_model<U: Symbol>(&self, model: &Self::Model<U>, buf: &mut Vec<u8>) {
+
+            buf.push(model.len() as u8);
+
+            for value in model {
+                let start = buf.len();
+                buf.resize(start + U::WIDTH, 0);
+                value.to_bytes(&mut buf[start..]);
+            }
+        }
+
+
+        fn deserialize_model<U: Symbol>(&self, input: &[u8], offset: usize) -> Result<(Self::Model<U>, usize), DecompressionError> {
+
+            let len = *input.get(offset).ok_or(DecompressionError::InvalidSymbol { offset })? as usize;
+
+            let model = (0..len)
+                .map(|i| {
+                    let start = offset + 1 + i * U::WIDTH;
+                    input.get(start..start + U::WIDTH)
+                        .and_then(U::from_bytes)
+                        .ok_or(DecompressionError::InvalidSymbol { offset: start })
+                })
+                .collect::<Result<_, _>>()?;
+
+            Ok((model, 1 + len * U::WIDTH))
+        }
+
+    }
+
+
+    #[test]
+    fn check_custom_codec() {
+
+        let text = "the quick brown fox jumps over the lazy dog, the quick brown fox";
+
+        let all_options = [
+            CompressionOptions::new(),
+            CompressionOptions { lz77: true, ..CompressionOptions::new() },
+            CompressionOptions { mtf: true, lz77: true, ..CompressionOptions::new() },
+        ];
+
+        for options in all_options {
+
+            let compressed = compress_with_codec(text.chars(), &options, &FixedWidthCodec);
+            let decompressed: String = decompress_with_codec::<char, _>(&compressed, &FixedWidthCodec).unwrap().iter().collect();
+
+            assert_eq!(decompressed, text);
+        }
+    }
+
+}
@@ -0,0 +1,1497 @@
+//! Container format: the header and the sections of the compressed payload.
+
+use std::error::Error;
+use std::fmt;
+use std::num::NonZeroUsize;
+
+use crate::bits::{BitOrder, BitVec};
+use crate::codecs::EntropyCodec;
+use crate::transforms::lz77::{self, Token};
+use crate::tree::{DecodingTree, EncodingTree};
+use crate::symbol::SymbolType;
+use crate::{CompressError, DecompressionError, Symbol};
+
+
+/// Version of the container format written by this library
+pub const FORMAT_VERSION: u8 = 4;
+
+
+/// Bit flags of the header flags bytes.
+/// Each flag signals the presence of the corresponding optional header fields.
+///
+/// The bits of the flags byte and of the extended flags byte must be understood: they change the layout of the
+/// header or of the payload, so a decoder rejects the bits it doesn't know with `UnknownFlags` instead of misreading
+/// the data. `extended::OPTIONAL` adds a byte of optional flags, which only carry hints that don't change how the data
+/// is read. Decoders ignore the optional flags they don't know, and later versions add a flag there when older
+/// versions can safely decode the data without it. `extended::SETTINGS` adds a record of informative encoder settings,
+/// which is length-prefixed so that later versions can append settings to it.
+pub mod flags {
+
+    /// The symbols were grouped into chunks. Followed by the chunk size and the chunk remainder.
+    pub const CHUNKED: u8 = 1 << 0;
+
+    /// The payload is an LZ77 token stream instead of a single tree and bitcode
+    pub const LZ77: u8 = 1 << 1;
+
+    /// The symbols were replaced by their move-to-front indices. The payload is preceded by the initial alphabet.
+    pub const MTF: u8 = 1 << 2;
+
+    /// The payload is a sequence of independently decodable blocks, terminated by an end block
+    pub const BLOCKS: u8 = 1 << 3;
+
+    /// The payload is encoded with the tree of an external dictionary. Followed by the dictionary identifier.
+    pub const DICTIONARY: u8 = 1 << 4;
+
+    /// The symbols were replaced by dense identifiers. The payload is preceded by the mapping table.
+    pub const REMAP: u8 = 1 << 5;
+
+    /// The trees are serialized in the compact layout, with one bit per node specifier
+    pub const COMPACT_TREE: u8 = 1 << 6;
+
+    /// The bitcode is preceded by a table of checkpoints for seeking. Only valid without the other transforms.
+    pub const CHECKPOINTS: u8 = 1 << 7;
+
+    /// All the flags known to this version of the library
+    pub const KNOWN: u8 = CHUNKED | LZ77 | MTF | BLOCKS | DICTIONARY | REMAP | COMPACT_TREE | CHECKPOINTS;
+
+    /// The flags that can't be combined with `CHECKPOINTS`, because seeking needs to decode the symbols independently
+    pub const NOT_SEEKABLE: u8 = CHUNKED | LZ77 | MTF | BLOCKS | DICTIONARY | REMAP;
+
+    /// Flags of the extended flags byte
+    pub mod extended {
+
+        /// The bitcode sections are packed from the least significant bit of each byte
+        pub const LSB_FIRST: u8 = 1 << 0;
+
+        /// The modification time of the original file is recorded. Followed by the seconds since the Unix epoch.
+        pub const MODIFIED: u8 = 1 << 1;
+
+        /// The Unix mode of the original file is recorded. Followed by the mode.
+        pub const MODE: u8 = 1 << 2;
+
+        /// The name of the original file is recorded. Followed by the length and the UTF-8 bytes of the name.
+        pub const NAME: u8 = 1 << 3;
+
+        /// The payloads of the blocks were transformed by the caller, for example encrypted. Only valid with `BLOCKS`.
+        pub const TRANSFORMED: u8 = 1 << 4;
+
+        /// The trees are serialized in the canonical layout, as the code lengths of the sorted symbols.
+        /// Not valid with `COMPACT_TREE`.
+        pub const CANONICAL_TREE: u8 = 1 << 5;
+
+        /// The settings of the encoder that the other flags don't record are stored.
+        /// Followed by the length of the settings record (1 byte) and the record, whose flags are in `settings`.
+        pub const SETTINGS: u8 = 1 << 6;
+
+        /// Followed by the optional flags byte, whose flags are in `optional`
+        pub const OPTIONAL: u8 = 1 << 7;
+
+        /// All the extended flags known to this version of the library
+        pub const KNOWN: u8 = LSB_FIRST | MODIFIED | MODE | NAME | TRANSFORMED | CANONICAL_TREE | SETTINGS | OPTIONAL;
+
+    }
+
+    /// Flags of the optional flags byte, which decoders ignore when they don't know them
+    pub mod optional {
+
+        /// Mask of the tag of the symbol type, from `SymbolType::tag`, or 0 if the type is not recorded
+        pub const SYMBOL_TYPE: u8 = 0b1111;
+
+        /// All the optional flags known to this version of the library
+        pub const KNOWN: u8 = SYMBOL_TYPE;
+
+    }
+
+    /// Flags of the settings record. The record starts with a byte of these flags, followed by the fields they announce in order.
+    /// Decoders ignore the unknown flags and the bytes after the fields they know, so later versions append their fields.
+    pub mod settings {
+
+        /// The chunk size was chosen by `compress_auto_grouped` on a sample of the input
+        pub const AUTO_GROUPED: u8 = 1 << 0;
+
+        /// Followed by the maximum number of symbols of the blocks of a stream (varint)
+        pub const MAX_BLOCK_SYMBOLS: u8 = 1 << 1;
+
+        /// Followed by the maximum estimated size in bytes of the blocks of a stream (varint)
+        pub const MAX_BLOCK_BYTES: u8 = 1 << 2;
+
+        /// The stream ends with a checksum block, so decoders checksum the symbols as they decode them
+        pub const CHECKSUM: u8 = 1 << 3;
+
+        /// Every data block of the stream is followed by the checksum of its symbols
+        pub const BLOCK_CHECKSUMS: u8 = 1 << 4;
+
+        /// The `\r\n` line endings of the text were replaced with `\n`, so decoders put a `\r` back in front of every `\n`.
+        /// Decoders that don't know the flag return the text with `\n` line endings.
+        pub const NORMALIZED_NEWLINES: u8 = 1 << 5;
+
+    }
+
+    /// The flags whose payloads are always packed from the most significant bit
+    pub const MSB_FIRST_ONLY: u8 = BLOCKS | DICTIONARY | CHECKPOINTS;
+
+}
+
+
+/// Attributes of the original file, recorded in the header like the optional fields of gzip
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileMetadata {
+
+    /// Name of the file, without its directory
+    pub name: Option<String>,
+
+    /// Modification time in seconds since the Unix epoch
+    pub modified: Option<u64>,
+
+    /// Unix mode of the file, with its permission bits
+    pub mode: Option<u32>,
+
+}
+
+impl FileMetadata {
+
+    pub const fn new() -> Self {
+        Self {
+            name: None,
+            modified: None,
+            mode: None
+        }
+    }
+
+
+    /// Number of bytes needed to serialize the recorded fields
+    const fn serialized_size(&self) -> usize {
+        (if self.modified.is_some() { 8 } else { 0 })
+            + if self.mode.is_some() { 4 } else { 0 }
+            + match &self.name {
+                Some (name) => 2 + name.len(),
+                None => 0
+            }
+    }
+
+}
+
+
+/// Settings of the encoder that produced the data, for the choices that the layout of the data doesn't reveal.
+/// Decoders only need `checksum` and `block_checksums`, to know which checksums of the symbols of a stream to verify,
+/// and `normalized_newlines`, to restore the line endings of text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EncoderSettings {
+
+    /// Whether the chunk size was chosen by `compress_auto_grouped` on a sample of the input
+    pub auto_grouped: bool,
+
+    /// Maximum number of symbols of the blocks of a stream, set by `EncoderOptions::max_block_symbols`
+    pub max_block_symbols: Option<NonZeroUsize>,
+
+    /// Maximum estimated size in bytes of the blocks of a stream, set by `EncoderOptions::max_block_bytes`
+    pub max_block_bytes: Option<NonZeroUsize>,
+
+    /// Whether the stream ends with a checksum of its symbols, set by `EncoderOptions::checksum`
+    pub checksum: bool,
+
+    /// Whether each data block of the stream is followed by a checksum of its symbols, set by `EncoderOptions::block_checksums`
+    pub block_checksums: bool,
+
+    /// Whether the `\r\n` line endings of the text were replaced with `\n`, set by `CompressionOptions::normalize_newlines`
+    pub normalized_newlines: bool,
+
+}
+
+impl EncoderSettings {
+
+    pub const fn new() -> Self {
+        Self {
+            auto_grouped: false,
+            max_block_symbols: None,
+            max_block_bytes: None,
+            checksum: false,
+            block_checksums: false,
+            normalized_newlines: false
+        }
+    }
+
+
+    /// Whether no setting is recorded, in which case the header has no settings record
+    pub const fn is_empty(&self) -> bool {
+        !self.auto_grouped && self.max_block_symbols.is_none() && self.max_block_bytes.is_none() && !self.checksum && !self.block_checksums
+            && !self.normalized_newlines
+    }
+
+
+    const fn flags(&self) -> u8 {
+        let mut flags = 0;
+        if self.auto_grouped {
+            flags |= flags::settings::AUTO_GROUPED;
+        }
+        if self.max_block_symbols.is_some() {
+            flags |= flags::settings::MAX_BLOCK_SYMBOLS;
+        }
+        if self.max_block_bytes.is_some() {
+            flags |= flags::settings::MAX_BLOCK_BYTES;
+        }
+        if self.checksum {
+            flags |= flags::settings::CHECKSUM;
+        }
+        if self.block_checksums {
+            flags |= flags::settings::BLOCK_CHECKSUMS;
+        }
+        if self.normalized_newlines {
+            flags |= flags::settings::NORMALIZED_NEWLINES;
+        }
+        flags
+    }
+
+
+    /// Number of bytes of the settings record, without its length
+    const fn record_size(&self) -> usize {
+        1 + match self.max_block_symbols {
+            Some (max) => varint_size(max.get()),
+            None => 0
+        } + match self.max_block_bytes {
+            Some (max) => varint_size(max.get()),
+            None => 0
+        }
+    }
+
+
+    /// Number of bytes needed to serialize the settings with their length, 0 if they're empty
+    const fn serialized_size(&self) -> usize {
+        if self.is_empty() { 0 } else { 1 + self.record_size() }
+    }
+
+
+    fn serialize(&self, buf: &mut Vec<u8>) {
+
+        buf.push(self.record_size() as u8);
+        buf.push(self.flags());
+
+        if let Some(max) = self.max_block_symbols {
+            write_varint(buf, max.get());
+        }
+        if let Some(max) = self.max_block_bytes {
+            write_varint(buf, max.get());
+        }
+    }
+
+
+    /// Read the settings from `record`, which starts at byte `offset` of the header
+    fn deserialize(record: &[u8], offset: usize) -> Result<Self, HeaderDeserializationError> {
+
+        let invalid = HeaderDeserializationError::InvalidSettings { offset };
+
+        let (&flags, _) = record.split_first().ok_or(invalid)?;
+
+        let mut settings = Self::new();
+        settings.auto_grouped = flags & flags::settings::AUTO_GROUPED != 0;
+        settings.checksum = flags & flags::settings::CHECKSUM != 0;
+        settings.block_checksums = flags & flags::settings::BLOCK_CHECKSUMS != 0;
+        settings.normalized_newlines = flags & flags::settings::NORMALIZED_NEWLINES != 0;
+
+        let mut position = 1;
+        let mut read_max = |flag: u8| -> Result<Option<NonZeroUsize>, HeaderDeserializationError> {
+            if flags & flag == 0 {
+                return Ok(None);
+            }
+            let (value, size) = read_varint(record, position).map_err(|_| invalid)?;
+            position += size;
+            NonZeroUsize::new(value).ok_or(invalid).map(Some)
+        };
+
+        settings.max_block_symbols = read_max(flags::settings::MAX_BLOCK_SYMBOLS)?;
+        settings.max_block_bytes = read_max(flags::settings::MAX_BLOCK_BYTES)?;
+
+        Ok(settings)
+    }
+
+}
+
+
+/// Metadata written in front of the compressed data.
+///
+/// Serialized layout:
+/// - format version (1 byte)
+/// - flags (1 byte)
+/// - extended flags (1 byte)
+/// - if the symbol type is recorded: optional flags (1 byte)
+/// - if chunked: chunk size (1 byte), chunk remainder (1 byte)
+/// - if encoded with a dictionary: dictionary identifier (4 bytes)
+/// - if the modification time is recorded: seconds since the Unix epoch (8 bytes)
+/// - if the mode is recorded: Unix mode (4 bytes)
+/// - if the name is recorded: name length (2 bytes), UTF-8 name
+/// - if encoder settings are recorded: record length (1 byte), settings record
+///
+/// Multi-byte fields are little-endian.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+
+    /// Number of symbols grouped in each chunk. 1 if the symbols were not chunked
+    pub chunk_size: u8,
+
+    /// Number of meaningful symbols in the last chunk. 0 if the last chunk is complete
+    pub chunk_remainder: u8,
+
+    /// Whether the payload was encoded as LZ77 tokens
+    pub lz77: bool,
+
+    /// Whether the symbols were replaced by their move-to-front indices
+    pub mtf: bool,
+
+    /// Whether the payload is a sequence of blocks produced by a streaming encoder
+    pub blocks: bool,
+
+    /// Whether the payloads of the blocks were transformed by an `OutputTransform`
+    pub transformed_blocks: bool,
+
+    /// Whether the symbols were replaced by dense identifiers
+    pub remap: bool,
+
+    /// Whether the trees are serialized in the compact layout
+    pub compact_tree: bool,
+
+    /// Whether the trees are serialized in the canonical layout
+    pub canonical_tree: bool,
+
+    /// Whether the bitcode is preceded by a table of checkpoints for seeking
+    pub checkpoints: bool,
+
+    /// Identifier of the dictionary whose tree encodes the payload, if any
+    pub dictionary: Option<u32>,
+
+    /// Order of the bits in the bytes of the bitcode sections.
+    /// Streams, dictionaries, and seekable data are always packed from the most significant bit.
+    pub bit_order: BitOrder,
+
+    /// Attributes of the original file, if the data was compressed from a file
+    pub metadata: FileMetadata,
+
+    /// Type of the symbols, if it's one of the types `decompress_any` can decode.
+    /// Only a hint: decoders don't need it to read the data.
+    pub symbol_type: Option<SymbolType>,
+
+    /// Settings of the encoder that the layout of the data doesn't reveal
+    pub settings: EncoderSettings,
+
+}
+
+impl Header {
+
+    /// Create a header for symbols that weren't chunked
+    pub const fn new() -> Self {
+        Self {
+            chunk_size: 1,
+            chunk_remainder: 0,
+            lz77: false,
+            mtf: false,
+            blocks: false,
+            transformed_blocks: false,
+            remap: false,
+            compact_tree: false,
+            canonical_tree: false,
+            checkpoints: false,
+            dictionary: None,
+            bit_order: BitOrder::MsbFirst,
+            metadata: FileMetadata::new(),
+            symbol_type: None,
+            settings: EncoderSettings::new()
+        }
+    }
+
+
+    /// Create a header for symbols grouped in chunks of `chunk_size` symbols
+    pub const fn chunked(chunk_size: u8, chunk_remainder: u8) -> Self {
+        Self {
+            chunk_size,
+            chunk_remainder,
+            lz77: false,
+            mtf: false,
+            blocks: false,
+            transformed_blocks: false,
+            remap: false,
+            compact_tree: false,
+            canonical_tree: false,
+            checkpoints: false,
+            dictionary: None,
+            bit_order: BitOrder::MsbFirst,
+            metadata: FileMetadata::new(),
+            symbol_type: None,
+            settings: EncoderSettings::new()
+        }
+    }
+
+
+    const fn flags(&self) -> u8 {
+        let mut flags = 0;
+        if self.chunk_size != 1 {
+            flags |= flags::CHUNKED;
+        }
+        if self.lz77 {
+            flags |= flags::LZ77;
+        }
+        if self.mtf {
+            flags |= flags::MTF;
+        }
+        if self.blocks {
+            flags |= flags::BLOCKS;
+        }
+        if self.dictionary.is_some() {
+            flags |= flags::DICTIONARY;
+        }
+        if self.remap {
+            flags |= flags::REMAP;
+        }
+        if self.compact_tree {
+            flags |= flags::COMPACT_TREE;
+        }
+        if self.checkpoints {
+            flags |= flags::CHECKPOINTS;
+        }
+        flags
+    }
+
+
+    const fn extended_flags(&self) -> u8 {
+        let mut flags = 0;
+        if let BitOrder::LsbFirst = self.bit_order {
+            flags |= flags::extended::LSB_FIRST;
+        }
+        if self.metadata.modified.is_some() {
+            flags |= flags::extended::MODIFIED;
+        }
+        if self.metadata.mode.is_some() {
+            flags |= flags::extended::MODE;
+        }
+        if self.metadata.name.is_some() {
+            flags |= flags::extended::NAME;
+        }
+        if self.transformed_blocks {
+            flags |= flags::extended::TRANSFORMED;
+        }
+        if self.canonical_tree {
+            flags |= flags::extended::CANONICAL_TREE;
+        }
+        if !self.settings.is_empty() {
+            flags |= flags::extended::SETTINGS;
+        }
+        if self.symbol_type.is_some() {
+            flags |= flags::extended::OPTIONAL;
+        }
+        flags
+    }
+
+
+    const fn optional_flags(&self) -> u8 {
+        match self.symbol_type {
+            Some (symbol_type) => symbol_type.tag() & flags::optional::SYMBOL_TYPE,
+            None => 0
+        }
+    }
+
+
+    /// Number of bytes needed to serialize the header
+    pub const fn serialized_size(&self) -> usize {
+        3 + if self.symbol_type.is_some() { 1 } else { 0 }
+            + if self.chunk_size != 1 { 2 } else { 0 }
+            + if self.dictionary.is_some() { 4 } else { 0 }
+            + self.metadata.serialized_size()
+            + self.settings.serialized_size()
+    }
+
+
+    /// Serialize the header.
+    ///
+    /// Panics if the name of the metadata is longer than `u16::MAX` bytes.
+    pub fn serialize(&self, buf: &mut Vec<u8>) {
+
+        let flags = self.flags();
+
+        buf.push(FORMAT_VERSION);
+        buf.push(flags);
+        let extended = self.extended_flags();
+        buf.push(extended);
+
+        if extended & flags::extended::OPTIONAL != 0 {
+            buf.push(self.optional_flags());
+        }
+
+        if flags & flags::CHUNKED != 0 {
+            buf.push(self.chunk_size);
+            buf.push(self.chunk_remainder);
+        }
+
+        if let Some(id) = self.dictionary {
+            write_u32(buf, id);
+        }
+
+        if let Some(modified) = self.metadata.modified {
+            buf.extend_from_slice(&modified.to_le_bytes());
+        }
+
+        if let Some(mode) = self.metadata.mode {
+            write_u32(buf, mode);
+        }
+
+        if let Some(name) = &self.metadata.name {
+            let len = u16::try_from(name.len()).expect("the file name must be at most 65535 bytes long");
+            buf.extend_from_slice(&len.to_le_bytes());
+            buf.extend_from_slice(name.as_bytes());
+        }
+
+        if extended & flags::extended::SETTINGS != 0 {
+            self.settings.serialize(buf);
+        }
+    }
+
+
+    /// Deserialize the header at the start of `input`.
+    /// Return the header and the number of bytes read.
+    pub fn deserialize(input: &[u8]) -> Result<(Self, usize), HeaderDeserializationError> {
+
+        let mut reader = FieldReader { input, offset: 0 };
+
+        let version = reader.read_u8("format version")?;
+        if version != FORMAT_VERSION {
+            return Err(HeaderDeserializationError::UnsupportedVersion { found: version, expected: FORMAT_VERSION });
+        }
+
+        let flags = reader.read_u8("flags")?;
+        // Every bit of the flags byte is assigned in this version, but retired flags must still be rejected
+        #[allow(clippy::bad_bit_mask)]
+        if flags & !flags::KNOWN != 0 {
+            return Err(HeaderDeserializationError::UnknownFlags { offset: 1, flags: flags & !flags::KNOWN });
+        }
+        if flags & flags::CHECKPOINTS != 0 && flags & flags::NOT_SEEKABLE != 0 {
+            return Err(HeaderDeserializationError::IncompatibleFlags { offset: 1, flags });
+        }
+
+        let extended = reader.read_u8("extended flags")?;
+        // Every bit of the extended flags byte is assigned too
+        #[allow(clippy::bad_bit_mask)]
+        if extended & !flags::extended::KNOWN != 0 {
+            return Err(HeaderDeserializationError::UnknownFlags { offset: 2, flags: extended & !flags::extended::KNOWN });
+        }
+        if extended & flags::extended::LSB_FIRST != 0 && flags & flags::MSB_FIRST_ONLY != 0 {
+            return Err(HeaderDeserializationError::IncompatibleFlags { offset: 2, flags: extended });
+        }
+        if extended & flags::extended::TRANSFORMED != 0 && flags & flags::BLOCKS == 0 {
+            return Err(HeaderDeserializationError::IncompatibleFlags { offset: 2, flags: extended });
+        }
+        if extended & flags::extended::CANONICAL_TREE != 0 && flags & flags::COMPACT_TREE != 0 {
+            return Err(HeaderDeserializationError::IncompatibleFlags { offset: 2, flags: extended });
+        }
+
+        // The unknown optional flags are hints that are safe to ignore
+        let optional = if extended & flags::extended::OPTIONAL != 0 {
+            reader.read_u8("optional flags")?
+        } else {
+            0
+        };
+
+        let mut header = Self::new();
+
+        header.symbol_type = SymbolType::from_tag(optional & flags::optional::SYMBOL_TYPE);
+
+        header.lz77 = flags & flags::LZ77 != 0;
+        header.mtf = flags & flags::MTF != 0;
+        header.blocks = flags & flags::BLOCKS != 0;
+        header.transformed_blocks = extended & flags::extended::TRANSFORMED != 0;
+        header.remap = flags & flags::REMAP != 0;
+        header.compact_tree = flags & flags::COMPACT_TREE != 0;
+        header.canonical_tree = extended & flags::extended::CANONICAL_TREE != 0;
+        header.checkpoints = flags & flags::CHECKPOINTS != 0;
+
+        if extended & flags::extended::LSB_FIRST != 0 {
+            header.bit_order = BitOrder::LsbFirst;
+        }
+
+        if flags & flags::CHUNKED != 0 {
+
+            let offset = reader.offset;
+            header.chunk_size = reader.read_u8("chunk size")?;
+            header.chunk_remainder = reader.read_u8("chunk remainder")?;
+
+            if header.chunk_size == 0 || header.chunk_remainder >= header.chunk_size {
+                return Err(HeaderDeserializationError::InvalidChunking { offset, chunk_size: header.chunk_size, chunk_remainder: header.chunk_remainder });
+            }
+        }
+
+        if flags & flags::DICTIONARY != 0 {
+            header.dictionary = Some(reader.read_u32("dictionary identifier")?);
+        }
+
+        if extended & flags::extended::MODIFIED != 0 {
+            header.metadata.modified = Some(u64::from_le_bytes(reader.read_array("modification time")?));
+        }
+
+        if extended & flags::extended::MODE != 0 {
+            header.metadata.mode = Some(reader.read_u32("file mode")?);
+        }
+
+        if extended & flags::extended::NAME != 0 {
+
+            let len = u16::from_le_bytes(reader.read_array("file name length")?);
+
+            let offset = reader.offset;
+            let name = reader.read_bytes(len as usize, "file name")?;
+
+            header.metadata.name = Some(
+                String::from_utf8(name.to_vec())
+                    .map_err(|_| HeaderDeserializationError::InvalidFileName { offset })?
+            );
+        }
+
+        if extended & flags::extended::SETTINGS != 0 {
+
+            let len = reader.read_u8("settings length")?;
+
+            let offset = reader.offset;
+            let record = reader.read_bytes(len as usize, "settings")?;
+
+            header.settings = EncoderSettings::deserialize(record, offset)?;
+        }
+
+        Ok((header, reader.offset))
+    }
+
+}
+
+impl Default for Header {
+
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Append `value` to `buf` as 4 little-endian bytes
+fn write_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+
+/// Read a little-endian `u32` at byte `offset` of `input`.
+/// Return `None` if the input is too short.
+fn read_u32(input: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        input.get(offset..offset + 4)?.try_into().unwrap()
+    ))
+}
+
+
+/// Append `value` to `buf` as a LEB128 varint: groups of 7 bits from the least significant,
+/// one per byte, with the most significant bit of every byte but the last set
+pub(crate) fn write_varint(buf: &mut Vec<u8>, mut value: usize) {
+
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+
+    buf.push(value as u8);
+}
+
+
+/// Number of bytes written by `write_varint`
+pub(crate) const fn varint_size(value: usize) -> usize {
+    let bits = usize::BITS - value.leading_zeros();
+    if bits == 0 { 1 } else { bits.div_ceil(7) as usize }
+}
+
+
+/// Read a varint written by `write_varint` at byte `offset` of `input`.
+/// Return the value and the number of bytes read.
+pub(crate) fn read_varint(input: &[u8], offset: usize) -> Result<(usize, usize), DecompressionError> {
+
+    let bytes = input.get(offset..).unwrap_or_default();
+
+    let mut value = 0_usize;
+
+    for (i, byte) in bytes.iter().enumerate() {
+
+        let group = (byte & 0x7f) as usize;
+        let shift = 7 * i as u32;
+
+        if shift >= usize::BITS || (group << shift) >> shift != group {
+            return Err(DecompressionError::InvalidVarint { offset });
+        }
+
+        value |= group << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+
+    Err(DecompressionError::TruncatedSection { offset, expected: bytes.len() + 1, remaining: bytes.len() })
+}
+
+
+
+/// Write the number of symbols in `alphabet` followed by the symbols
+pub(crate) fn write_alphabet<U>(buf: &mut Vec<u8>, alphabet: &[U])
+where
+    U: Symbol
+{
+
+    write_varint(buf, alphabet.len());
+
+    for symbol in alphabet {
+        let start = buf.len();
+        buf.resize(start + U::WIDTH, 0);
+        symbol.to_bytes(&mut buf[start..]);
+    }
+}
+
+
+/// Number of low bytes that differ between the symbols of `alphabet`.
+/// The remaining high bytes are the same for every symbol.
+fn varying_width<U>(alphabet: &[U]) -> usize
+where
+    U: Symbol
+{
+
+    let mut first = vec![0; U::WIDTH];
+    let mut bytes = vec![0; U::WIDTH];
+
+    let Some((head, tail)) = alphabet.split_first() else {
+        return 0;
+    };
+    head.to_bytes(&mut first);
+
+    let mut width = 0;
+
+    for symbol in tail {
+        symbol.to_bytes(&mut bytes);
+        if let Some(i) = bytes.iter().zip(&first).rposition(|(a, b)| a != b) {
+            width = width.max(i + 1);
+        }
+    }
+
+    width
+}
+
+
+/// Number of bytes written by `write_compact_alphabet`
+pub(crate) fn compact_alphabet_size<U>(alphabet: &[U]) -> usize
+where
+    U: Symbol
+{
+    let width = varying_width(alphabet);
+    varint_size(alphabet.len()) + 1 + (U::WIDTH - width) + alphabet.len() * width
+}
+
+
+/// Write the number of symbols in `alphabet`, the number of low bytes that vary between the symbols,
+/// the high bytes shared by all the symbols, and the varying low bytes of each symbol.
+/// `U::WIDTH` must fit in a byte.
+pub(crate) fn write_compact_alphabet<U>(buf: &mut Vec<u8>, alphabet: &[U])
+where
+    U: Symbol
+{
+
+    let width = varying_width(alphabet);
+
+    write_varint(buf, alphabet.len());
+    buf.push(width as u8);
+
+    let mut bytes = vec![0; U::WIDTH];
+
+    if let Some(first) = alphabet.first() {
+        first.to_bytes(&mut bytes);
+    }
+    buf.extend_from_slice(&bytes[width..]);
+
+    for symbol in alphabet {
+        symbol.to_bytes(&mut bytes);
+        buf.extend_from_slice(&bytes[..width]);
+    }
+}
+
+
+/// Read an alphabet written by `write_compact_alphabet` at byte `offset` of `input`.
+/// Return the alphabet and the number of bytes read.
+pub(crate) fn read_compact_alphabet<U>(input: &[u8], offset: usize) -> Result<(Vec<U>, usize), DecompressionError>
+where
+    U: Symbol
+{
+
+    let (len, len_size) = read_varint(input, offset)?;
+
+    let width_offset = offset + len_size;
+    let width = *input.get(width_offset)
+        .ok_or(DecompressionError::TruncatedSection { offset: width_offset, expected: 1, remaining: 0 })?
+        as usize;
+
+    if width > U::WIDTH {
+        return Err(DecompressionError::InvalidSymbol { offset: width_offset });
+    }
+
+    // The symbols are distinct, so a narrow alphabet can't be long. Without this check,
+    // a few bytes could announce millions of copies of the same symbol.
+    if let Some(max) = 1_usize.checked_shl(8 * width as u32).filter(|max| len > *max) {
+        return Err(DecompressionError::AlphabetTooLarge { offset, len, max });
+    }
+
+    let start = width_offset + 1;
+    let size = (U::WIDTH - width).saturating_add(len.saturating_mul(width));
+
+    let bytes = input.get(start..start.saturating_add(size))
+        .ok_or(DecompressionError::TruncatedSection { offset: start, expected: size, remaining: input.len() - start })?;
+
+    let (shared, varying) = bytes.split_at(U::WIDTH - width);
+
+    let mut symbol_bytes = vec![0; U::WIDTH];
+    symbol_bytes[width..].copy_from_slice(shared);
+
+    let alphabet = (0..len)
+        .map(|i| {
+            symbol_bytes[..width].copy_from_slice(&varying[i * width..(i + 1) * width]);
+            U::from_bytes(&symbol_bytes)
+                .ok_or(DecompressionError::InvalidSymbol { offset: start + U::WIDTH - width + i * width })
+        })
+        .collect::<Result<Vec<U>, _>>()?;
+
+    Ok((alphabet, len_size + 1 + size))
+}
+
+
+/// Read an alphabet written by `write_alphabet` at byte `offset` of `input`.
+/// Return the alphabet and the number of bytes read.
+pub(crate) fn read_alphabet<U>(input: &[u8], offset: usize) -> Result<(Vec<U>, usize), DecompressionError>
+where
+    U: Symbol
+{
+
+    let (len, len_size) = read_varint(input, offset)?;
+
+    let start = offset + len_size;
+    let size = len.saturating_mul(U::WIDTH);
+
+    let bytes = input.get(start..start.saturating_add(size))
+        .ok_or(DecompressionError::TruncatedSection { offset: start, expected: size, remaining: input.len() - start })?;
+
+    let alphabet = bytes.chunks_exact(U::WIDTH)
+        .enumerate()
+        .map(|(i, symbol)| U::from_bytes(symbol)
+            .ok_or(DecompressionError::InvalidSymbol { offset: start + i * U::WIDTH })
+        )
+        .collect::<Result<Vec<U>, _>>()?;
+
+    Ok((alphabet, len_size + size))
+}
+
+
+/// Write the encoding of `input` as an LZ77 payload or as a single tree and bitcode.
+/// The plain bitcode is not length-prefixed, so it must be the last section of the data.
+///
+/// A single tree can't be built without symbols, and the input is read several times,
+/// so it fails if the input is empty or if a later pass yields symbols the first pass didn't.
+pub(crate) fn write_payload<V, C>(buf: &mut Vec<u8>, input: impl Iterator<Item = V> + Clone, lz77: bool, bit_order: BitOrder, codec: &C) -> Result<(), CompressError>
+where
+    V: Symbol,
+    C: EntropyCodec
+{
+
+    trace_span!("write_payload", lz77, symbol_width = V::WIDTH);
+    trace_timer!(start);
+
+    if lz77 {
+
+        let data: Vec<V> = input.collect();
+        let tokens = lz77::encode(&data);
+
+        trace_event!(symbols = data.len(), tokens = tokens.len(), elapsed_us = start.elapsed().as_micros() as u64, "parsed LZ77 tokens");
+
+        write_lz77_payload(&tokens, buf, bit_order, codec);
+
+    } else {
+
+        let model = codec.build_model(input.clone()).ok_or(CompressError::EmptyInput)?;
+
+        trace_event!(elapsed_us = start.elapsed().as_micros() as u64, "built model");
+        trace_timer!(start);
+
+        // Counting the encoded symbols keeps the count consistent with the bitcode
+        let mut count = 0;
+        let bitcode = codec.try_encode(input.inspect(|_| count += 1), &model)
+            .map_err(|e| CompressError::InconsistentSource { position: e.position })?;
+
+        trace_event!(
+            symbols = count,
+            bits = bitcode.len_bits(),
+            elapsed_us = start.elapsed().as_micros() as u64,
+            throughput_mib_s = crate::trace::throughput(count * V::WIDTH, start),
+            "encoded"
+        );
+
+        codec.serialize_model(&model, buf);
+
+        write_counted_bitcode(buf, count, &bitcode, bit_order);
+    }
+
+    Ok(())
+}
+
+
+/// Write the plain payload of `data` like `write_payload` with the `TreeCodec`, counting the bytes in an array and looking
+/// up their codes in an array instead of hash tables.
+/// Fails if `data` is empty.
+pub(crate) fn write_byte_payload(buf: &mut Vec<u8>, data: &[u8], bit_order: BitOrder) -> Result<(), CompressError> {
+
+    trace_span!("write_byte_payload", symbols = data.len());
+    trace_timer!(start);
+
+    let (encoder, bitcode) = EncodingTree::encode_bytes(data);
+    let tree = encoder.into_decoder().ok_or(CompressError::EmptyInput)?;
+
+    trace_event!(
+        bits = bitcode.len_bits(),
+        elapsed_us = start.elapsed().as_micros() as u64,
+        throughput_mib_s = crate::trace::throughput(data.len(), start),
+        "encoded"
+    );
+
+    tree.serialize(buf);
+
+    write_counted_bitcode(buf, data.len(), &bitcode, bit_order);
+
+    Ok(())
+}
+
+
+/// Read the tree at byte `offset` of `input` in the layout selected by the header
+pub(crate) fn read_tree<V>(input: &[u8], offset: usize, header: &Header) -> Result<(DecodingTree<V>, usize), DecompressionError>
+where
+    V: Symbol
+{
+    if header.compact_tree {
+        DecodingTree::deserialize_compact_at(input, offset)
+    } else if header.canonical_tree {
+        DecodingTree::deserialize_canonical_at(input, offset)
+    } else {
+        DecodingTree::deserialize_at(input, offset)
+    }.map_err(DecompressionError::InvalidDecodingTree)
+}
+
+
+/// Read the payload written by `write_payload` at byte `offset` of `input`
+pub(crate) fn read_payload<V, C>(input: &[u8], offset: usize, lz77: bool, bit_order: BitOrder, codec: &C) -> Result<Box<[V]>, DecompressionError>
+where
+    V: Symbol,
+    C: EntropyCodec
+{
+
+    if lz77 {
+        return read_lz77_payload(input, offset, bit_order, codec);
+    }
+
+    let (model, model_size) = codec.deserialize_model(input, offset)?;
+
+    decode_to_end(codec, &model, input, offset + model_size, bit_order)
+}
+
+
+/// Write the number of symbols encoded in `bitcode`, followed by the unprefixed bitcode, which spans to the end of the data.
+/// The count is needed because the only value of a single-leaf tree is encoded with zero bits.
+pub(crate) fn write_counted_bitcode(buf: &mut Vec<u8>, count: usize, bitcode: &BitVec, bit_order: BitOrder) {
+
+    buf.reserve(varint_size(count) + 1 + bitcode.least_len_bytes());
+
+    write_varint(buf, count);
+
+    bitcode.serialize_with_order(buf, bit_order);
+}
+
+
+/// Read the symbol count written by `write_counted_bitcode` at byte `offset` of `input`,
+/// and check that the rest of the input can hold the padding byte and `min_code_bits` bits per symbol.
+/// Return the count and the number of bytes read.
+pub(crate) fn read_symbol_count(input: &[u8], offset: usize, min_code_bits: usize) -> Result<(usize, usize), DecompressionError> {
+
+    let got = input.len().saturating_sub(offset);
+
+    let (count, count_size) = read_varint(input, offset)
+        .map_err(|e| match e {
+            // At least the padding byte follows the count
+            DecompressionError::TruncatedSection { expected, .. } => DecompressionError::TruncatedPayload { expected_min: expected + 1, got },
+            e => e
+        })?;
+
+    let expected_min = count_size + 1 + count.saturating_mul(min_code_bits).div_ceil(8);
+    if got < expected_min {
+        return Err(DecompressionError::TruncatedPayload { expected_min, got });
+    }
+
+    Ok((count, count_size))
+}
+
+
+/// Decode the symbol count and the unprefixed bitcode written by `write_counted_bitcode` from byte `offset` to the end of `input`
+pub(crate) fn decode_to_end<V, C>(codec: &C, model: &C::Model<V>, input: &[u8], offset: usize, bit_order: BitOrder) -> Result<Box<[V]>, DecompressionError>
+where
+    V: Symbol,
+    C: EntropyCodec
+{
+
+    let (count, count_size) = read_symbol_count(input, offset, codec.min_code_bits(model))?;
+
+    let offset = offset + count_size;
+
+    let bitcode = BitVec::deserialize_with_order(&input[offset..], bit_order)
+        .map_err(|_| DecompressionError::InvalidBitCode { offset, remaining: input.len() - offset })?;
+
+    let symbols = codec.decode_exact(&bitcode.as_bit_view(), model, count)
+        .map_err(|source| DecompressionError::BitCodeDecodingError { offset, source })?;
+
+    if symbols.len() != count {
+        return Err(DecompressionError::SymbolCountMismatch { offset, expected: count, found: symbols.len() });
+    }
+
+    Ok(symbols.into_boxed_slice())
+}
+
+
+/// Write the LZ77 token count followed by the streams of token kinds, literals, match length classes, and match distance classes,
+/// and finally the extra bits of the match lengths and distances.
+/// Lengths and distances are split in a bit length class, coded with a tree, and raw extra bits, like DEFLATE does,
+/// because coding every distinct distance as a separate leaf would make the trees huge.
+fn write_lz77_payload<U, C>(tokens: &[Token<U>], buf: &mut Vec<u8>, bit_order: BitOrder, codec: &C)
+where
+    U: Symbol,
+    C: EntropyCodec
+{
+
+    write_varint(buf, tokens.len());
+
+    write_stream(buf, tokens.iter().map(|token| matches!(token, Token::Match { .. })), bit_order, codec);
+
+    write_stream(buf, tokens.iter().filter_map(|token| match token {
+        Token::Literal (value) => Some(value.clone()),
+        Token::Match { .. } => None
+    }), bit_order, codec);
+
+    let mut length_classes = Vec::new();
+    let mut distance_classes = Vec::new();
+    let mut extra_bits = BitVec::new();
+
+    for token in tokens {
+        if let Token::Match { length, distance } = token {
+
+            let (class, extra) = lz77::split_magnitude(lz77::length_to_magnitude(*length));
+            length_classes.push(class);
+            append_extra_bits(&mut extra_bits, class, extra);
+
+            let (class, extra) = lz77::split_magnitude(*distance);
+            distance_classes.push(class);
+            append_extra_bits(&mut extra_bits, class, extra);
+        }
+    }
+
+    write_stream(buf, length_classes.into_iter(), bit_order, codec);
+    write_stream(buf, distance_classes.into_iter(), bit_order, codec);
+
+    write_bitcode_section(buf, &extra_bits, bit_order);
+}
+
+
+/// Append the `class - 1` extra bits of a magnitude, most significant bit first
+fn append_extra_bits(bits: &mut BitVec, class: u8, extra: u32) {
+    for i in (0..class.saturating_sub(1)).rev() {
+        bits.append_bit(extra & (1 << i) != 0);
+    }
+}
+
+
+/// Write the model of `symbols` followed by their length-prefixed bitcode.
+/// Nothing is written for an empty stream.
+fn write_stream<V, C>(buf: &mut Vec<u8>, symbols: impl Iterator<Item = V> + Clone, bit_order: BitOrder, codec: &C)
+where
+    V: Symbol,
+    C: EntropyCodec
+{
+
+    let Some(model) = codec.build_model(symbols.clone()) else {
+        return;
+    };
+
+    codec.serialize_model(&model, buf);
+
+    write_bitcode_section(buf, &codec.encode(symbols, &model), bit_order);
+}
+
+
+/// Write `bitcode` prefixed by its serialized length
+pub(crate) fn write_bitcode_section(buf: &mut Vec<u8>, bitcode: &BitVec, bit_order: BitOrder) {
+
+    write_varint(buf, 1 + bitcode.least_len_bytes());
+
+    bitcode.serialize_with_order(buf, bit_order);
+}
+
+
+/// Read a bitcode written by `write_bitcode_section` at byte `offset` of `input`.
+/// Return the bitcode and the number of bytes read.
+pub(crate) fn read_bitcode_section(input: &[u8], offset: usize, bit_order: BitOrder) -> Result<(BitVec, usize), DecompressionError> {
+
+    let (length, length_size) = read_varint(input, offset)?;
+
+    let start = offset + length_size;
+    let section = input.get(start..start.saturating_add(length))
+        .ok_or(DecompressionError::TruncatedSection { offset: start, expected: length, remaining: input.len() - start })?;
+
+    let bitcode = BitVec::deserialize_with_order(section, bit_order)
+        .map_err(|_| DecompressionError::InvalidBitCode { offset: start, remaining: section.len() })?;
+
+    Ok((bitcode, length_size + length))
+}
+
+
+/// Read a stream of `count` symbols written by `write_stream` at byte `offset` of `input`.
+/// Return the symbols and the number of bytes read.
+fn read_stream<V, C>(input: &[u8], offset: usize, count: usize, bit_order: BitOrder, codec: &C) -> Result<(Vec<V>, usize), DecompressionError>
+where
+    V: Symbol,
+    C: EntropyCodec
+{
+
+    if count == 0 {
+        return Ok((Vec::new(), 0));
+    }
+
+    let (model, model_size) = codec.deserialize_model(input, offset)?;
+
+    let (symbols, bitcode_size) = read_bitcode_with(codec, &model, input, offset + model_size, count, bit_order)?;
+
+    Ok((symbols, model_size + bitcode_size))
+}
+
+
+/// Read a bitcode section at byte `offset` of `input` and decode `count` symbols with `model`.
+/// Return the symbols and the number of bytes read.
+pub(crate) fn read_bitcode_with<V, C>(codec: &C, model: &C::Model<V>, input: &[u8], bitcode_offset: usize, count: usize, bit_order: BitOrder) -> Result<(Vec<V>, usize), DecompressionError>
+where
+    V: Symbol,
+    C: EntropyCodec
+{
+
+    let (bitcode, bitcode_size) = read_bitcode_section(input, bitcode_offset, bit_order)?;
+
+    let symbols = codec.decode_exact(&bitcode.as_bit_view(), model, count)
+        .map_err(|source| DecompressionError::BitCodeDecodingError { offset: bitcode_offset, source })?;
+
+    if symbols.len() != count {
+        return Err(DecompressionError::SymbolCountMismatch { offset: bitcode_offset, expected: count, found: symbols.len() });
+    }
+
+    Ok((symbols, bitcode_size))
+}
+
+
+/// Read the LZ77 payload written by `write_lz77_payload` at byte `offset` of `input` and expand the tokens
+fn read_lz77_payload<U, C>(input: &[u8], offset: usize, bit_order: BitOrder, codec: &C) -> Result<Box<[U]>, DecompressionError>
+where
+    U: Symbol,
+    C: EntropyCodec
+{
+
+    let (token_count, token_count_size) = read_varint(input, offset)?;
+
+    let mut read = offset + token_count_size;
+
+    let (kinds, size) = read_stream::<bool, _>(input, read, token_count, bit_order, codec)?;
+    read += size;
+
+    let match_count = kinds.iter().filter(|is_match| **is_match).count();
+
+    let (literals, size) = read_stream::<U, _>(input, read, token_count - match_count, bit_order, codec)?;
+    read += size;
+
+    let (length_classes, size) = read_stream::<u8, _>(input, read, match_count, bit_order, codec)?;
+    read += size;
+
+    let (distance_classes, size) = read_stream::<u8, _>(input, read, match_count, bit_order, codec)?;
+    read += size;
+
+    let extra_offset = read;
+    let (extra_bits, _) = read_bitcode_section(input, extra_offset, bit_order)?;
+    let mut extra_bits = extra_bits.iter_bits();
+
+    let mut read_magnitude = |class: u8| -> Result<u32, DecompressionError> {
+        let mut extra = 0;
+        for _ in 1..class {
+            let bit = extra_bits.next()
+                .ok_or(DecompressionError::InvalidBitCode { offset: extra_offset, remaining: input.len() - extra_offset })?;
+            extra = (extra << 1) | bit as u32;
+        }
+        Ok(lz77::join_magnitude(class, extra))
+    };
+
+    let mut tokens = Vec::with_capacity(token_count);
+    let mut literals = literals.into_iter();
+    let mut classes = length_classes.into_iter().zip(distance_classes);
+
+    for (i, is_match) in kinds.into_iter().enumerate() {
+
+        // The stream lengths were checked against the token kinds
+        if is_match {
+
+            let (length_class, distance_class) = classes.next().unwrap();
+
+            let length = lz77::magnitude_to_length(read_magnitude(length_class)?)
+                .ok_or(DecompressionError::InvalidMatchLength { token: i })?;
+            let distance = read_magnitude(distance_class)?;
+
+            tokens.push(Token::Match { length, distance });
+
+        } else {
+            tokens.push(Token::Literal(literals.next().unwrap()));
+        }
+    }
+
+    let data = lz77::decode(tokens).map_err(DecompressionError::InvalidMatch)?;
+
+    Ok(data.into_boxed_slice())
+}
+
+
+/// Reads header fields from a byte buffer, keeping track of the current offset
+struct FieldReader<'a> {
+
+    input: &'a [u8],
+    offset: usize
+
+}
+
+impl FieldReader<'_> {
+
+    fn read_u8(&mut self, field: &'static str) -> Result<u8, HeaderDeserializationError> {
+
+        let byte = *self.input.get(self.offset)
+            .ok_or(HeaderDeserializationError::MissingField { offset: self.offset, field })?;
+
+        self.offset += 1;
+
+        Ok(byte)
+    }
+
+
+    fn read_u32(&mut self, field: &'static str) -> Result<u32, HeaderDeserializationError> {
+
+        let value = read_u32(self.input, self.offset)
+            .ok_or(HeaderDeserializationError::MissingField { offset: self.offset, field })?;
+
+        self.offset += 4;
+
+        Ok(value)
+    }
+
+
+    fn read_bytes(&mut self, len: usize, field: &'static str) -> Result<&[u8], HeaderDeserializationError> {
+
+        let bytes = self.input.get(self.offset..self.offset + len)
+            .ok_or(HeaderDeserializationError::MissingField { offset: self.offset, field })?;
+
+        self.offset += len;
+
+        Ok(bytes)
+    }
+
+
+    fn read_array<const N: usize>(&mut self, field: &'static str) -> Result<[u8; N], HeaderDeserializationError> {
+        Ok(self.read_bytes(N, field)?.try_into().unwrap())
+    }
+
+}
+
+
+/// Error returned when the container header is malformed.
+/// Offsets are byte offsets from the start of the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderDeserializationError {
+
+    /// The input ended before the header `field`
+    MissingField { offset: usize, field: &'static str },
+    /// The data was produced by an unsupported version of the container format
+    UnsupportedVersion { found: u8, expected: u8 },
+    /// The header has flags set that this version of the library doesn't know about and must understand,
+    /// usually because the data was written by a later version
+    UnknownFlags { offset: usize, flags: u8 },
+    /// The chunk size is zero or the chunk remainder is not smaller than the chunk size
+    InvalidChunking { offset: usize, chunk_size: u8, chunk_remainder: u8 },
+    /// The checkpoint flag is combined with transforms that prevent seeking,
+    /// or the least significant bit first order with a payload that doesn't support it
+    IncompatibleFlags { offset: usize, flags: u8 },
+    /// The recorded file name is not valid UTF-8
+    InvalidFileName { offset: usize },
+    /// The settings record at `offset` is empty, or a recorded setting is truncated or zero
+    InvalidSettings { offset: usize },
+
+}
+
+impl fmt::Display for HeaderDeserializationError {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeaderDeserializationError::MissingField { offset, field }
+                => write!(f, "missing header field `{field}` at byte {offset}"),
+            HeaderDeserializationError::UnsupportedVersion { found, expected }
+                => write!(f, "unsupported format version {found}, expected {expected}"),
+            HeaderDeserializationError::UnknownFlags { offset, flags }
+                => write!(f, "unknown header flags {flags:#010b} at byte {offset}"),
+            HeaderDeserializationError::InvalidChunking { offset, chunk_size, chunk_remainder }
+                => write!(f, "invalid chunk size {chunk_size} with remainder {chunk_remainder} at byte {offset}"),
+            HeaderDeserializationError::IncompatibleFlags { offset, flags }
+                => write!(f, "incompatible header flags {flags:#010b} at byte {offset}"),
+            HeaderDeserializationError::InvalidFileName { offset }
+                => write!(f, "the file name at byte {offset} is not valid UTF-8"),
+            HeaderDeserializationError::InvalidSettings { offset }
+                => write!(f, "invalid encoder settings at byte {offset}"),
+        }
+    }
+}
+
+impl Error for HeaderDeserializationError {}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+
+    #[test]
+    fn check_serde() {
+
+        let lz77 = Header { lz77: true, ..Header::chunked(2, 1) };
+        let mtf = Header { mtf: true, ..Header::new() };
+        let blocks = Header { blocks: true, ..Header::new() };
+        let transformed = Header { blocks: true, transformed_blocks: true, ..Header::new() };
+        let dictionary = Header { dictionary: Some(0xdead_beef), ..Header::chunked(2, 0) };
+        let remap = Header { remap: true, compact_tree: true, ..Header::new() };
+        let checkpoints = Header { checkpoints: true, ..Header::new() };
+        let lsb_first = Header { bit_order: BitOrder::LsbFirst, lz77: true, ..Header::chunked(2, 1) };
+        let metadata = Header {
+            dictionary: Some(7),
+            metadata: FileMetadata { name: Some("données.txt".to_string()), modified: Some(1 << 40), mode: Some(0o100644) },
+            ..Header::new()
+        };
+        let name_only = Header { metadata: FileMetadata { name: Some(String::new()), ..FileMetadata::new() }, ..Header::new() };
+        let typed = Header { symbol_type: Some(SymbolType::Char), remap: true, ..Header::chunked(2, 1) };
+
+        for header in [Header::new(), Header::chunked(3, 2), Header::chunked(255, 0), lz77, mtf, blocks, transformed, dictionary, remap, checkpoints, lsb_first, metadata, name_only, typed] {
+
+            let mut buf = Vec::new();
+            header.serialize(&mut buf);
+
+            assert_eq!(buf.len(), header.serialized_size());
+            assert_eq!(Header::deserialize(&buf), Ok((header, buf.len())));
+        }
+    }
+
+
+    #[test]
+    fn check_invalid_headers() {
+
+        assert_eq!(Header::deserialize(&[]), Err(HeaderDeserializationError::MissingField { offset: 0, field: "format version" }));
+        assert_eq!(Header::deserialize(&[FORMAT_VERSION + 1, 0]), Err(HeaderDeserializationError::UnsupportedVersion { found: FORMAT_VERSION + 1, expected: FORMAT_VERSION }));
+        assert_eq!(Header::deserialize(&[FORMAT_VERSION, flags::CHECKPOINTS | flags::LZ77]), Err(HeaderDeserializationError::IncompatibleFlags { offset: 1, flags: flags::CHECKPOINTS | flags::LZ77 }));
+        assert_eq!(Header::deserialize(&[FORMAT_VERSION, 0]), Err(HeaderDeserializationError::MissingField { offset: 2, field: "extended flags" }));
+        assert_eq!(Header::deserialize(&[FORMAT_VERSION, 0, flags::extended::SETTINGS]), Err(HeaderDeserializationError::MissingField { offset: 3, field: "settings length" }));
+        assert_eq!(Header::deserialize(&[FORMAT_VERSION, 0, flags::extended::SETTINGS, 2, 0]), Err(HeaderDeserializationError::MissingField { offset: 4, field: "settings" }));
+        assert_eq!(Header::deserialize(&[FORMAT_VERSION, 0, flags::extended::SETTINGS, 0]), Err(HeaderDeserializationError::InvalidSettings { offset: 4 }));
+        assert_eq!(Header::deserialize(&[FORMAT_VERSION, 0, flags::extended::SETTINGS, 2, flags::settings::MAX_BLOCK_SYMBOLS, 0]), Err(HeaderDeserializationError::InvalidSettings { offset: 4 }));
+        assert_eq!(Header::deserialize(&[FORMAT_VERSION, 0, flags::extended::SETTINGS, 2, flags::settings::MAX_BLOCK_BYTES, 0x80]), Err(HeaderDeserializationError::InvalidSettings { offset: 4 }));
+        assert_eq!(Header::deserialize(&[FORMAT_VERSION, 0, flags::extended::OPTIONAL]), Err(HeaderDeserializationError::MissingField { offset: 3, field: "optional flags" }));
+        assert_eq!(Header::deserialize(&[FORMAT_VERSION, flags::COMPACT_TREE, flags::extended::CANONICAL_TREE]), Err(HeaderDeserializationError::IncompatibleFlags { offset: 2, flags: flags::extended::CANONICAL_TREE }));
+        assert_eq!(Header::deserialize(&[FORMAT_VERSION, 0, flags::extended::TRANSFORMED]), Err(HeaderDeserializationError::IncompatibleFlags { offset: 2, flags: flags::extended::TRANSFORMED }));
+        assert_eq!(Header::deserialize(&[FORMAT_VERSION, flags::BLOCKS, flags::extended::LSB_FIRST]), Err(HeaderDeserializationError::IncompatibleFlags { offset: 2, flags: flags::extended::LSB_FIRST }));
+        assert_eq!(Header::deserialize(&[FORMAT_VERSION, flags::CHUNKED, 0, 2]), Err(HeaderDeserializationError::MissingField { offset: 4, field: "chunk remainder" }));
+        assert_eq!(Header::deserialize(&[FORMAT_VERSION, flags::CHUNKED, 0, 2, 2]), Err(HeaderDeserializationError::InvalidChunking { offset: 3, chunk_size: 2, chunk_remainder: 2 }));
+        assert_eq!(Header::deserialize(&[FORMAT_VERSION, flags::DICTIONARY, 0, 1, 2, 3]), Err(HeaderDeserializationError::MissingField { offset: 3, field: "dictionary identifier" }));
+        assert_eq!(Header::deserialize(&[FORMAT_VERSION, 0, flags::extended::MODIFIED, 1, 2, 3, 4]), Err(HeaderDeserializationError::MissingField { offset: 3, field: "modification time" }));
+        assert_eq!(Header::deserialize(&[FORMAT_VERSION, 0, flags::extended::NAME, 3, 0, b'a', b'b']), Err(HeaderDeserializationError::MissingField { offset: 5, field: "file name" }));
+        assert_eq!(Header::deserialize(&[FORMAT_VERSION, 0, flags::extended::NAME, 2, 0, b'a', 0xff]), Err(HeaderDeserializationError::InvalidFileName { offset: 5 }));
+    }
+
+
+    #[test]
+    fn check_optional_flags() {
+
+        // Unknown optional flags and symbol types are skipped, and the fields after them are read as usual
+        let input = [FORMAT_VERSION, 0, flags::extended::OPTIONAL | flags::extended::MODE, 0xff, 0xa4, 0x81, 0, 0];
+        let header = Header { metadata: FileMetadata { mode: Some(0o100_644), ..FileMetadata::new() }, ..Header::new() };
+
+        assert_eq!(Header::deserialize(&input), Ok((header, input.len())));
+    }
+
+
+    #[test]
+    fn check_settings() {
+
+        let settings = EncoderSettings { auto_grouped: true, max_block_symbols: NonZeroUsize::new(300), max_block_bytes: NonZeroUsize::new(1), checksum: true, block_checksums: true, normalized_newlines: true };
+        let header = Header { settings, ..Header::chunked(3, 1) };
+
+        let mut buf = Vec::new();
+        header.serialize(&mut buf);
+        assert_eq!(buf.len(), header.serialized_size());
+        assert_eq!(Header::deserialize(&buf), Ok((header, buf.len())));
+
+        // Empty settings aren't recorded
+        let mut buf = Vec::new();
+        Header::new().serialize(&mut buf);
+        assert_eq!(buf[2] & flags::extended::SETTINGS, 0);
+
+        // Unknown settings and the bytes after the known ones are skipped
+        let input = [FORMAT_VERSION, 0, flags::extended::SETTINGS, 4, flags::settings::MAX_BLOCK_BYTES | 1 << 7, 5, 0xff, 0xff];
+        let header = Header { settings: EncoderSettings { max_block_bytes: NonZeroUsize::new(5), ..EncoderSettings::new() }, ..Header::new() };
+        assert_eq!(Header::deserialize(&input), Ok((header, input.len())));
+    }
+
+
+    #[test]
+    fn check_varint() {
+
+        for value in [0, 1, 127, 128, 300, 16_383, 16_384, u32::MAX as usize, usize::MAX] {
+
+            let mut buf = vec![0xff];
+            write_varint(&mut buf, value);
+
+            assert_eq!(buf.len() - 1, varint_size(value));
+            assert_eq!(read_varint(&buf, 1), Ok((value, buf.len() - 1)));
+        }
+
+        let mut buf = Vec::new();
+        write_varint(&mut buf, 300);
+        assert_eq!(buf, [0xac, 0x02]);
+
+        assert_eq!(read_varint(&[0x80, 0x80], 0), Err(DecompressionError::TruncatedSection { offset: 0, expected: 3, remaining: 2 }));
+        assert_eq!(read_varint(&[0xff; 11], 1), Err(DecompressionError::InvalidVarint { offset: 1 }));
+    }
+
+
+    #[test]
+    fn check_compact_alphabet() {
+
+        let alphabets: [&[char]; 4] = [&[], &['a'], &['a', 'b', 'z'], &['a', 'é', '€', '🦀']];
+        let widths = [0, 0, 1, 3];
+
+        for (alphabet, width) in alphabets.into_iter().zip(widths) {
+
+            let mut buf = vec![0xff];
+            write_compact_alphabet(&mut buf, alphabet);
+
+            assert_eq!(varying_width(alphabet), width);
+            assert_eq!(buf.len(), 1 + compact_alphabet_size(alphabet));
+            assert_eq!(read_compact_alphabet::<char>(&buf, 1), Ok((alphabet.to_vec(), buf.len() - 1)));
+        }
+
+        // The shared high bytes make the code point invalid
+        assert_eq!(read_compact_alphabet::<char>(&[1, 1, 0, 0xd8, 0, 0], 0), Err(DecompressionError::InvalidSymbol { offset: 5 }));
+        assert_eq!(read_compact_alphabet::<char>(&[1, 5], 0), Err(DecompressionError::InvalidSymbol { offset: 1 }));
+
+        // Without varying bytes there is a single distinct symbol, and with one there are 256
+        assert_eq!(read_compact_alphabet::<u8>(&[2, 0, 7], 0), Err(DecompressionError::AlphabetTooLarge { offset: 0, len: 2, max: 1 }));
+        assert_eq!(read_compact_alphabet::<char>(&[0x81, 0x02, 1, 0, 0, 0], 0), Err(DecompressionError::AlphabetTooLarge { offset: 0, len: 257, max: 256 }));
+    }
+
+}
@@ -0,0 +1,201 @@
+//! Synthetic corpora for tests and benchmarks, available with the `corpus` feature.
+//!
+//! Natural text only exercises a narrow range of symbol distributions. The generators below produce data with a chosen
+//! alphabet size and skew, from a constant symbol to a uniform spread, including the geometric distributions that build
+//! the deepest trees. The output only depends on the options, so a failing case can be reproduced from its seed.
+//! Measure the entropy of the output with `FrequencyTable::entropy`.
+
+use std::num::NonZeroUsize;
+
+
+/// How often each symbol of the alphabet occurs, the symbol `0` being the most frequent
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Distribution {
+
+    /// Only the symbol `0`, with an entropy of 0 bits
+    Constant,
+    /// Every symbol with the same probability, with the maximum entropy of `log2(alphabet_size)` bits
+    Uniform,
+    /// Symbol `i` with a probability proportional to `1 / (i + 1)^exponent`, like the words of natural text.
+    /// Larger exponents lower the entropy.
+    Zipf { exponent: f64 },
+    /// Symbol `i` with a probability proportional to `ratio^i`, for `ratio` in `(0, 1]`.
+    /// Ratios around 0.5 give each symbol a code one bit longer than the previous one, which makes the tree as deep as the
+    /// alphabet is large.
+    Geometric { ratio: f64 },
+
+}
+
+
+/// Parameters of a synthetic corpus
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CorpusOptions {
+
+    /// Number of symbols to generate
+    pub len: usize,
+    /// Number of distinct symbols the corpus can contain
+    pub alphabet_size: NonZeroUsize,
+    pub distribution: Distribution,
+    /// Seed of the pseudo-random generator
+    pub seed: u64,
+
+}
+
+impl CorpusOptions {
+
+    pub const fn new() -> Self {
+        Self {
+            len: 1 << 16,
+            alphabet_size: NonZeroUsize::new(256).unwrap(),
+            distribution: Distribution::Zipf { exponent: 1.0 },
+            seed: 0
+        }
+    }
+
+}
+
+impl Default for CorpusOptions {
+
+    fn default() -> Self {
+        Self::new()
+    }
+
+}
+
+
+/// SplitMix64, a small pseudo-random generator that is good enough for test data and doesn't need a dependency
+struct SplitMix64 (u64);
+
+impl SplitMix64 {
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+
+    /// Uniform number in `[0, 1)`
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+}
+
+
+/// Cumulative weights of the symbols of the alphabet under `distribution`
+fn cumulative_weights(distribution: Distribution, alphabet_size: usize) -> Vec<f64> {
+
+    let weight = |i: usize| match distribution {
+        Distribution::Constant => if i == 0 { 1.0 } else { 0.0 },
+        Distribution::Uniform => 1.0,
+        Distribution::Zipf { exponent } => 1.0 / ((i + 1) as f64).powf(exponent),
+        Distribution::Geometric { ratio } => ratio.powi(i as i32),
+    };
+
+    (0..alphabet_size)
+        .scan(0.0, |total, i| {
+            *total += weight(i);
+            Some(*total)
+        })
+        .collect()
+}
+
+
+/// Generate the indices in the alphabet of the symbols of a corpus, each smaller than `options.alphabet_size`.
+///
+/// Panics if a Zipf exponent is negative or not finite, or if a geometric ratio is not in `(0, 1]`.
+pub fn generate(options: &CorpusOptions) -> Vec<u32> {
+
+    match options.distribution {
+        Distribution::Zipf { exponent } => assert!(exponent.is_finite() && exponent >= 0.0, "invalid Zipf exponent {exponent}"),
+        Distribution::Geometric { ratio } => assert!(ratio > 0.0 && ratio <= 1.0, "invalid geometric ratio {ratio}"),
+        Distribution::Constant | Distribution::Uniform => {}
+    }
+
+    let weights = cumulative_weights(options.distribution, options.alphabet_size.get());
+    let total = weights[weights.len() - 1];
+
+    let mut rng = SplitMix64(options.seed);
+
+    (0..options.len)
+        .map(|_| {
+            let target = rng.next_f64() * total;
+            // Rounding can leave `target` past the last weight, which is then the symbol drawn
+            weights.partition_point(|&weight| weight <= target).min(weights.len() - 1) as u32
+        })
+        .collect()
+}
+
+
+/// Generate a corpus of bytes, the most frequent being `0`.
+///
+/// Panics if the alphabet has more than 256 symbols.
+pub fn generate_bytes(options: &CorpusOptions) -> Vec<u8> {
+
+    assert!(options.alphabet_size.get() <= 256, "an alphabet of {} symbols doesn't fit in a byte", options.alphabet_size);
+
+    generate(options).into_iter().map(|symbol| symbol as u8).collect()
+}
+
+
+/// Generate a corpus whose symbols are taken from `alphabet`, the first being the most frequent.
+/// `options.alphabet_size` is ignored.
+///
+/// Panics if `alphabet` is empty.
+pub fn generate_from<U>(options: &CorpusOptions, alphabet: &[U]) -> Vec<U>
+where
+    U: Clone
+{
+    let alphabet_size = NonZeroUsize::new(alphabet.len()).expect("the alphabet must not be empty");
+
+    generate(&CorpusOptions { alphabet_size, ..*options })
+        .into_iter()
+        .map(|symbol| alphabet[symbol as usize].clone())
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::FrequencyTable;
+
+
+    fn entropy(data: &[u32]) -> f64 {
+        FrequencyTable::from_symbols(data.iter().copied()).entropy()
+    }
+
+
+    #[test]
+    fn check_distributions() {
+
+        let options = CorpusOptions { len: 1 << 14, alphabet_size: NonZeroUsize::new(16).unwrap(), ..CorpusOptions::new() };
+
+        let constant = generate(&CorpusOptions { distribution: Distribution::Constant, ..options });
+        assert!(constant.iter().all(|&symbol| symbol == 0));
+
+        let uniform = generate(&CorpusOptions { distribution: Distribution::Uniform, ..options });
+        assert!(entropy(&uniform) > 3.95);
+
+        let zipf = generate(&CorpusOptions { distribution: Distribution::Zipf { exponent: 1.0 }, ..options });
+        let steep = generate(&CorpusOptions { distribution: Distribution::Zipf { exponent: 3.0 }, ..options });
+        assert!(entropy(&steep) < entropy(&zipf) && entropy(&zipf) < entropy(&uniform));
+
+        let geometric = generate(&CorpusOptions { distribution: Distribution::Geometric { ratio: 0.5 }, ..options });
+        assert!((entropy(&geometric) - 2.0).abs() < 0.05);
+
+        assert!([&uniform, &zipf, &steep, &geometric].iter().all(|data| data.len() == options.len && data.iter().all(|&symbol| symbol < 16)));
+
+        // The output only depends on the options
+        assert_eq!(generate(&options), generate(&options));
+        assert_ne!(generate(&options), generate(&CorpusOptions { seed: 1, ..options }));
+
+        assert_eq!(generate_from(&CorpusOptions { distribution: Distribution::Constant, len: 3, ..options }, &['x', 'y']), ['x'; 3]);
+        assert!(generate_bytes(&options).iter().all(|&byte| byte < 16));
+    }
+
+}
@@ -0,0 +1,239 @@
+//! Failure injection: every decompressor is fed systematically corrupted copies of valid inputs.
+//! Decompression must either fail with an error or return symbols, and must never panic.
+//!
+//! Without a checksum, a corrupted bitcode may still decode to different symbols of the same alphabet.
+
+use std::num::NonZeroUsize;
+
+use crate::bits::BitOrder;
+use crate::container::write_varint;
+use crate::debug::{dump_container, dump_container_as};
+use crate::seek::{compress_seekable, DecompressCursor};
+use crate::stream::{Decoder, Encoder, EncoderOptions};
+use crate::{compress_chunked, compress_with_options, decompress, decompress_chunked, decompress_partial, deflate, CompressionOptions, DecodingError, DecompressionError, Dictionary, DictionaryRegistry, Header};
+
+
+const TEXT: &str = "the quick brown fox jumps over the lazy dog, the quick brown fox";
+
+
+/// Every corruption of `valid`: each single bit flipped, each byte replaced with 0x00 and 0xff, and each truncation
+fn corruptions(valid: &[u8]) -> impl Iterator<Item = Vec<u8>> + '_ {
+
+    let flips = (0..valid.len() * 8).map(|bit| {
+        let mut corrupted = valid.to_vec();
+        corrupted[bit / 8] ^= 0x80 >> (bit % 8);
+        corrupted
+    });
+
+    let replacements = (0..valid.len()).flat_map(|i| [0x00, 0xff].map(|byte| {
+        let mut corrupted = valid.to_vec();
+        corrupted[i] = byte;
+        corrupted
+    }));
+
+    let truncations = (0..valid.len()).map(|len| valid[..len].to_vec());
+
+    flips.chain(replacements).chain(truncations)
+}
+
+
+/// Decompress every corruption of `valid`, which must decompress to `expected` when it's intact
+fn check_corruptions<T, E>(valid: &[u8], expected: &T, decompress: impl Fn(&[u8]) -> Result<T, E>)
+where
+    T: PartialEq + std::fmt::Debug,
+    E: std::fmt::Debug
+{
+
+    assert_eq!(decompress(valid).unwrap(), *expected);
+
+    for corrupted in corruptions(valid) {
+        // Either outcome is fine, as long as the decompressor returns
+        let _ = decompress(&corrupted);
+    }
+}
+
+
+#[test]
+#[cfg_attr(miri, ignore = "decompresses thousands of inputs")]
+fn check_corrupted_payloads() {
+
+    let chars: Box<[char]> = TEXT.chars().collect();
+    let bytes: Box<[u8]> = TEXT.bytes().collect();
+
+    let all_options = [
+        CompressionOptions::new(),
+        CompressionOptions { lz77: true, ..CompressionOptions::new() },
+        CompressionOptions { mtf: true, ..CompressionOptions::new() },
+        CompressionOptions { lz77: true, mtf: true, compact_tree: true, canonical_tree: false, bit_order: BitOrder::LsbFirst, max_unique_symbols: None, normalize_newlines: false },
+        CompressionOptions { canonical_tree: true, ..CompressionOptions::new() },
+    ];
+
+    for options in &all_options {
+        check_corruptions(&compress_with_options(TEXT.chars(), options), &chars, decompress::<char>);
+        check_corruptions(&compress_with_options(TEXT.bytes(), options), &bytes, decompress::<u8>);
+    }
+
+    // Single-leaf trees encode their symbols with zero bits
+    for options in &all_options[..2] {
+        check_corruptions(&compress_with_options("aaaa".chars(), options), &['a'; 4][..].into(), decompress::<char>);
+    }
+
+    check_corruptions(&compress_chunked::<char, 2>(TEXT.chars()), &chars, decompress_chunked::<char, 2>);
+}
+
+
+#[test]
+fn check_huge_symbol_counts() {
+
+    // A single literal, so every stream has a single-leaf tree and the token count isn't backed by any bit
+    let valid = compress_with_options("a".chars(), &CompressionOptions { lz77: true, ..CompressionOptions::new() });
+
+    let (_, header_size) = Header::deserialize(&valid).unwrap();
+
+    let mut corrupted = valid[..header_size].to_vec();
+    write_varint(&mut corrupted, usize::MAX >> 2);
+    corrupted.extend_from_slice(&valid[header_size + 1..]);
+
+    assert!(matches!(
+        decompress::<char>(&corrupted),
+        Err(DecompressionError::BitCodeDecodingError { source: DecodingError::TooManySymbols { .. }, .. })
+    ));
+    assert!(dump_container_as::<char>(&corrupted).contains("error: "));
+
+    // A single-leaf tree of the byte 0 followed by a symbol count of 2^55 - 1
+    let payload = [4, 0, 128, 1, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x3f, 4, 0];
+    let expected = DecompressionError::BitCodeDecodingError { offset: 14, source: DecodingError::TooManySymbols { count: (1 << 55) - 1 } };
+    assert_eq!(decompress::<u8>(&payload), Err(expected));
+    let failure = decompress_partial::<u8>(&payload).unwrap_err();
+    assert_eq!(failure.error, expected);
+    assert!(failure.decoded_so_far.is_empty());
+
+    // A stream block of 2^62 - 1 symbols with a single-leaf tree of 'a', and no bitcode
+    let stream = [4, 8, 128, 1, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x3f, 4, 0, 97, 1, 0, 1];
+    for result in [decompress::<u8>(&stream).map(Vec::from), Decoder::<u8>::new().feed(&stream)] {
+        assert!(matches!(
+            result,
+            Err(DecompressionError::BitCodeDecodingError { source: DecodingError::TooManySymbols { count: 0x3fff_ffff_ffff_ffff }, .. })
+        ));
+    }
+
+    // A single-leaf tree of 'a' followed by a symbol count of 2^62 - 1
+    let dump = dump_container(&[4, 0, 128, 1, 0, 0x61, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x3f, 0, 0]);
+    assert!(dump.contains("4611686018427387903 × 97"));
+    assert!(dump.contains("error: could not decode the bitcode section at byte 15: 4611686018427387903 symbols can't be allocated"));
+}
+
+
+#[test]
+#[cfg_attr(miri, ignore = "decompresses thousands of inputs")]
+fn check_corrupted_streams() {
+
+    let mut encoder = Encoder::new(Vec::new());
+    for word in TEXT.split_inclusive(' ') {
+        encoder.write_symbols(word.chars()).unwrap();
+        encoder.flush_block().unwrap();
+    }
+    let stream = encoder.finish().unwrap();
+
+    check_corruptions(&stream, &TEXT.chars().collect::<Vec<_>>(), |input| Decoder::<char>::new().feed(input));
+}
+
+
+#[test]
+#[cfg_attr(miri, ignore = "decompresses thousands of inputs")]
+fn check_corrupted_dictionary_payloads() {
+
+    let dictionary = Dictionary::train([TEXT.chars()]).unwrap();
+
+    check_corruptions(&dictionary.compress("the lazy fox".chars()), &"the lazy fox".chars().collect(), |input| dictionary.decompress(input));
+
+    let mut serialized = Vec::new();
+    dictionary.serialize(&mut serialized);
+    for corrupted in corruptions(&serialized) {
+        let _ = Dictionary::<char>::deserialize(&corrupted);
+    }
+
+    let mut registry = DictionaryRegistry::new();
+    registry.insert(dictionary);
+    registry.insert(Dictionary::train(["hello world".chars()]).unwrap());
+
+    let mut serialized = Vec::new();
+    registry.serialize(&mut serialized);
+    for corrupted in corruptions(&serialized) {
+        let _ = DictionaryRegistry::<char>::deserialize(&corrupted);
+    }
+}
+
+
+#[test]
+#[cfg_attr(miri, ignore = "decompresses thousands of inputs")]
+fn check_corrupted_deflate_streams() {
+
+    check_corruptions(&deflate::compress_raw(TEXT.as_bytes()), &TEXT.as_bytes().into(), deflate::decompress_raw);
+}
+
+
+#[test]
+#[cfg_attr(miri, ignore = "decompresses thousands of inputs")]
+fn check_corrupted_seekable_payloads() {
+
+    let valid = compress_seekable(TEXT.chars(), NonZeroUsize::new(8).unwrap());
+    let chars: Vec<char> = TEXT.chars().collect();
+
+    check_corruptions(&valid, &chars[..].into(), decompress::<char>);
+
+    check_corruptions(&valid, &chars[20..].to_vec(), |input| {
+        let mut cursor = DecompressCursor::<char>::new(input)?;
+        cursor.seek_to_symbol(20)?;
+        cursor.collect::<Result<Vec<_>, _>>()
+    });
+}
+
+
+#[test]
+#[cfg_attr(miri, ignore = "decompresses thousands of inputs")]
+fn check_corrupted_dumps() {
+
+    let all_options = [
+        CompressionOptions::new(),
+        CompressionOptions { lz77: true, ..CompressionOptions::new() },
+        CompressionOptions { mtf: true, compact_tree: true, ..CompressionOptions::new() },
+        CompressionOptions { canonical_tree: true, bit_order: BitOrder::LsbFirst, ..CompressionOptions::new() },
+    ];
+
+    let mut valid: Vec<Box<[u8]>> = all_options.iter()
+        .map(|options| compress_with_options(TEXT.chars(), options))
+        .collect();
+
+    valid.push(compress_with_options("aaaa".chars(), &CompressionOptions { lz77: true, ..CompressionOptions::new() }));
+    valid.push(compress_seekable(TEXT.chars(), NonZeroUsize::new(8).unwrap()));
+
+    let options = EncoderOptions { checksum: true, block_checksums: true, ..EncoderOptions::new() };
+    let mut encoder = Encoder::with_options(Vec::new(), options);
+    for word in TEXT.split_inclusive(' ') {
+        encoder.write_symbols(word.chars()).unwrap();
+        encoder.flush_block().unwrap();
+    }
+    valid.push(encoder.finish().unwrap().into_boxed_slice());
+
+    for valid in &valid {
+
+        assert!(!dump_container_as::<char>(valid).contains("error: "));
+
+        // Either a complete dump or one that stops at an error is fine, as long as the dump returns
+        for corrupted in corruptions(valid) {
+            let _ = dump_container_as::<char>(&corrupted);
+        }
+    }
+
+    let chunked = compress_chunked::<char, 2>(TEXT.chars());
+    for corrupted in corruptions(&chunked) {
+        let _ = dump_container_as::<[char; 2]>(&corrupted);
+    }
+
+    // The chunk fields are annotated whenever the flag announces them, even for chunks of one symbol
+    let dump = dump_container(&[4, 0x21, 0, 1, 0, 32, 5, 0, 0, 0, 116, 0, 0, 0]);
+    assert!(dump.contains("flags: chunked, remapped"), "{dump}");
+    assert!(dump.contains("chunk size 1"), "{dump}");
+    assert!(!dump.contains("annotated fields"), "{dump}");
+}
@@ -0,0 +1,712 @@
+//! Annotated dumps of compressed data, for debugging interoperability and corruption issues without a hex editor.
+//!
+//! A dump lists the bytes of the data in hexadecimal, each range followed by what it encodes:
+//! the header fields, the nodes of the trees with the codes of their leaves, and the bitcode sections.
+//! Every bitcode section is followed by the symbols it encodes, one per line, with the byte and bit where their code starts:
+//!
+//! ```text
+//! -- header --
+//! 000000  04                        format version 4
+//! 000001  00                        flags: none
+//! 000002  00                        extended flags: none
+//! -- tree --
+//! 000003  01                        parent (root)
+//! 000004  01                        parent 0
+//! 000005  00 63                     leaf 99 = 00
+//! ...
+//! -- bitcode --
+//! 000011  0b                        symbol count 11
+//! 000012  04                        padding: 4 bits
+//! 000013  af 4b 2b d0               28 bits
+//! 000013.0  101                     97
+//! 000013.3  01                      98
+//! ...
+//! ```
+//!
+//! The dump stops at the first malformed section, with the error and the bytes left unparsed.
+
+use std::error::Error;
+use std::fmt::{self, Write};
+
+use crate::bits::{BitOrder, BitVec};
+use crate::container::{flags, read_alphabet, read_compact_alphabet, read_tree, read_varint, Header};
+use crate::seek::read_checkpoints;
+use crate::symbol::canonical_key;
+use crate::stream::{BlockKind, BLOCK_CHECKSUM_SIZE, CHECKSUM_BLOCK_SIZE, REUSE_TREE};
+use crate::transforms::remap;
+use crate::tree::{reserve_single_leaf, DecodingTree, NodeRef};
+use crate::{DecompressionError, Symbol};
+
+
+/// Number of bytes written on each line of a dump
+const BYTES_PER_LINE: usize = 8;
+
+/// Width of the column of the bytes
+const BYTES_COLUMN_WIDTH: usize = BYTES_PER_LINE * 3 + 1;
+
+
+/// Dump the byte ranges of the compressed `input`, whose symbols are bytes
+pub fn dump_container(input: &[u8]) -> String {
+    dump_container_as::<u8>(input)
+}
+
+
+/// Dump the byte ranges of the compressed `input`, whose symbols are of type `U`.
+/// A wrong `U` shows up as malformed trees, even if the header records another symbol type.
+/// For chunked data, `U` is the chunk type, such as `[char; 2]`.
+pub fn dump_container_as<U>(input: &[u8]) -> String
+where
+    U: Symbol + fmt::Debug
+{
+
+    let mut dump = Dump { input, out: String::new(), position: 0 };
+
+    if let Err(e) = dump.container::<U>() {
+
+        write!(dump.out, "error: {e}").unwrap();
+
+        let mut source = e.source();
+        while let Some(e) = source {
+            write!(dump.out, ": {e}").unwrap();
+            source = e.source();
+        }
+        dump.out.push('\n');
+
+        if dump.position < input.len() {
+            dump.range(input.len(), "unparsed");
+        }
+    }
+
+    dump.out
+}
+
+
+/// Dump being written, and the offset of the first byte that wasn't annotated yet
+struct Dump<'a> {
+
+    input: &'a [u8],
+    out: String,
+    position: usize,
+
+}
+
+impl Dump<'_> {
+
+    /// Annotate the bytes from the current position to `end` with `description`, and move to `end`
+    fn range(&mut self, end: usize, description: impl fmt::Display) {
+
+        let end = end.min(self.input.len());
+        let bytes = &self.input[self.position..end];
+
+        let mut lines = bytes.chunks(BYTES_PER_LINE);
+
+        let first = lines.next().unwrap_or_default();
+        writeln!(self.out, "{:06x}  {:<BYTES_COLUMN_WIDTH$} {description}", self.position, hex(first)).unwrap();
+
+        for (i, line) in lines.enumerate() {
+            writeln!(self.out, "{:06x}  {}", self.position + (i + 1) * BYTES_PER_LINE, hex(line)).unwrap();
+        }
+
+        self.position = end;
+    }
+
+
+    /// Annotate the next `len` bytes
+    fn field(&mut self, len: usize, description: impl fmt::Display) {
+        self.range(self.position.saturating_add(len), description);
+    }
+
+
+    /// Start a new section of the dump
+    fn heading(&mut self, title: impl fmt::Display) {
+        writeln!(self.out, "-- {title} --").unwrap();
+    }
+
+
+    /// Annotate the varint at the current position and return its value
+    fn varint(&mut self, description: &str) -> Result<usize, DecompressionError> {
+
+        let (value, size) = read_varint(self.input, self.position)?;
+
+        self.field(size, format_args!("{description} {value}"));
+
+        Ok(value)
+    }
+
+
+    fn container<U>(&mut self) -> Result<(), DecompressionError>
+    where
+        U: Symbol + fmt::Debug
+    {
+
+        let (header, header_size) = Header::deserialize(self.input).map_err(DecompressionError::InvalidHeader)?;
+
+        self.header(&header);
+
+        // The sections after the header would be annotated at the wrong bytes
+        if self.position != header_size {
+            writeln!(self.out, "error: the header ends at byte {header_size}, but its annotated fields end at byte {}", self.position).unwrap();
+            self.position = self.position.min(header_size);
+            self.range(self.input.len(), "unparsed");
+            return Ok(());
+        }
+
+        if header.blocks {
+            self.blocks::<U>(header.transformed_blocks, header.settings.block_checksums)?;
+
+        } else if let Some(id) = header.dictionary {
+
+            self.heading("bitcode");
+            self.counted_bitcode::<U>(None, header.bit_order)?;
+
+            writeln!(self.out, "the symbols are encoded with dictionary {id:#010x}, which is not part of the data").unwrap();
+
+        } else if header.mtf {
+
+            self.heading("move-to-front alphabet");
+            let (alphabet, _) = read_alphabet::<U>(self.input, self.position)?;
+            self.varint("alphabet length")?;
+            for (i, symbol) in alphabet.iter().enumerate() {
+                self.field(U::WIDTH, format_args!("{i}: {symbol:?}"));
+            }
+
+            self.payload::<u32>(&header)?;
+
+        } else if header.checkpoints {
+
+            let tree = self.tree::<U>(&header)?;
+
+            self.heading("checkpoints");
+            let (interval, checkpoints, size) = read_checkpoints(self.input, self.position)?;
+            self.field(size, format_args!("{} checkpoints, every {interval} symbols", checkpoints.len() - 1));
+
+            self.heading("bitcode");
+            self.counted_bitcode(Some(&tree), BitOrder::MsbFirst)?;
+
+        } else if header.remap {
+
+            self.heading("identifier mapping");
+            let (alphabet, _) = read_compact_alphabet::<U>(self.input, self.position)?;
+            let len = self.varint("alphabet length")?;
+            let width = self.input[self.position] as usize;
+            self.field(1, format_args!("{width} varying bytes per symbol"));
+            self.field(U::WIDTH - width, "shared high bytes");
+            for (i, symbol) in alphabet.iter().enumerate() {
+                self.field(width, format_args!("{i}: {symbol:?}"));
+            }
+
+            match remap::id_width(len) {
+                1 => self.payload::<u8>(&header)?,
+                2 => self.payload::<u16>(&header)?,
+                _ => self.payload::<u32>(&header)?,
+            }
+
+        } else {
+            self.payload::<U>(&header)?;
+        }
+
+        if self.position < self.input.len() {
+            self.range(self.input.len(), "trailing data");
+        }
+
+        Ok(())
+    }
+
+
+    fn header(&mut self, header: &Header) {
+
+        self.heading("header");
+
+        self.field(1, format_args!("format version {}", self.input[0]));
+
+        // Chunks of one symbol are the default, but the flag can still announce them
+        let chunked = self.input[1] & flags::CHUNKED != 0;
+
+        let flags = [
+            (chunked, "chunked"),
+            (header.lz77, "LZ77"),
+            (header.mtf, "move-to-front"),
+            (header.blocks, "blocks"),
+            (header.dictionary.is_some(), "dictionary"),
+            (header.remap, "remapped"),
+            (header.compact_tree, "compact tree"),
+            (header.checkpoints, "checkpoints"),
+        ];
+        self.field(1, format_args!("flags: {}", flag_names(&flags)));
+
+        let extended = [
+            (header.bit_order == BitOrder::LsbFirst, "LSB first"),
+            (header.metadata.modified.is_some(), "modification time"),
+            (header.metadata.mode.is_some(), "mode"),
+            (header.metadata.name.is_some(), "file name"),
+            (header.transformed_blocks, "transformed blocks"),
+            (header.canonical_tree, "canonical tree"),
+            (!header.settings.is_empty(), "settings"),
+        ];
+        self.field(1, format_args!("extended flags: {}", flag_names(&extended)));
+
+        if self.input[2] & flags::extended::OPTIONAL != 0 {
+            match header.symbol_type {
+                Some (symbol_type) => self.field(1, format_args!("optional flags: symbol type {symbol_type}")),
+                None => self.field(1, format_args!("optional flags {:#010b}, unknown", self.input[3])),
+            }
+        }
+
+        if chunked {
+            self.field(1, format_args!("chunk size {}", header.chunk_size));
+            self.field(1, format_args!("chunk remainder {}", header.chunk_remainder));
+        }
+        if let Some(id) = header.dictionary {
+            self.field(4, format_args!("dictionary {id:#010x}"));
+        }
+        if let Some(modified) = header.metadata.modified {
+            self.field(8, format_args!("modified {modified} seconds after the Unix epoch"));
+        }
+        if let Some(mode) = header.metadata.mode {
+            self.field(4, format_args!("mode {mode:o}"));
+        }
+        if let Some(name) = &header.metadata.name {
+            self.field(2, format_args!("file name length {}", name.len()));
+            self.field(name.len(), format_args!("file name {name:?}"));
+        }
+        if self.input[2] & flags::extended::SETTINGS != 0 {
+            let len = self.input[self.position] as usize;
+            self.field(1, format_args!("settings length {len}"));
+            self.field(len, format_args!("settings {:?}", header.settings));
+        }
+    }
+
+
+    /// Annotate a payload written by `write_payload`
+    fn payload<V>(&mut self, header: &Header) -> Result<(), DecompressionError>
+    where
+        V: Symbol + fmt::Debug
+    {
+
+        if header.lz77 {
+            return self.lz77::<V>(header);
+        }
+
+        let tree = self.tree::<V>(header)?;
+
+        self.heading("bitcode");
+        self.counted_bitcode(Some(&tree), header.bit_order)?;
+
+        Ok(())
+    }
+
+
+    /// Annotate an LZ77 payload: the token count, the streams of token kinds, literals, and match classes, and the extra bits
+    fn lz77<V>(&mut self, header: &Header) -> Result<(), DecompressionError>
+    where
+        V: Symbol + fmt::Debug
+    {
+
+        self.heading("LZ77 tokens");
+        let token_count = self.varint("token count")?;
+
+        let kinds = self.stream::<bool>("token kinds (true for matches)", token_count, header)?;
+        let match_count = kinds.iter().filter(|is_match| **is_match).count();
+
+        self.stream::<V>("literals", token_count - match_count, header)?;
+        self.stream::<u8>("match length classes", match_count, header)?;
+        self.stream::<u8>("match distance classes", match_count, header)?;
+
+        self.heading("extra bits of the match lengths and distances");
+        self.bitcode_section::<u8>(None, 0, header.bit_order)?;
+
+        Ok(())
+    }
+
+
+    /// Annotate a stream of `count` symbols written by `write_stream` and return its symbols.
+    /// Empty streams are not written.
+    fn stream<V>(&mut self, title: &str, count: usize, header: &Header) -> Result<Vec<V>, DecompressionError>
+    where
+        V: Symbol + fmt::Debug
+    {
+
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+
+        self.heading(title);
+
+        let tree = self.tree::<V>(header)?;
+
+        self.bitcode_section(Some(&tree), count, header.bit_order)
+    }
+
+
+    /// Annotate the nodes of the tree at the current position, in the layout selected by `header`, and return the tree
+    fn tree<V>(&mut self, header: &Header) -> Result<DecodingTree<V>, DecompressionError>
+    where
+        V: Symbol + fmt::Debug
+    {
+
+        self.heading("tree");
+
+        let (tree, size) = read_tree(self.input, self.position, header)?;
+
+        let mut nodes = Vec::new();
+        collect_nodes(tree.root(), String::new(), &mut nodes);
+
+        if header.canonical_tree {
+
+            let mut leaves: Vec<_> = nodes.iter().filter_map(|(code, value)| value.map(|value| (code, value))).collect();
+            leaves.sort_by_cached_key(|(_, value)| canonical_key(*value));
+
+            self.field(size - leaves.len() * (V::WIDTH + 1), format_args!("leaf count {}", leaves.len()));
+
+            for (code, value) in leaves {
+                self.field(V::WIDTH + 1, format_args!("leaf {value:?}, {} bits = {}", code.len(), code_label(code)));
+            }
+
+        } else if header.compact_tree {
+
+            let leaves: Vec<_> = nodes.iter().filter_map(|(code, value)| value.map(|value| (code, value))).collect();
+
+            self.field(size - leaves.len() * V::WIDTH, format_args!("structure of {} nodes", nodes.len()));
+
+            for (code, value) in leaves {
+                self.field(V::WIDTH, format_args!("leaf {value:?} = {}", code_label(code)));
+            }
+
+        } else {
+
+            for (code, value) in &nodes {
+                match value {
+                    Some (value) => self.field(1 + V::WIDTH, format_args!("leaf {value:?} = {}", code_label(code))),
+                    None if code.is_empty() => self.field(1, "parent (root)"),
+                    None => self.field(1, format_args!("parent {code}")),
+                }
+            }
+        }
+
+        Ok(tree)
+    }
+
+
+    /// Annotate a symbol count followed by a bitcode spanning to the end of the input, as written by `write_counted_bitcode`.
+    /// The symbols are listed if the `tree` is known.
+    fn counted_bitcode<V>(&mut self, tree: Option<&DecodingTree<V>>, bit_order: BitOrder) -> Result<(), DecompressionError>
+    where
+        V: Symbol + fmt::Debug
+    {
+
+        let count = self.varint("symbol count")?;
+
+        self.bitcode(tree, count, self.input.len(), bit_order)?;
+
+        Ok(())
+    }
+
+
+    /// Annotate a length-prefixed bitcode section, as written by `write_bitcode_section`, and return the `count` symbols it encodes.
+    /// The symbols are listed if the `tree` is known.
+    fn bitcode_section<V>(&mut self, tree: Option<&DecodingTree<V>>, count: usize, bit_order: BitOrder) -> Result<Vec<V>, DecompressionError>
+    where
+        V: Symbol + fmt::Debug
+    {
+
+        let length = self.varint("bitcode length")?;
+
+        let end = self.position.saturating_add(length);
+        if end > self.input.len() {
+            return Err(DecompressionError::TruncatedSection { offset: self.position, expected: length, remaining: self.input.len() - self.position });
+        }
+
+        self.bitcode(tree, count, end, bit_order)
+    }
+
+
+    /// Annotate the serialized bitcode from the current position to `end`, and list the `count` symbols it encodes with `tree`
+    fn bitcode<V>(&mut self, tree: Option<&DecodingTree<V>>, count: usize, end: usize, bit_order: BitOrder) -> Result<Vec<V>, DecompressionError>
+    where
+        V: Symbol + fmt::Debug
+    {
+
+        let offset = self.position;
+
+        let bitcode = BitVec::deserialize_with_order(&self.input[offset..end], bit_order)
+            .map_err(|_| DecompressionError::InvalidBitCode { offset, remaining: end - offset })?;
+
+        self.field(1, format_args!("padding: {} bits", self.input[offset]));
+        self.range(end, format_args!("{} bits", bitcode.len_bits()));
+
+        let Some(tree) = tree else {
+            return Ok(Vec::new());
+        };
+
+        // A single-leaf tree encodes its value with no bits, so its symbols would all start at the same position
+        if let Some(value) = tree.single_value() {
+            writeln!(self.out, "{:06x}.0  {:<width$} {count} × {value:?}", offset + 1, "", width = BYTES_COLUMN_WIDTH - 2).unwrap();
+
+            let mut symbols = Vec::new();
+            reserve_single_leaf(&mut symbols, count)
+                .map_err(|source| DecompressionError::BitCodeDecodingError { offset, source })?;
+            symbols.resize(count, value.clone());
+
+            return Ok(symbols);
+        }
+
+        let bits = bitcode.as_bit_view();
+
+        let mut symbols = Vec::with_capacity(count.min(bits.len_bits()));
+        let mut bit_offset = 0;
+
+        for _ in 0..count {
+
+            let (value, next) = tree.decode_one(&bits, bit_offset)
+                .map_err(|source| DecompressionError::BitCodeDecodingError { offset, source })?;
+
+            let code: String = (bit_offset..next).map(|i| if bits.get(i).unwrap() { '1' } else { '0' }).collect();
+
+            // The position takes two more characters than a byte offset, so the code column is narrower to keep the values aligned
+            writeln!(self.out, "{:06x}.{}  {code:<width$} {value:?}", offset + 1 + bit_offset / 8, bit_offset % 8, width = BYTES_COLUMN_WIDTH - 2).unwrap();
+
+            symbols.push(value.clone());
+            bit_offset = next;
+        }
+
+        Ok(symbols)
+    }
+
+
+    /// Annotate the blocks of a stream, up to its end block.
+    /// With `block_checksums`, each data block is followed by the checksum of its symbols.
+    fn blocks<U>(&mut self, transformed: bool, block_checksums: bool) -> Result<(), DecompressionError>
+    where
+        U: Symbol + fmt::Debug
+    {
+
+        let mut previous = None;
+
+        for index in 0_u64.. {
+
+            let offset = self.position;
+
+            let kind = *self.input.get(offset)
+                .ok_or(DecompressionError::TruncatedSection { offset, expected: 1, remaining: 0 })?;
+
+            let reuse_tree = kind & REUSE_TREE != 0;
+
+            match BlockKind::try_from(kind & !REUSE_TREE) {
+
+                Ok (BlockKind::End) if !reuse_tree => {
+                    self.heading("end of stream");
+                    self.field(1, "end block");
+                    return Ok(());
+                },
+
+                Ok (BlockKind::Checksum) if !reuse_tree => {
+
+                    let checksum = self.input.get(offset + 1..offset + CHECKSUM_BLOCK_SIZE)
+                        .ok_or(DecompressionError::TruncatedSection { offset, expected: CHECKSUM_BLOCK_SIZE, remaining: self.input.len() - offset })?;
+
+                    self.heading("checksum");
+                    self.field(1, "checksum block");
+                    self.field(4, format_args!("crc-32 {:#010x}", u32::from_le_bytes(checksum.try_into().unwrap())));
+                },
+
+                Ok (BlockKind::Data) => {
+
+                    self.heading(format_args!("block {index}"));
+
+                    self.field(1, if reuse_tree { "data block, with the previous tree" } else { "data block" });
+
+                    let count = self.varint("symbol count")?;
+                    let length = self.varint("payload length")?;
+
+                    let end = self.position.saturating_add(length);
+
+                    if transformed {
+                        self.range(end, "transformed payload");
+                    }
+                    else {
+
+                        if !reuse_tree {
+                            // The trees of blocks are always in the default layout
+                            previous = Some(self.tree::<U>(&Header::new())?);
+                        }
+
+                        let tree = previous.as_ref()
+                            .ok_or(DecompressionError::NoTreeToReuse { offset })?;
+
+                        self.heading("bitcode");
+                        self.bitcode_section(Some(tree), count, BitOrder::MsbFirst)?;
+
+                        if self.position < end {
+                            self.range(end, "unused payload bytes");
+                        }
+                    }
+
+                    if block_checksums {
+
+                        let checksum = self.input.get(self.position..self.position + BLOCK_CHECKSUM_SIZE)
+                            .ok_or(DecompressionError::TruncatedSection {
+                                offset: self.position,
+                                expected: BLOCK_CHECKSUM_SIZE,
+                                remaining: self.input.len().saturating_sub(self.position)
+                            })?;
+
+                        self.field(BLOCK_CHECKSUM_SIZE, format_args!("block crc-32 {:#010x}", u32::from_le_bytes(checksum.try_into().unwrap())));
+                    }
+                },
+
+                _ => return Err(DecompressionError::InvalidBlockKind { offset, found: kind, expected: BlockKind::VALID })
+            }
+        }
+
+        unreachable!()
+    }
+
+}
+
+
+/// Bytes in hexadecimal, separated by spaces
+fn hex(bytes: &[u8]) -> String {
+
+    let mut hex = String::with_capacity(bytes.len() * 3);
+
+    for byte in bytes {
+        if !hex.is_empty() {
+            hex.push(' ');
+        }
+        write!(hex, "{byte:02x}").unwrap();
+    }
+
+    hex
+}
+
+
+/// Names of the flags that are set, or `none`
+fn flag_names(flags: &[(bool, &str)]) -> String {
+
+    let names: Vec<&str> = flags.iter().filter(|(set, _)| *set).map(|(_, name)| *name).collect();
+
+    if names.is_empty() {
+        "none".to_string()
+    } else {
+        names.join(", ")
+    }
+}
+
+
+fn code_label(code: &str) -> &str {
+    if code.is_empty() { "empty code" } else { code }
+}
+
+
+/// Push the code of every node below `node` to `nodes` in pre-order, with the value of the leaves.
+/// `code` is the code of `node`.
+fn collect_nodes<'a, V>(node: NodeRef<'a, V>, code: String, nodes: &mut Vec<(String, Option<&'a V>)>) {
+
+    match node.children() {
+
+        Some ((left, right)) => {
+            nodes.push((code.clone(), None));
+            collect_nodes(left, code.clone() + "0", nodes);
+            collect_nodes(right, code + "1", nodes);
+        },
+
+        None => nodes.push((code, node.value())),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::stream::{Encoder, EncoderOptions};
+    use crate::{compress, compress_with_options, CompressionOptions};
+
+
+    #[test]
+    fn check_plain_dump() {
+
+        let compressed = compress("abracadabra".bytes());
+        let dump = dump_container(&compressed);
+
+        assert!(dump.starts_with("-- header --\n000000  04                        format version 4\n"));
+        assert!(dump.contains("flags: none"));
+        assert!(dump.contains("-- tree --"));
+        assert!(dump.contains("parent (root)"));
+        assert!(dump.contains("symbol count 11"));
+        assert!(!dump.contains("error"));
+
+        // Every symbol is listed after the bitcode, in order
+        let symbols: Vec<&str> = dump.lines()
+            .filter(|line| line.as_bytes().get(6) == Some(&b'.'))
+            .map(|line| line.rsplit(' ').next().unwrap())
+            .collect();
+        let expected: Vec<String> = "abracadabra".bytes().map(|byte| byte.to_string()).collect();
+        assert_eq!(symbols, expected);
+
+        // The byte ranges cover the whole input
+        let dumped_bytes = dump.lines()
+            .filter(|line| line.as_bytes().get(6) == Some(&b' '))
+            .map(|line| line[8..].get(..BYTES_COLUMN_WIDTH).unwrap_or(&line[8..]).split_whitespace().count())
+            .sum::<usize>();
+        assert_eq!(dumped_bytes, compressed.len());
+    }
+
+
+    #[test]
+    fn check_other_layouts() {
+
+        let text = "the quick brown fox jumps over the lazy dog, the quick brown fox";
+
+        let lz77 = compress_with_options(text.bytes(), &CompressionOptions { lz77: true, compact_tree: true, ..CompressionOptions::new() });
+        let dump = dump_container(&lz77);
+        assert!(dump.contains("flags: LZ77, compact tree"));
+        assert!(dump.contains("-- literals --"));
+        assert!(dump.contains("structure of"));
+        assert!(!dump.contains("error"));
+
+        let canonical = compress_with_options(text.bytes(), &CompressionOptions { canonical_tree: true, ..CompressionOptions::new() });
+        let dump = dump_container(&canonical);
+        assert!(dump.contains("canonical tree"));
+        assert!(dump.contains("leaf count"));
+        assert!(dump.contains("leaf 113"));
+        assert!(!dump.contains("error"));
+
+        let remapped = dump_container_as::<char>(&compress(text.chars()));
+        assert!(remapped.contains("-- identifier mapping --"));
+        assert!(remapped.contains("'q'"));
+        assert!(!remapped.contains("error"));
+
+        let options = EncoderOptions { max_block_bytes: std::num::NonZeroUsize::new(1 << 20), checksum: true, block_checksums: true, ..EncoderOptions::new() };
+        let mut encoder = Encoder::with_options(Vec::new(), options);
+        encoder.write_symbols(text.bytes()).unwrap();
+        encoder.flush_block().unwrap();
+        encoder.write_symbols(text.bytes()).unwrap();
+        let stream = encoder.finish().unwrap();
+
+        let dump = dump_container(&stream);
+        assert!(dump.contains("settings EncoderSettings"));
+        assert!(dump.contains("-- block 1 --"));
+        assert!(dump.contains("data block, with the previous tree"));
+        assert!(dump.contains("block crc-32 0x"));
+        assert!(dump.contains("-- checksum --"));
+        assert!(dump.contains("end block"));
+        assert!(!dump.contains("error"));
+    }
+
+
+    #[test]
+    fn check_corrupted_dump() {
+
+        let mut compressed = compress("abracadabra".bytes()).into_vec();
+        let (_, header_size) = Header::deserialize(&compressed).unwrap();
+        compressed[header_size] = 7;
+
+        let dump = dump_container(&compressed);
+        assert!(dump.contains("error: invalid decoding tree: "));
+        assert!(dump.contains("unparsed"));
+
+        assert_eq!(dump_container(&[9]), "error: invalid container header: unsupported format version 9, expected 4\n000000  09                        unparsed\n");
+    }
+
+}
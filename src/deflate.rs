@@ -0,0 +1,763 @@
+//! Compatibility with the raw DEFLATE format (RFC 1951).
+//!
+//! `compress_raw` emits a single dynamic Huffman block whose code lengths are taken from frequency trees,
+//! limited to the DEFLATE maximum and converted to canonical codes, so that the output can be inflated by existing tools.
+//! `decompress_raw` parses stored, fixed, and dynamic blocks.
+//!
+//! Unlike the rest of the crate, DEFLATE packs bits starting from the least significant bit of each byte.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::transforms::lz77::{self, Token};
+use crate::tree::EncodingTree;
+
+
+/// Longest code of the literal/length and distance alphabets
+pub const MAX_CODE_LENGTH: u8 = 15;
+
+/// Longest code of the code length alphabet
+pub const MAX_CODE_LENGTH_CODE_LENGTH: u8 = 7;
+
+const END_OF_BLOCK: u16 = 256;
+
+/// Number of symbols of the literal/length alphabet that can appear in a block
+const LITERAL_LENGTH_SYMBOLS: usize = 286;
+
+const DISTANCE_SYMBOLS: usize = 30;
+
+const CODE_LENGTH_SYMBOLS: usize = 19;
+
+/// Order in which the code length code lengths are stored
+const CODE_LENGTH_ORDER: [usize; CODE_LENGTH_SYMBOLS] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+
+const DISTANCE_BASE: [u32; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+
+/// Block type, written in the two bits after the final block flag
+#[repr(u8)]
+enum BlockType {
+
+    Stored,
+    Fixed,
+    Dynamic,
+
+}
+
+impl TryFrom<u8> for BlockType {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Stored),
+            1 => Ok(Self::Fixed),
+            2 => Ok(Self::Dynamic),
+            _ => Err(value)
+        }
+    }
+}
+
+
+/// Error returned when a raw DEFLATE stream can't be decompressed.
+/// Bit offsets count from the first bit of the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeflateError {
+
+    /// The input ended in the middle of a block
+    UnexpectedEnd,
+    /// The block type is the reserved value 3
+    InvalidBlockType { bit_offset: usize, found: u8 },
+    /// The length of a stored block doesn't match its one's complement
+    InvalidStoredLength { offset: usize, length: u16, complement: u16 },
+    /// The code lengths of the dynamic block header don't describe a valid prefix code
+    InvalidCodeLengths { bit_offset: usize },
+    /// The bits don't match any code, or decode to a symbol that can't appear in the data
+    InvalidCode { bit_offset: usize },
+    /// A match refers to bytes before the start of the data
+    InvalidDistance { bit_offset: usize, distance: u32, available: usize },
+
+}
+
+impl fmt::Display for DeflateError {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeflateError::UnexpectedEnd
+                => write!(f, "unexpected end of the DEFLATE stream"),
+            DeflateError::InvalidBlockType { bit_offset, found }
+                => write!(f, "invalid block type {found} at bit {bit_offset}"),
+            DeflateError::InvalidStoredLength { offset, length, complement }
+                => write!(f, "stored block length {length} at byte {offset} doesn't match its complement {complement}"),
+            DeflateError::InvalidCodeLengths { bit_offset }
+                => write!(f, "invalid code lengths in the block header ending at bit {bit_offset}"),
+            DeflateError::InvalidCode { bit_offset }
+                => write!(f, "invalid code at bit {bit_offset}"),
+            DeflateError::InvalidDistance { bit_offset, distance, available }
+                => write!(f, "match at bit {bit_offset} has distance {distance} but only {available} bytes precede it"),
+        }
+    }
+}
+
+impl Error for DeflateError {}
+
+
+/// Writes bits starting from the least significant bit of each byte
+struct BitWriter {
+
+    bytes: Vec<u8>,
+    /// Number of bits used in the last byte, 8 if it's full
+    used: u8,
+
+}
+
+impl BitWriter {
+
+    const fn new() -> Self {
+        Self {
+            bytes: Vec::new(),
+            used: 8
+        }
+    }
+
+
+    /// Write the `count` low bits of `value`, least significant bit first
+    fn write_bits(&mut self, value: u32, count: u8) {
+        for i in 0..count {
+            if self.used == 8 {
+                self.bytes.push(0);
+                self.used = 0;
+            }
+            *self.bytes.last_mut().unwrap() |= (((value >> i) & 1) as u8) << self.used;
+            self.used += 1;
+        }
+    }
+
+
+    /// Write a Huffman code, most significant bit first
+    fn write_code(&mut self, code: u16, length: u8) {
+        for i in (0..length).rev() {
+            self.write_bits(((code >> i) & 1) as u32, 1);
+        }
+    }
+
+}
+
+
+/// Reads bits starting from the least significant bit of each byte
+struct BitReader<'a> {
+
+    input: &'a [u8],
+    /// Offset of the next bit to read
+    bit_offset: usize,
+
+}
+
+impl<'a> BitReader<'a> {
+
+    const fn new(input: &'a [u8]) -> Self {
+        Self {
+            input,
+            bit_offset: 0
+        }
+    }
+
+
+    fn read_bit(&mut self) -> Result<u32, DeflateError> {
+
+        let byte = *self.input.get(self.bit_offset / 8).ok_or(DeflateError::UnexpectedEnd)?;
+        let bit = (byte >> (self.bit_offset % 8)) & 1;
+
+        self.bit_offset += 1;
+
+        Ok(bit as u32)
+    }
+
+
+    /// Read `count` bits into a value, least significant bit first
+    fn read_bits(&mut self, count: u8) -> Result<u32, DeflateError> {
+
+        let mut value = 0;
+        for i in 0..count {
+            value |= self.read_bit()? << i;
+        }
+
+        Ok(value)
+    }
+
+
+    /// Skip to the next byte boundary
+    const fn align(&mut self) {
+        self.bit_offset = self.bit_offset.next_multiple_of(8);
+    }
+
+}
+
+
+/// Assign canonical codes to the symbols with the given code lengths, as specified by DEFLATE:
+/// shorter codes precede longer codes, and codes of the same length are ordered by symbol.
+/// Symbols with length 0 are unused and get code 0.
+pub fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+
+    let max_length = lengths.iter().copied().max().unwrap_or(0) as usize;
+
+    let mut length_counts = vec![0_u16; max_length + 1];
+    for &length in lengths {
+        length_counts[length as usize] += 1;
+    }
+    length_counts[0] = 0;
+
+    let mut next_code = vec![0_u16; max_length + 1];
+    let mut code = 0;
+    for length in 1..=max_length {
+        code = (code + length_counts[length - 1]) << 1;
+        next_code[length] = code;
+    }
+
+    lengths.iter()
+        .map(|&length| {
+            if length == 0 {
+                0
+            } else {
+                let code = next_code[length as usize];
+                next_code[length as usize] += 1;
+                code
+            }
+        })
+        .collect()
+}
+
+
+/// Limit the nonzero code lengths to `max_length` bits, keeping a complete prefix code.
+/// Codes that are too long are shortened and the longest remaining codes are lengthened to make room for them.
+/// A single used symbol gets a companion code, because DEFLATE decoders may reject incomplete codes.
+pub fn limit_code_lengths(lengths: &mut [u8], max_length: u8) {
+
+    match lengths.iter().filter(|length| **length != 0).count() {
+
+        0 => return,
+
+        1 => {
+            // The companion is the first unused symbol
+            let used = lengths.iter().position(|length| *length != 0).unwrap();
+            lengths[used] = 1;
+            if let Some(companion) = lengths.iter_mut().enumerate().find(|(i, _)| *i != used) {
+                *companion.1 = 1;
+            }
+            return;
+        },
+
+        _ => ()
+    }
+
+    for length in lengths.iter_mut() {
+        *length = (*length).min(max_length);
+    }
+
+    // Kraft sum in units of the weight of a code of `max_length` bits
+    let capacity = 1_u32 << max_length;
+    let weight = |length: u8| 1_u32 << (max_length - length);
+
+    let mut kraft: u32 = lengths.iter()
+        .filter(|length| **length != 0)
+        .map(|length| weight(*length))
+        .sum();
+
+    // Oversubscribed: lengthen the longest codes that can still grow
+    while kraft > capacity {
+        let (i, length) = lengths.iter().copied().enumerate()
+            .filter(|(_, length)| *length != 0 && *length < max_length)
+            .max_by_key(|(_, length)| *length)
+            .unwrap();
+        kraft -= weight(length + 1);
+        lengths[i] += 1;
+    }
+
+    // Incomplete: shorten the longest codes while they fit
+    while kraft < capacity {
+        let (i, length) = lengths.iter().copied().enumerate()
+            .filter(|(_, length)| *length > 1 && kraft + weight(*length) <= capacity)
+            .max_by_key(|(_, length)| *length)
+            .unwrap();
+        kraft += weight(length);
+        lengths[i] -= 1;
+    }
+}
+
+
+/// Code lengths of the symbols `0..symbol_count` built with a frequency tree, limited to `max_length` bits
+fn tree_code_lengths(frequencies: &[usize], max_length: u8) -> Vec<u8> {
+
+    let used: Vec<(u16, usize)> = frequencies.iter()
+        .enumerate()
+        .filter(|(_, count)| **count != 0)
+        .map(|(symbol, count)| (symbol as u16, *count))
+        .collect();
+
+    let mut lengths = vec![0; frequencies.len()];
+
+    for (symbol, length) in EncodingTree::from_frequencies(&used).code_lengths() {
+        // The only symbol of a single-leaf tree has length 0, but it's used
+        lengths[symbol as usize] = length.max(1);
+    }
+
+    limit_code_lengths(&mut lengths, max_length);
+
+    lengths
+}
+
+
+/// Index of the last base not greater than `value`
+fn base_index<T: PartialOrd>(bases: &[T], value: T) -> usize {
+    bases.iter().rposition(|base| *base <= value).unwrap()
+}
+
+
+/// Run-length encode the code lengths with the code length alphabet.
+/// Return the code length symbols paired with their extra bits.
+fn run_length_encode(lengths: &[u8]) -> Vec<(u8, u32)> {
+
+    let mut symbols = Vec::new();
+    let mut i = 0;
+
+    while i < lengths.len() {
+
+        let length = lengths[i];
+        let run = lengths[i..].iter().take_while(|l| **l == length).count();
+
+        if length == 0 && run >= 11 {
+            let run = run.min(138);
+            symbols.push((18, (run - 11) as u32));
+            i += run;
+        } else if length == 0 && run >= 3 {
+            let run = run.min(10);
+            symbols.push((17, (run - 3) as u32));
+            i += run;
+        } else {
+            symbols.push((length, 0));
+            i += 1;
+
+            // Repeat the previous length
+            let mut remaining = run - 1;
+            while length != 0 && remaining >= 3 {
+                let repeat = remaining.min(6);
+                symbols.push((16, (repeat - 3) as u32));
+                remaining -= repeat;
+                i += repeat;
+            }
+        }
+    }
+
+    symbols
+}
+
+
+/// Number of extra bits following a code length symbol
+const fn code_length_extra_bits(symbol: u8) -> u8 {
+    match symbol {
+        16 => 2,
+        17 => 3,
+        18 => 7,
+        _ => 0
+    }
+}
+
+
+/// Compress `data` into a raw DEFLATE stream made of a single dynamic block.
+/// The literal/length and distance codes are built from frequency trees over the LZ77 tokens of the data.
+pub fn compress_raw(data: &[u8]) -> Box<[u8]> {
+
+    let tokens = lz77::encode(data);
+
+    let mut literal_frequencies = [0_usize; LITERAL_LENGTH_SYMBOLS];
+    let mut distance_frequencies = [0_usize; DISTANCE_SYMBOLS];
+
+    literal_frequencies[END_OF_BLOCK as usize] = 1;
+
+    for token in &tokens {
+        match token {
+            Token::Literal (byte) => literal_frequencies[*byte as usize] += 1,
+            Token::Match { length, distance } => {
+                literal_frequencies[257 + base_index(&LENGTH_BASE, *length)] += 1;
+                distance_frequencies[base_index(&DISTANCE_BASE, *distance)] += 1;
+            }
+        }
+    }
+
+    let literal_lengths = tree_code_lengths(&literal_frequencies, MAX_CODE_LENGTH);
+    let mut distance_lengths = tree_code_lengths(&distance_frequencies, MAX_CODE_LENGTH);
+
+    // At least one distance code must be described
+    if distance_lengths.iter().all(|length| *length == 0) {
+        distance_lengths[0] = 1;
+    }
+
+    let literal_count = literal_lengths.iter().rposition(|length| *length != 0).unwrap() + 1;
+    let distance_count = distance_lengths.iter().rposition(|length| *length != 0).unwrap() + 1;
+
+    let all_lengths = [&literal_lengths[..literal_count], &distance_lengths[..distance_count]].concat();
+    let code_length_symbols = run_length_encode(&all_lengths);
+
+    let mut code_length_frequencies = [0_usize; CODE_LENGTH_SYMBOLS];
+    for (symbol, _) in &code_length_symbols {
+        code_length_frequencies[*symbol as usize] += 1;
+    }
+    let code_length_lengths = tree_code_lengths(&code_length_frequencies, MAX_CODE_LENGTH_CODE_LENGTH);
+
+    let code_length_count = (4..=CODE_LENGTH_SYMBOLS).rev()
+        .find(|count| code_length_lengths[CODE_LENGTH_ORDER[count - 1]] != 0)
+        .unwrap_or(4);
+
+    let mut writer = BitWriter::new();
+
+    // Final block, dynamic codes
+    writer.write_bits(1, 1);
+    writer.write_bits(BlockType::Dynamic as u32, 2);
+
+    writer.write_bits((literal_count - 257) as u32, 5);
+    writer.write_bits((distance_count - 1) as u32, 5);
+    writer.write_bits((code_length_count - 4) as u32, 4);
+
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        writer.write_bits(code_length_lengths[symbol] as u32, 3);
+    }
+
+    let code_length_codes = canonical_codes(&code_length_lengths);
+    for (symbol, extra) in code_length_symbols {
+        writer.write_code(code_length_codes[symbol as usize], code_length_lengths[symbol as usize]);
+        writer.write_bits(extra, code_length_extra_bits(symbol));
+    }
+
+    let literal_codes = canonical_codes(&literal_lengths);
+    let distance_codes = canonical_codes(&distance_lengths);
+
+    let write_symbol = |writer: &mut BitWriter, symbol: usize| writer.write_code(literal_codes[symbol], literal_lengths[symbol]);
+
+    for token in tokens {
+        match token {
+
+            Token::Literal (byte) => write_symbol(&mut writer, byte as usize),
+
+            Token::Match { length, distance } => {
+
+                let i = base_index(&LENGTH_BASE, length);
+                write_symbol(&mut writer, 257 + i);
+                writer.write_bits((length - LENGTH_BASE[i]) as u32, LENGTH_EXTRA[i]);
+
+                let i = base_index(&DISTANCE_BASE, distance);
+                writer.write_code(distance_codes[i], distance_lengths[i]);
+                writer.write_bits(distance - DISTANCE_BASE[i], DISTANCE_EXTRA[i]);
+            },
+        }
+    }
+
+    write_symbol(&mut writer, END_OF_BLOCK as usize);
+
+    writer.bytes.into_boxed_slice()
+}
+
+
+/// Decoder for a canonical prefix code
+struct CanonicalDecoder {
+
+    /// Number of codes of each length
+    length_counts: [u16; MAX_CODE_LENGTH as usize + 1],
+    /// Symbols ordered by code
+    symbols: Vec<u16>,
+
+}
+
+impl CanonicalDecoder {
+
+    /// Build the decoder for the given code lengths.
+    /// Return `None` if the lengths are oversubscribed.
+    /// Incomplete codes are accepted, the missing codes are reported when they're read.
+    fn new(lengths: &[u8]) -> Option<Self> {
+
+        let mut length_counts = [0_u16; MAX_CODE_LENGTH as usize + 1];
+        for &length in lengths {
+            length_counts[length as usize] += 1;
+        }
+        length_counts[0] = 0;
+
+        let mut left: i32 = 1;
+        for count in &length_counts[1..] {
+            left = (left << 1) - *count as i32;
+            if left < 0 {
+                return None;
+            }
+        }
+
+        let mut symbols: Vec<u16> = (0..lengths.len() as u16)
+            .filter(|symbol| lengths[*symbol as usize] != 0)
+            .collect();
+        symbols.sort_by_key(|symbol| lengths[*symbol as usize]);
+
+        Some(Self { length_counts, symbols })
+    }
+
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, DeflateError> {
+
+        let bit_offset = reader.bit_offset;
+
+        let mut code: i32 = 0;
+        // First code of the current length
+        let mut first: i32 = 0;
+        // Index of the first symbol of the current length
+        let mut index: i32 = 0;
+
+        for count in &self.length_counts[1..] {
+
+            code |= reader.read_bit()? as i32;
+
+            let count = *count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(DeflateError::InvalidCode { bit_offset })
+    }
+
+}
+
+
+/// Literal/length and distance decoders of the fixed Huffman codes
+fn fixed_decoders() -> (CanonicalDecoder, CanonicalDecoder) {
+
+    let mut literal_lengths = [8_u8; 288];
+    literal_lengths[144..256].fill(9);
+    literal_lengths[256..280].fill(7);
+
+    // Both tables are complete
+    (
+        CanonicalDecoder::new(&literal_lengths).unwrap(),
+        CanonicalDecoder::new(&[5; 30]).unwrap()
+    )
+}
+
+
+/// Read the code lengths of a dynamic block header and build the literal/length and distance decoders
+fn read_dynamic_header(reader: &mut BitReader) -> Result<(CanonicalDecoder, CanonicalDecoder), DeflateError> {
+
+    let literal_count = reader.read_bits(5)? as usize + 257;
+    let distance_count = reader.read_bits(5)? as usize + 1;
+    let code_length_count = reader.read_bits(4)? as usize + 4;
+
+    let mut code_length_lengths = [0_u8; CODE_LENGTH_SYMBOLS];
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_length_lengths[symbol] = reader.read_bits(3)? as u8;
+    }
+
+    let invalid = |reader: &BitReader| DeflateError::InvalidCodeLengths { bit_offset: reader.bit_offset };
+
+    let code_length_decoder = CanonicalDecoder::new(&code_length_lengths).ok_or(invalid(reader))?;
+
+    let mut lengths: Vec<u8> = Vec::with_capacity(literal_count + distance_count);
+
+    while lengths.len() < literal_count + distance_count {
+
+        let (length, repeat) = match code_length_decoder.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last().ok_or(invalid(reader))?, 3 + reader.read_bits(2)?),
+            17 => (0, 3 + reader.read_bits(3)?),
+            18 => (0, 11 + reader.read_bits(7)?),
+            _ => return Err(invalid(reader))
+        };
+
+        if lengths.len() + repeat as usize > literal_count + distance_count {
+            return Err(invalid(reader));
+        }
+
+        lengths.extend(std::iter::repeat_n(length, repeat as usize));
+    }
+
+    let (literal_lengths, distance_lengths) = lengths.split_at(literal_count);
+
+    if literal_lengths[END_OF_BLOCK as usize] == 0 {
+        return Err(invalid(reader));
+    }
+
+    Ok((
+        CanonicalDecoder::new(literal_lengths).ok_or(invalid(reader))?,
+        CanonicalDecoder::new(distance_lengths).ok_or(invalid(reader))?
+    ))
+}
+
+
+/// Decode the symbols of a compressed block until the end of block symbol
+fn inflate_block(reader: &mut BitReader, literals: &CanonicalDecoder, distances: &CanonicalDecoder, output: &mut Vec<u8>) -> Result<(), DeflateError> {
+
+    loop {
+
+        let bit_offset = reader.bit_offset;
+
+        let symbol = literals.decode(reader)?;
+
+        match symbol {
+
+            0..=255 => output.push(symbol as u8),
+
+            END_OF_BLOCK => return Ok(()),
+
+            257..=285 => {
+
+                let i = symbol as usize - 257;
+                let length = LENGTH_BASE[i] as usize + reader.read_bits(LENGTH_EXTRA[i])? as usize;
+
+                let i = distances.decode(reader)? as usize;
+                if i >= DISTANCE_SYMBOLS {
+                    return Err(DeflateError::InvalidCode { bit_offset });
+                }
+                let distance = DISTANCE_BASE[i] + reader.read_bits(DISTANCE_EXTRA[i])?;
+
+                if distance as usize > output.len() {
+                    return Err(DeflateError::InvalidDistance { bit_offset, distance, available: output.len() });
+                }
+
+                let start = output.len() - distance as usize;
+
+                // The match may overlap the bytes it produces
+                for j in start..start + length {
+                    output.push(output[j]);
+                }
+            },
+
+            _ => return Err(DeflateError::InvalidCode { bit_offset })
+        }
+    }
+}
+
+
+/// Decompress a raw DEFLATE stream, made of stored, fixed, or dynamic blocks
+pub fn decompress_raw(input: &[u8]) -> Result<Box<[u8]>, DeflateError> {
+
+    let mut reader = BitReader::new(input);
+    let mut output = Vec::new();
+
+    loop {
+
+        let is_final = reader.read_bit()? == 1;
+
+        let bit_offset = reader.bit_offset;
+        let block_type = BlockType::try_from(reader.read_bits(2)? as u8)
+            .map_err(|found| DeflateError::InvalidBlockType { bit_offset, found })?;
+
+        match block_type {
+
+            BlockType::Stored => {
+
+                reader.align();
+                let offset = reader.bit_offset / 8;
+
+                let length = reader.read_bits(16)? as u16;
+                let complement = reader.read_bits(16)? as u16;
+
+                if length != !complement {
+                    return Err(DeflateError::InvalidStoredLength { offset, length, complement });
+                }
+
+                let start = offset + 4;
+                let bytes = input.get(start..start + length as usize).ok_or(DeflateError::UnexpectedEnd)?;
+                output.extend_from_slice(bytes);
+
+                reader.bit_offset = (start + length as usize) * 8;
+            },
+
+            BlockType::Fixed => {
+                let (literals, distances) = fixed_decoders();
+                inflate_block(&mut reader, &literals, &distances, &mut output)?;
+            },
+
+            BlockType::Dynamic => {
+                let (literals, distances) = read_dynamic_header(&mut reader)?;
+                inflate_block(&mut reader, &literals, &distances, &mut output)?;
+            },
+        }
+
+        if is_final {
+            return Ok(output.into_boxed_slice());
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+
+    #[test]
+    fn check_canonical_codes() {
+
+        // Example from RFC 1951, section 3.2.2
+        assert_eq!(canonical_codes(&[3, 3, 3, 3, 3, 2, 4, 4]), [0b010, 0b011, 0b100, 0b101, 0b110, 0b00, 0b1110, 0b1111]);
+    }
+
+
+    #[test]
+    fn check_limit_code_lengths() {
+
+        let kraft = |lengths: &[u8], max: u8| -> u32 {
+            lengths.iter().filter(|l| **l != 0).map(|l| 1 << (max - l)).sum()
+        };
+
+        // A degenerate tree, as produced by geometric frequencies
+        let mut lengths: Vec<u8> = (1..=20).chain([20]).collect();
+        limit_code_lengths(&mut lengths, 15);
+        assert!(lengths.iter().all(|l| (1..=15).contains(l)));
+        assert_eq!(kraft(&lengths, 15), 1 << 15);
+
+        let mut lengths = [0, 3, 0];
+        limit_code_lengths(&mut lengths, 15);
+        assert_eq!(lengths, [1, 1, 0]);
+    }
+
+
+    #[test]
+    fn check_round_trip() {
+
+        let texts: [&[u8]; 5] = [
+            b"",
+            b"a",
+            b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            b"the quick brown fox jumps over the lazy dog, the quick brown fox",
+            &(0..=255).cycle().take(5000).collect::<Vec<u8>>(),
+        ];
+
+        for text in texts {
+            assert_eq!(*decompress_raw(&compress_raw(text)).unwrap(), *text);
+        }
+    }
+
+
+    #[test]
+    fn check_zlib_streams() {
+
+        // Raw DEFLATE streams produced by zlib for "hello hello hello"
+        let fixed = [0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x90, 0x00];
+        assert_eq!(*decompress_raw(&fixed).unwrap(), *b"hello hello hello");
+
+        let stored = [0x01, 0x05, 0x00, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o'];
+        assert_eq!(*decompress_raw(&stored).unwrap(), *b"hello");
+    }
+
+
+    #[test]
+    fn check_invalid_streams() {
+
+        assert_eq!(decompress_raw(&[]), Err(DeflateError::UnexpectedEnd));
+        assert_eq!(decompress_raw(&[0b111]), Err(DeflateError::InvalidBlockType { bit_offset: 1, found: 3 }));
+        assert_eq!(decompress_raw(&[0x01, 0x05, 0x00, 0x00, 0x00]), Err(DeflateError::InvalidStoredLength { offset: 1, length: 5, complement: 0 }));
+    }
+
+}
@@ -0,0 +1,903 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::bits::BitOrder;
+use crate::codecs::TreeCodec;
+use crate::container::{decode_to_end, read_varint, varint_size, write_counted_bitcode, write_varint, Header};
+use crate::tree::{byte_histogram, encode_with_table, Code, value_frequencies, DecodingTree, EncodingTree, NodeDeserializationError};
+use crate::{DecompressionError, Symbol, FORMAT_VERSION};
+
+
+/// Magic bytes at the start of a serialized dictionary
+const MAGIC: &[u8; 4] = b"FTCD";
+
+/// Magic bytes at the start of a serialized dictionary registry
+const REGISTRY_MAGIC: &[u8; 4] = b"FTCR";
+
+/// Byte in front of a tree serialized in the front-coded layout.
+/// A tree in the default layout starts with a specifier byte, which is never this value.
+const FRONT_CODED_LAYOUT: u8 = 0xfc;
+
+
+/// Options controlling how `Dictionary::train_with_options` counts the symbol frequencies
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrainingOptions<U> {
+
+    /// Add one to the count of every symbol (Laplace smoothing), including the symbols of `alphabet` missing from the samples.
+    /// Symbols rare in the samples get shorter codes, and the symbols of `alphabet` all get a code.
+    pub add_one: bool,
+
+    /// Symbols the real data may contain even though the samples don't, only counted when `add_one` is set
+    pub alphabet: Vec<U>,
+
+}
+
+impl<U> TrainingOptions<U> {
+
+    pub const fn new() -> Self {
+        Self {
+            add_one: false,
+            alphabet: Vec::new()
+        }
+    }
+
+}
+
+/// Options controlling how `Dictionary::serialize_with_options` writes a dictionary
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DictionarySerializationOptions {
+
+    /// Append the quantized count of each leaf, like `Dictionary::serialize_with_counts` does
+    pub counts: bool,
+
+    /// Write the tree in the front-coded layout, which stores the leaf values sorted and without the bytes
+    /// they share with the previous value. It shrinks dictionaries of wide symbols, like groups of bytes or strings of characters.
+    pub front_coded: bool,
+
+}
+
+impl DictionarySerializationOptions {
+
+    pub const fn new() -> Self {
+        Self {
+            counts: false,
+            front_coded: false
+        }
+    }
+
+}
+
+
+impl TrainingOptions<u8> {
+
+    /// Add-one smoothing over every byte value, so that the dictionary can encode any data
+    pub fn all_bytes() -> Self {
+        Self {
+            add_one: true,
+            alphabet: (0..=u8::MAX).collect()
+        }
+    }
+
+}
+
+
+/// An encoding tree trained over a corpus of samples, shared by the compressor and the decompressor.
+/// Data compressed with a dictionary doesn't embed its own tree, which makes a large difference for many small inputs.
+///
+/// Serialized layout:
+/// - magic bytes `FTCD` (4 bytes)
+/// - format version (1 byte)
+/// - decoding tree, or `FRONT_CODED_LAYOUT` followed by the tree in the front-coded layout
+/// - optionally, the count of each leaf in pre-order, quantized by `quantize_count` (2 bytes each, little-endian)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Dictionary<U: Clone> {
+
+    tree: DecodingTree<U>,
+
+    /// Identifier recorded in the header of the data compressed with this dictionary
+    id: u32,
+
+}
+
+impl<U> Dictionary<U>
+where
+    U: Symbol
+{
+
+    fn from_tree(tree: DecodingTree<U>) -> Self {
+
+        let mut buf = Vec::new();
+        tree.serialize(&mut buf);
+
+        Self {
+            id: fnv1a(&buf),
+            tree
+        }
+    }
+
+
+    /// Train a dictionary over the symbol frequencies of all the samples.
+    /// Return `None` if the samples contain no symbols.
+    pub fn train<I>(samples: impl IntoIterator<Item = I>) -> Option<Self>
+    where
+        I: IntoIterator<Item = U>
+    {
+        Self::train_with_options(samples, &TrainingOptions::new())
+    }
+
+
+    /// Train a dictionary over the symbol frequencies of all the samples, counted as specified by `options`.
+    /// Return `None` if neither the samples nor the smoothed alphabet contain any symbol.
+    pub fn train_with_options<I>(samples: impl IntoIterator<Item = I>, options: &TrainingOptions<U>) -> Option<Self>
+    where
+        I: IntoIterator<Item = U>
+    {
+
+        let mut frequencies = value_frequencies(samples.into_iter().flatten());
+
+        if options.add_one {
+
+            let mut counts: HashMap<U, usize> = frequencies.into_vec().into_iter().collect();
+
+            for symbol in &options.alphabet {
+                counts.entry(symbol.clone()).or_insert(0);
+            }
+            for count in counts.values_mut() {
+                *count += 1;
+            }
+
+            frequencies = counts.into_iter().collect();
+        }
+
+        EncodingTree::from_frequencies(&frequencies)
+            .into_decoder()
+            .map(Self::from_tree)
+    }
+
+
+    /// Train a new dictionary over the samples, in addition to the symbol counts of this dictionary.
+    /// A dictionary deserialized without its counts only keeps the symbols of its tree, which get the counts of the samples.
+    pub fn retrain<I>(&self, samples: impl IntoIterator<Item = I>) -> Self
+    where
+        I: IntoIterator<Item = U>
+    {
+
+        let mut counts: HashMap<U, usize> = self.frequencies().into_iter().collect();
+
+        for symbol in samples.into_iter().flatten() {
+            *counts.entry(symbol).or_insert(0) += 1;
+        }
+
+        let frequencies: Vec<(U, usize)> = counts.into_iter().collect();
+
+        // The tree of the dictionary has at least a leaf
+        Self::from_tree(EncodingTree::from_frequencies(&frequencies).into_decoder().unwrap())
+    }
+
+
+    /// Symbol counts the tree was built from, in the pre-order of the leaves.
+    /// The counts are 0 if the dictionary was deserialized without them, and approximate if they were quantized.
+    pub fn frequencies(&self) -> Vec<(U, usize)> {
+        self.tree.leaf_counts().into_iter()
+            .map(|(symbol, count)| (symbol.clone(), count))
+            .collect()
+    }
+
+
+    /// Identifier of the dictionary, derived from its tree
+    pub const fn id(&self) -> u32 {
+        self.id
+    }
+
+
+    /// The decoding tree shared by the compressor and the decompressor
+    pub const fn tree(&self) -> &DecodingTree<U> {
+        &self.tree
+    }
+
+
+    /// Bytes that every payload compressed with the dictionary takes besides the part counted by `estimated_payload_bits`:
+    /// the header, which records the dictionary, and the padding byte of the bitcode.
+    /// Compared with the tree `compress` would embed, it tells whether data is smaller with the shared dictionary.
+    pub fn overhead_bytes(&self) -> usize {
+        Header { dictionary: Some(self.id), symbol_type: U::TYPE, ..Header::new() }.serialized_size() + 1
+    }
+
+
+    /// Size in bits of a payload with the given value frequencies compressed with the dictionary, without `overhead_bytes`:
+    /// the symbol count and the bitcode, so that the payload takes `overhead_bytes() + bits.div_ceil(8)` bytes.
+    /// Return `None` if the dictionary can't encode a value of `frequencies`, in which case `compress` embeds a tree.
+    pub fn estimated_payload_bits(&self, frequencies: &[(U, usize)]) -> Option<u64> {
+
+        let lengths: HashMap<&U, usize> = self.tree.iter()
+            .map(|(value, code)| (value, code.len()))
+            .collect();
+
+        let mut count = 0;
+        let mut bits = 0;
+
+        for (value, value_count) in frequencies {
+            count += value_count;
+            bits += *lengths.get(value)? as u64 * *value_count as u64;
+        }
+
+        Some(8 * varint_size(count) as u64 + bits)
+    }
+
+
+    /// Compress the input with the dictionary tree.
+    /// If the tree can't encode every symbol of the input, the input is compressed with its own tree like `compress` does.
+    pub fn compress(&self, input: impl Iterator<Item = U> + Clone) -> Box<[u8]> {
+
+        let mut res = Vec::new();
+
+        if !encode_with_dictionary(self.id, &self.tree.code_table(), input.clone(), &mut res) {
+            return crate::compress(input);
+        }
+
+        res.into_boxed_slice()
+    }
+
+
+    /// Decompress data produced by `compress` on this dictionary.
+    /// Data that was compressed without a dictionary is decompressed like `decompress` does.
+    pub fn decompress(&self, input: &[u8]) -> Result<Box<[U]>, DecompressionError> {
+
+        let (header, header_size) = Header::deserialize(input).map_err(DecompressionError::InvalidHeader)?;
+
+        match header.dictionary {
+
+            None => crate::decompress(input),
+
+            Some (found) if found != self.id
+                => Err(DecompressionError::DictionaryMismatch { expected: self.id, found }),
+
+            Some (_) => {
+
+                if header.chunk_size != 1 {
+                    return Err(DecompressionError::ChunkSizeMismatch { expected: 1, found: header.chunk_size as usize });
+                }
+
+                decode_to_end(&TreeCodec, &self.tree, input, header_size, BitOrder::MsbFirst)
+            }
+        }
+    }
+
+
+    pub fn serialize(&self, buf: &mut Vec<u8>) {
+
+        buf.extend_from_slice(MAGIC);
+        buf.push(FORMAT_VERSION);
+
+        self.tree.serialize(buf);
+    }
+
+
+    /// Serialize the dictionary followed by the quantized count of each leaf, so that it can be retrained after it's loaded.
+    /// The counts take 2 bytes per symbol and don't change the identifier of the dictionary.
+    pub fn serialize_with_counts(&self, buf: &mut Vec<u8>) {
+        self.serialize_with_options(buf, &DictionarySerializationOptions { counts: true, ..DictionarySerializationOptions::new() });
+    }
+
+
+    /// Serialize the dictionary as specified by `options`.
+    /// The layout doesn't change the identifier of the dictionary.
+    pub fn serialize_with_options(&self, buf: &mut Vec<u8>, options: &DictionarySerializationOptions) {
+
+        buf.extend_from_slice(MAGIC);
+        buf.push(FORMAT_VERSION);
+
+        if options.front_coded {
+            buf.push(FRONT_CODED_LAYOUT);
+            self.tree.serialize_front_coded(buf);
+        } else {
+            self.tree.serialize(buf);
+        }
+
+        if options.counts {
+            for (_, count) in self.tree.leaf_counts() {
+                buf.extend_from_slice(&quantize_count(count).to_le_bytes());
+            }
+        }
+    }
+
+
+    /// Deserialize a dictionary written by `serialize`, `serialize_with_counts`, or `serialize_with_options`
+    pub fn deserialize(input: &[u8]) -> Result<Self, DictionaryDeserializationError> {
+
+        let offset = check_preamble(input, MAGIC)?;
+
+        let (mut tree, tree_size) = match input.get(offset) {
+            Some (&FRONT_CODED_LAYOUT) => DecodingTree::deserialize_front_coded_at(input, offset + 1)
+                .map(|(tree, tree_size)| (tree, tree_size + 1)),
+            _ => DecodingTree::deserialize_at(input, offset)
+        }.map_err(DictionaryDeserializationError::InvalidTree)?;
+
+        let end = offset + tree_size;
+        let remaining = input.len() - end;
+
+        if remaining == 2 * tree.leaf_counts().len() {
+            tree.set_leaf_counts(input[end..].chunks_exact(2).map(|bytes| dequantize_count(u16::from_le_bytes([bytes[0], bytes[1]]))));
+        }
+        else if remaining != 0 {
+            return Err(DictionaryDeserializationError::TrailingData { offset: end, remaining });
+        }
+
+        Ok(Self::from_tree(tree))
+    }
+
+}
+
+
+/// Append the header and the counted bitcode of `input`, encoded with the `table` of the dictionary `id`, to `buf`.
+/// Return `false`, leaving `buf` untouched, if the table can't encode every symbol of the input.
+pub(crate) fn encode_with_dictionary<U>(id: u32, table: &HashMap<U, Code>, input: impl Iterator<Item = U> + Clone, buf: &mut Vec<u8>) -> bool
+where
+    U: Symbol
+{
+
+    if !input.clone().all(|symbol| table.contains_key(&symbol)) {
+        return false;
+    }
+
+    let header = Header { dictionary: Some(id), symbol_type: U::TYPE, ..Header::new() };
+
+    buf.reserve(header.serialized_size());
+    header.serialize(buf);
+
+    let count = input.clone().count();
+    write_counted_bitcode(buf, count, &encode_with_table(table, input, 0), BitOrder::MsbFirst);
+
+    true
+}
+
+
+impl Dictionary<u8> {
+
+    /// Train a byte dictionary over the contents of the sample files.
+    /// Every byte value is counted at least once, so that the dictionary can encode any file.
+    pub fn train_from_files<P>(paths: impl IntoIterator<Item = P>) -> io::Result<Self>
+    where
+        P: AsRef<Path>
+    {
+
+        let mut counts = [1_usize; 256];
+
+        for path in paths {
+            for (count, file_count) in counts.iter_mut().zip(byte_histogram(&fs::read(path)?)) {
+                *count += file_count;
+            }
+        }
+
+        let frequencies: Vec<(u8, usize)> = (0..=u8::MAX).zip(counts).collect();
+
+        Ok(Self::from_tree(
+            // There are always 256 values
+            EncodingTree::from_frequencies(&frequencies).into_decoder().unwrap()
+        ))
+    }
+
+}
+
+
+/// Compress many independent payloads of the same kind of data, such as the pages of a column, with a single tree
+/// trained over all of them. Each payload is compressed separately and decompressed with `Dictionary::decompress`,
+/// so the tree is stored once, with the dictionary, rather than in every payload.
+/// Return `None` if the payloads contain no symbols.
+#[allow(clippy::type_complexity)]
+pub fn compress_batch<U, I>(payloads: &[I]) -> Option<(Dictionary<U>, Vec<Box<[u8]>>)>
+where
+    U: Symbol,
+    I: Iterator<Item = U> + Clone
+{
+
+    let dictionary = Dictionary::train(payloads.iter().cloned())?;
+    let table = dictionary.tree.code_table();
+
+    let compressed = payloads.iter()
+        .map(|payload| {
+            let mut res = Vec::new();
+            // The dictionary was trained on every payload, so it can encode all their symbols
+            let encoded = encode_with_dictionary(dictionary.id, &table, payload.clone(), &mut res);
+            debug_assert!(encoded);
+            res.into_boxed_slice()
+        })
+        .collect();
+
+    Some((dictionary, compressed))
+}
+
+
+/// Steps of the quantized counts per doubling of the count
+const COUNT_STEPS_PER_OCTAVE: f64 = 1023.0;
+
+
+/// Quantize a count to 16 bits on a logarithmic scale: 0 stays 0, and other counts are approximated within 0.04%.
+/// Any `usize` fits, since `1 + 64 * COUNT_STEPS_PER_OCTAVE` is below `u16::MAX`.
+fn quantize_count(count: usize) -> u16 {
+
+    if count == 0 {
+        return 0;
+    }
+
+    1 + ((count as f64).log2() * COUNT_STEPS_PER_OCTAVE).round() as u16
+}
+
+
+/// Approximate the count quantized by `quantize_count`
+fn dequantize_count(quantized: u16) -> usize {
+
+    if quantized == 0 {
+        return 0;
+    }
+
+    // Saturates for the steps above the largest count
+    (f64::from(quantized - 1) / COUNT_STEPS_PER_OCTAVE).exp2().round() as usize
+}
+
+
+/// 32-bit FNV-1a hash, used to identify dictionaries
+const fn fnv1a(bytes: &[u8]) -> u32 {
+
+    let mut hash: u32 = 0x811c_9dc5;
+
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+        i += 1;
+    }
+
+    hash
+}
+
+
+
+/// Check the magic bytes and the format version at the start of `input`.
+/// Return the number of bytes read.
+fn check_preamble(input: &[u8], magic: &[u8; 4]) -> Result<usize, DictionaryDeserializationError> {
+
+    if input.get(..magic.len()) != Some(magic) {
+        return Err(DictionaryDeserializationError::NotADictionary);
+    }
+
+    let version = *input.get(magic.len())
+        .ok_or(DictionaryDeserializationError::NotADictionary)?;
+
+    if version != FORMAT_VERSION {
+        return Err(DictionaryDeserializationError::UnsupportedVersion { found: version, expected: FORMAT_VERSION });
+    }
+
+    Ok(magic.len() + 1)
+}
+
+
+/// A set of dictionaries indexed by identifier, for services that compress with many trained dictionaries.
+/// The registry resolves the dictionary recorded in the header of compressed data.
+///
+/// Serialized layout:
+/// - magic bytes `FTCR` (4 bytes)
+/// - format version (1 byte)
+/// - number of dictionaries (varint)
+/// - decoding tree of each dictionary, by increasing identifier
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DictionaryRegistry<U: Clone> {
+
+    dictionaries: BTreeMap<u32, Arc<Dictionary<U>>>,
+
+}
+
+impl<U> DictionaryRegistry<U>
+where
+    U: Symbol
+{
+
+    pub const fn new() -> Self {
+        Self {
+            dictionaries: BTreeMap::new()
+        }
+    }
+
+
+    /// Add a dictionary to the registry and return the shared handle stored for its identifier.
+    /// A dictionary with the same identifier has the same tree, so it's only stored once.
+    pub fn insert(&mut self, dictionary: Dictionary<U>) -> Arc<Dictionary<U>> {
+        self.dictionaries.entry(dictionary.id())
+            .or_insert_with(|| Arc::new(dictionary))
+            .clone()
+    }
+
+
+    pub fn get(&self, id: u32) -> Option<&Arc<Dictionary<U>>> {
+        self.dictionaries.get(&id)
+    }
+
+
+    pub fn remove(&mut self, id: u32) -> Option<Arc<Dictionary<U>>> {
+        self.dictionaries.remove(&id)
+    }
+
+
+    pub fn len(&self) -> usize {
+        self.dictionaries.len()
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.dictionaries.is_empty()
+    }
+
+
+    /// Iterate over the dictionaries by increasing identifier
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<Dictionary<U>>> {
+        self.dictionaries.values()
+    }
+
+
+    /// Return the dictionary that encoded `input` according to its header, or `None` if it was compressed without a dictionary
+    pub fn resolve(&self, input: &[u8]) -> Result<Option<&Arc<Dictionary<U>>>, DecompressionError> {
+
+        let (header, _) = Header::deserialize(input).map_err(DecompressionError::InvalidHeader)?;
+
+        header.dictionary
+            .map(|id| self.get(id).ok_or(DecompressionError::MissingDictionary { id }))
+            .transpose()
+    }
+
+
+    /// Decompress data compressed with any dictionary of the registry, or without a dictionary
+    pub fn decompress(&self, input: &[u8]) -> Result<Box<[U]>, DecompressionError> {
+        match self.resolve(input)? {
+            Some(dictionary) => dictionary.decompress(input),
+            None => crate::decompress(input),
+        }
+    }
+
+
+    pub fn serialize(&self, buf: &mut Vec<u8>) {
+
+        buf.extend_from_slice(REGISTRY_MAGIC);
+        buf.push(FORMAT_VERSION);
+
+        write_varint(buf, self.dictionaries.len());
+
+        for dictionary in self.dictionaries.values() {
+            dictionary.tree.serialize(buf);
+        }
+    }
+
+
+    pub fn deserialize(input: &[u8]) -> Result<Self, DictionaryDeserializationError> {
+
+        let mut offset = check_preamble(input, REGISTRY_MAGIC)?;
+
+        let (count, count_size) = read_varint(input, offset)
+            .map_err(|_| DictionaryDeserializationError::InvalidDictionaryCount { offset })?;
+        offset += count_size;
+
+        let mut registry = Self::new();
+
+        for _ in 0..count {
+
+            let (tree, tree_size) = DecodingTree::deserialize_at(input, offset)
+                .map_err(DictionaryDeserializationError::InvalidTree)?;
+            offset += tree_size;
+
+            registry.insert(Dictionary::from_tree(tree));
+        }
+
+        if offset != input.len() {
+            return Err(DictionaryDeserializationError::TrailingData { offset, remaining: input.len() - offset });
+        }
+
+        Ok(registry)
+    }
+
+}
+
+impl<U> Default for DictionaryRegistry<U>
+where
+    U: Symbol
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Error returned when a serialized dictionary is malformed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DictionaryDeserializationError {
+
+    /// The input doesn't start with the dictionary magic bytes and format version
+    NotADictionary,
+    /// The dictionary was produced by an unsupported version of the format
+    UnsupportedVersion { found: u8, expected: u8 },
+    /// The serialized tree is malformed
+    InvalidTree (NodeDeserializationError),
+    /// The input continues after the end of the tree
+    TrailingData { offset: usize, remaining: usize },
+    /// The number of dictionaries of a registry at `offset` is truncated or too large
+    InvalidDictionaryCount { offset: usize },
+
+}
+
+impl fmt::Display for DictionaryDeserializationError {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DictionaryDeserializationError::NotADictionary
+                => write!(f, "the data is not a dictionary"),
+            DictionaryDeserializationError::UnsupportedVersion { found, expected }
+                => write!(f, "unsupported dictionary format version {found}, expected {expected}"),
+            DictionaryDeserializationError::InvalidTree (_)
+                => write!(f, "invalid dictionary tree"),
+            DictionaryDeserializationError::TrailingData { offset, remaining }
+                => write!(f, "{remaining} bytes of trailing data after the dictionary tree at byte {offset}"),
+            DictionaryDeserializationError::InvalidDictionaryCount { offset }
+                => write!(f, "invalid number of dictionaries at byte {offset}"),
+        }
+    }
+}
+
+impl Error for DictionaryDeserializationError {
+
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DictionaryDeserializationError::InvalidTree (e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use crate::{compress, decompress};
+
+
+    const SAMPLES: [&str; 3] = [
+        "the quick brown fox jumps over the lazy dog",
+        "a lazy dog sleeps all day",
+        "the fox is quick and brown",
+    ];
+
+
+    #[test]
+    fn check_dictionary_compression() {
+
+        let dictionary = Dictionary::train(SAMPLES.map(str::chars)).unwrap();
+
+        let text = "the brown dog is lazy";
+
+        let compressed = dictionary.compress(text.chars());
+
+        assert!(compressed.len() < compress(text.chars()).len());
+        assert_eq!(dictionary.decompress(&compressed).unwrap().iter().collect::<String>(), text);
+        assert_eq!(decompress::<char>(&compressed), Err(DecompressionError::MissingDictionary { id: dictionary.id() }));
+
+        // Symbols missing from the dictionary fall back to an embedded tree
+        let compressed = dictionary.compress("xyz!".chars());
+        assert_eq!(decompress::<char>(&compressed).unwrap().iter().collect::<String>(), "xyz!");
+        assert_eq!(dictionary.decompress(&compressed).unwrap().iter().collect::<String>(), "xyz!");
+
+        // A single-leaf dictionary encodes its symbol with zero bits
+        let single = Dictionary::train(["aaa".chars()]).unwrap();
+        let compressed = single.compress("aaaaa".chars());
+        assert_eq!(Header::deserialize(&compressed).unwrap().0.dictionary, Some(single.id()));
+        assert_eq!(*single.decompress(&compressed).unwrap(), ['a'; 5]);
+
+        let other = Dictionary::train(["hello world".chars()]).unwrap();
+        let compressed = other.compress("hello".chars());
+        assert_eq!(dictionary.decompress(&compressed), Err(DecompressionError::DictionaryMismatch { expected: dictionary.id(), found: other.id() }));
+    }
+
+
+    #[test]
+    fn check_compress_batch() {
+
+        let payloads = SAMPLES.map(str::chars);
+        let (dictionary, compressed) = compress_batch(&payloads).unwrap();
+
+        assert_eq!(dictionary, Dictionary::train(SAMPLES.map(str::chars)).unwrap());
+        assert_eq!(compressed.len(), SAMPLES.len());
+
+        for (sample, compressed) in SAMPLES.iter().zip(&compressed) {
+            assert_eq!(dictionary.decompress(compressed).unwrap().iter().collect::<String>(), *sample);
+            assert!(compressed.len() < compress(sample.chars()).len());
+        }
+
+        // An empty payload among others is still compressed
+        let (dictionary, compressed) = compress_batch(&["abc".chars(), "".chars()]).unwrap();
+        assert!(dictionary.decompress(&compressed[1]).unwrap().is_empty());
+
+        assert_eq!(compress_batch::<char, _>(&["".chars()]), None);
+    }
+
+    #[test]
+    fn check_payload_estimate() {
+
+        let dictionary = Dictionary::train(SAMPLES.map(str::chars)).unwrap();
+
+        for text in ["the brown dog is lazy", "", &"a quick fox ".repeat(30)] {
+
+            let frequencies = value_frequencies(text.chars());
+            let bits = dictionary.estimated_payload_bits(&frequencies).unwrap();
+
+            assert_eq!(dictionary.compress(text.chars()).len() as u64, dictionary.overhead_bytes() as u64 + bits.div_ceil(8));
+        }
+
+        assert_eq!(dictionary.estimated_payload_bits(&[('!', 1)]), None);
+    }
+
+    #[test]
+    fn check_add_one_smoothing() {
+
+        let alphabet: Vec<char> = ('a'..='z').chain([' ', '!']).collect();
+        let options = TrainingOptions { add_one: true, alphabet };
+
+        let plain = Dictionary::train(SAMPLES.map(str::chars)).unwrap();
+        let smoothed = Dictionary::train_with_options(SAMPLES.map(str::chars), &options).unwrap();
+
+        // 'x' and '!' never occur in the samples
+        let text = "the lazy fox!";
+
+        let compressed = smoothed.compress(text.chars());
+        assert_eq!(Header::deserialize(&compressed).unwrap().0.dictionary, Some(smoothed.id()));
+        assert_eq!(smoothed.decompress(&compressed).unwrap().iter().collect::<String>(), text);
+
+        assert_eq!(Header::deserialize(&plain.compress(text.chars())).unwrap().0.dictionary, None);
+
+        // Without samples, the alphabet alone is trained uniformly
+        let uniform = Dictionary::train_with_options([""; 0].map(str::chars), &options).unwrap();
+        assert_eq!(uniform.tree.code_table().len(), 28);
+        assert!(Dictionary::train_with_options([""; 0].map(str::chars), &TrainingOptions { add_one: true, alphabet: Vec::new() }).is_none());
+
+        let bytes = Dictionary::train_with_options(SAMPLES.map(str::bytes), &TrainingOptions::all_bytes()).unwrap();
+        assert_eq!(bytes.tree.code_table().len(), 256);
+    }
+
+
+    #[test]
+    fn check_serde() {
+
+        let dictionary = Dictionary::train(SAMPLES.map(str::bytes)).unwrap();
+
+        let mut buf = Vec::new();
+        dictionary.serialize(&mut buf);
+
+        assert_eq!(Dictionary::deserialize(&buf), Ok(dictionary));
+
+        assert_eq!(Dictionary::<u8>::deserialize(b"FTC"), Err(DictionaryDeserializationError::NotADictionary));
+        assert_eq!(Dictionary::<u8>::deserialize(&[b'F', b'T', b'C', b'D', FORMAT_VERSION + 1]), Err(DictionaryDeserializationError::UnsupportedVersion { found: FORMAT_VERSION + 1, expected: FORMAT_VERSION }));
+
+        buf.push(0);
+        assert_eq!(Dictionary::<u8>::deserialize(&buf), Err(DictionaryDeserializationError::TrailingData { offset: buf.len() - 1, remaining: 1 }));
+    }
+
+
+    #[test]
+    fn check_quantized_counts() {
+
+        let dictionary = Dictionary::train(SAMPLES.map(str::bytes)).unwrap();
+
+        let mut plain = Vec::new();
+        dictionary.serialize(&mut plain);
+        let mut with_counts = Vec::new();
+        dictionary.serialize_with_counts(&mut with_counts);
+        assert_eq!(with_counts.len(), plain.len() + 2 * dictionary.frequencies().len());
+
+        // The counts are small enough to survive the quantization exactly
+        let loaded = Dictionary::<u8>::deserialize(&with_counts).unwrap();
+        assert_eq!(loaded, dictionary);
+        assert_eq!(loaded.frequencies(), dictionary.frequencies());
+        assert!(Dictionary::<u8>::deserialize(&plain).unwrap().frequencies().iter().all(|(_, count)| *count == 0));
+
+        // Retraining a loaded dictionary is like training over all the samples
+        let more = ["user 99 logged in", "user 98 logged in"];
+        let retrained = loaded.retrain(more.map(str::bytes));
+        let mut all_counts = retrained.frequencies();
+        all_counts.sort();
+        let mut expected = Dictionary::train(SAMPLES.iter().chain(&more).map(|sample| sample.bytes())).unwrap().frequencies();
+        expected.sort();
+        assert_eq!(all_counts, expected);
+
+        for count in [0, 1, 2, 3, 1000, 123_456_789, usize::MAX] {
+            let approximated = dequantize_count(quantize_count(count));
+            assert!(approximated.abs_diff(count) as f64 <= count as f64 * 0.0004, "{count} {approximated}");
+        }
+    }
+
+
+    #[test]
+    fn check_front_coded_dictionary() {
+
+        // Identifiers of 8 bytes share their first bytes
+        let lines: Vec<String> = (0..200).map(|i| format!("id:{i:05}").repeat(i % 3 + 1)).collect();
+        let groups = |line: &String| line.as_bytes().chunks_exact(8).map(|group| <[u8; 8]>::try_from(group).unwrap()).collect::<Vec<_>>();
+
+        let dictionary = Dictionary::train(lines.iter().map(groups)).unwrap();
+
+        let mut plain = Vec::new();
+        dictionary.serialize(&mut plain);
+
+        let options = DictionarySerializationOptions { counts: true, front_coded: true };
+        let mut front_coded = Vec::new();
+        dictionary.serialize_with_options(&mut front_coded, &options);
+
+        let counts_size = 2 * dictionary.frequencies().len();
+        assert!(front_coded.len() - counts_size < plain.len() * 2 / 3, "{} {}", front_coded.len() - counts_size, plain.len());
+
+        let loaded = Dictionary::<[u8; 8]>::deserialize(&front_coded).unwrap();
+        assert_eq!(loaded, dictionary);
+        assert_eq!(loaded.id(), dictionary.id());
+        assert_eq!(loaded.frequencies(), dictionary.frequencies());
+
+        let message = groups(&lines[42]);
+        assert_eq!(*loaded.decompress(&dictionary.compress(message.iter().copied())).unwrap(), *message);
+
+        front_coded.truncate(front_coded.len() - counts_size - 1);
+        assert!(matches!(Dictionary::<[u8; 8]>::deserialize(&front_coded), Err(DictionaryDeserializationError::InvalidTree (_))));
+    }
+
+
+    #[test]
+    fn check_registry() {
+
+        let first = Dictionary::train(SAMPLES.map(str::chars)).unwrap();
+        let second = Dictionary::train(["hello world".chars()]).unwrap();
+
+        let compressed_first = first.compress("the lazy fox".chars());
+        let compressed_second = second.compress("hello".chars());
+        let compressed_plain = compress("plain".chars());
+
+        let mut registry = DictionaryRegistry::new();
+        let handle = registry.insert(first.clone());
+        registry.insert(second);
+        assert!(Arc::ptr_eq(&handle, &registry.insert(first)));
+        assert_eq!(registry.len(), 2);
+
+        assert_eq!(registry.resolve(&compressed_first).unwrap().map(|dictionary| dictionary.id()), Some(handle.id()));
+        assert_eq!(registry.resolve(&compressed_plain), Ok(None));
+        assert_eq!(registry.decompress(&compressed_first).unwrap().iter().collect::<String>(), "the lazy fox");
+        assert_eq!(registry.decompress(&compressed_second).unwrap().iter().collect::<String>(), "hello");
+        assert_eq!(registry.decompress(&compressed_plain).unwrap().iter().collect::<String>(), "plain");
+
+        let mut buf = Vec::new();
+        registry.serialize(&mut buf);
+        let deserialized = DictionaryRegistry::<char>::deserialize(&buf).unwrap();
+        assert_eq!(deserialized, registry);
+        assert_eq!(deserialized.decompress(&compressed_second).unwrap().iter().collect::<String>(), "hello");
+
+        assert_eq!(DictionaryRegistry::<char>::deserialize(&buf[..5]), Err(DictionaryDeserializationError::InvalidDictionaryCount { offset: 5 }));
+        assert_eq!(DictionaryRegistry::<char>::deserialize(b"FTCD"), Err(DictionaryDeserializationError::NotADictionary));
+
+        let id = handle.id();
+        registry.remove(id);
+        assert_eq!(registry.resolve(&compressed_first), Err(DecompressionError::MissingDictionary { id }));
+    }
+
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads the test data files")]
+    fn check_train_from_files() {
+
+        let dictionary = Dictionary::train_from_files(["test_data/lorem.txt"]).unwrap();
+
+        // Every byte value is covered, even the ones missing from the samples
+        let data: Vec<u8> = (0..=u8::MAX).collect();
+
+        let compressed = dictionary.compress(data.iter().copied());
+
+        assert!(Header::deserialize(&compressed).unwrap().0.dictionary.is_some());
+        assert_eq!(*dictionary.decompress(&compressed).unwrap(), *data);
+    }
+
+}
@@ -0,0 +1,621 @@
+//! Forward error correction of the critical section of compressed data, for archives stored on unreliable media.
+//!
+//! A flipped bit in the bitcode only damages the symbols around it, but a damaged header or tree makes the whole payload
+//! undecodable. `protect` computes Reed-Solomon parity bytes over the header and the tree, or the alphabet and the tree,
+//! and prepends them to the compressed data, and `recover` corrects the errors in these bytes before they're decoded.
+//!
+//! Protected layout:
+//! - the `MAGIC` bytes
+//! - three copies of the parameters, which are read by a bytewise majority vote: the length of the protected section
+//!   (4 bytes, little-endian) and the number of parity bytes per codeword (1 byte)
+//! - the parity bytes of each codeword in order
+//! - the compressed data, unchanged
+//!
+//! The protected section is split into codewords of at most `255 - parity_bytes` bytes, and each codeword can correct up to
+//! half as many damaged bytes as it has parity bytes, wherever they are. The codes are the usual ones over GF(256),
+//! with the primitive polynomial `0x11d` and the roots `2^0` to `2^(parity_bytes - 1)`.
+//! The bytes after the protected section are not corrected: streams can localize their damage with block checksums instead.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::container::{read_alphabet, read_compact_alphabet, read_tree, Header};
+use crate::seek::read_checkpoints;
+use crate::transforms::remap;
+use crate::{compress, decompress, DecompressionError, Symbol, SymbolSource};
+
+
+/// First bytes of protected data
+pub const MAGIC: [u8; 4] = *b"FTC\xec";
+
+/// Size in bytes of a copy of the parameters
+const PARAMETERS_SIZE: usize = 5;
+
+/// Number of copies of the parameters
+const PARAMETER_COPIES: usize = 3;
+
+/// Maximum number of bytes of a codeword, data and parity
+const CODEWORD_SIZE: usize = 255;
+
+/// Primitive polynomial of GF(256), `x^8 + x^4 + x^3 + x^2 + 1`
+const PRIMITIVE: u16 = 0x11d;
+
+
+/// Powers of 2 in GF(256), repeated once so that the sum of two logarithms can be looked up without a modulo,
+/// and the logarithms of the nonzero elements
+const TABLES: ([u8; 2 * CODEWORD_SIZE], [u8; 256]) = {
+
+    let mut exp = [0; 2 * CODEWORD_SIZE];
+    let mut log = [0; 256];
+
+    let mut x: u16 = 1;
+    let mut i = 0;
+    while i < CODEWORD_SIZE {
+        exp[i] = x as u8;
+        log[x as usize] = i as u8;
+        x <<= 1;
+        if x & 0x100 != 0 {
+            x ^= PRIMITIVE;
+        }
+        i += 1;
+    }
+    while i < 2 * CODEWORD_SIZE {
+        exp[i] = exp[i - CODEWORD_SIZE];
+        i += 1;
+    }
+
+    (exp, log)
+};
+
+const EXP: [u8; 2 * CODEWORD_SIZE] = TABLES.0;
+const LOG: [u8; 256] = TABLES.1;
+
+
+fn mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 { 0 } else { EXP[LOG[a as usize] as usize + LOG[b as usize] as usize] }
+}
+
+
+/// `a / b`, for a nonzero `b`
+fn div(a: u8, b: u8) -> u8 {
+    if a == 0 { 0 } else { EXP[LOG[a as usize] as usize + CODEWORD_SIZE - LOG[b as usize] as usize] }
+}
+
+
+/// Inverse of a nonzero element
+fn inverse(x: u8) -> u8 {
+    EXP[CODEWORD_SIZE - LOG[x as usize] as usize]
+}
+
+
+/// `2^n`
+fn pow2(n: usize) -> u8 {
+    EXP[n % CODEWORD_SIZE]
+}
+
+
+// Polynomials are stored from the highest degree coefficient to the constant one
+
+
+fn poly_eval(p: &[u8], x: u8) -> u8 {
+    p.iter().fold(0, |y, &coefficient| mul(y, x) ^ coefficient)
+}
+
+
+fn poly_mul(p: &[u8], q: &[u8]) -> Vec<u8> {
+
+    let mut product = vec![0; p.len() + q.len() - 1];
+
+    for (j, &b) in q.iter().enumerate() {
+        for (i, &a) in p.iter().enumerate() {
+            product[i + j] ^= mul(a, b);
+        }
+    }
+
+    product
+}
+
+
+fn poly_add(p: &[u8], q: &[u8]) -> Vec<u8> {
+
+    let mut sum = vec![0; p.len().max(q.len())];
+
+    let len = sum.len();
+    for (i, &a) in p.iter().enumerate() {
+        sum[i + len - p.len()] = a;
+    }
+    for (i, &b) in q.iter().enumerate() {
+        sum[i + len - q.len()] ^= b;
+    }
+
+    sum
+}
+
+
+fn poly_scale(p: &[u8], x: u8) -> Vec<u8> {
+    p.iter().map(|&coefficient| mul(coefficient, x)).collect()
+}
+
+
+/// Generator polynomial of the code with `parity` parity bytes, whose roots are `2^0` to `2^(parity - 1)`
+fn generator(parity: usize) -> Vec<u8> {
+    (0..parity).fold(vec![1], |generator, i| poly_mul(&generator, &[1, pow2(i)]))
+}
+
+
+/// Parity bytes of the codeword whose data is `data`
+fn encode_codeword(data: &[u8], generator: &[u8]) -> Vec<u8> {
+
+    let parity = generator.len() - 1;
+
+    // Remainder of the division of the data, shifted by the parity, by the generator
+    let mut remainder = vec![0; parity];
+
+    for &byte in data {
+
+        let coefficient = byte ^ remainder[0];
+
+        remainder.rotate_left(1);
+        remainder[parity - 1] = 0;
+
+        for (r, &g) in remainder.iter_mut().zip(&generator[1..]) {
+            *r ^= mul(g, coefficient);
+        }
+    }
+
+    remainder
+}
+
+
+/// Syndromes of `codeword`, preceded by a zero so that the indices of the Berlekamp-Massey algorithm start at 1
+fn syndromes(codeword: &[u8], parity: usize) -> Vec<u8> {
+    std::iter::once(0)
+        .chain((0..parity).map(|i| poly_eval(codeword, pow2(i))))
+        .collect()
+}
+
+
+/// Correct the errors of `codeword`, whose last `parity` bytes are parity.
+/// Return the number of corrected bytes, or `None` if there are too many errors to correct.
+fn correct_codeword(codeword: &mut [u8], parity: usize) -> Option<usize> {
+
+    let syndromes = syndromes(codeword, parity);
+
+    if syndromes.iter().all(|&s| s == 0) {
+        return Some(0);
+    }
+
+    // Berlekamp-Massey: find the error locator polynomial
+    let mut locator = vec![1];
+    let mut old = vec![1];
+
+    for k in 1..=parity {
+
+        let mut delta = syndromes[k];
+        for j in 1..locator.len() {
+            delta ^= mul(locator[locator.len() - 1 - j], syndromes[k - j]);
+        }
+
+        old.push(0);
+
+        if delta != 0 {
+            if old.len() > locator.len() {
+                let scaled = poly_scale(&old, delta);
+                old = poly_scale(&locator, inverse(delta));
+                locator = scaled;
+            }
+            locator = poly_add(&locator, &poly_scale(&old, delta));
+        }
+    }
+
+    let leading_zeros = locator.iter().take_while(|&&coefficient| coefficient == 0).count();
+    let locator = &locator[leading_zeros..];
+
+    let errors = locator.len() - 1;
+    if errors * 2 > parity {
+        return None;
+    }
+
+    // Chien search: the roots of the locator give the positions of the errors, counted from the end of the codeword
+    let reversed: Vec<u8> = locator.iter().rev().copied().collect();
+    let powers: Vec<usize> = (0..codeword.len())
+        .filter(|&i| poly_eval(&reversed, pow2(i)) == 0)
+        .collect();
+
+    if powers.len() != errors {
+        return None;
+    }
+
+    // Forney: compute the magnitude of each error from the error evaluator polynomial
+    let errata_locator = powers.iter().fold(vec![1], |e, &i| poly_mul(&e, &[pow2(i), 1]));
+
+    let reversed_syndromes: Vec<u8> = syndromes.iter().rev().copied().collect();
+    let product = poly_mul(&reversed_syndromes, &errata_locator);
+    let evaluator = &product[product.len().saturating_sub(errata_locator.len())..];
+
+    let locations: Vec<u8> = powers.iter().map(|&i| pow2(i)).collect();
+
+    for (i, &x) in locations.iter().enumerate() {
+
+        let x_inverse = inverse(x);
+
+        let derivative = locations.iter().enumerate()
+            .filter(|&(j, _)| j != i)
+            .fold(1, |product, (_, &other)| mul(product, 1 ^ mul(x_inverse, other)));
+
+        if derivative == 0 {
+            return None;
+        }
+
+        let magnitude = div(mul(x, poly_eval(evaluator, x_inverse)), derivative);
+
+        codeword[codeword.len() - 1 - powers[i]] ^= magnitude;
+    }
+
+    syndromes_are_zero(codeword, parity).then_some(errors)
+}
+
+
+fn syndromes_are_zero(codeword: &[u8], parity: usize) -> bool {
+    (0..parity).all(|i| poly_eval(codeword, pow2(i)) == 0)
+}
+
+
+/// Options of `protect`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FecOptions {
+
+    /// Parity bytes of each codeword of at most 255 bytes, which corrects up to half as many damaged bytes.
+    /// Must be between 1 and 254.
+    pub parity_bytes: u8,
+
+}
+
+impl FecOptions {
+
+    pub const fn new() -> Self {
+        Self {
+            parity_bytes: 32
+        }
+    }
+
+}
+
+impl Default for FecOptions {
+
+    fn default() -> Self {
+        Self::new()
+    }
+
+}
+
+
+/// Size in bytes of the critical section of the compressed `data`, which `protect` corrects: the header,
+/// followed by the tree, by the alphabet and the tree of the `MTF` and `REMAP` layouts, or by the tree and the checkpoint table.
+///
+/// Only the header is critical in streams, whose trees are spread over their blocks, in LZ77 payloads, whose trees are spread
+/// over their token streams, in chunked data, and in data encoded with a dictionary, whose tree is in the dictionary.
+pub fn critical_section_size<U>(data: &[u8]) -> Result<usize, DecompressionError>
+where
+    U: Symbol
+{
+
+    let (header, header_size) = Header::deserialize(data).map_err(DecompressionError::InvalidHeader)?;
+
+    if header.blocks || header.lz77 || header.chunk_size != 1 || header.dictionary.is_some() {
+        return Ok(header_size);
+    }
+
+    let size = if header.mtf {
+
+        let (_, alphabet_size) = read_alphabet::<U>(data, header_size)?;
+        let (_, tree_size) = read_tree::<u32>(data, header_size + alphabet_size, &header)?;
+
+        alphabet_size + tree_size
+
+    } else if header.remap {
+
+        let (alphabet, alphabet_size) = read_compact_alphabet::<U>(data, header_size)?;
+        let offset = header_size + alphabet_size;
+
+        let tree_size = match remap::id_width(alphabet.len()) {
+            1 => read_tree::<u8>(data, offset, &header)?.1,
+            2 => read_tree::<u16>(data, offset, &header)?.1,
+            _ => read_tree::<u32>(data, offset, &header)?.1,
+        };
+
+        alphabet_size + tree_size
+
+    } else {
+
+        let (_, tree_size) = read_tree::<U>(data, header_size, &header)?;
+
+        if header.checkpoints {
+            tree_size + read_checkpoints(data, header_size + tree_size)?.2
+        } else {
+            tree_size
+        }
+    };
+
+    Ok(header_size + size)
+}
+
+
+/// Protect the critical section of the compressed `data`, whose symbols are of type `U`, with error correction codes.
+/// Fail if the critical section can't be read.
+///
+/// Panics if `options.parity_bytes` is 0 or 255.
+pub fn protect<U>(data: &[u8], options: &FecOptions) -> Result<Vec<u8>, DecompressionError>
+where
+    U: Symbol
+{
+    Ok(protect_prefix(data, critical_section_size::<U>(data)?, options))
+}
+
+
+/// Protect the first `len` bytes of `data` with error correction codes, for example a whole serialized dictionary.
+///
+/// Panics if `len` exceeds the length of `data`, if it doesn't fit in 32 bits, or if `options.parity_bytes` is 0 or 255.
+pub fn protect_prefix(data: &[u8], len: usize, options: &FecOptions) -> Vec<u8> {
+
+    let parity = options.parity_bytes as usize;
+    assert!((1..CODEWORD_SIZE).contains(&parity), "a codeword can't have {parity} parity bytes");
+    assert!(len <= data.len(), "the protected section of {len} bytes exceeds the {} bytes of the data", data.len());
+
+    let protected_len = u32::try_from(len).expect("the protected section must fit in 32 bits");
+
+    let generator = generator(parity);
+    let codewords = len.div_ceil(CODEWORD_SIZE - parity);
+
+    let mut protected = Vec::with_capacity(MAGIC.len() + PARAMETER_COPIES * PARAMETERS_SIZE + codewords * parity + data.len());
+    protected.extend_from_slice(&MAGIC);
+
+    for _ in 0..PARAMETER_COPIES {
+        protected.extend_from_slice(&protected_len.to_le_bytes());
+        protected.push(options.parity_bytes);
+    }
+
+    for chunk in data[..len].chunks(CODEWORD_SIZE - parity) {
+        protected.extend_from_slice(&encode_codeword(chunk, &generator));
+    }
+
+    protected.extend_from_slice(data);
+
+    protected
+}
+
+
+/// Correct the errors in the protected section of the output of `protect`, and return the compressed data without the parity bytes
+pub fn recover(protected: &[u8]) -> Result<Vec<u8>, FecError> {
+
+    if !protected.starts_with(&MAGIC) {
+        return Err(FecError::NotProtected);
+    }
+
+    let copies_end = MAGIC.len() + PARAMETER_COPIES * PARAMETERS_SIZE;
+    let copies = protected.get(MAGIC.len()..copies_end)
+        .ok_or(FecError::Truncated { expected: copies_end, found: protected.len() })?;
+
+    // A damaged byte is outvoted by its two other copies
+    let parameters: Vec<u8> = (0..PARAMETERS_SIZE)
+        .map(|i| {
+            let [a, b, c] = [0, 1, 2].map(|copy| copies[copy * PARAMETERS_SIZE + i]);
+            if a == b || a == c { a } else { b }
+        })
+        .collect();
+
+    let len = u32::from_le_bytes(parameters[..4].try_into().unwrap()) as usize;
+    let parity = parameters[4] as usize;
+
+    if !(1..CODEWORD_SIZE).contains(&parity) {
+        return Err(FecError::InvalidParameters);
+    }
+
+    let data_offset = copies_end + len.div_ceil(CODEWORD_SIZE - parity) * parity;
+    let expected = data_offset.saturating_add(len);
+    if protected.len() < expected {
+        return Err(FecError::Truncated { expected, found: protected.len() });
+    }
+
+    let mut data = protected[data_offset..].to_vec();
+
+    let parity_bytes = protected[copies_end..data_offset].chunks(parity);
+    let mut codeword = Vec::with_capacity(CODEWORD_SIZE);
+
+    for (index, (chunk, parity_bytes)) in data[..len].chunks_mut(CODEWORD_SIZE - parity).zip(parity_bytes).enumerate() {
+
+        codeword.clear();
+        codeword.extend_from_slice(chunk);
+        codeword.extend_from_slice(parity_bytes);
+
+        if correct_codeword(&mut codeword, parity).is_none() {
+            return Err(FecError::Unrecoverable { offset: index * (CODEWORD_SIZE - parity) });
+        }
+
+        chunk.copy_from_slice(&codeword[..chunk.len()]);
+    }
+
+    Ok(data)
+}
+
+
+/// Compress `input` and protect the critical section of the compressed data
+pub fn compress_protected<U>(input: impl SymbolSource<Symbol = U>, options: &FecOptions) -> Vec<u8>
+where
+    U: Symbol
+{
+    let compressed = compress(input);
+
+    // The output of `compress` always has a readable critical section
+    protect::<U>(&compressed, options).unwrap()
+}
+
+
+/// Correct the errors in the protected section of `protected` and decompress it
+pub fn decompress_protected<U>(protected: &[u8]) -> Result<Box<[U]>, FecError>
+where
+    U: Symbol
+{
+    decompress(&recover(protected)?).map_err(FecError::InvalidData)
+}
+
+
+/// Error returned when protected data can't be recovered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FecError {
+
+    /// The data doesn't start with `MAGIC`
+    NotProtected,
+    /// The data has `found` bytes, but the parameters require at least `expected`
+    Truncated { expected: usize, found: usize },
+    /// The parameters are invalid, even after the majority vote
+    InvalidParameters,
+    /// The codeword protecting the bytes of the compressed data starting at `offset` has too many errors to correct
+    Unrecoverable { offset: usize },
+    /// The recovered data can't be decompressed
+    InvalidData (DecompressionError),
+
+}
+
+impl fmt::Display for FecError {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FecError::NotProtected
+                => write!(f, "the data is not protected by error correction codes"),
+            FecError::Truncated { expected, found }
+                => write!(f, "the protected data has {found} bytes, expected at least {expected}"),
+            FecError::InvalidParameters
+                => write!(f, "invalid error correction parameters"),
+            FecError::Unrecoverable { offset }
+                => write!(f, "too many errors to correct in the bytes starting at {offset}"),
+            FecError::InvalidData (_)
+                => write!(f, "could not decompress the recovered data"),
+        }
+    }
+}
+
+impl Error for FecError {
+
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FecError::NotProtected |
+            FecError::Truncated { .. } |
+            FecError::InvalidParameters |
+            FecError::Unrecoverable { .. } => None,
+            FecError::InvalidData (e) => Some(e),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::num::NonZeroUsize;
+
+    use super::*;
+    use crate::seek::compress_seekable;
+    use crate::{compress_with_options, CompressionOptions};
+
+
+    #[test]
+    fn check_reed_solomon() {
+
+        let data: Vec<u8> = (0..200).map(|i| (i * 7 + 3) as u8).collect();
+
+        for parity in [1, 2, 8, 32] {
+
+            let generator = generator(parity);
+            let mut codeword = data.clone();
+            codeword.extend(encode_codeword(&data, &generator));
+
+            assert!(syndromes_are_zero(&codeword, parity));
+
+            // Up to `parity / 2` errors anywhere in the codeword are corrected
+            let mut damaged = codeword.clone();
+            for i in 0..parity / 2 {
+                damaged[i * 13 % codeword.len()] ^= 0x5a + i as u8;
+            }
+            assert_eq!(correct_codeword(&mut damaged, parity), Some(parity / 2));
+            assert_eq!(damaged, codeword);
+        }
+
+        // One error more than the code corrects is detected
+        let generator = generator(4);
+        let mut codeword = data.clone();
+        codeword.extend(encode_codeword(&data, &generator));
+        for i in [0, 50, 100] {
+            codeword[i] ^= 0xff;
+        }
+        assert_eq!(correct_codeword(&mut codeword, 4), None);
+    }
+
+
+    #[test]
+    fn check_protected_round_trip() {
+
+        let text = "the header and the tree are critical, the bitcode is not";
+
+        let layouts = [
+            CompressionOptions::new(),
+            CompressionOptions { mtf: true, ..CompressionOptions::new() },
+            CompressionOptions { canonical_tree: true, ..CompressionOptions::new() },
+            CompressionOptions { compact_tree: true, ..CompressionOptions::new() },
+        ];
+
+        for options in layouts {
+
+            let compressed = compress_with_options(text.chars(), &options);
+            let critical = critical_section_size::<char>(&compressed).unwrap();
+            assert!(critical > Header::deserialize(&compressed).unwrap().1 && critical < compressed.len());
+
+            let protected = protect::<char>(&compressed, &FecOptions::new()).unwrap();
+            assert_eq!(recover(&protected).unwrap(), *compressed);
+
+            // Damage the first bytes of the critical section, past the magic bytes and the parameters
+            let mut damaged = protected.clone();
+            let data_offset = protected.len() - compressed.len();
+            for i in 0..critical.min(16) {
+                damaged[data_offset + i] ^= 0xa5;
+            }
+            damaged[MAGIC.len()] ^= 1;
+            assert!(!decompress::<char>(&damaged[data_offset..]).is_ok_and(|decoded| decoded.iter().collect::<String>() == text));
+            assert_eq!(decompress_protected::<char>(&damaged).unwrap().iter().collect::<String>(), text);
+        }
+
+        // The checkpoint table is needed to seek
+        let seekable = compress_seekable(text.bytes(), NonZeroUsize::new(8).unwrap());
+        let (header, header_size) = Header::deserialize(&seekable).unwrap();
+        let (_, tree_size) = read_tree::<u8>(&seekable, header_size, &header).unwrap();
+        assert!(critical_section_size::<u8>(&seekable).unwrap() > header_size + tree_size);
+
+        let protected = compress_protected(text.bytes(), &FecOptions { parity_bytes: 4 });
+        assert_eq!(*decompress_protected::<u8>(&protected).unwrap(), *text.as_bytes());
+    }
+
+
+    #[test]
+    fn check_unrecoverable() {
+
+        let compressed = compress("abracadabra".chars());
+        let protected = protect::<char>(&compressed, &FecOptions { parity_bytes: 2 }).unwrap();
+        let data_offset = protected.len() - compressed.len();
+
+        assert_eq!(recover(&compressed), Err(FecError::NotProtected));
+        assert_eq!(recover(&protected[..data_offset]), Err(FecError::Truncated { expected: protected.len() - compressed.len() + critical_section_size::<char>(&compressed).unwrap(), found: data_offset }));
+
+        let mut damaged = protected.clone();
+        damaged[data_offset] ^= 1;
+        damaged[data_offset + 1] ^= 1;
+        assert_eq!(recover(&damaged), Err(FecError::Unrecoverable { offset: 0 }));
+
+        // Two damaged copies of the parameters outvote the third
+        let mut damaged = protected.clone();
+        for copy in 1..PARAMETER_COPIES {
+            damaged[MAGIC.len() + copy * PARAMETERS_SIZE + 4] = 0;
+        }
+        assert_eq!(recover(&damaged), Err(FecError::InvalidParameters));
+    }
+
+}
@@ -0,0 +1,422 @@
+//! Compression of files, recording their name, modification time, and mode in the header so that extraction can restore them.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::codecs::TreeCodec;
+use crate::container::{FileMetadata, Header};
+use crate::bits::BitOrder;
+use crate::symbol::SymbolType;
+use crate::writer::for_each_symbol;
+use crate::{compress_empty, compress_with_header, decompress, decompress_auto_grouped, DecompressionError, Symbol};
+
+
+impl FileMetadata {
+
+    /// Read the metadata of the file at `path`.
+    /// The name is left out if it's not valid UTF-8, and the mode is only recorded on Unix.
+    pub fn of_file(path: &Path) -> io::Result<Self> {
+
+        let attributes = fs::metadata(path)?;
+
+        let modified = attributes.modified()?
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|since_epoch| since_epoch.as_secs());
+
+        #[cfg(unix)]
+        let mode = Some(std::os::unix::fs::PermissionsExt::mode(&attributes.permissions()));
+        #[cfg(not(unix))]
+        let mode = None;
+
+        Ok(Self {
+            name: path.file_name().and_then(|name| name.to_str()).map(String::from),
+            modified,
+            mode
+        })
+    }
+
+
+    /// Apply the recorded modification time and mode to the file at `path`.
+    /// The name is not applied: the caller decides where the file is extracted.
+    /// The mode is ignored on platforms other than Unix.
+    pub fn restore(&self, path: &Path) -> io::Result<()> {
+
+        if let Some(modified) = self.modified {
+            let time = UNIX_EPOCH + Duration::from_secs(modified);
+            File::options().write(true).open(path)?.set_modified(time)?;
+        }
+
+        #[cfg(unix)]
+        if let Some(mode) = self.mode {
+            fs::set_permissions(path, std::os::unix::fs::PermissionsExt::from_mode(mode))?;
+        }
+
+        Ok(())
+    }
+
+
+    /// The recorded modification time
+    pub fn modified_time(&self) -> Option<SystemTime> {
+        self.modified.map(|modified| UNIX_EPOCH + Duration::from_secs(modified))
+    }
+
+}
+
+
+/// Compress the bytes of the file at `path`, recording its name, modification time, and mode in the header.
+/// Decompress with `decompress`, or with `decompress_with_metadata` to get the metadata back.
+pub fn compress_file(path: impl AsRef<Path>) -> io::Result<Box<[u8]>> {
+
+    let path = path.as_ref();
+
+    let data = fs::read(path)?;
+
+    let header = Header { metadata: FileMetadata::of_file(path)?, symbol_type: u8::TYPE, ..Header::new() };
+
+    if data.is_empty() {
+        return Ok(compress_empty(header));
+    }
+
+    Ok(compress_with_header(header, data.iter().copied(), &TreeCodec)?)
+}
+
+
+/// Decompress `input` and return the file metadata recorded in its header, which is empty if the data wasn't compressed from a file
+pub fn decompress_with_metadata<U>(input: &[u8]) -> Result<(Box<[U]>, FileMetadata), DecompressionError>
+where
+    U: Symbol
+{
+
+    let decompressed = decompress(input)?;
+
+    // The header was already validated by `decompress`
+    let (header, _) = Header::deserialize(input).map_err(DecompressionError::InvalidHeader)?;
+
+    Ok((decompressed, header.metadata))
+}
+
+
+/// How the symbols of compressed data were laid out by the encoder, as recorded in its header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+
+    /// Every symbol encoded with a single tree, by `compress` and its variants
+    Single,
+    /// Symbols grouped in chunks of `size` symbols, by `compress_chunked`
+    Chunked { size: u8 },
+    /// Characters grouped in chunks of `size` characters chosen on a sample of the input, by `compress_auto_grouped`
+    AutoGrouped { size: u8 },
+    /// Blocks with their own trees, by a `stream::Encoder`
+    Stream,
+    /// A single tree with a table of checkpoints, by `seek::compress_seekable`
+    Seekable,
+    /// The tree of the dictionary with identifier `id`, by `Dictionary::compress`
+    Dictionary { id: u32 },
+
+}
+
+impl Strategy {
+
+    /// The strategy that produced the data with `header`
+    pub const fn of(header: &Header) -> Self {
+        if header.blocks {
+            Strategy::Stream
+        } else if header.checkpoints {
+            Strategy::Seekable
+        } else if let Some(id) = header.dictionary {
+            Strategy::Dictionary { id }
+        } else if header.settings.auto_grouped {
+            Strategy::AutoGrouped { size: header.chunk_size }
+        } else if header.chunk_size > 1 {
+            Strategy::Chunked { size: header.chunk_size }
+        } else {
+            Strategy::Single
+        }
+    }
+
+}
+
+impl fmt::Display for Strategy {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Strategy::Single => write!(f, "single tree"),
+            Strategy::Chunked { size } => write!(f, "chunks of {size} symbols"),
+            Strategy::AutoGrouped { size } => write!(f, "auto-grouped chunks of {size} characters"),
+            Strategy::Stream => write!(f, "stream of blocks"),
+            Strategy::Seekable => write!(f, "seekable"),
+            Strategy::Dictionary { id } => write!(f, "dictionary {id:#010x}"),
+        }
+    }
+
+}
+
+
+/// Summary of compressed data checked by `verify`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveInfo {
+
+    /// Size of the compressed data in bytes
+    pub compressed_size: usize,
+    /// Number of bytes the data decompresses to
+    pub decompressed_size: usize,
+    /// Header of the compressed data, with the metadata of the original file
+    pub header: Header,
+
+}
+
+impl ArchiveInfo {
+
+    /// Ratio of the decompressed size to the compressed size
+    pub fn ratio(&self) -> f64 {
+        self.decompressed_size as f64 / self.compressed_size as f64
+    }
+
+
+    /// The strategy that produced the data
+    pub const fn strategy(&self) -> Strategy {
+        Strategy::of(&self.header)
+    }
+
+
+    /// Names of the transforms applied to the symbols before encoding, in the order they were applied
+    pub fn transforms(&self) -> Vec<&'static str> {
+        [
+            (self.header.remap, "remap"),
+            (self.header.mtf, "mtf"),
+            (self.header.lz77, "lz77"),
+            (self.header.transformed_blocks, "output transform"),
+        ]
+        .into_iter()
+        .filter_map(|(applied, name)| applied.then_some(name))
+        .collect()
+    }
+
+
+    /// One-line description of how the data was produced: the strategy, the transforms, the tree layout,
+    /// the bit order, and the recorded encoder settings
+    pub fn describe(&self) -> String {
+
+        let header = &self.header;
+
+        let mut parts = vec![self.strategy().to_string()];
+
+        if let Some(symbol_type) = header.symbol_type {
+            parts.push(format!("{symbol_type} symbols"));
+        }
+
+        parts.extend(self.transforms().into_iter().map(String::from));
+
+        if header.compact_tree {
+            parts.push("compact tree".to_string());
+        }
+        if header.canonical_tree {
+            parts.push("canonical tree".to_string());
+        }
+        if header.bit_order == BitOrder::LsbFirst {
+            parts.push("lsb-first".to_string());
+        }
+        if let Some(max) = header.settings.max_block_symbols {
+            parts.push(format!("blocks of at most {max} symbols"));
+        }
+        if let Some(max) = header.settings.max_block_bytes {
+            parts.push(format!("blocks of at most {max} bytes"));
+        }
+        if header.settings.checksum {
+            parts.push("checksum".to_string());
+        }
+        if header.settings.block_checksums {
+            parts.push("block checksums".to_string());
+        }
+        if header.settings.normalized_newlines {
+            parts.push("normalized newlines".to_string());
+        }
+
+        parts.join(", ")
+    }
+
+}
+
+
+/// Decode the whole compressed bytes in `input` without keeping the output, like `gzip -t`.
+/// The header, the trees, the symbol counts, and the lengths of the payloads are checked.
+///
+/// The symbols are decoded as the type recorded in the header, as UTF-8 text if they were grouped by `compress_auto_grouped`,
+/// and as bytes otherwise.
+///
+/// Only streams written with `EncoderOptions::checksum` or `block_checksums` carry checksums, so in the other layouts a corrupted bitcode that still
+/// decodes to the expected number of symbols is not detected.
+pub fn verify(input: &[u8]) -> Result<ArchiveInfo, DecompressionError> {
+
+    let (header, _) = Header::deserialize(input).map_err(DecompressionError::InvalidHeader)?;
+
+    // The symbols are only counted as they're decoded, except for auto-grouped text, whose chunks are decoded whole
+    let decompressed_size = match header.symbol_type {
+        None if header.settings.auto_grouped => decompress_auto_grouped(input)?.len(),
+        Some (SymbolType::U8) | None => decoded_size::<u8>(input)?,
+        Some (SymbolType::U16) => decoded_size::<u16>(input)?,
+        Some (SymbolType::U32) => decoded_size::<u32>(input)?,
+        Some (SymbolType::U64) => decoded_size::<u64>(input)?,
+        Some (SymbolType::U128) => decoded_size::<u128>(input)?,
+        Some (SymbolType::I8) => decoded_size::<i8>(input)?,
+        Some (SymbolType::I16) => decoded_size::<i16>(input)?,
+        Some (SymbolType::I32) => decoded_size::<i32>(input)?,
+        Some (SymbolType::I64) => decoded_size::<i64>(input)?,
+        Some (SymbolType::I128) => decoded_size::<i128>(input)?,
+        Some (SymbolType::Bool) => decoded_size::<bool>(input)?,
+        // Characters are counted as UTF-8 text
+        Some (SymbolType::Char) => {
+            let mut size = 0;
+            for_each_symbol(input, |symbol: &char| {
+                size += symbol.len_utf8();
+                Ok::<_, DecompressionError>(())
+            })?;
+            size
+        },
+    };
+
+    Ok(ArchiveInfo {
+        compressed_size: input.len(),
+        decompressed_size,
+        header
+    })
+}
+
+
+/// Size in bytes of the serialized symbols of `input`, which are decoded without being kept
+fn decoded_size<U>(input: &[u8]) -> Result<usize, DecompressionError>
+where
+    U: Symbol
+{
+
+    let mut count = 0;
+
+    for_each_symbol(input, |_: &U| {
+        count += 1;
+        Ok::<_, DecompressionError>(())
+    })?;
+
+    Ok(count * U::WIDTH)
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+
+    #[test]
+    fn check_file_round_trip() {
+
+        let dir = std::env::temp_dir().join(format!("ftc-file-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let original = dir.join("original.txt");
+        fs::write(&original, "the quick brown fox jumps over the lazy dog").unwrap();
+
+        let modified = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        File::options().write(true).open(&original).unwrap().set_modified(modified).unwrap();
+
+        #[cfg(unix)]
+        fs::set_permissions(&original, std::os::unix::fs::PermissionsExt::from_mode(0o640)).unwrap();
+
+        let compressed = compress_file(&original).unwrap();
+
+        let (data, metadata) = decompress_with_metadata::<u8>(&compressed).unwrap();
+        assert_eq!(*data, *b"the quick brown fox jumps over the lazy dog");
+        assert_eq!(metadata.name.as_deref(), Some("original.txt"));
+        assert_eq!(metadata.modified_time(), Some(modified));
+
+        let extracted = dir.join("extracted.txt");
+        fs::write(&extracted, &data).unwrap();
+        metadata.restore(&extracted).unwrap();
+
+        let attributes = fs::metadata(&extracted).unwrap();
+        assert_eq!(attributes.modified().unwrap(), modified);
+
+        #[cfg(unix)]
+        assert_eq!(std::os::unix::fs::PermissionsExt::mode(&attributes.permissions()) & 0o777, 0o640);
+
+        // Empty files keep their metadata
+        let empty = dir.join("empty");
+        fs::write(&empty, []).unwrap();
+        let (data, metadata) = decompress_with_metadata::<u8>(&compress_file(&empty).unwrap()).unwrap();
+        assert!(data.is_empty());
+        assert_eq!(metadata.name.as_deref(), Some("empty"));
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Data compressed from memory has no metadata
+        let (_, metadata) = decompress_with_metadata::<u8>(&crate::compress(b"abc".iter().copied())).unwrap();
+        assert_eq!(metadata, FileMetadata::new());
+    }
+
+
+    #[test]
+    fn check_verify() {
+
+        let data = b"the quick brown fox jumps over the lazy dog".repeat(20);
+        let compressed = crate::compress_bytes(&data);
+
+        let info = verify(&compressed).unwrap();
+        assert_eq!(info.compressed_size, compressed.len());
+        assert_eq!(info.decompressed_size, data.len());
+        assert_eq!(info.header, Header { symbol_type: Some(crate::SymbolType::U8), ..Header::new() });
+        assert_eq!(info.strategy(), Strategy::Single);
+        assert_eq!(info.describe(), "single tree, u8 symbols");
+        assert!(info.ratio() > 1.0);
+
+        assert_eq!(verify(&crate::compress_bytes(&[])).unwrap().decompressed_size, 0);
+
+        // Characters are counted as UTF-8 text, also in streams
+        let text = "déjà vu, ünïcödé";
+        assert_eq!(verify(&crate::compress(text.chars())).unwrap().decompressed_size, text.len());
+        let mut encoder = crate::stream::Encoder::new(Vec::new());
+        encoder.write_symbols(text.chars()).unwrap();
+        assert_eq!(verify(&encoder.finish().unwrap()).unwrap().decompressed_size, text.len());
+
+        // Missing bytes are reported
+        assert!(verify(&compressed[..compressed.len() - 1]).is_err());
+        assert!(verify(&compressed[..2]).is_err());
+
+        // Data encoded with a dictionary can't be decoded without it
+        let dictionary = crate::Dictionary::train([data.iter().copied()]).unwrap();
+        assert!(matches!(verify(&dictionary.compress(data.iter().copied())), Err(DecompressionError::MissingDictionary { .. })));
+    }
+
+
+    #[test]
+    fn check_recorded_strategy() {
+
+        let text = "the settings of the encoder are recorded in the header. ".repeat(10);
+
+        let info = verify(&crate::compress_auto_grouped(&text)).unwrap();
+        assert!(matches!(info.strategy(), Strategy::AutoGrouped { size: 1..=4 }));
+        assert!(info.header.settings.auto_grouped);
+        assert_eq!(info.decompressed_size, text.len());
+
+        let (header, _) = Header::deserialize(&crate::compress_chunked::<u8, 2>(text.bytes())).unwrap();
+        assert_eq!(Strategy::of(&header), Strategy::Chunked { size: 2 });
+        assert!(header.settings.is_empty());
+
+        let numbers: Vec<u32> = (0..100).map(|i| i % 7).collect();
+        let options = crate::CompressionOptions { mtf: true, lz77: true, ..crate::CompressionOptions::new() };
+        let info = verify(&crate::compress_with_options(numbers.iter().copied(), &options)).unwrap();
+        assert_eq!(info.decompressed_size, numbers.len() * 4);
+        assert_eq!(info.transforms(), ["mtf", "lz77"]);
+
+        let options = crate::stream::EncoderOptions { max_block_symbols: std::num::NonZeroUsize::new(16), ..crate::stream::EncoderOptions::new() };
+        let mut encoder = crate::stream::Encoder::with_options(Vec::new(), options);
+        encoder.write_symbols(text.bytes()).unwrap();
+        let info = verify(&encoder.finish().unwrap()).unwrap();
+        assert_eq!(info.strategy(), Strategy::Stream);
+        assert_eq!(info.header.settings.max_block_symbols, std::num::NonZeroUsize::new(16));
+        assert_eq!(info.describe(), "stream of blocks, u8 symbols, blocks of at most 16 symbols");
+    }
+
+}
@@ -0,0 +1,193 @@
+//! Symbol frequency tables, for inspecting the statistics a frequency tree is built from.
+//!
+//! A file that compresses poorly usually has a flat histogram: many distinct symbols with similar counts.
+//! `FrequencyTable` counts the symbols of some data and renders the counts as CSV or as a terminal bar chart.
+
+use std::fmt::{self, Write};
+
+use crate::symbol::canonical_key;
+use crate::tree::{byte_frequencies, value_frequencies};
+use crate::Symbol;
+
+
+/// Number of occurrences of each distinct symbol of some data, sorted by decreasing count.
+/// Symbols with the same count are sorted by value, so the table of some data is always the same.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrequencyTable<U> {
+
+    entries: Box<[(U, usize)]>,
+    total: usize,
+
+}
+
+impl<U> FrequencyTable<U>
+where
+    U: Symbol
+{
+
+    /// Count the occurrences of each symbol of `data`
+    pub fn from_symbols(data: impl Iterator<Item = U>) -> Self {
+        Self::from_frequencies(value_frequencies(data))
+    }
+
+
+    fn from_frequencies(mut entries: Box<[(U, usize)]>) -> Self {
+
+        entries.sort_by_cached_key(|(value, count)| (usize::MAX - count, canonical_key(value)));
+
+        let total = entries.iter().map(|(_, count)| count).sum();
+
+        Self { entries, total }
+    }
+
+
+    /// The distinct symbols and their counts, by decreasing count
+    pub fn entries(&self) -> &[(U, usize)] {
+        &self.entries
+    }
+
+
+    /// Number of distinct symbols
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+
+    /// Number of symbols counted, the sum of the counts
+    pub const fn total(&self) -> usize {
+        self.total
+    }
+
+
+    /// Shannon entropy of the frequencies in bits per symbol, the lower bound of the average code length of any prefix code.
+    /// Return 0 for an empty table.
+    pub fn entropy(&self) -> f64 {
+        self.entries.iter()
+            .map(|(_, count)| {
+                let p = *count as f64 / self.total as f64;
+                -p * p.log2()
+            })
+            .sum()
+    }
+
+}
+
+impl FrequencyTable<u8> {
+
+    /// Count the occurrences of each byte of `data` with a byte histogram instead of a hash table
+    pub fn from_bytes(data: &[u8]) -> Self {
+        Self::from_frequencies(byte_frequencies(data))
+    }
+
+}
+
+impl<U> FrequencyTable<U>
+where
+    U: Symbol + fmt::Debug
+{
+
+    /// Render the table as CSV with a header line, one `symbol,count,frequency` line per distinct symbol.
+    /// Symbols are written with their `Debug` representation, quoted if needed.
+    pub fn to_csv(&self) -> String {
+
+        let mut csv = String::from("symbol,count,frequency\n");
+
+        for (value, count) in &self.entries {
+            writeln!(csv, "{},{count},{:.6}", csv_field(&format!("{value:?}")), *count as f64 / self.total as f64).unwrap();
+        }
+
+        csv
+    }
+
+
+    /// Render the table as a bar chart with one line per distinct symbol: its `Debug` representation,
+    /// a bar of `#` proportional to its count, the count, and its percentage of the total.
+    /// The bar of the most frequent symbol is `width` characters long, and every other symbol has a bar of at least one character.
+    pub fn to_histogram_string(&self, width: usize) -> String {
+
+        let labels: Vec<String> = self.entries.iter().map(|(value, _)| format!("{value:?}")).collect();
+        let label_width = labels.iter().map(|label| label.chars().count()).max().unwrap_or(0);
+
+        let max_count = self.entries.first().map_or(0, |(_, count)| *count);
+
+        let mut histogram = String::new();
+
+        for (label, (_, count)) in labels.iter().zip(&self.entries) {
+
+            let bar_len = (count * width).div_ceil(max_count);
+
+            writeln!(
+                histogram,
+                "{label:<label_width$} {:<width$} {count} ({:.2}%)",
+                "#".repeat(bar_len),
+                *count as f64 / self.total as f64 * 100.0
+            ).unwrap();
+        }
+
+        histogram
+    }
+
+}
+
+
+/// Quote a CSV field if it contains a separator, a quote, or a line break, doubling its quotes
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+
+    #[test]
+    fn check_frequency_table() {
+
+        let table = FrequencyTable::from_symbols("abracadabra".chars());
+
+        assert_eq!(table.entries(), [('a', 5), ('b', 2), ('r', 2), ('c', 1), ('d', 1)]);
+        assert_eq!(table.len(), 5);
+        assert_eq!(table.total(), 11);
+        assert_eq!(FrequencyTable::from_bytes(b"abracadabra"), FrequencyTable::from_symbols("abracadabra".bytes()));
+
+        // Multi-byte symbols with the same count are sorted by value
+        assert_eq!(FrequencyTable::from_symbols([0x1ff_u16, 0x100, 0x2ff, 0x100].into_iter()).entries(), [(0x100, 2), (0x1ff, 1), (0x2ff, 1)]);
+
+        assert_eq!(FrequencyTable::from_symbols("aaaa".chars()).entropy(), 0.0);
+        assert_eq!(FrequencyTable::from_symbols("abcd".chars()).entropy(), 2.0);
+
+        let empty = FrequencyTable::<u8>::from_bytes(&[]);
+        assert!(empty.is_empty());
+        assert_eq!(empty.entropy(), 0.0);
+        assert_eq!(empty.to_histogram_string(10), "");
+    }
+
+
+    #[test]
+    fn check_rendering() {
+
+        let table = FrequencyTable::from_symbols("a,a,\"b".chars());
+
+        assert_eq!(table.to_csv(), "symbol,count,frequency\n\"','\",2,0.333333\n'a',2,0.333333\n\"'\"\"'\",1,0.166667\n'b',1,0.166667\n");
+
+        assert_eq!(
+            table.to_histogram_string(4),
+            "',' #### 2 (33.33%)\n\
+             'a' #### 2 (33.33%)\n\
+             '\"' ##   1 (16.67%)\n\
+             'b' ##   1 (16.67%)\n"
+        );
+    }
+
+}
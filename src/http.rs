@@ -0,0 +1,193 @@
+//! Compressed bodies of HTTP responses, available with the `http` feature.
+//!
+//! A `CompressStream` turns a stream of body chunks into a stream of compressed chunks, as accepted by the body types
+//! of `hyper` and `axum`. Each chunk of the source is compressed into a block of a `stream`, so every compressed chunk
+//! holds whole blocks: the client can decode the body chunk by chunk with a `stream::Decoder`, and a server that sends
+//! the chunks of a live feed as they come doesn't hold them back until a block fills up.
+//!
+//! The compressed body is meant to be served with `CONTENT_ENCODING` as its `Content-Encoding`, to clients that list it
+//! in their `Accept-Encoding`.
+
+use std::io;
+use std::mem;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use bytes::Bytes;
+use futures_core::Stream;
+
+use crate::stream::{Encoder, EncoderOptions};
+
+
+/// Content coding of compressed bodies, for the `Content-Encoding` and `Accept-Encoding` headers
+pub const CONTENT_ENCODING: &str = "x-ftc";
+
+
+/// Stream of the compressed chunks of the body chunks yielded by `inner`.
+///
+/// The first compressed chunk starts with the stream header, and the last one ends the stream once `inner` is exhausted.
+/// An error of `inner` is passed on and ends the compressed stream, since the body can't be completed anymore.
+/// `inner` must be `Unpin`: pin other streams with `Box::pin`.
+pub struct CompressStream<S> {
+
+    inner: S,
+    /// `None` once the end of the stream is written, or after an error
+    encoder: Option<Encoder<u8, Vec<u8>>>,
+
+}
+
+impl<S> CompressStream<S> {
+
+    pub fn new(inner: S) -> Self {
+        Self::with_options(inner, EncoderOptions::new())
+    }
+
+
+    /// Large chunks are split into several blocks according to the limits of the options
+    pub fn with_options(inner: S, options: EncoderOptions) -> Self {
+        Self {
+            inner,
+            encoder: Some(Encoder::with_options(Vec::new(), options))
+        }
+    }
+
+
+    /// Get a reference to the underlying stream
+    pub const fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+
+    /// Return the underlying stream, with the chunks not compressed yet
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+}
+
+impl<S, B, E> Stream for CompressStream<S>
+where
+    S: Stream<Item = Result<B, E>> + Unpin,
+    B: AsRef<[u8]>,
+    E: From<io::Error>
+{
+
+    type Item = Result<Bytes, E>;
+
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+
+        let this = self.get_mut();
+
+        loop {
+
+            let Some(encoder) = &mut this.encoder else {
+                return Poll::Ready(None);
+            };
+
+            let compressed = match ready!(Pin::new(&mut this.inner).poll_next(cx)) {
+
+                Some (Ok (chunk)) => encoder.write_symbols(chunk.as_ref().iter().copied())
+                    .and_then(|()| encoder.flush_block())
+                    .map(|()| mem::take(encoder.get_mut())),
+
+                Some (Err (error)) => {
+                    this.encoder = None;
+                    return Poll::Ready(Some(Err(error)));
+                },
+
+                None => this.encoder.take().unwrap().finish()
+            };
+
+            match compressed {
+                // An empty chunk past the header has no block
+                Ok (compressed) if compressed.is_empty() => continue,
+                Ok (compressed) => return Poll::Ready(Some(Ok(compressed.into()))),
+                Err (error) => {
+                    this.encoder = None;
+                    return Poll::Ready(Some(Err(error.into())));
+                }
+            }
+        }
+    }
+
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match &self.encoder {
+            // Empty chunks yield nothing, and the end of the stream yields a chunk of its own
+            Some (_) => (1, self.inner.size_hint().1.and_then(|upper| upper.checked_add(1))),
+            None => (0, Some(0))
+        }
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::task::Waker;
+
+    use futures_util::stream;
+
+    use super::*;
+    use crate::stream::Decoder;
+
+
+    /// Poll a stream whose items are always ready to the end
+    fn collect<S>(mut stream: S) -> Vec<S::Item>
+    where
+        S: Stream + Unpin
+    {
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut items = Vec::new();
+
+        while let Poll::Ready(Some(item)) = Pin::new(&mut stream).poll_next(&mut cx) {
+            items.push(item);
+        }
+
+        items
+    }
+
+
+    #[test]
+    fn check_compress_stream() {
+
+        let chunks = ["first line\n", "", "second line\n", "third line\n"];
+        let source = stream::iter(chunks.map(Ok::<_, io::Error>));
+
+        let compressed = collect(CompressStream::new(source));
+
+        // A chunk per nonempty source chunk, and the end of the stream
+        assert_eq!(compressed.len(), 4);
+
+        let mut decoder = Decoder::<u8>::new();
+        for (chunk, expected) in compressed.iter().zip(["first line\n", "second line\n", "third line\n", ""]) {
+            assert_eq!(decoder.feed(chunk.as_ref().unwrap()).unwrap(), expected.as_bytes());
+        }
+        assert!(decoder.is_finished());
+
+        // An empty body is a stream without data blocks
+        let compressed = collect(CompressStream::new(stream::empty::<Result<Vec<u8>, io::Error>>()));
+        assert_eq!(compressed.len(), 1);
+        assert!(crate::decompress::<u8>(compressed[0].as_ref().unwrap()).unwrap().is_empty());
+    }
+
+
+    #[test]
+    fn check_compress_stream_error() {
+
+        let source = stream::iter([
+            Ok (b"sent".to_vec()),
+            Err (io::Error::other("connection reset")),
+            Ok (b"never sent".to_vec())
+        ]);
+
+        let compressed = collect(CompressStream::new(source));
+
+        assert_eq!(compressed.len(), 2);
+        assert!(compressed[0].is_ok());
+        assert_eq!(compressed[1].as_ref().unwrap_err().to_string(), "connection reset");
+    }
+
+}
@@ -1,524 +1,962 @@
-#![allow(incomplete_features)]
-#![feature(generic_const_exprs)]
+#![cfg_attr(feature = "forbid-unsafe", forbid(unsafe_code))]
+
+#[macro_use]
+mod trace;
+mod symbol;
+mod dictionary;
+mod file;
+mod writer;
+mod transcode;
+mod tagged;
+mod shared;
+pub mod bits;
+pub mod tree;
+pub mod container;
+pub mod codecs;
+pub mod transforms;
+pub mod stream;
+pub mod deflate;
+pub mod seek;
+pub mod source;
+pub mod archive;
+pub mod volume;
+pub mod channels;
+pub mod armor;
+pub mod fec;
+pub mod debug;
+pub mod spec;
+pub mod frequency;
+pub mod checksum;
+pub mod prelude;
+#[cfg(any(test, feature = "corpus"))]
+pub mod corpus;
+#[cfg(feature = "http")]
+pub mod http;
 
-
-use core::slice;
-use std::mem;
-use std::collections::HashMap;
-use std::hash::Hash;
-
-use bitvec_padded::{least_bytes_repr_for_bits, BitVec, BitView};
-
-
-#[derive(Debug, Clone, Copy)]
+#[cfg(test)]
+mod corruption_tests;
+
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::num::NonZeroUsize;
+
+use container::{compact_alphabet_size, read_alphabet, read_compact_alphabet, read_payload, write_alphabet, write_byte_payload, write_compact_alphabet, write_payload};
+use transforms::lz77;
+use transforms::mtf;
+use transforms::newlines;
+use transforms::remap;
+use codecs::{CanonicalTreeCodec, CompactTreeCodec, EntropyCodec, TreeCodec};
+use bits::{BitOrder, BitVec, BitView};
+use source::Passes;
+use writer::decoded_symbols;
+
+pub use symbol::{Symbol, SymbolType, ChunkedSymbols};
+pub use source::SymbolSource;
+pub use frequency::FrequencyTable;
+pub use container::{EncoderSettings, FileMetadata, Header, HeaderDeserializationError, FORMAT_VERSION};
+pub use file::{compress_file, decompress_with_metadata, verify, ArchiveInfo, Strategy};
+pub use writer::decompress_into_writer;
+pub use transcode::{transcode, TranscodeError};
+pub use tagged::{decompress_any, DecodedPayload};
+pub use armor::{compress_to_armored, decompress_armored};
+pub use dictionary::{compress_batch, Dictionary, DictionaryDeserializationError, DictionaryRegistry, DictionarySerializationOptions, TrainingOptions};
+pub use shared::{Producer, SharedEncoder};
+pub use tree::{Code, CodeLengthError, CodeLengthStats, Codes, DecodingError, DecodingTree, DuplicateValue, DynDecodingTree, EncodingTree, IntoCodes, NodeDeserializationError, NodeRef, StaticDecodingTree, TreeBuildError, TreeBuilder, UnknownValue};
+
+
+/// Error returned by `decompress` when the input is not a valid compressed payload.
+/// Every variant records where in the input the problem was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DecompressionError {
 
-    InvalidBitCode,
+    /// The container header at the start of the input is malformed
+    InvalidHeader (HeaderDeserializationError),
+    /// The data was compressed with a different chunk size than the one requested
+    ChunkSizeMismatch { expected: usize, found: usize },
+    /// The data was compressed in chunks larger than the `max` chunk size supported by the decompression function
+    UnsupportedChunkSize { found: usize, max: usize },
+    /// The bitcode section after the decoding tree is missing or malformed
+    InvalidBitCode { offset: usize, remaining: usize },
+    /// The serialized decoding tree at the start of the input is malformed
     InvalidDecodingTree (NodeDeserializationError),
-    BitCodeDecodingError (DecodingError)
+    /// The bitcode section starting at `offset` could not be decoded with the decoding tree
+    BitCodeDecodingError { offset: usize, source: DecodingError },
+    /// The input ended before the end of the length-prefixed section starting at `offset`
+    TruncatedSection { offset: usize, expected: usize, remaining: usize },
+    /// The stream starting at `offset` doesn't contain the number of symbols announced by the payload
+    SymbolCountMismatch { offset: usize, expected: usize, found: usize },
+    /// An LZ77 match refers to symbols before the start of the data
+    InvalidMatch (lz77::InvalidMatch),
+    /// The length of the LZ77 match `token` is out of the valid range
+    InvalidMatchLength { token: usize },
+    /// The bytes at `offset` don't represent a valid symbol
+    InvalidSymbol { offset: usize },
+    /// A move-to-front index is out of the alphabet
+    InvalidMtfIndex (mtf::InvalidIndex),
+    /// The block kind specifier is not one of the `expected` values
+    InvalidBlockKind { offset: usize, found: u8, expected: &'static [u8] },
+    /// The data fed to a stream decoder was not produced by a streaming encoder
+    NotAStream,
+    /// The input continues after the end of the stream
+    TrailingData { offset: usize, remaining: usize },
+    /// The block at `offset` reuses the tree of the previous block, but there is no previous block
+    NoTreeToReuse { offset: usize },
+    /// The data was encoded with the dictionary `id`, which was not provided
+    MissingDictionary { id: u32 },
+    /// The data was encoded with a different dictionary than the one provided
+    DictionaryMismatch { expected: u32, found: u32 },
+    /// A dense identifier is out of the mapping table
+    InvalidRemappedId (remap::InvalidId),
+    /// The variable-length integer at `offset` doesn't fit in a `usize`
+    InvalidVarint { offset: usize },
+    /// The data opened by a seekable cursor was compressed without checkpoints
+    NotSeekable,
+    /// The checkpoint table at `offset` doesn't match the symbol count or the bitcode
+    InvalidCheckpoints { offset: usize },
+    /// The symbol `position` is past the end of the `len` symbols of the data
+    SymbolOutOfRange { position: usize, len: usize },
+    /// The alphabet at `offset` announces `len` symbols, but its varying bytes can only represent `max` distinct symbols
+    AlphabetTooLarge { offset: usize, len: usize, max: usize },
+    /// The payloads of the blocks were transformed when compressing, and no `InputTransform` was provided to revert it
+    MissingTransform,
+    /// An `InputTransform` was provided, but the payloads of the blocks were not transformed
+    UntransformedBlocks,
+    /// The `InputTransform` rejected the payload of the block at `offset`
+    RejectedBlock { offset: usize },
+    /// The payload after the model has `got` bytes, fewer than the `expected_min` bytes needed by its symbol count and bitcode
+    TruncatedPayload { expected_min: usize, got: usize },
+    /// The unused bits of the last byte of the bitcode at `offset` are not all zero
+    DirtyPadding { offset: usize },
+    /// The block at `offset` has `remaining` bytes after the end of its bitcode
+    UnusedBlockBytes { offset: usize, remaining: usize },
+    /// The header doesn't record the type of the symbols, so they can't be decoded without knowing it
+    UnknownSymbolType,
+    /// The block at `offset` records the checksum `expected`, but the decoded symbols it covers have the checksum `found`.
+    /// The block is either the checksum block of the stream or a data block followed by its own checksum.
+    ChecksumMismatch { offset: usize, expected: u32, found: u32 },
+    /// The block at `offset` is a checksum block, but the header doesn't announce one
+    UnexpectedChecksum { offset: usize },
+    /// The header announces a checksum, but the stream ends at `offset` without a checksum block after its last data block
+    MissingChecksum { offset: usize },
+    /// The decompressed bytes aren't valid UTF-8 text: the sequence at byte `offset` of the decompressed bytes is invalid
+    InvalidUtf8 { offset: usize },
 
 }
 
+impl fmt::Display for DecompressionError {
 
-#[repr(u8)]
-enum SerialSpecifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecompressionError::InvalidHeader (_)
+                => write!(f, "invalid container header"),
+            DecompressionError::ChunkSizeMismatch { expected, found }
+                => write!(f, "the data was compressed in chunks of {found} symbols, expected {expected}"),
+            DecompressionError::UnsupportedChunkSize { found, max }
+                => write!(f, "the data was compressed in chunks of {found} symbols, at most {max} are supported"),
+            DecompressionError::InvalidBitCode { offset, remaining }
+                => write!(f, "invalid bitcode section at byte {offset} ({remaining} bytes remaining)"),
+            DecompressionError::InvalidDecodingTree (_)
+                => write!(f, "invalid decoding tree"),
+            DecompressionError::BitCodeDecodingError { offset, .. }
+                => write!(f, "could not decode the bitcode section at byte {offset}"),
+            DecompressionError::TruncatedSection { offset, expected, remaining }
+                => write!(f, "truncated section at byte {offset}: expected {expected} bytes, {remaining} remaining"),
+            DecompressionError::SymbolCountMismatch { offset, expected, found }
+                => write!(f, "the stream at byte {offset} contains {found} symbols, expected {expected}"),
+            DecompressionError::InvalidMatch (_)
+                => write!(f, "invalid LZ77 match"),
+            DecompressionError::InvalidMatchLength { token }
+                => write!(f, "LZ77 match {token} has an invalid length"),
+            DecompressionError::InvalidSymbol { offset }
+                => write!(f, "invalid symbol at byte {offset}"),
+            DecompressionError::InvalidMtfIndex (_)
+                => write!(f, "invalid move-to-front index"),
+            DecompressionError::InvalidBlockKind { offset, found, expected }
+                => write!(f, "invalid block kind {found} at byte {offset}, expected one of {expected:?}"),
+            DecompressionError::NotAStream
+                => write!(f, "the data is not a block stream"),
+            DecompressionError::TrailingData { offset, remaining }
+                => write!(f, "{remaining} bytes of trailing data after the end of the stream at byte {offset}"),
+            DecompressionError::NoTreeToReuse { offset }
+                => write!(f, "the block at byte {offset} reuses the tree of a previous block, but it's the first block"),
+            DecompressionError::MissingDictionary { id }
+                => write!(f, "the data was encoded with dictionary {id:#010x}, which was not provided"),
+            DecompressionError::DictionaryMismatch { expected, found }
+                => write!(f, "the data was encoded with dictionary {found:#010x}, but dictionary {expected:#010x} was provided"),
+            DecompressionError::InvalidRemappedId (_)
+                => write!(f, "invalid dense symbol identifier"),
+            DecompressionError::InvalidVarint { offset }
+                => write!(f, "the variable-length integer at byte {offset} is too large"),
+            DecompressionError::NotSeekable
+                => write!(f, "the data was compressed without checkpoints"),
+            DecompressionError::InvalidCheckpoints { offset }
+                => write!(f, "invalid checkpoint table at byte {offset}"),
+            DecompressionError::SymbolOutOfRange { position, len }
+                => write!(f, "symbol {position} is out of the {len} symbols of the data"),
+            DecompressionError::AlphabetTooLarge { offset, len, max }
+                => write!(f, "the alphabet at byte {offset} has {len} symbols, but at most {max} distinct symbols fit in its varying bytes"),
+            DecompressionError::MissingTransform
+                => write!(f, "the blocks were transformed, but no transform was provided to revert them"),
+            DecompressionError::UntransformedBlocks
+                => write!(f, "a transform was provided, but the blocks were not transformed"),
+            DecompressionError::RejectedBlock { offset }
+                => write!(f, "the transform rejected the payload of the block at byte {offset}"),
+            DecompressionError::TruncatedPayload { expected_min, got }
+                => write!(f, "the payload has {got} bytes, expected at least {expected_min}"),
+            DecompressionError::DirtyPadding { offset }
+                => write!(f, "the padding bits of the bitcode at byte {offset} are not all zero"),
+            DecompressionError::UnusedBlockBytes { offset, remaining }
+                => write!(f, "the block at byte {offset} has {remaining} unused bytes after its bitcode"),
+            DecompressionError::UnknownSymbolType
+                => write!(f, "the header doesn't record the type of the symbols"),
+            DecompressionError::ChecksumMismatch { offset, expected, found }
+                => write!(f, "checksum mismatch in the block at byte {offset}: the block records {expected:#010x}, the decoded symbols have {found:#010x}"),
+            DecompressionError::UnexpectedChecksum { offset }
+                => write!(f, "unexpected checksum block at byte {offset}: the header doesn't announce a checksum"),
+            DecompressionError::MissingChecksum { offset }
+                => write!(f, "the stream ends at byte {offset} without the checksum announced by its header"),
+            DecompressionError::InvalidUtf8 { offset }
+                => write!(f, "invalid UTF-8 sequence at byte {offset} of the decompressed data"),
+        }
+    }
+}
+
+/// Error returned by the fallible compression functions when the input can't be compressed as requested
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressError {
 
-    Leaf,
-    Parent,
+    /// The input has no symbols to build a tree from
+    EmptyInput,
+    /// The input has more distinct symbols than the `max` allowed by `CompressionOptions::max_unique_symbols`
+    AlphabetTooLarge { max: usize },
+    /// A pass over the source yielded a symbol at `position` that its first pass didn't contain
+    InconsistentSource { position: usize },
 
 }
 
-impl TryFrom<u8> for SerialSpecifier {
-    type Error = NodeDeserializationError;
+impl fmt::Display for CompressError {
 
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
-        if value > Self::Parent as u8 {
-            Err(NodeDeserializationError::InvalidNodeTypeSpecifier (value))
-        } else {
-            Ok( unsafe {
-                mem::transmute(value)
-            })
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompressError::EmptyInput
+                => write!(f, "the input has no symbols to build a tree from; `compress_bytes` and streams can encode empty data"),
+            CompressError::AlphabetTooLarge { max }
+                => write!(f, "the input has more than {max} distinct symbols; compress smaller symbols instead, such as the bytes of the values, whose alphabet is at most 256 symbols"),
+            CompressError::InconsistentSource { position }
+                => write!(f, "the symbol at position {position} was not in the first pass over the source; every pass must yield the same symbols"),
         }
     }
 }
 
+impl Error for CompressError {}
 
-#[derive(Debug)]
-enum Node<U> {
+impl From<CompressError> for io::Error {
 
-    Parent { count: usize, left: Box<Node<U>>, right: Box<Node<U>> },
-    Leaf { count: usize, value: U },
+    fn from(e: CompressError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, e)
+    }
+}
+
+
+/// Error returned by `decompress_partial`, with the symbols decoded before the error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeFailure<U> {
+
+    /// Symbols decoded before the error, in order
+    pub decoded_so_far: Vec<U>,
+
+    /// The error that stopped the decoding
+    pub error: DecompressionError,
+
+    /// Offset in bits, from the start of the bitcode, of the code that couldn't be decoded, if the error is an invalid code
+    pub bit_offset: Option<usize>,
 
 }
 
-impl<U> PartialEq for Node<U>
-where
-    U: Clone + PartialEq
-{
-    fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
+impl<U> DecodeFailure<U> {
 
-            (Self::Parent { left: l_left, right: l_right, .. }, Self::Parent { left: r_left, right: r_right, .. }) => l_left == r_left && l_right == r_right,
+    fn new(decoded_so_far: Vec<U>, error: DecompressionError) -> Self {
 
-            (Self::Leaf { value: l_value, .. }, Self::Leaf { value: r_value, .. }) => l_value == r_value,
+        let bit_offset = match error {
+            DecompressionError::BitCodeDecodingError { source: DecodingError::InvalidEncoding { bit_offset }, .. } => Some(bit_offset),
+            _ => None
+        };
 
-            _ => false,
-        }
+        Self { decoded_so_far, error, bit_offset }
+    }
+
+}
+
+impl<U> fmt::Display for DecodeFailure<U> {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "decoding failed after {} symbols", self.decoded_so_far.len())
     }
 }
 
-impl<U> Node<U>
+impl<U> Error for DecodeFailure<U>
 where
-    U: Clone + PartialEq,
-    [(); mem::size_of::<U>()]:
+    U: fmt::Debug
 {
 
-    pub const fn count(&self) -> usize {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+
+impl From<DecompressionError> for io::Error {
+
+    fn from(e: DecompressionError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+impl Error for DecompressionError {
+
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
-            Node::Parent { count, .. } |
-            Node::Leaf { count, .. }
-                => *count
+            DecompressionError::InvalidHeader (e) => Some(e),
+            DecompressionError::ChunkSizeMismatch { .. } |
+            DecompressionError::UnsupportedChunkSize { .. } |
+            DecompressionError::InvalidBitCode { .. } => None,
+            DecompressionError::InvalidDecodingTree (e) => Some(e),
+            DecompressionError::BitCodeDecodingError { source, .. } => Some(source),
+            DecompressionError::TruncatedSection { .. } |
+            DecompressionError::SymbolCountMismatch { .. } |
+            DecompressionError::InvalidMatchLength { .. } |
+            DecompressionError::InvalidSymbol { .. } |
+            DecompressionError::InvalidBlockKind { .. } |
+            DecompressionError::NotAStream |
+            DecompressionError::TrailingData { .. } |
+            DecompressionError::NoTreeToReuse { .. } |
+            DecompressionError::MissingDictionary { .. } |
+            DecompressionError::DictionaryMismatch { .. } |
+            DecompressionError::InvalidVarint { .. } |
+            DecompressionError::NotSeekable |
+            DecompressionError::InvalidCheckpoints { .. } |
+            DecompressionError::SymbolOutOfRange { .. } |
+            DecompressionError::AlphabetTooLarge { .. } |
+            DecompressionError::MissingTransform |
+            DecompressionError::UntransformedBlocks |
+            DecompressionError::RejectedBlock { .. } |
+            DecompressionError::TruncatedPayload { .. } |
+            DecompressionError::DirtyPadding { .. } |
+            DecompressionError::UnusedBlockBytes { .. } |
+            DecompressionError::UnknownSymbolType |
+            DecompressionError::ChecksumMismatch { .. } |
+            DecompressionError::UnexpectedChecksum { .. } |
+            DecompressionError::MissingChecksum { .. } |
+            DecompressionError::InvalidUtf8 { .. } => None,
+            DecompressionError::InvalidMtfIndex (e) => Some(e),
+            DecompressionError::InvalidMatch (e) => Some(e),
+            DecompressionError::InvalidRemappedId (e) => Some(e),
         }
     }
+}
 
 
-    pub fn insert(&mut self, freq: usize, insert_value: U) {
 
-        match self {
 
-            Node::Parent { count, left, right } => {
+/// Compress the symbols of `input` with a frequency tree.
+/// Decompress with `decompress`. Byte slices are compressed faster by `compress_bytes`.
+///
+/// Panics if the input is empty, or if the source doesn't yield the same symbols on every pass.
+pub fn compress<U>(input: impl SymbolSource<Symbol = U>) -> Box<[u8]>
+where
+    U: Symbol
+{
+    try_compress(input).unwrap_or_else(|e| panic!("{e}"))
+}
 
-                if right.count() > left.count() {
-                    left.insert(freq, insert_value);
-                } else {
-                    right.insert(freq, insert_value);
-                }
 
-                *count += freq;
-            },
+/// Like `compress`, but return an error instead of panicking if the input can't be compressed
+pub fn try_compress<U>(input: impl SymbolSource<Symbol = U>) -> Result<Box<[u8]>, CompressError>
+where
+    U: Symbol
+{
+    compress_with_header(Header::new(), Passes::new(&input), &TreeCodec)
+}
+
 
-            Node::Leaf { count, value } => {
+/// Compress arbitrary binary data. The bytes are not interpreted as text, so any sequence round-trips exactly
+/// through `decompress_bytes`, including invalid UTF-8 and empty data, which `compress` can't encode with a tree.
+///
+/// The bytes are counted and encoded with arrays indexed by the byte values instead of the hash tables of the generic
+/// path, so this is several times faster than `compress` over the same bytes.
+pub fn compress_bytes(data: &[u8]) -> Box<[u8]> {
 
-                *self = Node::Parent {
-                    count: *count + freq,
-                    left: Box::new(Node::Leaf { count: *count, value: value.clone() }),
-                    right: Box::new(Node::Leaf { count: freq, value: insert_value })
-                };
-            },
+    let header = Header { symbol_type: u8::TYPE, ..Header::new() };
 
-        }
+    if data.is_empty() {
+        return compress_empty(header);
     }
 
+    let mut res = Vec::with_capacity(header.serialized_size() + data.len() / 2);
+    header.serialize(&mut res);
 
-    pub fn encode(&self, encoding: Encoding, target: U) -> Option<Encoding> {
+    write_byte_payload(&mut res, data, header.bit_order).unwrap_or_else(|e| panic!("{e}"));
 
-        match self {
+    res.into_boxed_slice()
+}
 
-            Node::Parent { left, right, .. } => {
 
-                if let Some(ret) = left.encode(encoding.step_left(), target.clone()) {
-                    Some(ret)
-                } else {
-                    right.encode(encoding.step_right(), target)
-                }
-            },
+/// Decompress the data compressed by `compress_bytes`, or by any other function compressing bytes
+pub fn decompress_bytes(input: &[u8]) -> Result<Box<[u8]>, DecompressionError> {
+    decompress(input)
+}
 
-            Node::Leaf { value, .. } => {
 
-                if *value == target {
-                    Some(encoding)
-                } else {
-                    None
-                }
-            },
-        }
-    }
+/// Decompress bytes that hold UTF-8 text.
+/// Fail with `DecompressionError::InvalidUtf8` at the offset of the first invalid sequence if the bytes aren't valid UTF-8.
+pub fn decompress_to_string(input: &[u8]) -> Result<String, DecompressionError> {
+    String::from_utf8(decompress_bytes(input)?.into_vec())
+        .map_err(|e| DecompressionError::InvalidUtf8 { offset: e.utf8_error().valid_up_to() })
+}
 
 
-    pub fn deserialize(buf: &[u8]) -> Result<(Self, usize), NodeDeserializationError> {
+/// Decompress bytes that hold UTF-8 text, replacing the invalid sequences with `U+FFFD REPLACEMENT CHARACTER`
+pub fn decompress_to_string_lossy(input: &[u8]) -> Result<String, DecompressionError> {
+    String::from_utf8(decompress_bytes(input)?.into_vec())
+        .or_else(|e| Ok(String::from_utf8_lossy(e.as_bytes()).into_owned()))
+}
 
-        match SerialSpecifier::try_from(
-            *buf.get(0)
-                .ok_or(NodeDeserializationError::MissingNodeTypeSpecifier)?
-        )? {
 
-            SerialSpecifier::Leaf => {
+/// Write empty data after `header` as a stream without data blocks, since a tree can't be built without symbols
+pub(crate) fn compress_empty(header: Header) -> Box<[u8]> {
 
-                if buf.len() < 1 + mem::size_of::<U>() {
-                    return Err(NodeDeserializationError::MissingNodeUnitData);
-                }
+    let header = Header { blocks: true, ..header };
 
-                let value = unsafe {
-                    &*(&buf[1..1 + mem::size_of::<U>()] as *const _ as *const [u8; mem::size_of::<U>()]) as &[u8; mem::size_of::<U>()]
-                };
+    let mut res = Vec::with_capacity(header.serialized_size() + 1);
+    header.serialize(&mut res);
+    res.push(stream::BlockKind::End as u8);
 
-                Ok((
-                    Self::Leaf {
-                        count: 0, // The count won't be needed during decoding.
-                        value: unsafe {
-                            mem::transmute::<&[u8; mem::size_of::<U>()], &U>(value).clone()
-                        }
-                    },
-                    1 + mem::size_of::<U>()
-                ))
-            },
-
-            SerialSpecifier::Parent => {
-
-                let (left, read1) = Self::deserialize(&buf[1..])?;
-                let (right, read2) = Self::deserialize(&buf[1 + read1..])?;
-
-                Ok((
-                    Self::Parent {
-                        count: 0, // The count won't be needed during decoding.
-                        left: Box::new(left),
-                        right: Box::new(right)
-                    },
-                    1 + read1 + read2
-                ))
-            },
+    res.into_boxed_slice()
+}
 
-        }
 
-    }
+/// Compress the symbols of a fallible source, such as a file reader, and return the first error of the source.
+/// The compressor reads its input several times, so the symbols are buffered: a cloneable source of symbols is cheaper to compress with `compress`.
+pub fn compress_fallible<U, E>(input: impl Iterator<Item = Result<U, E>>) -> Result<Box<[u8]>, E>
+where
+    U: Symbol
+{
+    let symbols = input.collect::<Result<Vec<U>, E>>()?;
+
+    Ok(compress(symbols.into_iter()))
+}
 
 
-    pub fn serialize(&self, buf: &mut Vec<u8>) {
+/// Options controlling how `compress_with_options` compresses the data
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompressionOptions {
 
-        // Don't include the count field in the serialized data because it won't be needed during decoding.
-        match self {
+    /// Replace repeated sequences with LZ77 back-references before entropy coding.
+    /// The literals, match lengths, and match distances are then coded with separate trees, like DEFLATE does.
+    pub lz77: bool,
 
-            Node::Parent { left, right, .. } => {
+    /// Apply the move-to-front transform before any other stage.
+    /// Locally repeated symbols become runs of small indices, which improves compression of locally correlated data.
+    pub mtf: bool,
 
-                buf.push(SerialSpecifier::Parent as u8);
+    /// Serialize the trees with one bit per node specifier instead of one byte.
+    /// Only used by `compress_with_options`: with `compress_with_codec`, the codec decides how its model is serialized.
+    pub compact_tree: bool,
 
-                left.serialize(buf);
-                right.serialize(buf);
-            },
+    /// Serialize the trees as the code lengths of the sorted symbols, and encode with canonical codes of the same lengths.
+    /// The trees of similar data then serialize to similar bytes. Takes precedence over `compact_tree`.
+    pub canonical_tree: bool,
 
-            Node::Leaf { value, .. } => {
+    /// Order of the bits in the bytes of the bitcode, recorded in the header.
+    /// `BitOrder::LsbFirst` packs them like DEFLATE, for tools that read the bitcode with an existing bit reader.
+    pub bit_order: BitOrder,
 
-                buf.push(SerialSpecifier::Leaf as u8);
+    /// Maximum number of distinct symbols, checked before building any tree.
+    /// Inputs with more distinct symbols, such as 64-bit identifiers, would build one leaf per symbol and rarely compress:
+    /// `try_compress_with_options` rejects them with `CompressError::AlphabetTooLarge` instead.
+    pub max_unique_symbols: Option<NonZeroUsize>,
 
-                let bytes = unsafe {
-                    slice::from_raw_parts(
-                        value as *const U as *const u8,
-                        mem::size_of::<U>()
-                    )
-                };
+    /// Replace the `\r\n` line endings of text with `\n`, recorded in the header so that decompression restores them.
+    /// Only applied to bytes and characters whose every `\n` follows a `\r`: other inputs are compressed unchanged.
+    pub normalize_newlines: bool,
 
-                buf.extend_from_slice(bytes);
-            },
+}
+
+impl CompressionOptions {
+
+    pub const fn new() -> Self {
+        Self {
+            lz77: false,
+            mtf: false,
+            compact_tree: false,
+            canonical_tree: false,
+            bit_order: BitOrder::MsbFirst,
+            max_unique_symbols: None,
+            normalize_newlines: false
         }
     }
 
 }
 
 
-#[derive(Debug, Clone, Copy)]
-pub enum DecodingError {
+/// Compress the symbols of `input` as specified by `options`.
+/// Decompress with `decompress`.
+///
+/// Panics if the input has more distinct symbols than `options.max_unique_symbols`.
+pub fn compress_with_options<U>(input: impl SymbolSource<Symbol = U>, options: &CompressionOptions) -> Box<[u8]>
+where
+    U: Symbol
+{
+    try_compress_with_options(input, options).unwrap_or_else(|e| panic!("{e}"))
+}
 
-    InvalidEncoding
 
+/// Like `compress_with_options`, but return an error instead of panicking if the input can't be compressed as specified by `options`
+pub fn try_compress_with_options<U>(input: impl SymbolSource<Symbol = U>, options: &CompressionOptions) -> Result<Box<[u8]>, CompressError>
+where
+    U: Symbol
+{
+    compress_with_selected_codec(Header::new(), Passes::new(&input), options)
 }
 
 
-/// Encodes a value in the tree
-#[derive(Debug, Clone)]
-struct Encoding {
-
-    /// The actual encoded value
-    bits: u64,
+/// Compress the symbols of `input` after `header`, with the tree layout selected by `options`
+pub(crate) fn compress_with_selected_codec<U>(header: Header, input: impl Iterator<Item = U> + Clone, options: &CompressionOptions) -> Result<Box<[u8]>, CompressError>
+where
+    U: Symbol
+{
+    if options.canonical_tree {
+        compress_with_pipeline(Header { canonical_tree: true, ..header }, input, options, &CanonicalTreeCodec)
+    } else if options.compact_tree {
+        compress_with_pipeline(Header { compact_tree: true, ..header }, input, options, &CompactTreeCodec)
+    } else {
+        compress_with_pipeline(header, input, options, &TreeCodec)
+    }
+}
 
-    /// How many bits have meaning
-    meaningful: u8
 
+/// Compress the symbols of `input` as specified by `options`, entropy coding them with `codec`.
+/// Decompress with `decompress_with_codec` and the same codec.
+///
+/// Panics if the input has more distinct symbols than `options.max_unique_symbols`.
+pub fn compress_with_codec<U, C>(input: impl Iterator<Item = U> + Clone, options: &CompressionOptions, codec: &C) -> Box<[u8]>
+where
+    U: Symbol,
+    C: EntropyCodec
+{
+    compress_with_pipeline(Header::new(), input, options, codec).unwrap_or_else(|e| panic!("{e}"))
 }
 
-impl Encoding {
 
-    /// Create a new `Encoding` object with all bits initialized to zero
-    pub const fn new_zeroed() -> Self {
-        Self {
-            bits: 0,
-            meaningful: 0
-        }
-    }
+/// Apply the transforms selected by `options` and compress the result after `header`
+fn compress_with_pipeline<U, C>(header: Header, input: impl Iterator<Item = U> + Clone, options: &CompressionOptions, codec: &C) -> Result<Box<[u8]>, CompressError>
+where
+    U: Symbol,
+    C: EntropyCodec
+{
 
+    if let Some(max) = options.max_unique_symbols {
+        check_alphabet_size(input.clone(), max.get())?;
+    }
 
-    pub const fn step_left(&self) -> Self {
-        // No operation is necessary because on a well-formed steps argument the uninitialized bits are already 0
-        Self {
-            bits: self.bits,
-            meaningful: self.meaningful + 1
+    if options.normalize_newlines {
+        if let Some(normalized) = newlines::normalize(&input.clone().collect::<Vec<U>>()) {
+            let header = Header { settings: EncoderSettings { normalized_newlines: true, ..header.settings }, ..header };
+            return compress_with_pipeline(header, normalized.into_iter(), &CompressionOptions { normalize_newlines: false, ..options.clone() }, codec);
         }
     }
 
+    let header = Header { lz77: options.lz77, mtf: options.mtf, bit_order: options.bit_order, symbol_type: U::TYPE, ..header };
 
-    pub const fn step_right(&self) -> Self {
-        Self {
-            bits: (self.bits.to_be() | (1_u64 << 63-self.meaningful)).to_be(),
-            meaningful: self.meaningful + 1
-        }
+    if !options.mtf {
+        return compress_with_header(header, input, codec);
     }
 
+    let data: Vec<U> = input.collect();
+    let (alphabet, indices) = mtf::encode(&data);
 
-    #[allow(dead_code)]
-    pub fn iter_bits(&self) -> impl Iterator<Item = bool> + '_ {
-        (0..self.meaningful)
-            .rev()
-            .map(|i| (self.bits & (1_u64 << i)) != 0)
-    }
+    let mut res = Vec::new();
+
+    header.serialize(&mut res);
+
+    write_alphabet(&mut res, &alphabet);
+
+    write_payload(&mut res, indices.into_iter(), options.lz77, options.bit_order, codec)?;
+
+    Ok(res.into_boxed_slice())
+}
 
 
-    pub fn as_bits<'a>(&'a self) -> BitView<'a> {
-        BitView::from_padded_bytes(
-            & unsafe { mem::transmute::<&u64, &[u8; 8]>(&self.bits) } [0..least_bytes_repr_for_bits(self.meaningful as usize)],
-            (8 - (self.meaningful % 8)) * (self.meaningful % 8 != 0) as u8
-        )
+/// Check that `input` has at most `max` distinct symbols, stopping at the first symbol past the limit
+fn check_alphabet_size<U>(input: impl Iterator<Item = U>, max: usize) -> Result<(), CompressError>
+where
+    U: Symbol
+{
+
+    let mut alphabet = HashSet::new();
+
+    for symbol in input {
+        if alphabet.insert(symbol) && alphabet.len() > max {
+            return Err(CompressError::AlphabetTooLarge { max });
+        }
     }
 
+    Ok(())
 }
 
 
-#[derive(Debug, PartialEq)]
-pub struct DecodingTree<U: Clone> {
 
-    root: Node<U>
 
+/// Compress the symbols of `input` grouped in chunks of `N` symbols.
+/// The number of symbols doesn't need to be a multiple of `N`: the length of the last chunk is recorded in the header.
+///
+/// Panics if the input is empty.
+pub fn compress_chunked<S, const N: usize>(input: impl Iterator<Item = S> + Clone) -> Box<[u8]>
+where
+    S: Symbol
+{
+    compress_chunked_with_settings::<S, N>(input, EncoderSettings::new())
 }
 
-impl<U> DecodingTree<U>
+
+/// Compress `input` in chunks of `N` symbols, recording `settings` in the header
+fn compress_chunked_with_settings<S, const N: usize>(input: impl Iterator<Item = S> + Clone, settings: EncoderSettings) -> Box<[u8]>
 where
-    U: Clone + PartialEq,
-    [(); mem::size_of::<U>()]:
+    S: Symbol
 {
+    const { assert!(N <= u8::MAX as usize, "the chunk size must fit in a byte") };
 
-    /// Decode the data unit represented by the given bit code
-    pub fn decode(&self, bitcode: &BitView) -> Result<Box<[U]>, DecodingError> {
+    let chunks = ChunkedSymbols::<_, N>::new(input);
 
-        let mut decoded = Vec::new();
+    let header = Header { settings, ..Header::chunked(N as u8, chunks.remainder() as u8) };
 
-        let mut node = &self.root;
+    compress_with_header(header, chunks, &TreeCodec).unwrap_or_else(|e| panic!("{e}"))
+}
 
-        for bit in bitcode.iter_bits() {
 
-            if let Node::Parent { left, right, .. } = node {
+/// Number of characters of the input analyzed by `compress_auto_grouped` to choose the group size
+const AUTO_GROUPING_SAMPLE_CHARS: usize = 64 * 1024;
 
-                let next_node = [left, right][bit as usize];
-                match next_node.as_ref() {
+/// Largest group size tried by `compress_auto_grouped`
+const MAX_AUTO_GROUP_SIZE: usize = 4;
 
-                    Node::Parent { .. } => {
-                        node = next_node;
-                    },
 
-                    Node::Leaf { value, .. } => {
-                        decoded.push(value.clone());
-                        node = &self.root;
-                    },
-                }
+/// Compress `text` grouping its characters in chunks of 1 to 4 characters, whichever yields the best compression ratio
+/// on a sample of the input. The chosen group size is recorded in the header, along with `EncoderSettings::auto_grouped`.
+/// Decompress with `decompress_auto_grouped`. Empty text is written as a stream without blocks, with a group size of 1.
+pub fn compress_auto_grouped(text: &str) -> Box<[u8]> {
 
-            } else {
-                unreachable!()
-            }
-        }
+    let settings = EncoderSettings { auto_grouped: true, ..EncoderSettings::new() };
 
-        if let Node::Leaf { value, .. } = node {
-            decoded.push(value.clone());
-        } else if node as *const Node<U> != &self.root as *const Node<U> {
-            return Err(DecodingError::InvalidEncoding);
-        }
+    if text.is_empty() {
+        return compress_empty(Header { settings, symbol_type: <[char; 1]>::TYPE, ..Header::chunked(1, 0) });
+    }
 
-        Ok(decoded.into_boxed_slice())
+    let sample = text.chars().take(AUTO_GROUPING_SAMPLE_CHARS);
+
+    let sample_sizes = [
+        compress_chunked::<char, 1>(sample.clone()).len(),
+        compress_chunked::<char, 2>(sample.clone()).len(),
+        compress_chunked::<char, 3>(sample.clone()).len(),
+        compress_chunked::<char, 4>(sample).len(),
+    ];
+
+    let best_group_size = sample_sizes.iter()
+        .enumerate()
+        .min_by_key(|(_, size)| **size)
+        .map(|(i, _)| i + 1)
+        .unwrap();
+
+    match best_group_size {
+        1 => compress_chunked_with_settings::<char, 1>(text.chars(), settings),
+        2 => compress_chunked_with_settings::<char, 2>(text.chars(), settings),
+        3 => compress_chunked_with_settings::<char, 3>(text.chars(), settings),
+        4 => compress_chunked_with_settings::<char, 4>(text.chars(), settings),
+        _ => unreachable!()
     }
+}
 
 
-    pub fn serialize(&self, buf: &mut Vec<u8>) {
+/// Compress the symbols after `header`.
+/// Wide symbols are replaced by dense identifiers when the mapping table costs less than the bytes saved in the tree leaves.
+pub(crate) fn compress_with_header<U, C>(mut header: Header, input: impl Iterator<Item = U> + Clone, codec: &C) -> Result<Box<[u8]>, CompressError>
+where
+    U: Symbol,
+    C: EntropyCodec
+{
 
-        self.root.serialize(buf);
-    }
+    header.symbol_type = U::TYPE;
 
+    let mut res = Vec::with_capacity(header.serialized_size());
 
-    pub fn deserialize(input: &[u8]) -> Result<(Self, usize), NodeDeserializationError>
-    where
-        [(); mem::size_of::<U>()]:
-    {
+    // The mapping table stores the symbol width in a byte
+    if U::WIDTH > 1 && U::WIDTH <= u8::MAX as usize {
 
-        let (root, read) = Node::deserialize(input)?;
+        // Only the alphabet is kept, and the symbols are mapped to their ids in the later passes
+        let (alphabet, index) = remap::index(input.clone());
 
-        Ok((
-            Self {
-                root
-            },
-            read
-        ))
-    }
+        let id_width = remap::id_width(alphabet.len());
+        let saved = alphabet.len() * U::WIDTH.saturating_sub(id_width);
 
-}
+        if saved > compact_alphabet_size(&alphabet) {
 
+            header.remap = true;
+            header.serialize(&mut res);
 
-#[derive(Debug, Clone, Copy)]
-pub enum NodeDeserializationError {
+            write_compact_alphabet(&mut res, &alphabet);
 
-    MissingNodeTypeSpecifier,
-    InvalidNodeTypeSpecifier (u8),
-    MissingNodeUnitData
+            // A symbol out of the alphabet, from a pass that differs from the first, is replaced with a valid id and reported
+            // once the payload is written
+            let unknown = Cell::new(None);
+            let ids = input.enumerate().map(|(position, symbol)| index.get(&symbol).copied().unwrap_or_else(|| {
+                unknown.set(unknown.get().or(Some(position)));
+                0
+            }));
 
-}
+            match id_width {
+                1 => write_payload(&mut res, ids.map(|id| id as u8), header.lz77, header.bit_order, codec),
+                2 => write_payload(&mut res, ids.map(|id| id as u16), header.lz77, header.bit_order, codec),
+                _ => write_payload(&mut res, ids, header.lz77, header.bit_order, codec),
+            }?;
 
+            if let Some (position) = unknown.get() {
+                return Err(CompressError::InconsistentSource { position });
+            }
 
-#[derive(Debug, PartialEq)]
-pub struct EncodingTree<U: Clone> {
+            return Ok(res.into_boxed_slice());
+        }
+    }
 
-    /// Root node of the binary tree
-    root: Option<Node<U>>,
+    header.serialize(&mut res);
 
-    /// Total number of leaf nodes in the tree
-    leaf_count: usize,
+    write_payload(&mut res, input, header.lz77, header.bit_order, codec)?;
 
+    Ok(res.into_boxed_slice())
 }
 
-impl<U> EncodingTree<U>
+
+pub fn decompress<U>(input: &[u8]) -> Result<Box<[U]>, DecompressionError>
 where
-    U: Clone + Eq + Hash + PartialEq,
-    [(); mem::size_of::<U>()]:
+    U: Symbol
 {
 
-    const fn new() -> Self {
-        Self {
-            root: None,
-            leaf_count: 0
-        }
+    // The header records the layout of the trees
+    match Header::deserialize(input) {
+        Ok ((header, _)) if header.compact_tree => return decompress_with_codec(input, &CompactTreeCodec),
+        Ok ((header, _)) if header.canonical_tree => return decompress_with_codec(input, &CanonicalTreeCodec),
+        _ => ()
     }
 
-
-    pub const fn leaf_node_count(&self) -> usize {
-        self.leaf_count
-    }
-
-
-    pub const fn parent_node_count(&self) -> usize {
-        self.leaf_count - (self.leaf_count > 1) as usize
-    }
+    decompress_with_codec(input, &TreeCodec)
+}
 
 
-    pub const fn total_node_count(&self) -> usize {
-        self.leaf_node_count() + self.parent_node_count()
-    }
+/// Like `decompress`, but return the symbols decoded before the error along with it, to salvage truncated or damaged data.
+/// Bitcodes are decoded until they end even if they're too short for their symbol count, and streams yield the symbols
+/// of the blocks before the invalid one. LZ77 payloads are decoded whole, so they yield no symbols on error.
+pub fn decompress_partial<U>(input: &[u8]) -> Result<Box<[U]>, DecodeFailure<U>>
+where
+    U: Symbol
+{
 
+    let mut decoded = Vec::new();
 
-    fn add_value(&mut self, freq: usize, value: U) {
+    let symbols = decoded_symbols::<U>(input, Some(&mut |count| tree::reserve_single_leaf(&mut decoded, count)))
+        .map_err(|error| DecodeFailure::new(Vec::new(), error))?;
 
-        if let Some(root) = &mut self.root {
-            root.insert(freq, value);
-        } else {
-            self.root = Some(Node::Leaf { count: freq, value });
+    for symbol in symbols {
+        match symbol {
+            Ok (symbol) => decoded.push(symbol),
+            Err (error) => return Err(DecodeFailure::new(decoded, error))
         }
-
-        self.leaf_count += 1;
     }
 
+    Ok(decoded.into_boxed_slice())
+}
 
-    fn encode_value(&self, value: U) -> Encoding {
-        self.root.as_ref()
-            .unwrap()
-            .encode(Encoding::new_zeroed(), value)
-            .unwrap()
-    }
 
+/// Decompress data produced by `compress_with_codec` with the same codec
+pub fn decompress_with_codec<U, C>(input: &[u8], codec: &C) -> Result<Box<[U]>, DecompressionError>
+where
+    U: Symbol,
+    C: EntropyCodec
+{
 
-    pub fn encode(data: impl Iterator<Item = U> + Clone) -> (Self, BitVec) {
+    let (_, decoded) = decompress_with_header(input, 1, codec)?;
 
-        let mut frequencies = value_frequencies(data.clone());
-        sort_frequencies(&mut frequencies);
+    Ok(decoded)
+}
 
-        let mut encoder = Self::new();
 
-        for (value, freq) in frequencies.iter() {
-            encoder.add_value(*freq, value.clone());
-        }
+/// Decompress data produced by `compress_chunked` with the same chunk size `N`.
+/// Return the original, ungrouped symbols.
+pub fn decompress_chunked<S, const N: usize>(input: &[u8]) -> Result<Box<[S]>, DecompressionError>
+where
+    S: Symbol
+{
 
-        let mut encoded = BitVec::new();
+    let (header, decoded) = decompress_with_header::<[S; N], _>(input, N, &TreeCodec)?;
 
-        for ch in data {
-            encoded.extend_from_bits(
-                &encoder.encode_value(ch).as_bits()
-            );
-        }
+    let mut symbols: Vec<S> = decoded.iter().flatten().cloned().collect();
 
-        (encoder, encoded)
-    }
+    if header.chunk_remainder != 0 {
 
+        // The header claims a partial last chunk, but there are no chunks at all
+        let full_chunks_len = symbols.len().checked_sub(N)
+            .ok_or(DecompressionError::InvalidHeader(
+                HeaderDeserializationError::InvalidChunking { offset: 2, chunk_size: header.chunk_size, chunk_remainder: header.chunk_remainder }
+            ))?;
 
-    /// Convert the `EncodingTree` into a `DecodingTree`
-    /// Return `None` if the tree is not initialized
-    pub fn into_decoder(self) -> Option<DecodingTree<U>> {
-        Some(DecodingTree {
-            root: self.root?
-        })
+        symbols.truncate(full_chunks_len + header.chunk_remainder as usize);
     }
 
+    Ok(symbols.into_boxed_slice())
 }
 
 
-fn sort_frequencies<T>(frequencies: &mut [(T, usize)]) {
-    frequencies.sort_by_key(|pair| pair.1)
+/// Decompress text produced by `compress_auto_grouped`, using the group size recorded in the header
+pub fn decompress_auto_grouped(input: &[u8]) -> Result<String, DecompressionError> {
+
+    let (header, _) = Header::deserialize(input).map_err(DecompressionError::InvalidHeader)?;
+
+    let chars = match header.chunk_size as usize {
+        1 => decompress_chunked::<char, 1>(input),
+        2 => decompress_chunked::<char, 2>(input),
+        3 => decompress_chunked::<char, 3>(input),
+        4 => decompress_chunked::<char, 4>(input),
+        found => return Err(DecompressionError::UnsupportedChunkSize { found, max: MAX_AUTO_GROUP_SIZE })
+    }?;
+
+    Ok(chars.iter().collect())
 }
 
 
-fn value_frequencies<U, I>(data: I) -> Box<[(U, usize)]>
+/// Decompress the input, checking that its symbols were grouped in chunks of `chunk_size` symbols
+fn decompress_with_header<U, C>(input: &[u8], chunk_size: usize, codec: &C) -> Result<(Header, Box<[U]>), DecompressionError>
 where
-    U: Eq + Hash,
-    I: Iterator<Item = U>
+    U: Symbol,
+    C: EntropyCodec
 {
 
-    let mut frequencies: HashMap<U, usize> = HashMap::new();
+    trace_span!("decompress", input_bytes = input.len(), symbol_width = U::WIDTH);
+    trace_timer!(start);
 
-    for unit in data {
+    let (header, header_size) = Header::deserialize(input).map_err(DecompressionError::InvalidHeader)?;
 
-        frequencies.entry(unit)
-            .and_modify(|counter| *counter += 1)
-            .or_insert(1);
+    if header.chunk_size as usize != chunk_size {
+        return Err(DecompressionError::ChunkSizeMismatch { expected: chunk_size, found: header.chunk_size as usize });
     }
 
-    frequencies.drain().collect()
-}
+    if let Some(id) = header.dictionary {
+        return Err(DecompressionError::MissingDictionary { id });
+    }
 
+    let decoded = if header.blocks {
 
-pub fn compress<U>(input: impl Iterator<Item = U> + Clone) -> Box<[u8]>
-where
-    U: Clone + Eq + Hash,
-    [(); mem::size_of::<U>()]:
-{
+        if header.transformed_blocks {
+            return Err(DecompressionError::MissingTransform);
+        }
 
-    let (encoder, bitcode) = EncodingTree::encode(input);
+        stream::read_blocks(input, header_size, &header.settings)?
 
-    let tree_repr_size = (1 + mem::size_of::<U>()) * encoder.leaf_node_count() + encoder.parent_node_count();
-    let bitcode_repr_size = 1 + bitcode.least_len_bytes();
+    } else if header.mtf {
 
-    let mut res = Vec::with_capacity(tree_repr_size + bitcode_repr_size);
+        let (alphabet, alphabet_size) = read_alphabet::<U>(input, header_size)?;
 
-    encoder.into_decoder().unwrap().serialize(&mut res);
+        let indices = read_payload::<u32, _>(input, header_size + alphabet_size, header.lz77, header.bit_order, codec)?;
 
-    bitcode.serialize(&mut res);
+        mtf::decode(alphabet, indices)
+            .map_err(DecompressionError::InvalidMtfIndex)?
+            .into_boxed_slice()
 
-    res.into_boxed_slice()
+    } else if header.checkpoints {
+
+        seek::read_seekable_payload(input, header_size, codec)?
+
+    } else if header.remap {
+
+        let (alphabet, alphabet_size) = read_compact_alphabet::<U>(input, header_size)?;
+
+        let offset = header_size + alphabet_size;
+        let ids: Vec<u32> = match remap::id_width(alphabet.len()) {
+            1 => read_payload::<u8, _>(input, offset, header.lz77, header.bit_order, codec)?.iter().map(|id| *id as u32).collect(),
+            2 => read_payload::<u16, _>(input, offset, header.lz77, header.bit_order, codec)?.iter().map(|id| *id as u32).collect(),
+            _ => read_payload::<u32, _>(input, offset, header.lz77, header.bit_order, codec)?.into_vec(),
+        };
+
+        remap::decode(&alphabet, ids)
+            .map_err(DecompressionError::InvalidRemappedId)?
+            .into_boxed_slice()
+
+    } else {
+        read_payload(input, header_size, header.lz77, header.bit_order, codec)?
+    };
+
+    let decoded = if header.settings.normalized_newlines {
+        newlines::restore(decoded.into_vec()).into_boxed_slice()
+    } else {
+        decoded
+    };
+
+    trace_event!(
+        symbols = decoded.len(),
+        elapsed_us = start.elapsed().as_micros() as u64,
+        throughput_mib_s = trace::throughput(decoded.len() * U::WIDTH, start),
+        "decoded"
+    );
+
+    Ok((header, decoded))
 }
 
 
-pub fn decompress<U>(input: &[u8]) -> Result<Box<[U]>, DecompressionError>
+/// Encode `input` with a frequency tree, without the container format.
+/// The tree and the bitcode can be serialized separately with `DecodingTree::serialize` and `BitVec::serialize`,
+/// for example to store them in different places, and decoded again with `decode_raw`.
+/// Return `None` if the input is empty.
+///
+/// If the input contains a single distinct value, its code is empty, so the bitcode doesn't record how many values there are.
+/// Store the number of values alongside the bitcode in that case.
+pub fn encode_raw<U>(input: impl Iterator<Item = U> + Clone) -> Option<(DecodingTree<U>, BitVec)>
 where
-    U: Clone + PartialEq,
-    [(); mem::size_of::<U>()]:
+    U: Symbol
 {
+    let (tree, bitcode) = EncodingTree::encode(input);
 
-    let (decoder, read) = DecodingTree::deserialize(input).map_err(|e| DecompressionError::InvalidDecodingTree(e))?;
+    Some((tree.into_decoder()?, bitcode))
+}
 
-    let bitcode = BitVec::deserialize(&input[read..]).map_err(|_| DecompressionError::InvalidBitCode)?;
 
-    let decoded = decoder.decode(&bitcode.as_bit_view()).map_err(|e| DecompressionError::BitCodeDecodingError(e))?;
+/// Decode a bitcode produced by `encode_raw` with its tree
+pub fn decode_raw<U>(tree: &DecodingTree<U>, bitcode: &BitView) -> Result<Box<[U]>, DecodingError>
+where
+    U: Symbol
+{
+    tree.decode(bitcode)
+}
 
-    Ok(decoded)
+
+/// Encode `input` with a tree managed by the application, for example a tree shared by both sides of a protocol,
+/// without the container format or the symbol count. The code table of the tree is built on the first call and cached in the tree.
+/// Fail if a symbol of the input is not in the tree.
+///
+/// The only symbol of a single-leaf tree is encoded with zero bits, so the number of symbols must be stored separately in that case.
+pub fn encode_payload<U>(tree: &DecodingTree<U>, input: impl Iterator<Item = U>) -> Result<BitVec, UnknownValue>
+where
+    U: Symbol
+{
+    tree::try_encode_with_table(tree.cached_code_table(), input, 0)
+}
+
+
+/// Decode a bitcode produced by `encode_payload` with the same tree
+pub fn decode_payload<U>(tree: &DecodingTree<U>, bitcode: &BitView) -> Result<Vec<U>, DecodingError>
+where
+    U: Symbol
+{
+    tree.decode(bitcode).map(Vec::from)
 }
 
 
@@ -527,19 +965,18 @@ mod tests {
 
     use std::{fs, path::Path};
 
-    use rand::{rngs::StdRng, Rng, SeedableRng};
-
     use super::*;
+    use tree::SerialSpecifier;
 
 
-    const TEST_DATA_DIR: &'static str = "test_data";
+    const TEST_DATA_DIR: &str = "test_data";
 
 
     fn load_text<P>(file_path: &P) -> String
     where
         P: AsRef<Path> + ?Sized
     {
-        fs::read_to_string(&file_path).unwrap_or_else(
+        fs::read_to_string(file_path).unwrap_or_else(
             |e| panic!("Could not read file {}:\n{}", file_path.as_ref().display(), e))
     }
 
@@ -569,74 +1006,656 @@ mod tests {
 
 
     #[test]
-    fn check_encoding() {
+    #[cfg_attr(miri, ignore = "reads the test data files")]
+    fn check_coherency() {
 
-        let mut rng = StdRng::seed_from_u64(0);
+        for text in get_test_files() {
+
+            let (encoder, compressed) = EncodingTree::encode(text.chars());
 
-        for _ in 0..100 {
+            let decoder = encoder.into_decoder().unwrap();
+
+            let decoded = decoder.decode(&compressed.as_bit_view())
+                .unwrap()
+                .iter()
+                .collect::<String>();
 
-            let mut enc = Encoding::new_zeroed();
+            assert_eq!(text, decoded);
 
-            for _ in 0..8 {
-                if rng.gen_bool(0.5) {
-                    enc = enc.step_left();
-                } else {
-                    enc = enc.step_right()
+            let mut ser = Vec::new();
+            decoder.serialize(&mut ser);
+
+            let des = DecodingTree::<char>::deserialize(&ser).unwrap().0;
+
+            assert_eq!(decoder, des);
+        }
+    }
+
+
+    #[test]
+    fn check_decompression_errors() {
+
+        // Too few distinct characters for remapping to pay off, so the leaves hold the characters
+        let compressed = compress("He".chars());
+        let (header, header_size) = Header::deserialize(&compressed).unwrap();
+
+        // The leaf node of the first character was cut in half
+        let err = decompress::<char>(&compressed[..header_size + 4]).unwrap_err();
+        assert_eq!(err, DecompressionError::InvalidDecodingTree(
+            NodeDeserializationError::MissingNodeUnitData { offset: header_size + 2, expected: 4, remaining: 2 }
+        ));
+        assert!(err.source().is_some());
+
+        let mut corrupted = compressed.to_vec();
+        corrupted[header_size] = 0x80;
+        let err = decompress::<char>(&corrupted).unwrap_err();
+        assert_eq!(err, DecompressionError::InvalidDecodingTree(
+            NodeDeserializationError::InvalidNodeTypeSpecifier { offset: header_size, found: 0x80, expected: SerialSpecifier::VALID }
+        ));
+
+        // Node kinds of later format versions are told apart from corrupted data
+        corrupted[header_size] = 7;
+        let err = decompress::<char>(&corrupted).unwrap_err();
+        assert_eq!(err, DecompressionError::InvalidDecodingTree(NodeDeserializationError::ReservedNodeTypeSpecifier { offset: header_size, found: 7 }));
+
+        let (tree, _) = DecodingTree::<char>::deserialize(&compressed[header_size..]).unwrap();
+        let mut tree_only = Vec::new();
+        header.serialize(&mut tree_only);
+        tree.serialize(&mut tree_only);
+        let err = decompress::<char>(&tree_only).unwrap_err();
+        assert_eq!(err, DecompressionError::TruncatedPayload { expected_min: 2, got: 0 });
+
+        // The symbol count is present, but not the bitcode
+        tree_only.push(2);
+        let err = decompress::<char>(&tree_only).unwrap_err();
+        assert_eq!(err, DecompressionError::TruncatedPayload { expected_min: 3, got: 1 });
+
+        // The bitcode encodes fewer symbols than counted
+        let mut miscounted = compressed.to_vec();
+        let count_offset = tree_only.len() - 1;
+        miscounted[count_offset] = 3;
+        let err = decompress::<char>(&miscounted).unwrap_err();
+        assert_eq!(err, DecompressionError::SymbolCountMismatch { offset: count_offset + 1, expected: 3, found: 2 });
+
+        // A surrogate code point is not a valid char
+        let mut corrupted = compressed.to_vec();
+        corrupted[header_size + 2..header_size + 6].copy_from_slice(&0xD800_u32.to_le_bytes());
+        let err = decompress::<char>(&corrupted).unwrap_err();
+        assert_eq!(err, DecompressionError::InvalidDecodingTree(
+            NodeDeserializationError::InvalidNodeUnitData { offset: header_size + 2 }
+        ));
+
+        let chunked = compress_chunked::<char, 2>("He".chars());
+        let err = decompress::<char>(&chunked).unwrap_err();
+        assert_eq!(err, DecompressionError::ChunkSizeMismatch { expected: 1, found: 2 });
+    }
+
+
+    #[test]
+    fn check_truncated_payload() {
+
+        // 1, 2, 4, and 8 occurrences: every code is 2 bits long
+        let data = b"abbccccdddddddd".repeat(20);
+        let compressed = compress_bytes(&data);
+
+        // Header with the symbol type and tree, then a 2-byte count, the padding byte, and 75 bytes of bitcode
+        let payload_offset = 4 + 11;
+        assert_eq!(compressed.len(), payload_offset + 2 + 1 + 75);
+
+        for len in payload_offset..compressed.len() {
+
+            let got = len - payload_offset;
+            let err = decompress_bytes(&compressed[..len]).unwrap_err();
+
+            let expected_min = match got {
+                // The count is missing or incomplete
+                0 | 1 => got + 2,
+                // 300 codes of 2 bits
+                _ => 2 + 1 + 75,
+            };
+            assert_eq!(err, DecompressionError::TruncatedPayload { expected_min, got }, "{len}");
+            assert_eq!(err.to_string(), format!("the payload has {got} bytes, expected at least {expected_min}"));
+        }
+
+        // Zero-bit codes can't be checked against the length of the bitcode
+        let compressed = compress_bytes(b"zzzz");
+        assert_eq!(decompress_bytes(&compressed[..compressed.len() - 1]), Err(DecompressionError::TruncatedPayload { expected_min: 2, got: 1 }));
+
+        for len in 0..compressed.len() - 1 {
+            assert!(decompress_bytes(&compressed[..len]).is_err());
+        }
+    }
+
+
+    #[test]
+    fn check_chunked_compression_decompression() {
+
+        for text in ["", "a", "ab", "abc", "Hello, world!\n"] {
+
+            let chars: Vec<char> = text.chars().collect();
+
+            if !chars.is_empty() {
+                assert_eq!(*decompress_chunked::<char, 1>(&compress_chunked::<char, 1>(chars.iter().cloned())).unwrap(), *chars);
+            }
+            if chars.len() >= 2 {
+                assert_eq!(*decompress_chunked::<char, 2>(&compress_chunked::<char, 2>(chars.iter().cloned())).unwrap(), *chars);
+            }
+            if chars.len() >= 3 {
+                assert_eq!(*decompress_chunked::<char, 3>(&compress_chunked::<char, 3>(chars.iter().cloned())).unwrap(), *chars);
+            }
+        }
+
+        let compressed = compress_chunked::<char, 3>("abcd".chars());
+        let err = decompress_chunked::<char, 2>(&compressed).unwrap_err();
+        assert_eq!(err, DecompressionError::ChunkSizeMismatch { expected: 2, found: 3 });
+    }
+
+
+    #[test]
+    fn check_lz77_compression_decompression() {
+
+        let options = CompressionOptions { lz77: true, ..CompressionOptions::new() };
+
+        for text in ["a", "aaaaaaaaaaaaaaaa", "abcabcabcabcabcabd", "no repeated sequences"] {
+
+            let compressed = compress_with_options(text.chars(), &options);
+
+            let decompressed: String = decompress::<char>(&compressed).unwrap().iter().collect();
+
+            assert_eq!(decompressed, text);
+        }
+
+        // The token count claims more tokens than the streams contain
+        let mut compressed = compress_with_options("abcabcabc".bytes(), &options).to_vec();
+        let (_, header_size) = Header::deserialize(&compressed).unwrap();
+        compressed[header_size] += 1;
+        assert!(matches!(decompress::<u8>(&compressed), Err(DecompressionError::SymbolCountMismatch { .. })));
+    }
+
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads the test data files")]
+    fn check_pipeline_test_files() {
+
+        for options in [
+            CompressionOptions { lz77: true, mtf: false, compact_tree: false, canonical_tree: false, bit_order: BitOrder::MsbFirst, max_unique_symbols: None, normalize_newlines: false },
+            CompressionOptions { lz77: false, mtf: true, compact_tree: false, canonical_tree: false, bit_order: BitOrder::MsbFirst, max_unique_symbols: None, normalize_newlines: false },
+            CompressionOptions { lz77: true, mtf: true, compact_tree: false, canonical_tree: false, bit_order: BitOrder::MsbFirst, max_unique_symbols: None, normalize_newlines: false },
+            CompressionOptions { lz77: false, mtf: false, compact_tree: true, canonical_tree: false, bit_order: BitOrder::MsbFirst, max_unique_symbols: None, normalize_newlines: false },
+            CompressionOptions { lz77: true, mtf: true, compact_tree: true, canonical_tree: false, bit_order: BitOrder::MsbFirst, max_unique_symbols: None, normalize_newlines: false },
+            CompressionOptions { lz77: false, mtf: false, compact_tree: false, canonical_tree: false, bit_order: BitOrder::LsbFirst, max_unique_symbols: None, normalize_newlines: false },
+            CompressionOptions { lz77: true, mtf: true, compact_tree: true, canonical_tree: false, bit_order: BitOrder::LsbFirst, max_unique_symbols: None, normalize_newlines: false },
+            CompressionOptions { lz77: true, mtf: true, compact_tree: false, canonical_tree: true, bit_order: BitOrder::LsbFirst, max_unique_symbols: None, normalize_newlines: false },
+        ] {
+            for text in get_test_files() {
+
+                let compressed = compress_with_options(text.chars(), &options);
+
+                let decompressed: String = decompress::<char>(&compressed).unwrap().iter().collect();
+
+                assert_eq!(decompressed, text);
+            }
+        }
+    }
+
+
+    #[test]
+    fn check_compact_tree_compression() {
+
+        let text = "the quick brown fox jumps over the lazy dog";
+
+        let compact = compress_with_options(text.bytes(), &CompressionOptions { compact_tree: true, ..CompressionOptions::new() });
+        let plain = compress_with_options(text.bytes(), &CompressionOptions::new());
+
+        assert!(Header::deserialize(&compact).unwrap().0.compact_tree);
+        assert!(compact.len() < plain.len());
+
+        assert_eq!(*decompress::<u8>(&compact).unwrap(), *text.as_bytes());
+    }
+
+
+    #[test]
+    fn check_canonical_tree_compression() {
+
+        // Distinct frequencies, so that the trees don't depend on how ties are broken
+        let text = "abbccccdddddddd";
+
+        let canonical = compress_with_options(text.bytes(), &CompressionOptions { canonical_tree: true, ..CompressionOptions::new() });
+        let plain = compress_with_options(text.bytes(), &CompressionOptions::new());
+
+        let (header, header_size) = Header::deserialize(&canonical).unwrap();
+        assert!(header.canonical_tree && !header.compact_tree);
+
+        // The canonical codes have the same lengths, so only the size of the tree changes
+        let (tree, tree_size) = DecodingTree::<u8>::deserialize_canonical_at(&canonical, header_size).unwrap();
+        let (plain_tree, plain_tree_size) = DecodingTree::<u8>::deserialize_at(&plain, header_size).unwrap();
+        assert_eq!(tree, plain_tree.to_canonical());
+        assert_eq!(canonical.len() - tree_size, plain.len() - plain_tree_size);
+
+        assert_eq!(*decompress::<u8>(&canonical).unwrap(), *text.as_bytes());
+
+        let mut out = Vec::new();
+        decompress_into_writer::<u8, _>(&canonical, &mut out).unwrap();
+        assert_eq!(out, text.as_bytes());
+
+        // The two layouts are exclusive
+        let mut both = canonical.to_vec();
+        both[1] |= container::flags::COMPACT_TREE;
+        assert!(matches!(decompress::<u8>(&both), Err(DecompressionError::InvalidHeader (HeaderDeserializationError::IncompatibleFlags { offset: 2, .. }))));
+    }
+
+
+    #[test]
+    fn check_bit_order() {
+
+        let text = "the quick brown fox jumps over the lazy dog";
+
+        let options = CompressionOptions { bit_order: BitOrder::LsbFirst, ..CompressionOptions::new() };
+        let compressed = compress_with_options(text.chars(), &options);
+
+        assert_eq!(Header::deserialize(&compressed).unwrap().0.bit_order, BitOrder::LsbFirst);
+        assert_eq!(decompress::<char>(&compressed).unwrap().iter().collect::<String>(), text);
+
+        // Bytes aren't remapped, so the payload is the tree, the symbol count, and the bitcode packed from the least significant bit
+        let compressed = compress_with_options(text.bytes(), &options);
+        let (_, header_size) = Header::deserialize(&compressed).unwrap();
+        let (tree, tree_size) = DecodingTree::<u8>::deserialize(&compressed[header_size..]).unwrap();
+        let bitcode_offset = header_size + tree_size + container::varint_size(text.len());
+
+        let bitcode = BitVec::deserialize_with_order(&compressed[bitcode_offset..], BitOrder::LsbFirst).unwrap();
+        assert_eq!(*tree.decode(&bitcode.as_bit_view()).unwrap(), *text.as_bytes());
+
+        let reversed = BitVec::deserialize_with_order(&compressed[bitcode_offset..], BitOrder::MsbFirst).unwrap();
+        assert_ne!(tree.decode(&reversed.as_bit_view()).ok().as_deref(), Some(text.as_bytes()));
+    }
+
+
+    #[test]
+    fn check_small_alphabets() {
+
+        let all_options = [
+            CompressionOptions::new(),
+            CompressionOptions { lz77: true, ..CompressionOptions::new() },
+            CompressionOptions { mtf: true, ..CompressionOptions::new() },
+            CompressionOptions { lz77: true, mtf: true, compact_tree: true, canonical_tree: false, bit_order: BitOrder::LsbFirst, max_unique_symbols: None, normalize_newlines: false },
+        ];
+
+        // Alphabets of 1, 2, and 3 symbols with 1-bit codes, including bit streams stored as bytes.
+        // Every length up to 17 ends the bitcode at a different offset in its last byte.
+        for alphabet in [&[7_u8][..], &[0, 1], &[0, 1, 2]] {
+            for len in 1..=17 {
+
+                let patterns: [Vec<u8>; 3] = [
+                    vec![alphabet[0]; len],
+                    (0..len).map(|i| alphabet[i % alphabet.len()]).collect(),
+                    (0..len).map(|i| alphabet[(i * i / 3) % alphabet.len()]).collect(),
+                ];
+
+                for data in &patterns {
+                    for options in &all_options {
+                        let compressed = compress_with_options(data.iter().copied(), options);
+                        assert_eq!(*decompress::<u8>(&compressed).unwrap(), **data, "{data:?} with {options:?}");
+                    }
+                    assert_eq!(*decompress_chunked::<u8, 2>(&compress_chunked::<u8, 2>(data.iter().copied())).unwrap(), **data);
                 }
             }
+        }
+
+        // Long runs of a single symbol only cost the tree and the symbol count
+        let compressed = compress("a".repeat(100_000).chars());
+        assert!(compressed.len() < 16);
+        assert_eq!(*decompress::<char>(&compressed).unwrap(), ['a'; 100_000]);
+    }
 
-            let v = enc.as_bits();
-            let expected = enc.iter_bits().collect::<Vec<bool>>();
 
-            assert_eq!(*v.to_bool_slice(), expected);
+    #[test]
+    fn check_mtf_compression_decompression() {
+
+        let options = CompressionOptions { mtf: true, ..CompressionOptions::new() };
+
+        for text in ["ab", "aaaabbbbaaaabbbbccccdddd", "Hello, world!"] {
+
+            let compressed = compress_with_options(text.chars(), &options);
+
+            let decompressed: String = decompress::<char>(&compressed).unwrap().iter().collect();
+
+            assert_eq!(decompressed, text);
         }
+
+        // The alphabet claims more symbols than the input contains
+        let mut compressed = compress_with_options("ab".chars(), &options).to_vec();
+        let (_, header_size) = Header::deserialize(&compressed).unwrap();
+        compressed[header_size] = 100;
+        let offset = header_size + 1;
+        assert_eq!(decompress::<char>(&compressed), Err(DecompressionError::TruncatedSection { offset, expected: 400, remaining: compressed.len() - offset }));
     }
 
 
     #[test]
-    fn small_coherency() {
+    fn check_newline_normalization() {
+
+        let options = CompressionOptions { normalize_newlines: true, ..CompressionOptions::new() };
+        let text = "first line\r\nsecond line\r\n\r\nlast line\r\n".repeat(10);
+
+        let compressed = compress_with_options(text.chars(), &options);
+        assert!(Header::deserialize(&compressed).unwrap().0.settings.normalized_newlines);
+        assert!(compressed.len() < compress(text.chars()).len());
 
-        let text = "He";
+        assert_eq!(decompress::<char>(&compressed).unwrap().iter().collect::<String>(), text);
+        assert_eq!(decompress_partial::<char>(&compressed).unwrap().iter().collect::<String>(), text);
 
-        let (encoder, compressed) = EncodingTree::encode(text.chars());
+        let mut written = Vec::new();
+        decompress_into_writer::<u8, _>(&compress_with_options(text.bytes(), &options), &mut written).unwrap();
+        assert_eq!(written, text.as_bytes());
+
+        // Bare line feeds, and symbols that aren't text, are compressed unchanged
+        for compressed in [compress_with_options("a\r\nb\n".chars(), &options), compress_with_options([13_u16, 10].into_iter(), &options)] {
+            assert!(!Header::deserialize(&compressed).unwrap().0.settings.normalized_newlines);
+        }
+    }
 
-        let decoded = encoder.into_decoder().unwrap().decode(&compressed.as_bit_view())
-            .unwrap()
-            .iter()
-            .collect::<String>();
+    #[test]
+    fn check_auto_grouped_compression_decompression() {
 
-        assert_eq!(text, decoded);
+        for text in ["", "a", "abcde", "Hello, world!\n"] {
+            assert_eq!(decompress_auto_grouped(&compress_auto_grouped(text)).unwrap(), text);
+        }
 
+        let compressed = compress_chunked::<char, 5>("abcde".chars());
+        let err = decompress_auto_grouped(&compressed).unwrap_err();
+        assert_eq!(err, DecompressionError::UnsupportedChunkSize { found: 5, max: MAX_AUTO_GROUP_SIZE });
     }
 
 
     #[test]
-    fn check_coherency() {
+    #[cfg_attr(miri, ignore = "reads the test data files")]
+    fn check_auto_grouped_test_files() {
 
         for text in get_test_files() {
 
-            let (encoder, compressed) = EncodingTree::encode(text.chars());
+            let compressed = compress_auto_grouped(&text);
 
-            let decoder = encoder.into_decoder().unwrap();
+            assert_eq!(decompress_auto_grouped(&compressed).unwrap(), text);
+        }
+    }
 
-            let decoded = decoder.decode(&compressed.as_bit_view())
-                .unwrap()
-                .iter()
-                .collect::<String>();
 
-            assert_eq!(text, decoded);
+    #[test]
+    fn check_remapped_compression() {
 
-            let mut ser = Vec::new();
-            decoder.serialize(&mut ser);
+        let texts = [
+            "the quick brown fox jumps over the lazy dog",
+            "Größenwahn, Ærø, ελληνικά, русский, 日本語, 🦀🦀🦀 and some ASCII too",
+        ];
 
-            let des = DecodingTree::<char>::deserialize(&ser).unwrap().0;
+        for text in texts {
 
-            assert_eq!(decoder, des);
+            let compressed = compress(text.chars());
+            assert_eq!(decompress::<char>(&compressed).unwrap().iter().collect::<String>(), text);
+
+            let lz77 = compress_with_options(text.chars(), &CompressionOptions { lz77: true, ..CompressionOptions::new() });
+            assert_eq!(decompress::<char>(&lz77).unwrap().iter().collect::<String>(), text);
+
+            let chunked = compress_chunked::<char, 2>(text.chars());
+            assert_eq!(decompress_chunked::<char, 2>(&chunked).unwrap().iter().collect::<String>(), text);
         }
+
+        let (header, _) = Header::deserialize(&compress(texts[0].chars())).unwrap();
+        assert!(header.remap);
+
+        // Single-byte symbols have nothing to compact
+        let (header, _) = Header::deserialize(&compress(texts[0].bytes())).unwrap();
+        assert!(!header.remap);
+    }
+
+
+    #[test]
+    fn check_fallible_compression() {
+
+        use std::io::{self, Read};
+
+        let text = b"the quick brown fox";
+
+        let compressed = compress_fallible(text.iter().copied().map(Ok::<_, io::Error>)).unwrap();
+        assert_eq!(*decompress::<u8>(&compressed).unwrap(), *text);
+
+        let compressed = compress_fallible(io::Cursor::new(text).bytes()).unwrap();
+        assert_eq!(*decompress::<u8>(&compressed).unwrap(), *text);
+
+        // The source error is returned as is
+        let source = text.iter().copied().enumerate().map(|(i, byte)| if i == 4 { Err(i) } else { Ok(byte) });
+        assert_eq!(compress_fallible(source), Err(4));
     }
 
 
     #[test]
+    fn check_decompress_to_string() {
+
+        let text = "Grüße, 世界!";
+        let compressed = compress_bytes(text.as_bytes());
+        assert_eq!(decompress_to_string(&compressed).as_deref(), Ok(text));
+        assert_eq!(decompress_to_string_lossy(&compressed).as_deref(), Ok(text));
+
+        // A lone continuation byte, and a sequence cut short by the end of the data
+        let mut invalid = b"valid \x80 text ".to_vec();
+        invalid.extend(&"é".as_bytes()[..1]);
+        let compressed = compress_bytes(&invalid);
+        assert_eq!(decompress_to_string(&compressed), Err(DecompressionError::InvalidUtf8 { offset: 6 }));
+        assert_eq!(decompress_to_string_lossy(&compressed).as_deref(), Ok("valid \u{fffd} text \u{fffd}"));
+
+        assert_eq!(decompress_to_string(&compress_bytes(b"")).as_deref(), Ok(""));
+        assert!(decompress_to_string(&compressed[..4]).is_err());
+    }
+
+    #[test]
+    fn check_binary_round_trip() {
+
+        use std::num::NonZeroUsize;
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(1646);
+
+        let random: Vec<u8> = (0..4096).map(|_| rng.gen()).collect();
+        let all_values: Vec<u8> = (0..=255).collect();
+        let mut mixed = b"text\0with\0NULs ".to_vec();
+        mixed.extend([0xff; 300]);
+        mixed.extend(b"\xc3\x28 \xed\xa0\x80 \xf8\x88\x80\x80\x80 invalid UTF-8");
+        mixed.extend([0; 300]);
+
+        let inputs: [&[u8]; 7] = [&[], &[0], &[0xff; 1000], &[0; 10], &random, &all_values, &mixed];
+
+        for data in inputs {
+
+            assert_eq!(*decompress_bytes(&compress_bytes(data)).unwrap(), *data);
+
+            if data.is_empty() {
+                continue;
+            }
+
+            for options in [
+                CompressionOptions { lz77: true, ..CompressionOptions::new() },
+                CompressionOptions { mtf: true, compact_tree: true, ..CompressionOptions::new() },
+                CompressionOptions { lz77: true, bit_order: BitOrder::LsbFirst, ..CompressionOptions::new() },
+            ] {
+                assert_eq!(*decompress_bytes(&compress_with_options(data.iter().copied(), &options)).unwrap(), *data);
+            }
+
+            let chunked = compress_chunked::<u8, 3>(data.iter().copied());
+            assert_eq!(*decompress_chunked::<u8, 3>(&chunked).unwrap(), *data);
+
+            assert_eq!(*decompress_bytes(&seek::compress_seekable(data.iter().copied(), NonZeroUsize::new(64).unwrap())).unwrap(), *data);
+            assert_eq!(*armor::decompress_armored::<u8>(&armor::compress_to_armored(data.iter().copied())).unwrap(), *data);
+        }
+
+        // The byte path builds the same tree as the generic path when the frequencies are distinct
+        let distinct: Vec<u8> = (1..=20_u8).flat_map(|byte| std::iter::repeat_n(byte, byte as usize)).collect();
+        assert_eq!(compress_bytes(&distinct), compress(distinct.iter().copied()));
+        assert_eq!(*decompress_bytes(&compress_bytes(&[42])).unwrap(), [42]);
+
+        // The empty data is a stream without blocks, after a header with the symbol type
+        let empty = compress_bytes(&[]);
+        assert_eq!(empty.len(), 5);
+        assert_eq!(*stream::Decoder::<u8>::new().feed(&empty).unwrap(), [0_u8; 0]);
+    }
+
+
+    #[test]
+    fn check_synthetic_corpora() {
+
+        use corpus::{CorpusOptions, Distribution};
+
+        let distributions = [
+            Distribution::Constant,
+            Distribution::Uniform,
+            Distribution::Zipf { exponent: 1.0 },
+            Distribution::Zipf { exponent: 2.5 },
+            Distribution::Geometric { ratio: 0.5 },
+        ];
+
+        for distribution in distributions {
+            for alphabet_size in [1, 2, 200, 5000] {
+
+                let corpus = CorpusOptions {
+                    len: 1 << 12,
+                    alphabet_size: NonZeroUsize::new(alphabet_size).unwrap(),
+                    distribution,
+                    seed: alphabet_size as u64
+                };
+                let data = corpus::generate(&corpus);
+
+                for options in [
+                    CompressionOptions::new(),
+                    CompressionOptions { lz77: true, mtf: true, ..CompressionOptions::new() },
+                    CompressionOptions { canonical_tree: true, bit_order: BitOrder::LsbFirst, ..CompressionOptions::new() },
+                    CompressionOptions { compact_tree: true, lz77: true, ..CompressionOptions::new() },
+                ] {
+                    let compressed = compress_with_options(data.iter().copied(), &options);
+                    assert_eq!(*decompress::<u32>(&compressed).unwrap(), *data, "{distribution:?} over {alphabet_size} symbols with {options:?}");
+                }
+
+                let chunked = compress_chunked::<u32, 2>(data.iter().copied());
+                assert_eq!(*decompress_chunked::<u32, 2>(&chunked).unwrap(), *data);
+
+                let seekable = seek::compress_seekable(data.iter().copied(), NonZeroUsize::new(100).unwrap());
+                assert_eq!(*decompress::<u32>(&seekable).unwrap(), *data);
+
+                let options = stream::EncoderOptions { max_block_symbols: NonZeroUsize::new(1000), ..stream::EncoderOptions::new() };
+                let mut encoder = stream::Encoder::with_options(Vec::new(), options);
+                encoder.write_symbols(data.iter().copied()).unwrap();
+                assert_eq!(*stream::Decoder::<u32>::new().feed(&encoder.finish().unwrap()).unwrap(), *data);
+            }
+        }
+    }
+
+
+    #[test]
+    fn check_max_unique_symbols() {
+
+        let ids: Vec<u64> = (0..1000_u64).map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15)).collect();
+
+        let options = CompressionOptions { max_unique_symbols: NonZeroUsize::new(256), ..CompressionOptions::new() };
+
+        assert_eq!(try_compress_with_options(ids.iter().copied(), &options), Err(CompressError::AlphabetTooLarge { max: 256 }));
+        assert_eq!(try_compress_with_options(ids.iter().copied(), &CompressionOptions { mtf: true, ..options.clone() }), Err(CompressError::AlphabetTooLarge { max: 256 }));
+
+        // The bytes of the identifiers fit in the limit
+        let bytes: Vec<u8> = ids.iter().flat_map(|id| id.to_le_bytes()).collect();
+        let compressed = try_compress_with_options(bytes.iter().copied(), &options).unwrap();
+        assert_eq!(*decompress::<u8>(&compressed).unwrap(), *bytes);
+
+        // Exactly `max` distinct symbols are accepted
+        let compressed = try_compress_with_options(ids[..256].iter().copied(), &options).unwrap();
+        assert_eq!(*decompress::<u64>(&compressed).unwrap(), ids[..256]);
+    }
+
+
+    #[test]
+    fn check_try_compress() {
+
+        assert_eq!(try_compress("".chars()), Err(CompressError::EmptyInput));
+        assert_eq!(try_compress_with_options("".bytes(), &CompressionOptions { mtf: true, ..CompressionOptions::new() }), Err(CompressError::EmptyInput));
+        assert_eq!(decompress::<char>(&try_compress("abc".chars()).unwrap()).unwrap().iter().collect::<String>(), "abc");
+
+        // A source whose passes differ, like a file modified while it's compressed
+        let passes = Cell::new(0_u8);
+        let changing = source::from_fn(|| {
+            passes.set(passes.get() + 1);
+            std::iter::once(passes.get())
+        });
+        assert_eq!(try_compress(changing), Err(CompressError::InconsistentSource { position: 0 }));
+
+        // Passes with a different number of the same symbols are still encoded consistently
+        let passes = Cell::new(0);
+        let resized = source::from_fn(|| {
+            passes.set(passes.get() + 1);
+            "ab".bytes().cycle().take(2 * passes.get())
+        });
+        assert!(decompress::<u8>(&try_compress(resized).unwrap()).is_ok());
+
+        // Wide symbols are remapped from fresh passes, without buffering the source
+        let text: Vec<char> = "the quick brown fox jumps over the lazy dog".chars().collect();
+        let passes = Cell::new(0);
+        let wide = source::from_fn(|| {
+            passes.set(passes.get() + 1);
+            text.iter().copied()
+        });
+        let compressed = try_compress(wide).unwrap();
+        assert!(Header::deserialize(&compressed).unwrap().0.remap);
+        assert_eq!(passes.get(), 3);
+        assert_eq!(*decompress::<char>(&compressed).unwrap(), *text);
+
+        // A symbol out of the remapped alphabet of the first pass read is reported
+        let passes = Cell::new(0);
+        let changing = source::from_fn(|| {
+            passes.set(passes.get() + 1);
+            text.iter().copied().chain(char::from_digit(passes.get(), 10))
+        });
+        assert_eq!(try_compress(changing), Err(CompressError::InconsistentSource { position: text.len() }));
+    }
+
+
+    #[test]
+    fn check_raw_encoding() {
+
+        let text = "the quick brown fox jumps over the lazy dog";
+
+        let (tree, bitcode) = encode_raw(text.chars()).unwrap();
+
+        // Store the parts separately, as in different database columns
+        let mut tree_column = Vec::new();
+        tree.serialize(&mut tree_column);
+        let mut bitcode_column = Vec::new();
+        bitcode.serialize(&mut bitcode_column);
+
+        let (tree, read) = DecodingTree::<char>::deserialize(&tree_column).unwrap();
+        assert_eq!(read, tree_column.len());
+        let bitcode = BitVec::deserialize(&bitcode_column).unwrap();
+
+        let decoded: String = decode_raw(&tree, &bitcode.as_bit_view()).unwrap().iter().collect();
+        assert_eq!(decoded, text);
+
+        assert!(encode_raw("".chars()).is_none());
+    }
+
+
+    #[test]
+    fn check_payload_encoding() {
+
+        let (tree, _) = encode_raw("the quick brown fox jumps over the lazy dog".chars()).unwrap();
+
+        // The same tree encodes any message made of its symbols
+        for message in ["", "the lazy fox", "dog"] {
+            let bitcode = encode_payload(&tree, message.chars()).unwrap();
+            assert_eq!(bitcode, tree.encoder().encode(message.chars()).unwrap());
+            assert_eq!(decode_payload(&tree, &bitcode.as_bit_view()).unwrap().into_iter().collect::<String>(), message);
+        }
+
+        assert_eq!(encode_payload(&tree, "hello!".chars()), Err(UnknownValue { position: 5 }));
+    }
+
+
+    #[test]
+    #[cfg_attr(miri, ignore = "reads the test data files")]
     fn check_compression_decompression() {
 
         for text in get_test_files() {
@@ -651,4 +1670,38 @@ mod tests {
         }
     }
 
+
+    #[test]
+    fn check_partial_decompression() {
+
+        let text = "the quick brown fox jumps over the lazy dog, ".repeat(20);
+
+        let compressed = compress(text.bytes());
+        assert_eq!(decompress_partial::<u8>(&compressed).unwrap(), decompress::<u8>(&compressed).unwrap());
+
+        // A truncated file yields a prefix of the original data
+        let truncated = &compressed[..compressed.len() / 2];
+        assert!(decompress::<u8>(truncated).is_err());
+        let failure = decompress_partial::<u8>(truncated).unwrap_err();
+        assert!(failure.decoded_so_far.len() > text.len() / 4);
+        assert!(text.as_bytes().starts_with(&failure.decoded_so_far));
+        assert!(matches!(failure.error, DecompressionError::BitCodeDecodingError { .. } | DecompressionError::SymbolCountMismatch { .. }));
+        assert_eq!(failure.bit_offset.is_some(), matches!(failure.error, DecompressionError::BitCodeDecodingError { .. }));
+
+        // The blocks of a stream before a truncated one are kept
+        let mut encoder = stream::Encoder::new(Vec::new());
+        encoder.write_symbols(text.bytes()).unwrap();
+        encoder.flush_block().unwrap();
+        encoder.write_symbols(text.bytes()).unwrap();
+        let stream = encoder.finish().unwrap();
+        let failure = decompress_partial::<u8>(&stream[..stream.len() - 10]).unwrap_err();
+        assert_eq!(failure.decoded_so_far, text.as_bytes());
+        assert_eq!(failure.bit_offset, None);
+
+        // Errors before any symbol
+        let failure = decompress_partial::<u8>(&[]).unwrap_err();
+        assert!(failure.decoded_so_far.is_empty());
+        assert!(matches!(failure.error, DecompressionError::InvalidHeader(_)));
+    }
+
 }
@@ -0,0 +1,14 @@
+//! Commonly used items, meant to be glob-imported with `use frequency_tree_compression::prelude::*`.
+
+pub use crate::{
+    compress, try_compress, compress_with_options, try_compress_with_options, compress_fallible, decompress, decompress_partial,
+    compress_bytes, decompress_bytes, decompress_to_string, decompress_to_string_lossy, decompress_into_writer, transcode,
+    compress_chunked, decompress_chunked,
+    compress_auto_grouped, decompress_auto_grouped,
+    encode_raw, decode_raw, encode_payload, decode_payload,
+    decompress_any, DecodedPayload, SymbolType,
+    CompressError, CompressionOptions, DecompressionError, Dictionary, DictionaryRegistry, Symbol, SymbolSource, TrainingOptions,
+};
+pub use crate::bits::BitOrder;
+pub use crate::codecs::{CanonicalTreeCodec, CompactTreeCodec, EntropyCodec, TreeCodec};
+pub use crate::seek::{compress_seekable, DecompressCursor};
@@ -0,0 +1,354 @@
+//! Seekable compression: a table of checkpoints lets a cursor start decoding at any symbol
+//! without decoding the data in front of it.
+//!
+//! Every code of a frequency tree starts at the root, so the state of the decoder at a symbol boundary is just a bit offset.
+//! The compressor records the bit offset of every `interval`-th symbol, and the cursor decodes at most `interval - 1` symbols
+//! after the nearest checkpoint. Transforms whose state depends on the previous symbols, like LZ77 and move-to-front,
+//! can't be combined with checkpoints.
+//!
+//! Payload layout, after a container header with the checkpoints flag set:
+//! - decoding tree
+//! - checkpoint interval (varint)
+//! - number of checkpoints (varint)
+//! - bit offset of each checkpoint, minus the bit offset of the previous one (varints)
+//! - symbol count (varint) and bitcode, like a plain payload
+//!
+//! The first symbol implicitly has a checkpoint at bit 0, so the table starts at symbol `interval`.
+
+use std::num::NonZeroUsize;
+
+use crate::bits::{BitOrder, BitView, BitWriter};
+use crate::codecs::{EntropyCodec, TreeCodec};
+use crate::container::{decode_to_end, read_symbol_count, read_tree, read_varint, write_counted_bitcode, write_varint, Header};
+use crate::tree::{value_frequencies, DecodingError, DecodingTree, EncodingTree};
+use crate::{DecompressionError, Symbol};
+
+
+/// Compress the symbols of `input` with a checkpoint every `interval` symbols, so that `DecompressCursor` can seek in the result.
+/// Smaller intervals make seeking faster and the output larger. Decompress the whole data with `decompress`.
+pub fn compress_seekable<U>(input: impl Iterator<Item = U> + Clone, interval: NonZeroUsize) -> Box<[u8]>
+where
+    U: Symbol
+{
+
+    let tree = EncodingTree::from_frequencies(&value_frequencies(input.clone()))
+        .into_decoder()
+        .unwrap();
+
+    let table = tree.code_table();
+
+    let mut bitcode = BitWriter::new();
+    let mut checkpoints = Vec::new();
+    let mut count = 0;
+
+    for (i, symbol) in input.enumerate() {
+
+        if i != 0 && i % interval == 0 {
+            checkpoints.push(bitcode.len_bits());
+        }
+
+        let code = &table[&symbol];
+        bitcode.write_bits(code.as_word(), code.len() as u32);
+        count += 1;
+    }
+
+    let bitcode = bitcode.finish();
+
+    let mut res = Vec::new();
+
+    Header { checkpoints: true, symbol_type: U::TYPE, ..Header::new() }.serialize(&mut res);
+
+    tree.serialize(&mut res);
+
+    write_varint(&mut res, interval.get());
+    write_varint(&mut res, checkpoints.len());
+
+    let mut previous = 0;
+    for checkpoint in checkpoints {
+        write_varint(&mut res, checkpoint - previous);
+        previous = checkpoint;
+    }
+
+    write_counted_bitcode(&mut res, count, &bitcode, BitOrder::MsbFirst);
+
+    res.into_boxed_slice()
+}
+
+
+/// Read the checkpoint table at byte `offset` of `input`.
+/// Return the interval, the bit offsets of the checkpoints including the implicit first one, and the number of bytes read.
+pub(crate) fn read_checkpoints(input: &[u8], offset: usize) -> Result<(usize, Vec<usize>, usize), DecompressionError> {
+
+    let (interval, interval_size) = read_varint(input, offset)?;
+    let (count, count_size) = read_varint(input, offset + interval_size)?;
+
+    if interval == 0 {
+        return Err(DecompressionError::InvalidCheckpoints { offset });
+    }
+
+    let mut read = offset + interval_size + count_size;
+
+    // Every checkpoint takes at least a byte, so the remaining input bounds the allocation
+    let mut checkpoints = Vec::with_capacity(1 + count.min(input.len() - read));
+    checkpoints.push(0);
+
+    let mut bit_offset: usize = 0;
+    for _ in 0..count {
+
+        let (delta, delta_size) = read_varint(input, read)?;
+        read += delta_size;
+
+        bit_offset = bit_offset.checked_add(delta)
+            .ok_or(DecompressionError::InvalidCheckpoints { offset })?;
+        checkpoints.push(bit_offset);
+    }
+
+    Ok((interval, checkpoints, read - offset))
+}
+
+
+/// Read the payload written by `compress_seekable` at byte `offset` of `input`, ignoring the checkpoints
+pub(crate) fn read_seekable_payload<U, C>(input: &[u8], offset: usize, codec: &C) -> Result<Box<[U]>, DecompressionError>
+where
+    U: Symbol,
+    C: EntropyCodec
+{
+
+    let (model, model_size) = codec.deserialize_model(input, offset)?;
+
+    let (_, _, table_size) = read_checkpoints(input, offset + model_size)?;
+
+    decode_to_end(codec, &model, input, offset + model_size + table_size, BitOrder::MsbFirst)
+}
+
+
+/// Cursor over the symbols of data compressed by `compress_seekable`.
+/// It iterates over the symbols from its position, which `seek_to_symbol` moves without decoding from the start.
+#[derive(Debug, Clone)]
+pub struct DecompressCursor<'a, U: Clone> {
+
+    tree: DecodingTree<U>,
+
+    bitcode: BitView<'a>,
+
+    /// Byte offset of the bitcode in the input, reported in decoding errors
+    bitcode_offset: usize,
+
+    /// Number of symbols between two checkpoints
+    interval: usize,
+
+    /// Bit offset of every `interval`-th symbol, starting from the first one
+    checkpoints: Box<[usize]>,
+
+    /// Total number of symbols
+    len: usize,
+
+    /// Index of the next symbol
+    position: usize,
+
+    /// Bit offset of the code of the next symbol
+    bit_offset: usize,
+
+}
+
+impl<'a, U> DecompressCursor<'a, U>
+where
+    U: Symbol
+{
+
+    /// Open a cursor at the first symbol of `input`, which must have been produced by `compress_seekable`
+    pub fn new(input: &'a [u8]) -> Result<Self, DecompressionError> {
+
+        let (header, header_size) = Header::deserialize(input).map_err(DecompressionError::InvalidHeader)?;
+
+        if !header.checkpoints {
+            return Err(DecompressionError::NotSeekable);
+        }
+
+        let (tree, tree_size) = read_tree(input, header_size, &header)?;
+
+        let table_offset = header_size + tree_size;
+        let (interval, checkpoints, table_size) = read_checkpoints(input, table_offset)?;
+
+        let (len, len_size) = read_symbol_count(input, table_offset + table_size, TreeCodec.min_code_bits(&tree))?;
+
+        let bitcode_offset = table_offset + table_size + len_size;
+        let bitcode = BitView::deserialize(&input[bitcode_offset..])
+            .map_err(|_| DecompressionError::InvalidBitCode { offset: bitcode_offset, remaining: input.len() - bitcode_offset })?;
+
+        // There is a checkpoint at each multiple of the interval smaller than the symbol count, and they must lie in the bitcode
+        let expected = len.saturating_sub(1) / interval + 1;
+        if checkpoints.len() != expected || checkpoints.last().is_some_and(|last| *last > bitcode.len_bits()) {
+            return Err(DecompressionError::InvalidCheckpoints { offset: table_offset });
+        }
+
+        Ok(Self {
+            tree,
+            bitcode,
+            bitcode_offset,
+            interval,
+            checkpoints: checkpoints.into_boxed_slice(),
+            len,
+            position: 0,
+            bit_offset: 0
+        })
+    }
+
+
+    /// Total number of symbols of the data
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+
+    /// Index of the symbol that the cursor returns next
+    pub const fn position(&self) -> usize {
+        self.position
+    }
+
+
+    /// Move the cursor to symbol `position`, decoding only the symbols after the closest checkpoint in front of it.
+    /// Seeking to `len()` moves the cursor to the end of the data.
+    pub fn seek_to_symbol(&mut self, position: usize) -> Result<(), DecompressionError> {
+
+        if position > self.len {
+            return Err(DecompressionError::SymbolOutOfRange { position, len: self.len });
+        }
+
+        let checkpoint = (position / self.interval).min(self.checkpoints.len() - 1);
+
+        self.position = checkpoint * self.interval;
+        self.bit_offset = self.checkpoints[checkpoint];
+
+        while self.position < position {
+
+            let (_, next) = self.tree.decode_one(&self.bitcode, self.bit_offset)
+                .map_err(|source| self.decoding_error(source))?;
+
+            self.bit_offset = next;
+            self.position += 1;
+        }
+
+        Ok(())
+    }
+
+
+    const fn decoding_error(&self, source: DecodingError) -> DecompressionError {
+        DecompressionError::BitCodeDecodingError { offset: self.bitcode_offset, source }
+    }
+
+}
+
+impl<U> Iterator for DecompressCursor<'_, U>
+where
+    U: Symbol
+{
+    type Item = Result<U, DecompressionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+
+        if self.position >= self.len {
+            return None;
+        }
+
+        match self.tree.decode_one(&self.bitcode, self.bit_offset) {
+
+            Ok((value, next)) => {
+                self.bit_offset = next;
+                self.position += 1;
+                Some(Ok(value.clone()))
+            },
+
+            Err(source) => {
+                // The rest of the bitcode can't be trusted
+                self.position = self.len;
+                Some(Err(self.decoding_error(source)))
+            },
+        }
+    }
+
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.len - self.position))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use crate::{compress, decompress};
+
+
+    const TEXT: &str = "the quick brown fox jumps over the lazy dog, the quick brown fox";
+
+
+    #[test]
+    fn check_seeking() {
+
+        let chars: Vec<char> = TEXT.chars().collect();
+
+        for interval in [1, 3, 16, chars.len(), 1000] {
+
+            let compressed = compress_seekable(TEXT.chars(), NonZeroUsize::new(interval).unwrap());
+
+            assert_eq!(*decompress::<char>(&compressed).unwrap(), *chars);
+
+            let mut cursor = DecompressCursor::<char>::new(&compressed).unwrap();
+            assert_eq!(cursor.len(), chars.len());
+
+            for position in [0, 1, 7, 16, 17, 40, chars.len() - 1, chars.len()] {
+
+                cursor.seek_to_symbol(position).unwrap();
+                assert_eq!(cursor.position(), position);
+
+                let rest: Vec<char> = cursor.by_ref().map(Result::unwrap).collect();
+                assert_eq!(rest, chars[position..]);
+            }
+
+            assert_eq!(cursor.seek_to_symbol(chars.len() + 1), Err(DecompressionError::SymbolOutOfRange { position: chars.len() + 1, len: chars.len() }));
+        }
+
+        // A single-leaf tree encodes every symbol with zero bits
+        let compressed = compress_seekable("aaaaa".chars(), NonZeroUsize::new(2).unwrap());
+        let mut cursor = DecompressCursor::<char>::new(&compressed).unwrap();
+        cursor.seek_to_symbol(3).unwrap();
+        assert_eq!(cursor.map(Result::unwrap).collect::<String>(), "aa");
+    }
+
+
+    #[test]
+    fn check_invalid_checkpoints() {
+
+        assert_eq!(DecompressCursor::<char>::new(&compress(TEXT.chars())).unwrap_err(), DecompressionError::NotSeekable);
+
+        let compressed = compress_seekable("abcab".bytes(), NonZeroUsize::new(2).unwrap());
+
+        // Header with the symbol type, then the tree of 3 leaves: 5 specifiers and 3 values
+        let table_offset = 4 + 8;
+        assert_eq!(compressed[table_offset..table_offset + 2], [2, 2]);
+
+        let mut corrupted = compressed.to_vec();
+        corrupted[table_offset] = 0;
+        assert_eq!(DecompressCursor::<u8>::new(&corrupted).unwrap_err(), DecompressionError::InvalidCheckpoints { offset: table_offset });
+
+        // One checkpoint fewer than the symbol count requires
+        let mut corrupted = compressed.to_vec();
+        corrupted[table_offset + 1] = 1;
+        corrupted.remove(table_offset + 2);
+        assert_eq!(DecompressCursor::<u8>::new(&corrupted).unwrap_err(), DecompressionError::InvalidCheckpoints { offset: table_offset });
+
+        // A checkpoint past the end of the bitcode
+        let mut corrupted = compressed.to_vec();
+        corrupted[table_offset + 3] = 100;
+        assert_eq!(DecompressCursor::<u8>::new(&corrupted).unwrap_err(), DecompressionError::InvalidCheckpoints { offset: table_offset });
+    }
+
+}
@@ -0,0 +1,227 @@
+//! Concurrent encoding of many small messages with the same dictionary, for message queues with several producer threads.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::dictionary::encode_with_dictionary;
+use crate::tree::Code;
+use crate::{Dictionary, Symbol};
+
+
+/// Encoder of messages with a dictionary, shared by any number of threads.
+///
+/// The code table of the dictionary is built once and shared behind an `Arc`, so cloning the encoder or creating a `Producer`
+/// doesn't clone the tree. The table is never modified, so the threads encode without waiting for each other:
+/// only the message counters are synchronized, with atomics.
+///
+/// The messages are encoded like `Dictionary::compress` does, and decompressed with `Dictionary::decompress`.
+#[derive(Debug, Clone)]
+pub struct SharedEncoder<U: Symbol> {
+
+    shared: Arc<Shared<U>>,
+
+}
+
+#[derive(Debug)]
+struct Shared<U: Symbol> {
+
+    dictionary: Arc<Dictionary<U>>,
+    table: HashMap<U, Code>,
+
+    /// Number of messages encoded so far
+    messages: AtomicU64,
+    /// Number of messages that embed their own tree because the dictionary can't encode them
+    fallbacks: AtomicU64,
+
+}
+
+impl<U> SharedEncoder<U>
+where
+    U: Symbol
+{
+
+    pub fn new(dictionary: impl Into<Arc<Dictionary<U>>>) -> Self {
+
+        let dictionary = dictionary.into();
+
+        Self {
+            shared: Arc::new(Shared {
+                table: dictionary.tree().code_table(),
+                dictionary,
+                messages: AtomicU64::new(0),
+                fallbacks: AtomicU64::new(0),
+            })
+        }
+    }
+
+
+    /// The dictionary the messages are encoded with
+    pub fn dictionary(&self) -> &Arc<Dictionary<U>> {
+        &self.shared.dictionary
+    }
+
+
+    /// Create a producer with its own output buffer, meant to be moved to a producer thread
+    pub fn producer(&self) -> Producer<U> {
+        Producer {
+            shared: Arc::clone(&self.shared),
+            buffer: Vec::new()
+        }
+    }
+
+
+    /// Encode a single message into a new allocation.
+    /// Producers encoding many messages should reuse the buffer of a `Producer` instead.
+    pub fn encode(&self, message: impl Iterator<Item = U> + Clone) -> Box<[u8]> {
+
+        let mut buf = Vec::new();
+        self.shared.encode_into(message, &mut buf);
+
+        buf.into_boxed_slice()
+    }
+
+
+    /// Number of messages encoded so far, by all the producers
+    pub fn messages(&self) -> u64 {
+        self.shared.messages.load(Ordering::Relaxed)
+    }
+
+
+    /// Number of messages encoded so far that contain symbols missing from the dictionary, and embed their own tree.
+    /// A growing share of fallbacks means that the dictionary should be trained again on recent messages.
+    pub fn fallbacks(&self) -> u64 {
+        self.shared.fallbacks.load(Ordering::Relaxed)
+    }
+
+}
+
+impl<U> Shared<U>
+where
+    U: Symbol
+{
+
+    /// Replace the content of `buf` with the encoded `message`
+    fn encode_into(&self, message: impl Iterator<Item = U> + Clone, buf: &mut Vec<u8>) {
+
+        buf.clear();
+
+        self.messages.fetch_add(1, Ordering::Relaxed);
+
+        if !encode_with_dictionary(self.dictionary.id(), &self.table, message.clone(), buf) {
+            self.fallbacks.fetch_add(1, Ordering::Relaxed);
+            buf.extend_from_slice(&crate::compress(message));
+        }
+    }
+
+}
+
+
+/// Handle of a `SharedEncoder` owned by a single producer thread.
+/// The encoded messages are written to the producer's own buffer, which is reused from one message to the next.
+#[derive(Debug)]
+pub struct Producer<U: Symbol> {
+
+    shared: Arc<Shared<U>>,
+    buffer: Vec<u8>,
+
+}
+
+impl<U> Producer<U>
+where
+    U: Symbol
+{
+
+    /// Encode a message and return the encoded bytes, valid until the next message is encoded
+    pub fn encode(&mut self, message: impl Iterator<Item = U> + Clone) -> &[u8] {
+
+        self.shared.encode_into(message, &mut self.buffer);
+
+        &self.buffer
+    }
+
+}
+
+impl<U> Clone for Producer<U>
+where
+    U: Symbol
+{
+
+    /// Create another producer of the same encoder, with its own buffer
+    fn clone(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
+            buffer: Vec::new()
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::thread;
+
+    use super::*;
+    use crate::{decompress, TrainingOptions};
+
+
+    const SAMPLES: [&str; 3] = [
+        "user 42 logged in",
+        "user 17 logged out",
+        "order 1337 shipped to user 42",
+    ];
+
+
+    #[test]
+    fn check_concurrent_producers() {
+
+        let dictionary = Dictionary::train_with_options(SAMPLES.map(str::bytes), &TrainingOptions::all_bytes()).unwrap();
+        let encoder = SharedEncoder::new(dictionary.clone());
+
+        let encoded: Vec<Vec<(String, Vec<u8>)>> = thread::scope(|scope| {
+
+            let handles: Vec<_> = (0..4).map(|thread| {
+
+                let mut producer = encoder.producer();
+
+                scope.spawn(move || {
+                    (0..100).map(|i| {
+                        let message = format!("user {} logged {}", thread * 100 + i, if i % 2 == 0 { "in" } else { "out" });
+                        let encoded = producer.encode(message.bytes()).to_vec();
+                        (message, encoded)
+                    }).collect()
+                })
+            }).collect();
+
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+
+        for (message, encoded) in encoded.iter().flatten() {
+            assert_eq!(*encoded, *dictionary.compress(message.bytes()));
+            assert_eq!(*dictionary.decompress(encoded).unwrap(), *message.as_bytes());
+        }
+
+        assert_eq!(encoder.messages(), 400);
+        assert_eq!(encoder.fallbacks(), 0);
+    }
+
+
+    #[test]
+    fn check_shared_fallback() {
+
+        let encoder = SharedEncoder::new(Dictionary::train(SAMPLES.map(str::bytes)).unwrap());
+        let mut producer = encoder.clone().producer();
+
+        // 'z' is missing from the samples
+        let encoded = producer.encode("zzz".bytes()).to_vec();
+        assert_eq!(*decompress::<u8>(&encoded).unwrap(), *b"zzz");
+
+        let encoded = encoder.encode("user 1".bytes());
+        assert_eq!(*encoder.dictionary().decompress(&encoded).unwrap(), *b"user 1");
+
+        assert_eq!(encoder.messages(), 2);
+        assert_eq!(encoder.fallbacks(), 1);
+    }
+
+}
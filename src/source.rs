@@ -0,0 +1,159 @@
+//! Sources of symbols that the compressor can read several times.
+//!
+//! The compressor reads its input once to count the symbol frequencies and again to encode them,
+//! so it needs a way to restart from the first symbol. Cloneable iterators are sources already,
+//! and `from_fn` turns any function that re-creates an iterator, like one reopening a file or re-running a query, into a source.
+
+use crate::Symbol;
+
+
+/// A sequence of symbols that can be read from the start any number of times
+pub trait SymbolSource {
+
+    type Symbol: Symbol;
+
+    /// Iterator over a single pass of the symbols
+    type Pass<'a>: Iterator<Item = Self::Symbol>
+    where
+        Self: 'a;
+
+
+    /// Start a new pass over all the symbols, from the first one.
+    /// Every pass must yield the same symbols.
+    fn pass(&self) -> Self::Pass<'_>;
+
+}
+
+impl<I> SymbolSource for I
+where
+    I: Iterator + Clone,
+    I::Item: Symbol
+{
+    type Symbol = I::Item;
+
+    type Pass<'a> = I
+    where
+        I: 'a;
+
+
+    fn pass(&self) -> Self::Pass<'_> {
+        self.clone()
+    }
+}
+
+
+/// Source that calls a function to start every pass. Created by `from_fn`.
+#[derive(Debug, Clone, Copy)]
+pub struct FromFn<F> (F);
+
+impl<F, I> SymbolSource for FromFn<F>
+where
+    F: Fn() -> I,
+    I: Iterator,
+    I::Item: Symbol
+{
+    type Symbol = I::Item;
+
+    type Pass<'a> = I
+    where
+        F: 'a;
+
+
+    fn pass(&self) -> Self::Pass<'_> {
+        (self.0)()
+    }
+}
+
+
+/// Create a source that calls `f` to start every pass, for iterators that can be re-created but not cloned
+pub const fn from_fn<F, I>(f: F) -> FromFn<F>
+where
+    F: Fn() -> I,
+    I: Iterator
+{
+    FromFn(f)
+}
+
+
+/// A pass over a source, whose clones start new passes.
+/// The compressor only clones passes before reading from them, so a clone yields the same symbols as the original.
+pub(crate) struct Passes<'a, S>
+where
+    S: SymbolSource + 'a
+{
+
+    source: &'a S,
+
+    pass: S::Pass<'a>,
+
+}
+
+impl<'a, S> Passes<'a, S>
+where
+    S: SymbolSource
+{
+
+    pub fn new(source: &'a S) -> Self {
+        Self {
+            source,
+            pass: source.pass()
+        }
+    }
+
+}
+
+impl<S> Clone for Passes<'_, S>
+where
+    S: SymbolSource
+{
+    fn clone(&self) -> Self {
+        Self::new(self.source)
+    }
+}
+
+impl<S> Iterator for Passes<'_, S>
+where
+    S: SymbolSource
+{
+    type Item = S::Symbol;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.pass.next()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::cell::Cell;
+    use std::io::{Cursor, Read};
+
+    use super::*;
+
+    use crate::{compress, decompress};
+
+
+    #[test]
+    fn check_re_creatable_source() {
+
+        let data = b"the quick brown fox jumps over the lazy dog";
+
+        let passes = Cell::new(0);
+
+        // `io::Bytes` isn't `Clone`, so every pass reopens the reader
+        let source = from_fn(|| {
+            passes.set(passes.get() + 1);
+            Cursor::new(data).bytes().map(Result::unwrap)
+        });
+
+        let compressed = compress(source);
+
+        assert!(passes.get() > 1);
+        assert_eq!(*decompress::<u8>(&compressed).unwrap(), *data);
+
+        // Cloneable iterators are sources
+        assert_eq!(*decompress::<u8>(&compress(data.iter().copied())).unwrap(), *data);
+    }
+
+}
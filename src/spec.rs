@@ -0,0 +1,361 @@
+//! Specification of the container format, for implementations in other languages.
+//!
+//! The constants and the field descriptions below are the ones the library reads and writes, and `test_vectors`
+//! generates compressed data whose bytes are fixed by the format, so an independent decoder can check its output
+//! against `TestVector::symbols`, and an independent encoder can check its output against `TestVector::compressed`.
+//!
+//! # Layout
+//!
+//! Compressed data starts with the header described by `HEADER_FIELDS`. Multi-byte fields are little-endian.
+//! Decoders reject the unknown bits of the flags bytes, which change how the data is read, and ignore the unknown bits
+//! of the optional flags byte, which don't.
+//! The payload that follows depends on the flags of the header:
+//! - plain: tree, symbol count (varint), bitcode to the end of the data
+//! - `MTF`: initial alphabet (varint length, then the symbols), then the payload of the `u32` move-to-front indices
+//! - `REMAP`: compact alphabet, then the payload of the identifiers of the symbols in the alphabet, as `u8`, `u16`, or `u32`
+//!   for alphabets of at most 256, 65536, or more symbols
+//! - `CHECKPOINTS`: tree, checkpoint table, symbol count (varint), bitcode to the end of the data
+//! - `DICTIONARY`: symbol count (varint), bitcode to the end of the data, encoded with the tree of the dictionary
+//! - `BLOCKS`: data blocks, each a kind byte, a symbol count (varint), a payload length (varint), and the payload
+//!   (tree, unless `REUSE_TREE` is set, then the length-prefixed bitcode), terminated by an end block.
+//!   With `flags::settings::BLOCK_CHECKSUMS`, the payload of each data block is followed by the little-endian CRC-32 of the
+//!   serialized symbols of the block.
+//!   With `flags::settings::CHECKSUM`, the end block is preceded by a checksum block: its kind byte and the little-endian
+//!   CRC-32 (IEEE 802.3) of the serialized symbols of the data blocks
+//!
+//! With `flags::settings::NORMALIZED_NEWLINES`, the decoded text gets a `\r` in front of every `\n`, in any layout.
+//!
+//! # Trees
+//!
+//! Trees are serialized in pre-order. A leaf is the byte `LEAF` followed by the `Symbol::WIDTH` bytes of its value,
+//! and a parent is the byte `PARENT` followed by its left subtree and then its right subtree.
+//! The bytes of `RESERVED_SPECIFIERS` are reserved for later node kinds, and the other bytes are invalid.
+//! The values of the leaves are distinct: a tree with two leaves of the same value is invalid.
+//! With `COMPACT_TREE`, the specifiers are single bits instead, `1` for a parent, packed from the most significant bit
+//! and padded to a whole byte, followed by the values of the leaves in pre-order.
+//! With `flags::extended::CANONICAL_TREE`, the tree is a leaf count (varint) followed by one entry per leaf, the
+//! `Symbol::WIDTH` bytes of its value and then the length of its code as a byte, ordered by the value bytes read from the
+//! last one to the first one. Codes are assigned in order of increasing length and then in entry order, each one the
+//! previous code plus one, shifted left when the length grows, starting from all zeros.
+//!
+//! The code of a symbol is the path from the root to its leaf: `0` for a left child and `1` for a right child.
+//! A tree with a single leaf encodes its symbol with zero bits.
+//!
+//! # Bitcode
+//!
+//! A bitcode is a padding byte, the number of unused bits of the last byte (0 to 7, and 0 if there are no data bytes),
+//! followed by the codes of the symbols concatenated in order. The bits are packed from the most significant bit of each byte,
+//! or from the least significant bit if `flags::extended::LSB_FIRST` is set. The unused bits are the last ones in packing order.
+//!
+//! # Varints
+//!
+//! Counts and lengths are LEB128 varints: 7 bits per byte, least significant group first,
+//! with the most significant bit of each byte set when more bytes follow.
+
+use std::ops::RangeInclusive;
+
+use crate::bits::BitOrder;
+use crate::stream::{BlockKind, Encoder, REUSE_TREE as REUSE_TREE_BIT};
+use crate::tree::SerialSpecifier;
+use crate::{compress_bytes, compress_with_options, CompressionOptions};
+
+pub use crate::container::flags;
+pub use crate::symbol::SymbolType;
+pub use crate::FORMAT_VERSION;
+
+
+/// Specifier byte of a serialized leaf
+pub const LEAF: u8 = SerialSpecifier::Leaf as u8;
+
+/// Specifier byte of a serialized parent
+pub const PARENT: u8 = SerialSpecifier::Parent as u8;
+
+/// Specifier bytes reserved for node kinds of later format versions, such as escape leaves and references to the trees of dictionaries.
+/// Decoders reject them with `NodeDeserializationError::ReservedNodeTypeSpecifier` rather than as corrupted data.
+pub const RESERVED_SPECIFIERS: RangeInclusive<u8> = SerialSpecifier::RESERVED;
+
+/// Kind byte of a data block
+pub const DATA_BLOCK: u8 = BlockKind::Data as u8;
+
+/// Kind byte of the block that ends a stream
+pub const END_BLOCK: u8 = BlockKind::End as u8;
+
+/// Kind byte of the block holding the checksum of a stream
+pub const CHECKSUM_BLOCK: u8 = BlockKind::Checksum as u8;
+
+/// Bit of the kind byte of a data block set when the block reuses the tree of the previous one
+pub const REUSE_TREE: u8 = REUSE_TREE_BIT;
+
+
+/// Flags byte holding a flag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlagsByte {
+
+    /// The flags byte, at offset 1, whose flags are in `flags`
+    Flags,
+    /// The extended flags byte, at offset 2, whose flags are in `flags::extended`
+    Extended,
+
+}
+
+
+/// Condition for a header field to be present
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Presence {
+
+    Always,
+    /// Present when the `mask` bit of the flags `byte` is set
+    Flag { byte: FlagsByte, mask: u8 },
+
+}
+
+
+/// Size of a header field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldSize {
+
+    /// Fixed number of bytes
+    Fixed (usize),
+    /// Little-endian length of `length_size` bytes, followed by that many bytes
+    LengthPrefixed { length_size: usize },
+
+}
+
+
+/// Description of a header field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+
+    pub name: &'static str,
+    pub size: FieldSize,
+    pub presence: Presence,
+    pub description: &'static str,
+
+}
+
+
+/// The fields of the header, in order
+pub const HEADER_FIELDS: &[Field] = &[
+    Field {
+        name: "version",
+        size: FieldSize::Fixed(1),
+        presence: Presence::Always,
+        description: "format version, equal to `FORMAT_VERSION`",
+    },
+    Field {
+        name: "flags",
+        size: FieldSize::Fixed(1),
+        presence: Presence::Always,
+        description: "bits of `flags`, unknown bits must be understood and are rejected",
+    },
+    Field {
+        name: "extended flags",
+        size: FieldSize::Fixed(1),
+        presence: Presence::Always,
+        description: "bits of `flags::extended`, unknown bits must be understood and are rejected",
+    },
+    Field {
+        name: "optional flags",
+        size: FieldSize::Fixed(1),
+        presence: Presence::Flag { byte: FlagsByte::Extended, mask: flags::extended::OPTIONAL },
+        description: "bits of `flags::optional` and tag of the `SymbolType`, unknown bits and tags are hints that are ignored",
+    },
+    Field {
+        name: "chunk size",
+        size: FieldSize::Fixed(1),
+        presence: Presence::Flag { byte: FlagsByte::Flags, mask: flags::CHUNKED },
+        description: "number of symbols grouped in each chunk",
+    },
+    Field {
+        name: "chunk remainder",
+        size: FieldSize::Fixed(1),
+        presence: Presence::Flag { byte: FlagsByte::Flags, mask: flags::CHUNKED },
+        description: "number of meaningful symbols in the last chunk, 0 if it's complete",
+    },
+    Field {
+        name: "dictionary",
+        size: FieldSize::Fixed(4),
+        presence: Presence::Flag { byte: FlagsByte::Flags, mask: flags::DICTIONARY },
+        description: "identifier of the dictionary whose tree encodes the payload",
+    },
+    Field {
+        name: "modified",
+        size: FieldSize::Fixed(8),
+        presence: Presence::Flag { byte: FlagsByte::Extended, mask: flags::extended::MODIFIED },
+        description: "modification time of the original file in seconds since the Unix epoch",
+    },
+    Field {
+        name: "mode",
+        size: FieldSize::Fixed(4),
+        presence: Presence::Flag { byte: FlagsByte::Extended, mask: flags::extended::MODE },
+        description: "Unix mode of the original file",
+    },
+    Field {
+        name: "name",
+        size: FieldSize::LengthPrefixed { length_size: 2 },
+        presence: Presence::Flag { byte: FlagsByte::Extended, mask: flags::extended::NAME },
+        description: "UTF-8 name of the original file, without its directory",
+    },
+    Field {
+        name: "settings",
+        size: FieldSize::LengthPrefixed { length_size: 1 },
+        presence: Presence::Flag { byte: FlagsByte::Extended, mask: flags::extended::SETTINGS },
+        description: "byte of `flags::settings` followed by the varints they announce, unknown bits and trailing bytes are ignored",
+    },
+];
+
+
+/// Compressed data and the symbols it decompresses to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestVector {
+
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Width in bytes of the serialized symbols
+    pub symbol_width: usize,
+    /// The serialized symbols, in order
+    pub symbols: Vec<u8>,
+    pub compressed: Box<[u8]>,
+
+}
+
+
+/// Generate the test vectors of the current format version.
+///
+/// The symbol frequencies of every vector are distinct, so that the shape of the trees, and thus the compressed bytes,
+/// don't depend on how ties are broken.
+pub fn test_vectors() -> Vec<TestVector> {
+
+    // 1, 2, 4, and 8 occurrences
+    const BYTES: &[u8] = b"abbccccdddddddd";
+    const TEXT: &str = "h\u{e9}\u{e9}lll\u{1f600}\u{1f600}\u{1f600}\u{1f600}";
+
+    let mut stream = Encoder::new(Vec::new());
+    stream.write_symbols(BYTES.iter().copied()).unwrap();
+    stream.flush_block().unwrap();
+    stream.write_symbols(BYTES.iter().copied()).unwrap();
+
+    vec![
+        TestVector {
+            name: "empty",
+            description: "no symbols: a stream holding only its end block",
+            symbol_width: 1,
+            symbols: Vec::new(),
+            compressed: compress_bytes(&[]),
+        },
+        TestVector {
+            name: "single symbol",
+            description: "a single-leaf tree, whose symbol is encoded with zero bits",
+            symbol_width: 1,
+            symbols: b"zzzz".to_vec(),
+            compressed: compress_bytes(b"zzzz"),
+        },
+        TestVector {
+            name: "bytes",
+            description: "plain payload of bytes",
+            symbol_width: 1,
+            symbols: BYTES.to_vec(),
+            compressed: compress_bytes(BYTES),
+        },
+        TestVector {
+            name: "bytes lsb first",
+            description: "plain payload of bytes with the bitcode packed from the least significant bit",
+            symbol_width: 1,
+            symbols: BYTES.to_vec(),
+            compressed: compress_with_options(BYTES.iter().copied(), &CompressionOptions { bit_order: BitOrder::LsbFirst, ..CompressionOptions::new() }),
+        },
+        TestVector {
+            name: "compact tree",
+            description: "plain payload of bytes with the tree in the compact layout",
+            symbol_width: 1,
+            symbols: BYTES.to_vec(),
+            compressed: compress_with_options(BYTES.iter().copied(), &CompressionOptions { compact_tree: true, ..CompressionOptions::new() }),
+        },
+        TestVector {
+            name: "canonical tree",
+            description: "plain payload of bytes with the tree in the canonical layout",
+            symbol_width: 1,
+            symbols: BYTES.to_vec(),
+            compressed: compress_with_options(BYTES.iter().copied(), &CompressionOptions { canonical_tree: true, ..CompressionOptions::new() }),
+        },
+        TestVector {
+            name: "chars",
+            description: "Unicode scalar values, serialized as 4 little-endian bytes",
+            symbol_width: 4,
+            symbols: TEXT.chars().flat_map(|c| (c as u32).to_le_bytes()).collect(),
+            compressed: compress_with_options(TEXT.chars(), &CompressionOptions::new()),
+        },
+        TestVector {
+            name: "stream",
+            description: "two data blocks, the second one reusing the tree of the first one",
+            symbol_width: 1,
+            symbols: BYTES.repeat(2),
+            compressed: stream.finish().unwrap().into_boxed_slice(),
+        },
+    ]
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{decompress, Header};
+
+
+    #[test]
+    fn check_test_vectors() {
+
+        for vector in test_vectors() {
+
+            let decompressed: Vec<u8> = match vector.symbol_width {
+                1 => decompress::<u8>(&vector.compressed).unwrap().into_vec(),
+                _ => decompress::<char>(&vector.compressed).unwrap().iter().flat_map(|c| (*c as u32).to_le_bytes()).collect(),
+            };
+
+            assert_eq!(decompressed, vector.symbols, "{}", vector.name);
+
+            // The vectors are reproducible
+            assert_eq!(test_vectors().iter().find(|other| other.name == vector.name), Some(&vector));
+        }
+
+        let bytes = test_vectors().into_iter().find(|vector| vector.name == "bytes").unwrap();
+        assert_eq!(*bytes.compressed, [
+            FORMAT_VERSION, 0, flags::extended::OPTIONAL, SymbolType::U8.tag(),
+            // Tree: a is 00, c is 01, b is 10, d is 11
+            PARENT, PARENT, LEAF, b'a', LEAF, b'c', PARENT, LEAF, b'b', LEAF, b'd',
+            // 15 symbols, 2 bits of padding
+            15, 2,
+            0b0010_1001, 0b0101_0111, 0b1111_1111, 0b1111_1100
+        ]);
+    }
+
+
+    #[test]
+    fn check_header_fields() {
+
+        let header = Header {
+            dictionary: Some(7),
+            symbol_type: Some(SymbolType::U16),
+            metadata: crate::FileMetadata { name: Some("a.txt".to_string()), modified: Some(1), mode: Some(0o644) },
+            settings: crate::EncoderSettings { auto_grouped: true, ..crate::EncoderSettings::new() },
+            ..Header::chunked(2, 1)
+        };
+
+        let mut buf = Vec::new();
+        header.serialize(&mut buf);
+
+        // Every field is present
+        let size: usize = HEADER_FIELDS.iter()
+            .map(|field| match field.size {
+                FieldSize::Fixed (size) => size,
+                FieldSize::LengthPrefixed { length_size: 2 } => 2 + "a.txt".len(),
+                // The settings record is a single byte of flags
+                FieldSize::LengthPrefixed { length_size } => length_size + 1,
+            })
+            .sum();
+
+        assert_eq!(size, buf.len());
+    }
+
+}
@@ -0,0 +1,2150 @@
+//! Block-framed stream format.
+//!
+//! A stream is a container header with the blocks flag set, followed by a sequence of blocks.
+//! The encoder emits a block every time it's flushed, so that a receiver can decode everything sent so far
+//! without waiting for the end of the stream. It can also split blocks on its own after a number of symbols or of output bytes,
+//! so that the trees of long streams follow the drifting statistics of the data, or when it detects that the statistics
+//! of the latest symbols diverge from the ones of the current block.
+//!
+//! Block layout:
+//! - block kind (1 byte), whose most significant bit is set if the block reuses the tree of the previous data block
+//! - for data blocks: symbol count (varint), payload length (varint), payload (decoding tree unless reused, and length-prefixed bitcode),
+//!   and with `EncoderOptions::block_checksums`, the little-endian CRC-32 of the serialized symbols of the block (4 bytes)
+//! - for checksum blocks: little-endian CRC-32 of the serialized symbols of the previous data blocks (4 bytes)
+//!
+//! With `EncoderOptions::checksum`, the header announces a checksum and the encoder writes a checksum block before the end of the stream.
+//! Both sides update the checksum block by block as they encode and decode the symbols, so that streams are still read and written in a single pass.
+//! The checksums of the data blocks tell which blocks are corrupted instead, and a lenient `Decoder` keeps decoding the blocks after them.
+//!
+//! The payload of each data block can be passed through an `OutputTransform` supplied by the caller, such as an encryption,
+//! and reverted by the matching `InputTransform` before decoding. The rest of the block framing is left as is,
+//! so that the receiver can still split the stream into blocks.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+use std::num::NonZeroUsize;
+use std::time::{Duration, Instant};
+
+use crate::bits::{has_dirty_padding, BitOrder, BitVec};
+use crate::checksum::SymbolChecksum;
+use crate::codecs::{EntropyCodec, TreeCodec};
+use crate::container::{read_bitcode_section, read_varint, varint_size, write_bitcode_section, write_varint, EncoderSettings, Header, HeaderDeserializationError};
+use crate::tree::{encode_with_table, reserve_single_leaf, value_frequencies, Code, DecodingTree, EncodingTree};
+use crate::{DecompressionError, Symbol};
+
+
+/// Kind of block, written in the first byte of each block
+#[repr(u8)]
+pub(crate) enum BlockKind {
+
+    /// A block containing encoded symbols
+    Data,
+    /// Marks the end of the stream
+    End,
+    /// The checksum of the symbols of the stream
+    Checksum,
+
+}
+
+impl BlockKind {
+
+    /// All the valid serialized block kinds
+    pub(crate) const VALID: &'static [u8] = &[Self::Data as u8, Self::End as u8, Self::Checksum as u8];
+
+}
+
+impl TryFrom<u8> for BlockKind {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Data),
+            1 => Ok(Self::End),
+            2 => Ok(Self::Checksum),
+            _ => Err(value)
+        }
+    }
+}
+
+
+/// Set in the block kind byte of data blocks encoded with the tree of the previous data block
+pub(crate) const REUSE_TREE: u8 = 1 << 7;
+
+/// Size in bytes of a checksum block
+pub(crate) const CHECKSUM_BLOCK_SIZE: usize = 5;
+
+/// Size in bytes of the checksum that follows the payload of a data block
+pub(crate) const BLOCK_CHECKSUM_SIZE: usize = 4;
+
+
+/// Create the container header of a stream
+const fn stream_header() -> Header {
+    let mut header = Header::new();
+    header.blocks = true;
+    header
+}
+
+
+/// Transform applied to the payload of each data block written by an `Encoder`, for example an authenticated encryption.
+///
+/// Only the payload is transformed: the block kind, the symbol count, and the end of the stream are written in clear,
+/// and an encryption should bind them to the payload as associated data if they need to be authenticated.
+pub trait OutputTransform {
+
+    /// Transform the `payload` of the data block number `index`, counting from 0.
+    /// The index is unique within a stream and can serve as a nonce.
+    fn transform(&mut self, index: u64, payload: Vec<u8>) -> io::Result<Vec<u8>>;
+
+}
+
+impl<F> OutputTransform for F
+where
+    F: FnMut(u64, Vec<u8>) -> io::Result<Vec<u8>>
+{
+
+    fn transform(&mut self, index: u64, payload: Vec<u8>) -> io::Result<Vec<u8>> {
+        self(index, payload)
+    }
+}
+
+
+/// Inverse of an `OutputTransform`, applied by a `Decoder` to the payload of each data block before decoding it.
+/// The offsets of the errors found in a reverted payload are relative to its start.
+pub trait InputTransform {
+
+    /// Revert the transform of the `payload` of the data block number `index`, counting from 0.
+    /// Fail if the payload can't be reverted, for example because its authentication failed.
+    fn revert(&mut self, index: u64, payload: &[u8]) -> Result<Vec<u8>, RejectedBlock>;
+
+}
+
+impl<F> InputTransform for F
+where
+    F: FnMut(u64, &[u8]) -> Result<Vec<u8>, RejectedBlock>
+{
+
+    fn revert(&mut self, index: u64, payload: &[u8]) -> Result<Vec<u8>, RejectedBlock> {
+        self(index, payload)
+    }
+}
+
+
+/// Error returned by an `InputTransform` that can't revert the payload of a block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RejectedBlock;
+
+impl fmt::Display for RejectedBlock {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the payload of the block could not be reverted")
+    }
+}
+
+impl Error for RejectedBlock {}
+
+
+/// Detection of changes in the statistics of a stream, such as text followed by base64 blobs.
+///
+/// The symbols are observed in windows of `window` symbols. When a window is complete, the encoder measures how many bits per symbol
+/// encoding it with the statistics of the current block wastes, compared to encoding it with its own statistics
+/// (the Kullback-Leibler divergence, where symbols missing from the block cost their full width).
+/// If the waste exceeds `threshold_bits`, the symbols before the window are written as a block and the window starts a new one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DriftDetection {
+
+    /// Number of symbols observed at a time
+    pub window: NonZeroUsize,
+
+    /// Wasted bits per symbol above which a new block is started
+    pub threshold_bits: f64,
+
+}
+
+
+/// Options of a streaming `Encoder`, which control when it starts a new block on its own
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EncoderOptions {
+
+    /// Start a new block after this many symbols
+    pub max_block_symbols: Option<NonZeroUsize>,
+
+    /// Start a new block once the symbols of the current block are estimated to take this many bytes.
+    /// The estimate uses the code lengths of the last tree, and the symbol width for the symbols it doesn't contain.
+    pub max_block_bytes: Option<NonZeroUsize>,
+
+    /// Always emit a new tree in the blocks started automatically, instead of reusing the previous tree
+    /// when that's estimated to take fewer bytes
+    pub retrain_on_split: bool,
+
+    /// Start a new block when the statistics of the symbols drift away from the ones of the current block
+    pub drift_detection: Option<DriftDetection>,
+
+    /// End the stream with a CRC-32 of its symbols, which decoders verify
+    pub checksum: bool,
+
+    /// Follow each data block with a CRC-32 of its symbols, so that decoders can tell which blocks are corrupted
+    pub block_checksums: bool,
+
+}
+
+impl EncoderOptions {
+
+    pub const fn new() -> Self {
+        Self {
+            max_block_symbols: None,
+            max_block_bytes: None,
+            retrain_on_split: false,
+            drift_detection: None,
+            checksum: false,
+            block_checksums: false
+        }
+    }
+
+
+    /// Default options with the block size picked by `BlockSizeTuning` for `symbol_count` symbols of type `U`
+    pub fn tuned<U>(symbol_count: usize) -> Self
+    where
+        U: Symbol
+    {
+        Self {
+            max_block_symbols: BlockSizeTuning::new().max_block_symbols::<U>(symbol_count),
+            ..Self::new()
+        }
+    }
+
+}
+
+
+/// Fewest symbols of a block sized by `BlockSizeTuning`, so that the tree of each block is amortized over its symbols
+/// even when the symbols are wide
+pub const MIN_TUNED_BLOCK_SYMBOLS: usize = 4096;
+
+
+/// Heuristic that picks the `max_block_symbols` of an `Encoder` from the size of its input.
+///
+/// Small inputs are encoded in a single block, which gives the best compression rate since a single tree is serialized.
+/// Larger inputs are split into blocks whose decoded symbols fit in the cache along with the decoding tree and the encoded block,
+/// so that decoding a block doesn't spill its working set to memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockSizeTuning {
+
+    /// Size in bytes of the cache the decoding of a block should fit in, usually the L2 or the L3 cache of each core
+    pub cache_bytes: NonZeroUsize,
+
+    /// Inputs of up to this many bytes, counting the width of the symbols, are encoded in a single block
+    pub single_block_bytes: usize,
+
+}
+
+impl BlockSizeTuning {
+
+    /// Tuning for a 2 MiB cache, the L2 cache of recent cores, with a single block for inputs of up to 4 MiB
+    pub const fn new() -> Self {
+        Self {
+            cache_bytes: NonZeroUsize::new(2 << 20).unwrap(),
+            single_block_bytes: 4 << 20
+        }
+    }
+
+
+    /// Maximum number of symbols of the blocks of an input of `symbol_count` symbols of type `U`,
+    /// or `None` if the input should be encoded in a single block.
+    /// The input is split into blocks of the same size, so that the last block isn't much smaller than the others.
+    pub fn max_block_symbols<U>(&self, symbol_count: usize) -> Option<NonZeroUsize>
+    where
+        U: Symbol
+    {
+
+        if symbol_count.saturating_mul(U::WIDTH) <= self.single_block_bytes {
+            return None;
+        }
+
+        // Half of the cache holds the decoded symbols, and the rest the encoded block and the tree
+        let block_symbols = (self.cache_bytes.get() / 2 / U::WIDTH).max(MIN_TUNED_BLOCK_SYMBOLS);
+
+        if block_symbols >= symbol_count {
+            return None;
+        }
+
+        let block_count = symbol_count.div_ceil(block_symbols);
+
+        NonZeroUsize::new(symbol_count.div_ceil(block_count))
+    }
+
+}
+
+impl Default for BlockSizeTuning {
+
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// How a push `Decoder` handles data that an `Encoder` never writes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DecodeMode {
+
+    /// Reject nonzero padding bits, bits left after the last symbol of a block, bytes left after the bitcode of a block,
+    /// and bytes after the end of the stream
+    #[default]
+    Strict,
+
+    /// Recover as many symbols as possible from damaged streams, and record each problem as a warning instead.
+    /// A block whose bitcode can't be decoded yields the symbols decoded before the error, and the decoding continues with the next block.
+    /// A block that can't be framed ends the decoding, as if the stream ended there.
+    Lenient,
+
+}
+
+
+/// Options of a push `Decoder`, which bound the work done by each call to `feed` and select how damaged data is handled.
+///
+/// When a limit is reached, the call returns the symbols decoded so far, and the next call resumes where it stopped,
+/// even in the middle of a block, so that real-time systems can spread the decoding of a large block over several ticks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecompressionOptions {
+
+    /// Stop decoding once a call to `feed` has run for this long.
+    /// The clock is checked between blocks and every `DEADLINE_CHECK_INTERVAL` symbols, so a call can overrun it slightly.
+    pub deadline: Option<Duration>,
+
+    /// Return at most this many symbols from a call to `feed`
+    pub max_symbols_per_call: Option<NonZeroUsize>,
+
+    /// Whether anomalies fail the decoding or are recorded as warnings
+    pub mode: DecodeMode,
+
+}
+
+impl DecompressionOptions {
+
+    /// Number of symbols decoded between two checks of the deadline
+    pub const DEADLINE_CHECK_INTERVAL: usize = 1024;
+
+
+    pub const fn new() -> Self {
+        Self {
+            deadline: None,
+            max_symbols_per_call: None,
+            mode: DecodeMode::Strict
+        }
+    }
+
+}
+
+
+/// Streaming encoder that writes symbols to `sink` in independently decodable blocks.
+/// Symbols are buffered until the block is flushed with `flush_block()`, or until the limits of the `EncoderOptions` split it.
+pub struct Encoder<U, W>
+where
+    W: io::Write
+{
+
+    sink: W,
+    options: EncoderOptions,
+    /// Symbols of the block being built
+    pending: Vec<U>,
+    /// Estimated size in bits of the encoded pending symbols, compared to `max_block_bytes`
+    pending_bits: usize,
+    /// Code table of the tree used by the last data block
+    previous: Option<HashMap<U, Code>>,
+    /// With drift detection, frequencies of the pending symbols before the current window
+    block_counts: HashMap<U, usize>,
+    /// With drift detection, frequencies of the pending symbols of the current window
+    window_counts: HashMap<U, usize>,
+    /// Transform applied to the payload of each data block
+    transform: Option<Box<dyn OutputTransform + Send>>,
+    /// With carried statistics, decayed frequencies of the symbols of the previous blocks, scaled by `CARRIED_SCALE`
+    carried: Option<HashMap<U, usize>>,
+    /// Number of data blocks written so far
+    data_blocks: u64,
+    /// With `EncoderOptions::checksum`, checksum of the symbols written in blocks so far
+    checksum: Option<SymbolChecksum>,
+    header_written: bool,
+
+}
+
+impl<U, W> Encoder<U, W>
+where
+    U: Symbol,
+    W: io::Write
+{
+
+    pub fn new(sink: W) -> Self {
+        Self::with_options(sink, EncoderOptions::new())
+    }
+
+
+    pub fn with_options(sink: W, options: EncoderOptions) -> Self {
+        Self {
+            sink,
+            options,
+            pending: Vec::new(),
+            pending_bits: 0,
+            previous: None,
+            block_counts: HashMap::new(),
+            window_counts: HashMap::new(),
+            transform: None,
+            carried: None,
+            data_blocks: 0,
+            checksum: options.checksum.then(SymbolChecksum::default),
+            header_written: false
+        }
+    }
+
+
+    /// Create an encoder that passes the payload of each data block through `transform` before writing it.
+    /// The stream can only be decoded by a `Decoder` with the matching `InputTransform`.
+    pub fn with_transform(sink: W, options: EncoderOptions, transform: impl OutputTransform + Send + 'static) -> Self {
+        Self {
+            transform: Some(Box::new(transform)),
+            ..Self::with_options(sink, options)
+        }
+    }
+
+
+    /// Build the tree of each new block from the statistics of the previous blocks, decayed at every block,
+    /// in addition to the symbols of the block. The trees then cover the symbols recently seen in the stream,
+    /// so that more blocks can reuse the tree of the previous one instead of serializing their own.
+    /// Disabling it forgets the carried statistics.
+    pub fn carry_over_statistics(&mut self, carry: bool) {
+        self.carried = carry.then(|| self.carried.take().unwrap_or_default());
+    }
+
+
+    /// Add a symbol to the current block.
+    /// Fail if a limit of the options is reached and writing the block fails.
+    pub fn write_symbol(&mut self, symbol: U) -> io::Result<()> {
+
+        if self.options.max_block_bytes.is_some() {
+            self.pending_bits += self.estimated_bits(&symbol);
+        }
+
+        if self.options.drift_detection.is_some() {
+            *self.window_counts.entry(symbol.clone()).or_insert(0) += 1;
+        }
+
+        self.pending.push(symbol);
+
+        if self.block_is_full() {
+            self.write_header_once()?;
+            self.write_block(self.pending.len(), self.options.retrain_on_split)?;
+        }
+        else if let Some(drift) = self.options.drift_detection {
+            if self.pending.len().is_multiple_of(drift.window.get()) {
+                self.end_window(drift)?;
+            }
+        }
+
+        Ok(())
+    }
+
+
+    /// Estimated size in bits of `symbol` encoded with the last tree
+    fn estimated_bits(&self, symbol: &U) -> usize {
+        self.previous.as_ref()
+            .and_then(|table| table.get(symbol))
+            .map_or(U::WIDTH * 8, Code::len)
+    }
+
+
+    /// Compare the statistics of the complete window to the ones of the pending symbols before it,
+    /// and write these symbols as a block if they diverge by more than the threshold.
+    fn end_window(&mut self, drift: DriftDetection) -> io::Result<()> {
+
+        let window_counts = std::mem::take(&mut self.window_counts);
+
+        if !self.block_counts.is_empty() && window_divergence(&self.block_counts, &window_counts) > drift.threshold_bits {
+
+            trace_event!(symbols = self.pending.len() - drift.window.get(), "statistics drift detected");
+
+            self.write_header_once()?;
+            self.write_block(self.pending.len() - drift.window.get(), self.options.retrain_on_split)?;
+
+            self.block_counts = window_counts;
+        }
+        else {
+            for (symbol, count) in window_counts {
+                *self.block_counts.entry(symbol).or_insert(0) += count;
+            }
+        }
+
+        Ok(())
+    }
+
+
+    /// Add the symbols to the current block.
+    /// Fail if a limit of the options is reached and writing the block fails.
+    pub fn write_symbols(&mut self, symbols: impl IntoIterator<Item = U>) -> io::Result<()> {
+
+        // The checksums are only computed when the blocks are written
+        if (EncoderOptions { checksum: false, block_checksums: false, ..self.options }) == EncoderOptions::new() {
+            self.pending.extend(symbols);
+            return Ok(());
+        }
+
+        symbols.into_iter().try_for_each(|symbol| self.write_symbol(symbol))
+    }
+
+
+    /// Whether the pending symbols reached a limit of the options
+    fn block_is_full(&self) -> bool {
+        self.options.max_block_symbols.is_some_and(|max| self.pending.len() >= max.get())
+            || self.options.max_block_bytes.is_some_and(|max| self.pending_bits / 8 >= max.get())
+    }
+
+
+    /// Get a reference to the underlying sink
+    pub const fn get_ref(&self) -> &W {
+        &self.sink
+    }
+
+
+    /// Get a mutable reference to the underlying sink
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.sink
+    }
+
+
+    /// Number of symbols waiting to be flushed
+    pub fn pending_symbols(&self) -> usize {
+        self.pending.len()
+    }
+
+
+    fn write_header_once(&mut self) -> io::Result<()> {
+
+        if !self.header_written {
+
+            let settings = EncoderSettings {
+                max_block_symbols: self.options.max_block_symbols,
+                max_block_bytes: self.options.max_block_bytes,
+                checksum: self.options.checksum,
+                block_checksums: self.options.block_checksums,
+                ..EncoderSettings::new()
+            };
+
+            let header = Header { transformed_blocks: self.transform.is_some(), symbol_type: U::TYPE, settings, ..stream_header() };
+
+            let mut buf = Vec::new();
+            header.serialize(&mut buf);
+
+            self.sink.write_all(&buf)?;
+            self.header_written = true;
+        }
+
+        Ok(())
+    }
+
+
+    /// Encode the pending symbols into a block, write it, and flush the sink.
+    /// After this call the receiver can decode every symbol written so far.
+    /// Nothing is written if there are no pending symbols.
+    ///
+    /// The block reuses the tree of the previous block if that's estimated to take fewer bytes than emitting a new tree.
+    pub fn flush_block(&mut self) -> io::Result<()> {
+
+        self.write_header_once()?;
+
+        self.write_block(self.pending.len(), false)?;
+
+        self.sink.flush()
+    }
+
+
+    /// Encode the first `count` pending symbols into a block and write it, unless there are none.
+    /// With `new_tree`, the block carries a new tree even if reusing the previous one would be smaller.
+    fn write_block(&mut self, count: usize, new_tree: bool) -> io::Result<()> {
+
+        if count != 0 {
+
+            let symbols = &self.pending[..count];
+
+            if let Some(checksum) = &mut self.checksum {
+                checksum.update(symbols);
+            }
+
+            let frequencies = value_frequencies(symbols.iter().cloned());
+
+            let tree = match &mut self.carried {
+                Some (carried) => {
+                    carry_frequencies(carried, &frequencies);
+                    EncodingTree::from_frequencies(&carried.iter().map(|(symbol, count)| (symbol.clone(), *count)).collect::<Vec<_>>())
+                },
+                None => EncodingTree::from_frequencies(&frequencies)
+            };
+            let table = tree.code_table();
+
+            let new_tree_bits = tree.encoded_size_bits(&frequencies) as usize;
+            let new_tree_cost = tree.serialized_size() * 8 + new_tree_bits;
+
+            let reuse_cost = self.previous.as_ref()
+                .and_then(|previous| code_cost(previous, &frequencies));
+
+            let mut payload = Vec::new();
+
+            let kind = if let Some(reuse_bits) = reuse_cost.filter(|cost| !new_tree && *cost <= new_tree_cost) {
+
+                // The previous table is kept for the next block
+                let bitcode = encode_with_table(self.previous.as_ref().unwrap(), symbols.iter().cloned(), reuse_bits);
+                write_bitcode_section(&mut payload, &bitcode, BitOrder::MsbFirst);
+
+                BlockKind::Data as u8 | REUSE_TREE
+
+            } else {
+
+                let bitcode = encode_with_table(&table, symbols.iter().cloned(), new_tree_bits);
+                tree.into_decoder().unwrap().serialize(&mut payload);
+                write_bitcode_section(&mut payload, &bitcode, BitOrder::MsbFirst);
+
+                self.previous = Some(table);
+
+                BlockKind::Data as u8
+            };
+
+            if let Some(transform) = &mut self.transform {
+                payload = transform.transform(self.data_blocks, payload)?;
+            }
+            self.data_blocks += 1;
+
+            let mut block = Vec::with_capacity(1 + varint_size(count) + varint_size(payload.len()) + payload.len() + BLOCK_CHECKSUM_SIZE);
+            block.push(kind);
+            write_varint(&mut block, count);
+            write_varint(&mut block, payload.len());
+            block.extend_from_slice(&payload);
+
+            if self.options.block_checksums {
+                let mut checksum = SymbolChecksum::default();
+                checksum.update(symbols);
+                block.extend_from_slice(&checksum.value().to_le_bytes());
+            }
+
+            self.sink.write_all(&block)?;
+
+            trace_event!(symbols = count, bytes = block.len(), reused_tree = kind & REUSE_TREE != 0, "flushed block");
+
+            if count == self.pending.len() {
+                self.block_counts.clear();
+                self.window_counts.clear();
+            }
+
+            self.pending.drain(..count);
+
+            self.pending_bits = if self.options.max_block_bytes.is_some() {
+                self.pending.iter().map(|symbol| self.estimated_bits(symbol)).sum()
+            } else {
+                0
+            };
+        }
+
+        Ok(())
+    }
+
+
+    /// Flush the pending symbols and write the end of the stream.
+    /// Return the underlying sink.
+    pub fn finish(mut self) -> io::Result<W> {
+
+        self.flush_block()?;
+
+        if let Some(checksum) = &self.checksum {
+            let mut block = [BlockKind::Checksum as u8; CHECKSUM_BLOCK_SIZE];
+            block[1..].copy_from_slice(&checksum.value().to_le_bytes());
+            self.sink.write_all(&block)?;
+        }
+
+        self.sink.write_all(&[BlockKind::End as u8])?;
+        self.sink.flush()?;
+
+        Ok(self.sink)
+    }
+
+}
+
+
+/// Weight of an occurrence in the carried statistics, so that the decay keeps a fraction of the occurrences of each block
+const CARRIED_SCALE: usize = 16;
+
+
+/// Decay the `carried` statistics by a quarter, forgetting the symbols whose count drops to zero, and add the `frequencies` of a new block
+fn carry_frequencies<U>(carried: &mut HashMap<U, usize>, frequencies: &[(U, usize)])
+where
+    U: Symbol
+{
+
+    carried.retain(|_, count| {
+        *count -= count.div_ceil(4);
+        *count > 0
+    });
+
+    for (symbol, count) in frequencies {
+        *carried.entry(symbol.clone()).or_insert(0) += count.saturating_mul(CARRIED_SCALE);
+    }
+}
+
+
+/// Compressor of the consecutive messages of a session, such as the requests and replies of a chatty protocol.
+///
+/// Each message is compressed into a block of a stream, and the receiver decodes the messages in order by feeding them to a `Decoder`.
+/// Messages whose symbols are all covered by the tree of the previous message reuse it, if that's smaller than a new tree.
+pub struct Compressor<U> {
+
+    encoder: Encoder<U, Vec<u8>>,
+
+}
+
+impl<U> Compressor<U>
+where
+    U: Symbol
+{
+
+    pub fn new() -> Self {
+        Self {
+            encoder: Encoder::new(Vec::new())
+        }
+    }
+
+
+    /// Build the tree of each message from the decayed statistics of the previous messages too, instead of starting each message cold.
+    /// See `Encoder::carry_over_statistics`.
+    pub fn carry_over_statistics(&mut self, carry: bool) {
+        self.encoder.carry_over_statistics(carry);
+    }
+
+
+    /// Compress a message. The first message also carries the header of the stream.
+    pub fn compress(&mut self, message: impl IntoIterator<Item = U>) -> Vec<u8> {
+
+        self.encoder.pending.extend(message);
+
+        self.encoder.flush_block().expect("writing to a Vec can't fail");
+
+        std::mem::take(self.encoder.get_mut())
+    }
+
+
+    /// Return the end of the stream, after which the receiver rejects any other message
+    pub fn finish(self) -> Vec<u8> {
+        self.encoder.finish().expect("writing to a Vec can't fail")
+    }
+
+}
+
+impl<U> Default for Compressor<U>
+where
+    U: Symbol
+{
+
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Number of input bytes compressed into each block by a `CompressReader` whose options don't bound the blocks
+pub const READER_BLOCK_SIZE: usize = 64 * 1024;
+
+
+/// Reader that yields the bytes of `inner` compressed into a stream, for APIs that pull their data from a `Read`,
+/// such as the bodies of HTTP responses, or `io::copy`.
+///
+/// The input is read and compressed a block at a time, `max_block_symbols` bytes of the options or `READER_BLOCK_SIZE`,
+/// so only a block of input and its compressed bytes are held in memory. The output decompresses like any stream.
+pub struct CompressReader<R> {
+
+    inner: R,
+    /// `None` once the end of the stream is written
+    encoder: Option<Encoder<u8, Vec<u8>>>,
+    /// Number of input bytes compressed into each block
+    block_size: usize,
+    /// Input bytes of the block being compressed
+    input: Vec<u8>,
+    /// Compressed bytes not read yet, from `position` on
+    output: Vec<u8>,
+    position: usize,
+
+}
+
+impl<R> CompressReader<R>
+where
+    R: Read
+{
+
+    pub fn new(inner: R) -> Self {
+        Self::with_options(inner, EncoderOptions::new())
+    }
+
+
+    pub fn with_options(inner: R, options: EncoderOptions) -> Self {
+        Self {
+            inner,
+            encoder: Some(Encoder::with_options(Vec::new(), options)),
+            block_size: options.max_block_symbols.map_or(READER_BLOCK_SIZE, NonZeroUsize::get),
+            input: Vec::new(),
+            output: Vec::new(),
+            position: 0
+        }
+    }
+
+
+    /// Get a reference to the underlying reader
+    pub const fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+
+    /// Return the underlying reader, with the bytes not compressed yet
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+
+    /// Compress the next block of the input into `output`, or the end of the stream once the input is exhausted.
+    /// Return `false` if the end of the stream was already written.
+    fn compress_block(&mut self) -> io::Result<bool> {
+
+        let Some(encoder) = &mut self.encoder else {
+            return Ok(false);
+        };
+
+        self.input.clear();
+        (&mut self.inner).take(self.block_size as u64).read_to_end(&mut self.input)?;
+
+        encoder.write_symbols(self.input.iter().copied())?;
+
+        // A short block is the end of the input
+        if self.input.len() < self.block_size {
+            self.output = self.encoder.take().unwrap().finish()?;
+        } else {
+            encoder.flush_block()?;
+            self.output = std::mem::take(encoder.get_mut());
+        }
+
+        self.position = 0;
+
+        Ok(true)
+    }
+
+}
+
+impl<R> Read for CompressReader<R>
+where
+    R: Read
+{
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+
+        while self.position == self.output.len() {
+            if buf.is_empty() || !self.compress_block()? {
+                return Ok(0);
+            }
+        }
+
+        let read = buf.len().min(self.output.len() - self.position);
+        buf[..read].copy_from_slice(&self.output[self.position..self.position + read]);
+        self.position += read;
+
+        Ok(read)
+    }
+
+}
+
+
+/// A block parsed from the input
+enum ParsedBlock<U> {
+
+    Data (Vec<U>),
+    Checksum (u32),
+    End,
+
+}
+
+
+/// Average number of bits per symbol wasted by encoding the symbols of `window` with the statistics of `block`
+/// instead of their own. Symbols missing from `block` are counted as their full width.
+fn window_divergence<U>(block: &HashMap<U, usize>, window: &HashMap<U, usize>) -> f64
+where
+    U: Symbol
+{
+    let block_total = block.values().sum::<usize>() as f64;
+    let window_total = window.values().sum::<usize>() as f64;
+
+    window.iter()
+        .map(|(symbol, &count)| {
+            let own_bits = -(count as f64 / window_total).log2();
+            let block_bits = block.get(symbol)
+                .map_or((U::WIDTH * 8) as f64, |&block_count| -(block_count as f64 / block_total).log2());
+
+            count as f64 * (block_bits - own_bits)
+        })
+        .sum::<f64>() / window_total
+}
+
+
+/// Number of bits needed to encode values with the given frequencies using `table`.
+/// Return `None` if some value is not in the table.
+fn code_cost<U>(table: &HashMap<U, Code>, frequencies: &[(U, usize)]) -> Option<usize>
+where
+    U: Symbol
+{
+    frequencies.iter()
+        .map(|(value, freq)| table.get(value).map(|code| code.len() * freq))
+        .sum()
+}
+
+
+/// A block framed from the input, whose bitcode isn't decoded yet
+enum FramedBlock {
+
+    /// A data block of `count` symbols, to decode with the tree of the last data block, followed by the checksum of its symbols
+    /// if the stream has block checksums
+    Data { count: usize, bitcode: BitVec, bitcode_offset: usize, checksum: Option<u32> },
+    /// A checksum block recording this checksum
+    Checksum (u32),
+    End,
+
+}
+
+
+/// Frame the block at byte `offset` of `input` and deserialize its tree, without decoding its bitcode.
+/// `previous` is the tree of the last data block, and is replaced if the block carries a new tree.
+/// If a `transform` is given, it reverts the payload of the block, which is data block number `index`.
+/// With `block_checksums`, data blocks are followed by the checksum of their symbols.
+/// Anomalies that don't prevent decoding the block, like nonzero padding bits, are pushed to `anomalies`.
+/// Return `None` if the input doesn't contain the whole block yet, otherwise the block and its size in bytes.
+fn frame_block<U>(
+    input: &[u8],
+    offset: usize,
+    previous: &mut Option<DecodingTree<U>>,
+    transform: Option<&mut dyn InputTransform>,
+    index: u64,
+    block_checksums: bool,
+    anomalies: &mut Vec<DecompressionError>
+) -> Result<Option<(FramedBlock, usize)>, DecompressionError>
+where
+    U: Symbol
+{
+
+    let Some(&kind) = input.get(offset) else {
+        return Ok(None);
+    };
+
+    let invalid_kind = DecompressionError::InvalidBlockKind { offset, found: kind, expected: BlockKind::VALID };
+
+    let reuse_tree = kind & REUSE_TREE != 0;
+
+    match BlockKind::try_from(kind & !REUSE_TREE).map_err(|_| invalid_kind)? {
+
+        BlockKind::End | BlockKind::Checksum if reuse_tree => Err(invalid_kind),
+
+        BlockKind::End => Ok(Some((FramedBlock::End, 1))),
+
+        BlockKind::Checksum => Ok(
+            input.get(offset + 1..offset + CHECKSUM_BLOCK_SIZE)
+                .map(|bytes| (FramedBlock::Checksum(u32::from_le_bytes(bytes.try_into().unwrap())), CHECKSUM_BLOCK_SIZE))
+        ),
+
+        BlockKind::Data => {
+
+            let (count, count_size) = match read_varint(input, offset + 1) {
+                Ok(field) => field,
+                Err(DecompressionError::TruncatedSection { .. }) => return Ok(None),
+                Err(e) => return Err(e)
+            };
+
+            let (length, length_size) = match read_varint(input, offset + 1 + count_size) {
+                Ok(field) => field,
+                Err(DecompressionError::TruncatedSection { .. }) => return Ok(None),
+                Err(e) => return Err(e)
+            };
+
+            let header_size = 1 + count_size + length_size;
+            let payload_offset = offset + header_size;
+            let checksum_size = if block_checksums { BLOCK_CHECKSUM_SIZE } else { 0 };
+
+            if (input.len() - payload_offset).saturating_sub(checksum_size) < length {
+                return Ok(None);
+            }
+
+            let checksum = block_checksums.then(|| {
+                let checksum_offset = payload_offset + length;
+                u32::from_le_bytes(input[checksum_offset..checksum_offset + BLOCK_CHECKSUM_SIZE].try_into().unwrap())
+            });
+
+            let reverted;
+            let (payload, payload_offset) = match transform {
+                Some (transform) => {
+                    reverted = transform.revert(index, &input[payload_offset..payload_offset + length])
+                        .map_err(|_| DecompressionError::RejectedBlock { offset })?;
+                    (&reverted[..], 0)
+                },
+                None => (&input[..payload_offset + length], payload_offset)
+            };
+
+            let bitcode_offset = if reuse_tree {
+
+                if previous.is_none() {
+                    return Err(DecompressionError::NoTreeToReuse { offset });
+                }
+
+                payload_offset
+
+            } else {
+
+                let (tree, tree_size) = DecodingTree::deserialize_at(payload, payload_offset)
+                    .map_err(DecompressionError::InvalidDecodingTree)?;
+
+                *previous = Some(tree);
+
+                payload_offset + tree_size
+            };
+
+            let (bitcode, section_size) = read_bitcode_section(payload, bitcode_offset, BitOrder::MsbFirst)?;
+
+            let (_, length_size) = read_varint(payload, bitcode_offset)?;
+            let section_start = bitcode_offset + length_size;
+            let section_end = bitcode_offset + section_size;
+
+            if has_dirty_padding(&payload[section_start..section_end], BitOrder::MsbFirst) {
+                anomalies.push(DecompressionError::DirtyPadding { offset: section_start });
+            }
+            if section_end < payload.len() {
+                anomalies.push(DecompressionError::UnusedBlockBytes { offset, remaining: payload.len() - section_end });
+            }
+
+            Ok(Some((FramedBlock::Data { count, bitcode, bitcode_offset, checksum }, header_size + length + checksum_size)))
+        },
+    }
+}
+
+
+/// Parse and decode the block at byte `offset` of `input`, like `frame_block` does, and verify the checksum of data blocks.
+fn parse_block<U>(
+    input: &[u8],
+    offset: usize,
+    previous: &mut Option<DecodingTree<U>>,
+    transform: Option<&mut dyn InputTransform>,
+    index: u64,
+    block_checksums: bool
+) -> Result<Option<(ParsedBlock<U>, usize)>, DecompressionError>
+where
+    U: Symbol
+{
+
+    // Whole inputs accept the anomalies that don't prevent decoding
+    let Some((block, size)) = frame_block(input, offset, previous, transform, index, block_checksums, &mut Vec::new())? else {
+        return Ok(None);
+    };
+
+    let (count, bitcode, bitcode_offset, expected) = match block {
+        FramedBlock::Data { count, bitcode, bitcode_offset, checksum } => (count, bitcode, bitcode_offset, checksum),
+        FramedBlock::Checksum (checksum) => return Ok(Some((ParsedBlock::Checksum(checksum), size))),
+        FramedBlock::End => return Ok(Some((ParsedBlock::End, size)))
+    };
+
+    // Framing a data block always leaves its tree in `previous`
+    let Some(tree) = previous.as_ref() else {
+        return Err(DecompressionError::NoTreeToReuse { offset });
+    };
+
+    let symbols = TreeCodec.decode_exact(&bitcode.as_bit_view(), tree, count)
+        .map_err(|source| DecompressionError::BitCodeDecodingError { offset: bitcode_offset, source })?;
+
+    if symbols.len() != count {
+        return Err(DecompressionError::SymbolCountMismatch { offset: bitcode_offset, expected: count, found: symbols.len() });
+    }
+
+    if let Some(expected) = expected {
+        let mut checksum = SymbolChecksum::default();
+        checksum.update(&symbols);
+        verify_checksum(Some(&checksum), expected, offset)?;
+    }
+
+    Ok(Some((ParsedBlock::Data(symbols), size)))
+}
+
+
+/// Check the checksum recorded by the block at byte `offset` against the `checksum` of the symbols it covers,
+/// which is `None` if the header doesn't announce a checksum
+fn verify_checksum(checksum: Option<&SymbolChecksum>, expected: u32, offset: usize) -> Result<(), DecompressionError> {
+
+    let Some(checksum) = checksum else {
+        return Err(DecompressionError::UnexpectedChecksum { offset });
+    };
+
+    if checksum.value() != expected {
+        return Err(DecompressionError::ChecksumMismatch { offset, expected, found: checksum.value() });
+    }
+
+    Ok(())
+}
+
+
+/// Decode a whole stream whose blocks start at byte `offset` of `input`, verifying the checksums announced by the `settings` of its header
+pub(crate) fn read_blocks<U>(input: &[u8], offset: usize, settings: &EncoderSettings) -> Result<Box<[U]>, DecompressionError>
+where
+    U: Symbol
+{
+
+    let mut decoded = Vec::new();
+
+    for_each_block(input, offset, settings, |symbols| {
+        decoded.extend(symbols);
+        Ok::<_, DecompressionError>(())
+    })?;
+
+    Ok(decoded.into_boxed_slice())
+}
+
+
+/// Decode the blocks of a stream starting at byte `offset` of `input` one at a time, passing the symbols of each data block to `emit`.
+/// The checksums announced by the `settings` of the header are verified, the one of each data block before its symbols are emitted.
+pub(crate) fn for_each_block<U, E>(input: &[u8], mut offset: usize, settings: &EncoderSettings, mut emit: impl FnMut(Vec<U>) -> Result<(), E>) -> Result<(), E>
+where
+    U: Symbol,
+    E: From<DecompressionError>
+{
+
+    let mut previous = None;
+    let mut checksum = settings.checksum.then(SymbolChecksum::default);
+    // Whether a checksum block follows the last data block
+    let mut checked = false;
+
+    loop {
+
+        let (block, size) = parse_block(input, offset, &mut previous, None, 0, settings.block_checksums)?
+            .ok_or(DecompressionError::TruncatedSection { offset, expected: 1, remaining: input.len() - offset })?;
+
+        match block {
+
+            ParsedBlock::Data (symbols) => {
+                if let Some(checksum) = &mut checksum {
+                    checksum.update(&symbols);
+                }
+                checked = false;
+                emit(symbols)?;
+            },
+
+            ParsedBlock::Checksum (expected) => {
+                verify_checksum(checksum.as_ref(), expected, offset)?;
+                checked = true;
+            },
+
+            ParsedBlock::End if checksum.is_some() && !checked => return Err(DecompressionError::MissingChecksum { offset }.into()),
+
+            ParsedBlock::End => return Ok(())
+        }
+
+        offset += size;
+    }
+}
+
+
+/// Work a call to `Decoder::feed` may still do
+struct Budget {
+
+    /// Symbols that may still be returned
+    symbols: usize,
+    /// Instant at which the call must stop
+    deadline: Option<Instant>,
+    /// Symbols decoded so far by the call
+    spent: usize,
+
+}
+
+impl Budget {
+
+    fn new(options: &DecompressionOptions) -> Self {
+        Self {
+            symbols: options.max_symbols_per_call.map_or(usize::MAX, NonZeroUsize::get),
+            deadline: options.deadline.map(|deadline| Instant::now() + deadline),
+            spent: 0
+        }
+    }
+
+
+    /// Whether the call must stop before decoding another symbol.
+    /// Every call decodes at least one symbol, so that the decoding progresses however short the deadline.
+    fn is_exhausted(&self) -> bool {
+        self.symbols == 0 || (self.spent > 0 && self.deadline.is_some_and(|deadline| Instant::now() >= deadline))
+    }
+
+
+    /// Count a decoded symbol and tell whether the call must stop, checking the clock every `DEADLINE_CHECK_INTERVAL` symbols
+    fn spend(&mut self) -> bool {
+        self.symbols -= 1;
+        self.spent += 1;
+        self.symbols == 0 || (self.spent.is_multiple_of(DecompressionOptions::DEADLINE_CHECK_INTERVAL) && self.is_exhausted())
+    }
+
+}
+
+
+/// Data block whose decoding was suspended when a call to `Decoder::feed` ran out of budget
+struct PendingBlock {
+
+    count: usize,
+    bitcode: BitVec,
+    /// Offset of the bitcode in the block, or in the reverted payload if the block was transformed
+    bitcode_offset: usize,
+    /// Size in bytes of the block, which is kept in the buffer until it's decoded
+    size: usize,
+    /// Symbols decoded so far
+    decoded: usize,
+    /// Offset in the bitcode of the next code
+    bit_offset: usize,
+    /// With block checksums, the checksum recorded after the block and the one of the symbols decoded so far
+    checksum: Option<(u32, SymbolChecksum)>,
+
+}
+
+impl PendingBlock {
+
+    /// Decode symbols with `tree` into `decoded` until the end of the block or of the `budget`.
+    /// Return whether the block is complete. `block_offset` is added to the offsets of the errors.
+    fn decode<U>(&mut self, tree: &DecodingTree<U>, block_offset: usize, decoded: &mut Vec<U>, budget: &mut Budget) -> Result<bool, DecompressionError>
+    where
+        U: Symbol
+    {
+
+        let offset = block_offset + self.bitcode_offset;
+        let bits = self.bitcode.as_bit_view();
+
+        if tree.single_value().is_some() {
+            reserve_single_leaf(decoded, (self.count - self.decoded).min(budget.symbols))
+                .map_err(|source| DecompressionError::BitCodeDecodingError { offset, source })?;
+        }
+
+        while self.decoded < self.count {
+
+            if budget.is_exhausted() {
+                return Ok(false);
+            }
+
+            if self.bit_offset == bits.len_bits() && tree.single_value().is_none() {
+                return Err(DecompressionError::SymbolCountMismatch { offset, expected: self.count, found: self.decoded });
+            }
+
+            let (value, next) = tree.decode_one(&bits, self.bit_offset)
+                .map_err(|source| DecompressionError::BitCodeDecodingError { offset, source })?;
+
+            decoded.push(value.clone());
+            self.decoded += 1;
+            self.bit_offset = next;
+
+            if budget.spend() && self.decoded < self.count {
+                return Ok(false);
+            }
+        }
+
+        // The bits of a single-leaf tree are ignored, like `decode_exact` does
+        if self.bit_offset < bits.len_bits() && tree.single_value().is_none() {
+
+            let found = self.count + tree.decode(&bits.split_at_bit(self.bit_offset).1).map_or(0, |extra| extra.len());
+
+            return Err(DecompressionError::SymbolCountMismatch { offset, expected: self.count, found });
+        }
+
+        Ok(true)
+    }
+
+}
+
+
+/// Push decoder for streams produced by `Encoder`.
+/// Bytes can be fed in arbitrary pieces: every complete block is decoded as soon as it's received,
+/// unless the `DecompressionOptions` bound the work of each call.
+/// Error offsets are relative to the start of the bytes that haven't been decoded yet.
+pub struct Decoder<U: Clone> {
+
+    /// Received bytes that haven't been decoded yet
+    buffer: Vec<u8>,
+    /// Tree of the last data block
+    previous: Option<DecodingTree<U>>,
+    /// Transform reverted on the payload of each data block
+    transform: Option<Box<dyn InputTransform + Send>>,
+    options: DecompressionOptions,
+    /// Block at the start of the buffer whose decoding was suspended
+    pending: Option<PendingBlock>,
+    /// Number of data blocks framed so far
+    data_blocks: u64,
+    /// Problems recovered from in `DecodeMode::Lenient`
+    warnings: Vec<DecompressionError>,
+    /// If the header announces a checksum, checksum of the symbols decoded so far
+    checksum: Option<SymbolChecksum>,
+    /// Whether a checksum block follows the last data block
+    checked: bool,
+    /// Whether each data block is followed by the checksum of its symbols
+    block_checksums: bool,
+    header_read: bool,
+    suspended: bool,
+    finished: bool,
+
+}
+
+impl<U> Decoder<U>
+where
+    U: Symbol
+{
+
+    pub const fn new() -> Self {
+        Self::with_options(DecompressionOptions::new())
+    }
+
+
+    pub const fn with_options(options: DecompressionOptions) -> Self {
+        Self {
+            buffer: Vec::new(),
+            previous: None,
+            transform: None,
+            options,
+            pending: None,
+            data_blocks: 0,
+            warnings: Vec::new(),
+            checksum: None,
+            checked: false,
+            block_checksums: false,
+            header_read: false,
+            suspended: false,
+            finished: false
+        }
+    }
+
+
+    /// Create a decoder for streams written by an `Encoder` with the `OutputTransform` that `transform` reverts
+    pub fn with_transform(transform: impl InputTransform + Send + 'static) -> Self {
+        Self {
+            transform: Some(Box::new(transform)),
+            ..Self::new()
+        }
+    }
+
+
+    /// Change the limits of the next calls to `feed`, for example to follow the time left in each frame
+    pub fn set_options(&mut self, options: DecompressionOptions) {
+        self.options = options;
+    }
+
+
+    /// Whether the end of the stream was received
+    pub const fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+
+    /// Whether the last call to `feed` ran out of budget before decoding all the bytes it had received.
+    /// The decoding resumes on the next call, which can be fed no bytes.
+    pub const fn is_suspended(&self) -> bool {
+        self.suspended
+    }
+
+
+    /// Problems recovered from so far in `DecodeMode::Lenient`, in the order they were found.
+    /// Their offsets are relative to the start of the bytes that weren't decoded yet when they were found.
+    pub fn warnings(&self) -> &[DecompressionError] {
+        &self.warnings
+    }
+
+
+    /// Feed received bytes to the decoder.
+    /// Return the symbols of all the blocks completed by these bytes, or as many as the `DecompressionOptions` allow.
+    ///
+    /// In `DecodeMode::Lenient`, only an invalid header fails: the other problems are recorded in `warnings`.
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<Vec<U>, DecompressionError> {
+
+        self.buffer.extend_from_slice(bytes);
+
+        let mut budget = Budget::new(&self.options);
+        let lenient = self.options.mode == DecodeMode::Lenient;
+        let mut decoded = Vec::new();
+        let mut read = 0;
+
+        if !self.header_read {
+
+            match Header::deserialize(&self.buffer) {
+
+                Ok((header, size)) => {
+
+                    if !header.blocks {
+                        return Err(DecompressionError::NotAStream);
+                    }
+                    if header.chunk_size != 1 {
+                        return Err(DecompressionError::ChunkSizeMismatch { expected: 1, found: header.chunk_size as usize });
+                    }
+                    match (header.transformed_blocks, self.transform.is_some()) {
+                        (true, false) => return Err(DecompressionError::MissingTransform),
+                        (false, true) => return Err(DecompressionError::UntransformedBlocks),
+                        _ => ()
+                    }
+
+                    self.checksum = header.settings.checksum.then(SymbolChecksum::default);
+                    self.block_checksums = header.settings.block_checksums;
+                    self.header_read = true;
+                    read = size;
+                },
+
+                // Wait for the rest of the header
+                Err(HeaderDeserializationError::MissingField { .. }) => return Ok(decoded),
+
+                Err(e) => return Err(DecompressionError::InvalidHeader(e))
+            }
+        }
+
+        loop {
+
+            if let Some(block) = &mut self.pending {
+
+                // A pending block is always a data block, framed with its tree
+                let Some(tree) = &self.previous else {
+                    return Err(DecompressionError::NoTreeToReuse { offset: read });
+                };
+
+                // The offsets of the errors in a reverted payload are relative to its start
+                let block_offset = if self.transform.is_some() { 0 } else { read };
+
+                let start = decoded.len();
+                let result = block.decode(tree, block_offset, &mut decoded, &mut budget);
+
+                if let Some(checksum) = &mut self.checksum {
+                    checksum.update(&decoded[start..]);
+                }
+                if let Some((_, checksum)) = &mut block.checksum {
+                    checksum.update(&decoded[start..]);
+                }
+
+                match result {
+                    Ok (false) => break,
+                    Ok (true) => {
+                        // A corrupted block doesn't prevent decoding the next ones
+                        if let Some((expected, checksum)) = &block.checksum {
+                            match verify_checksum(Some(checksum), *expected, read) {
+                                Ok (()) => (),
+                                Err (e) if lenient => self.warnings.push(e),
+                                Err (e) => return Err(e)
+                            }
+                        }
+                    },
+                    // Keep the symbols decoded before the error and skip the rest of the block
+                    Err (e) if lenient => self.warnings.push(e),
+                    Err (e) => return Err(e)
+                }
+
+                trace_event!(symbols = block.count, bytes = block.size, "decoded block");
+                read += block.size;
+                self.pending = None;
+            }
+
+            if self.finished || budget.is_exhausted() {
+                break;
+            }
+
+            let transform = self.transform.as_deref_mut().map(|transform| transform as &mut dyn InputTransform);
+
+            let mut anomalies = Vec::new();
+
+            let framed = match frame_block(&self.buffer, read, &mut self.previous, transform, self.data_blocks, self.block_checksums, &mut anomalies) {
+                Ok (framed) => framed,
+                // The next block can't be found without framing this one
+                Err (e) if lenient => {
+                    self.warnings.push(e);
+                    self.finished = true;
+                    read = self.buffer.len();
+                    break;
+                },
+                Err (e) => return Err(e)
+            };
+
+            match (anomalies.first(), lenient) {
+                (Some (&e), false) => return Err(e),
+                _ => self.warnings.append(&mut anomalies)
+            }
+
+            match framed {
+
+                Some ((FramedBlock::Data { count, bitcode, bitcode_offset, checksum }, size)) => {
+
+                    // Keep the offsets relative to the block, since the bytes before it are dropped if the decoding is suspended
+                    let bitcode_offset = if self.transform.is_some() { bitcode_offset } else { bitcode_offset - read };
+
+                    let checksum = checksum.map(|expected| (expected, SymbolChecksum::default()));
+
+                    self.pending = Some(PendingBlock { count, bitcode, bitcode_offset, size, decoded: 0, bit_offset: 0, checksum });
+                    self.data_blocks += 1;
+                    self.checked = false;
+                },
+
+                Some ((FramedBlock::Checksum (expected), size)) => {
+
+                    match verify_checksum(self.checksum.as_ref(), expected, read) {
+                        Ok (()) => (),
+                        Err (e) if lenient => self.warnings.push(e),
+                        Err (e) => return Err(e)
+                    }
+
+                    self.checked = true;
+                    read += size;
+                },
+
+                Some ((FramedBlock::End, size)) => {
+
+                    if self.checksum.is_some() && !self.checked {
+
+                        let missing = DecompressionError::MissingChecksum { offset: read };
+
+                        if !lenient {
+                            return Err(missing);
+                        }
+                        self.warnings.push(missing);
+                    }
+
+                    self.finished = true;
+                    read += size;
+                },
+
+                None => break
+            }
+        }
+
+        self.suspended = self.pending.is_some() || (!self.finished && read < self.buffer.len() && budget.is_exhausted());
+
+        if self.finished && read < self.buffer.len() {
+
+            let trailing = DecompressionError::TrailingData { offset: read, remaining: self.buffer.len() - read };
+
+            if !lenient {
+                return Err(trailing);
+            }
+
+            self.warnings.push(trailing);
+            read = self.buffer.len();
+        }
+
+        self.buffer.drain(..read);
+
+        Ok(decoded)
+    }
+
+}
+
+impl<U> Default for Decoder<U>
+where
+    U: Symbol
+{
+
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::decompress;
+    use rand::{Rng, SeedableRng};
+
+
+    /// Size of the header of the streams encoded by the tests, which records the symbol type
+    const HEADER_SIZE: usize = 4;
+
+
+    #[test]
+    fn check_flush_points() {
+
+        let mut encoder = Encoder::new(Vec::new());
+        let mut decoder = Decoder::<char>::new();
+
+        let messages = ["Hello", "", "a", "How are you?", "aaaa"];
+
+        for message in messages {
+
+            encoder.write_symbols(message.chars()).unwrap();
+            encoder.flush_block().unwrap();
+
+            let sent = std::mem::take(encoder.get_mut());
+
+            let received: String = decoder.feed(&sent).unwrap().into_iter().collect();
+
+            assert_eq!(received, message);
+        }
+
+        let sent = encoder.finish().unwrap();
+        assert!(decoder.feed(&sent).unwrap().is_empty());
+        assert!(decoder.is_finished());
+    }
+
+
+    #[test]
+    fn check_partial_feeding() {
+
+        let mut encoder = Encoder::new(Vec::new());
+
+        encoder.write_symbols("first block".chars()).unwrap();
+        encoder.flush_block().unwrap();
+        encoder.write_symbols("second block".chars()).unwrap();
+
+        let stream = encoder.finish().unwrap();
+
+        // Feed one byte at a time
+        let mut decoder = Decoder::<char>::new();
+        let mut received = String::new();
+
+        for byte in &stream {
+            received.extend(decoder.feed(&[*byte]).unwrap());
+        }
+
+        assert_eq!(received, "first blocksecond block");
+        assert!(decoder.is_finished());
+
+        let decompressed: String = decompress::<char>(&stream).unwrap().iter().collect();
+        assert_eq!(decompressed, received);
+    }
+
+
+    #[test]
+    fn check_checksum() {
+
+        let options = EncoderOptions { checksum: true, ..EncoderOptions::new() };
+        let mut encoder = Encoder::with_options(Vec::new(), options);
+
+        encoder.write_symbols("first block".chars()).unwrap();
+        encoder.flush_block().unwrap();
+        encoder.write_symbols("abababab".chars()).unwrap();
+
+        let stream = encoder.finish().unwrap();
+        assert!(Header::deserialize(&stream).unwrap().0.settings.checksum);
+
+        let crc = |text: &str| {
+            let mut checksum = SymbolChecksum::default();
+            checksum.update(&text.chars().collect::<Vec<_>>());
+            checksum.value()
+        };
+
+        let checksum_offset = stream.len() - 1 - CHECKSUM_BLOCK_SIZE;
+        assert_eq!(stream[checksum_offset], BlockKind::Checksum as u8);
+        assert_eq!(stream[checksum_offset + 1..stream.len() - 1], crc("first blockabababab").to_le_bytes());
+
+        // The checksum is verified while feeding one byte at a time
+        let mut decoder = Decoder::<char>::new();
+        let mut received = String::new();
+        for byte in &stream {
+            received.extend(decoder.feed(&[*byte]).unwrap());
+        }
+        assert_eq!(received, "first blockabababab");
+        assert!(decoder.is_finished());
+
+        assert_eq!(decompress::<char>(&stream).unwrap().iter().collect::<String>(), received);
+
+        // With two leaves, every bit decodes to a symbol, so flipping one keeps the symbol count
+        let mut flipped = stream.clone();
+        flipped[checksum_offset - 1] ^= 0x80;
+        let mismatch = DecompressionError::ChecksumMismatch {
+            offset: checksum_offset,
+            expected: crc("first blockabababab"),
+            found: crc("first blockbbababab")
+        };
+        assert_eq!(decompress::<char>(&flipped), Err(mismatch));
+
+        let mut decoder = Decoder::<char>::with_options(DecompressionOptions { mode: DecodeMode::Lenient, ..DecompressionOptions::new() });
+        assert_eq!(decoder.feed(&flipped).unwrap().into_iter().collect::<String>(), "first blockbbababab");
+        assert_eq!(decoder.warnings(), [mismatch]);
+
+        // Cutting off the checksum block doesn't go unnoticed
+        let mut stripped = stream.clone();
+        stripped.drain(checksum_offset..checksum_offset + CHECKSUM_BLOCK_SIZE);
+        assert_eq!(decompress::<char>(&stripped), Err(DecompressionError::MissingChecksum { offset: checksum_offset }));
+        assert_eq!(Decoder::<char>::new().feed(&stripped), Err(DecompressionError::MissingChecksum { offset: checksum_offset }));
+
+        // Checksum blocks need the header to announce them
+        let mut unannounced = Encoder::new(Vec::new());
+        unannounced.write_symbols("abababab".chars()).unwrap();
+        let mut unannounced = unannounced.finish().unwrap();
+        let end = unannounced.len() - 1;
+        unannounced.splice(end..end, stream[checksum_offset..checksum_offset + CHECKSUM_BLOCK_SIZE].iter().copied());
+        assert_eq!(decompress::<char>(&unannounced), Err(DecompressionError::UnexpectedChecksum { offset: end }));
+    }
+
+
+    #[test]
+    fn check_block_checksums() {
+
+        let options = EncoderOptions { block_checksums: true, ..EncoderOptions::new() };
+        let mut encoder = Encoder::with_options(Vec::new(), options);
+
+        for block in ["first block", "abababab", "third block"] {
+            encoder.write_symbols(block.chars()).unwrap();
+            encoder.flush_block().unwrap();
+        }
+
+        let stream = encoder.finish().unwrap();
+        let (header, header_size) = Header::deserialize(&stream).unwrap();
+        assert!(header.settings.block_checksums);
+        assert_eq!(decompress::<char>(&stream).unwrap().iter().collect::<String>(), "first blockababababthird block");
+
+        // Symbols decoded over several calls are checksummed too
+        let options = DecompressionOptions { max_symbols_per_call: NonZeroUsize::new(3), ..DecompressionOptions::new() };
+        let mut decoder = Decoder::<char>::with_options(options);
+        let mut received: String = decoder.feed(&stream).unwrap().into_iter().collect();
+        while decoder.is_suspended() {
+            received.extend(decoder.feed(&[]).unwrap());
+        }
+        assert_eq!(received, "first blockababababthird block");
+
+        // Find the second block, whose two leaves decode any bit
+        let mut offset = header_size;
+        let mut previous = None;
+        let (_, first_size) = parse_block::<char>(&stream, offset, &mut previous, None, 0, true).unwrap().unwrap();
+        offset += first_size;
+        let (_, second_size) = parse_block::<char>(&stream, offset, &mut previous, None, 1, true).unwrap().unwrap();
+
+        // The last bitcode byte comes right before the checksum of the block
+        let mut flipped = stream.clone();
+        flipped[offset + second_size - BLOCK_CHECKSUM_SIZE - 1] ^= 0x80;
+
+        assert!(matches!(decompress::<char>(&flipped), Err(DecompressionError::ChecksumMismatch { offset: found, .. }) if found == offset));
+
+        // A lenient decoder reports the corrupted block and decodes the next ones
+        let mut decoder = Decoder::<char>::with_options(DecompressionOptions { mode: DecodeMode::Lenient, ..DecompressionOptions::new() });
+        let received: String = decoder.feed(&flipped).unwrap().into_iter().collect();
+        assert_eq!(received, "first blockbbabababthird block");
+        assert!(matches!(decoder.warnings(), [DecompressionError::ChecksumMismatch { offset: found, .. }] if *found == offset));
+    }
+
+
+    #[test]
+    fn check_block_size_tuning() {
+
+        let tuning = BlockSizeTuning::new();
+
+        // Small inputs in a single block, counting the width of the symbols
+        assert_eq!(tuning.max_block_symbols::<u8>(4 << 20), None);
+        assert_eq!(tuning.max_block_symbols::<char>(1 << 20), None);
+        assert!(tuning.max_block_symbols::<char>((1 << 20) + 1).is_some());
+
+        // Equal blocks of at most half the cache
+        let max = tuning.max_block_symbols::<u8>((5 << 20) + 1).unwrap().get();
+        assert!(max <= 1 << 20);
+        assert_eq!(((5 << 20) + 1_usize).div_ceil(max), 6);
+        assert_eq!(tuning.max_block_symbols::<u32>(usize::MAX), NonZeroUsize::new(usize::MAX.div_ceil(usize::MAX.div_ceil(1 << 18))));
+
+        // Wide symbols still get enough symbols per block to amortize the trees
+        assert_eq!(tuning.max_block_symbols::<[u8; 4096]>(10_000), NonZeroUsize::new(3334));
+
+        let tuning = BlockSizeTuning { cache_bytes: NonZeroUsize::new(64 << 10).unwrap(), single_block_bytes: 0 };
+        assert_eq!(tuning.max_block_symbols::<u8>(1 << 20), NonZeroUsize::new(32 << 10));
+        assert_eq!(tuning.max_block_symbols::<u8>(100), None);
+
+        let text = "the quick brown fox jumps over the lazy dog. ".repeat(100_000);
+        let options = EncoderOptions::tuned::<u8>(text.len());
+        assert!(options.max_block_symbols.is_some());
+
+        let mut encoder = Encoder::with_options(Vec::new(), options);
+        encoder.write_symbols(text.bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let (header, _) = Header::deserialize(&compressed).unwrap();
+        assert_eq!(header.settings.max_block_symbols, options.max_block_symbols);
+        assert_eq!(*decompress::<u8>(&compressed).unwrap(), *text.as_bytes());
+    }
+
+
+    #[test]
+    fn check_compress_reader() {
+
+        let text = "the quick brown fox jumps over the lazy dog. ".repeat(100);
+        let options = EncoderOptions { max_block_symbols: NonZeroUsize::new(1000), checksum: true, ..EncoderOptions::new() };
+
+        let mut compressed = Vec::new();
+        io::copy(&mut CompressReader::with_options(text.as_bytes(), options), &mut compressed).unwrap();
+
+        assert!(compressed.len() < text.len());
+        assert_eq!(*decompress::<u8>(&compressed).unwrap(), *text.as_bytes());
+
+        let (header, header_size) = Header::deserialize(&compressed).unwrap();
+        assert!(header.settings.checksum);
+        assert_eq!(read_blocks::<u8>(&compressed, header_size, &header.settings).unwrap().len(), text.len());
+
+        // Reads of a single byte, across the block boundaries
+        let mut reader = CompressReader::with_options(text.as_bytes(), options);
+        let mut byte = [0];
+        let mut bytewise = Vec::new();
+        while reader.read(&mut byte).unwrap() == 1 {
+            bytewise.push(byte[0]);
+        }
+        assert_eq!(bytewise, compressed);
+        assert_eq!(reader.read(&mut byte).unwrap(), 0);
+
+        // Empty input is a stream without data blocks
+        let mut empty = Vec::new();
+        CompressReader::new(io::empty()).read_to_end(&mut empty).unwrap();
+        assert!(decompress::<u8>(&empty).unwrap().is_empty());
+    }
+
+    #[test]
+    fn check_auto_split() {
+
+        let text = "aaaaaaaaaa bbbbbbbbbb cccccccccc dddddddddd eeeeeeeeee";
+
+        let limits = [
+            EncoderOptions { max_block_symbols: NonZeroUsize::new(11), ..EncoderOptions::new() },
+            EncoderOptions { max_block_bytes: NonZeroUsize::new(4), ..EncoderOptions::new() },
+            EncoderOptions { max_block_symbols: NonZeroUsize::new(11), retrain_on_split: true, ..EncoderOptions::new() },
+        ];
+
+        for options in limits {
+
+            let mut encoder = Encoder::with_options(Vec::new(), options);
+            let mut decoder = Decoder::<char>::new();
+
+            // Blocks are written as soon as a limit is reached, before any flush
+            encoder.write_symbols(text.chars()).unwrap();
+            assert!(encoder.pending_symbols() < text.len());
+
+            let received: String = decoder.feed(&std::mem::take(encoder.get_mut())).unwrap().into_iter().collect();
+            assert!(!received.is_empty() && text.starts_with(&received));
+
+            let rest: String = decoder.feed(&encoder.finish().unwrap()).unwrap().into_iter().collect();
+            assert_eq!(received + &rest, text);
+        }
+
+        // 54 data units make four full blocks of 11, each with a new tree, and a partial one
+        let options = EncoderOptions { max_block_symbols: NonZeroUsize::new(11), ..EncoderOptions::new() };
+        let mut encoder = Encoder::with_options(Vec::new(), options);
+        for symbol in text.chars() {
+            encoder.write_symbol(symbol).unwrap();
+        }
+        assert_eq!(encoder.pending_symbols(), 10);
+
+        let stream = encoder.finish().unwrap();
+        let (header, mut offset) = Header::deserialize(&stream).unwrap();
+        assert_eq!(header.settings.max_block_symbols, NonZeroUsize::new(11));
+        let mut previous = None;
+        let mut blocks = 0;
+        while let Some((ParsedBlock::Data (symbols), size)) = parse_block::<char>(&stream, offset, &mut previous, None, 0, false).unwrap() {
+            assert_eq!(stream[offset] & REUSE_TREE, 0);
+            assert_eq!(symbols.len(), if blocks < 4 { 11 } else { 10 });
+            blocks += 1;
+            offset += size;
+        }
+        assert_eq!(blocks, 5);
+    }
+
+
+    #[test]
+    fn check_drift_detection() {
+
+        const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1636);
+
+        let text = "the quick brown fox jumps over the lazy dog, ".repeat(400);
+        let blob: String = (0..20000).map(|_| BASE64[rng.gen_range(0..BASE64.len())] as char).collect();
+        let data = text.clone() + &blob;
+
+        let window = 256;
+        let options = EncoderOptions {
+            drift_detection: Some(DriftDetection { window: NonZeroUsize::new(window).unwrap(), threshold_bits: 1.0 }),
+            ..EncoderOptions::new()
+        };
+
+        let mut encoder = Encoder::with_options(Vec::new(), options);
+        encoder.write_symbols(data.chars()).unwrap();
+        let with_drift = encoder.finish().unwrap();
+
+        let mut encoder = Encoder::new(Vec::new());
+        encoder.write_symbols(data.chars()).unwrap();
+        let without_drift = encoder.finish().unwrap();
+
+        assert_eq!(read_blocks::<char>(&with_drift, HEADER_SIZE, &EncoderSettings::new()).unwrap().iter().collect::<String>(), data);
+        assert!(with_drift.len() < without_drift.len());
+
+        // The first block ends within a window of the end of the text
+        let mut previous = None;
+        let (first, _) = parse_block::<char>(&with_drift, HEADER_SIZE, &mut previous, None, 0, false).unwrap().unwrap();
+        let ParsedBlock::Data (symbols) = first else { panic!("expected a data block") };
+        assert!(symbols.len().abs_diff(text.len()) <= window);
+    }
+
+
+    #[test]
+    fn check_tree_reuse() {
+
+        let mut encoder = Encoder::new(Vec::new());
+        let mut decoder = Decoder::<char>::new();
+
+        // Fresh tree, same distribution as the previous block, symbols missing from the previous tree
+        let blocks = [("abracadabra", 3, false), ("abracadabra", 0, true), ("xyz", 0, false)];
+
+        for (message, header_size, reused) in blocks {
+
+            encoder.write_symbols(message.chars()).unwrap();
+            encoder.flush_block().unwrap();
+
+            let sent = std::mem::take(encoder.get_mut());
+
+            assert_eq!(sent[header_size] & REUSE_TREE != 0, reused);
+
+            let received: String = decoder.feed(&sent).unwrap().into_iter().collect();
+            assert_eq!(received, message);
+        }
+    }
+
+
+    /// Toy authenticated cipher: XOR with the block index, followed by a checksum byte
+    fn seal(index: u64, mut payload: Vec<u8>) -> io::Result<Vec<u8>> {
+        let checksum = payload.iter().fold(index as u8, |sum, byte| sum.wrapping_add(*byte));
+        payload.iter_mut().for_each(|byte| *byte ^= index as u8 ^ 0x5a);
+        payload.push(checksum);
+        Ok(payload)
+    }
+
+
+    fn open(index: u64, sealed: &[u8]) -> Result<Vec<u8>, RejectedBlock> {
+        let (checksum, payload) = sealed.split_last().ok_or(RejectedBlock)?;
+        let payload: Vec<u8> = payload.iter().map(|byte| byte ^ index as u8 ^ 0x5a).collect();
+        if payload.iter().fold(index as u8, |sum, byte| sum.wrapping_add(*byte)) != *checksum {
+            return Err(RejectedBlock);
+        }
+        Ok(payload)
+    }
+
+
+    #[test]
+    fn check_block_transform() {
+
+        let messages = ["abracadabra", "abracadabra", "the quick brown fox"];
+
+        let mut encoder = Encoder::with_transform(Vec::new(), EncoderOptions::new(), seal);
+        for message in messages {
+            encoder.write_symbols(message.chars()).unwrap();
+            encoder.flush_block().unwrap();
+        }
+        let stream = encoder.finish().unwrap();
+
+        let mut decoder = Decoder::<char>::with_transform(open);
+        let received: String = decoder.feed(&stream).unwrap().into_iter().collect();
+        assert_eq!(received, messages.concat());
+        assert!(decoder.is_finished());
+
+        assert_eq!(Decoder::<char>::new().feed(&stream), Err(DecompressionError::MissingTransform));
+        assert_eq!(decompress::<char>(&stream), Err(DecompressionError::MissingTransform));
+
+        let plain = Encoder::<char, _>::new(Vec::new()).finish().unwrap();
+        assert_eq!(Decoder::<char>::with_transform(open).feed(&plain), Err(DecompressionError::UntransformedBlocks));
+
+        // Tampering with the payload of the first block, right after its kind, count, and length
+        let mut tampered = stream.clone();
+        tampered[HEADER_SIZE + 3] ^= 1;
+        assert_eq!(Decoder::<char>::with_transform(open).feed(&tampered), Err(DecompressionError::RejectedBlock { offset: HEADER_SIZE }));
+
+        // The output transform can fail the write
+        let mut failing = Encoder::with_transform(Vec::new(), EncoderOptions::new(), |_, _| Err(io::Error::other("no key")));
+        failing.write_symbol('a').unwrap();
+        assert!(failing.flush_block().is_err());
+    }
+
+
+    #[test]
+    fn check_decoding_budget() {
+
+        let text = "the quick brown fox jumps over the lazy dog, ".repeat(200);
+
+        let mut encoder = Encoder::new(Vec::new());
+        encoder.write_symbols(text.chars()).unwrap();
+        encoder.flush_block().unwrap();
+        encoder.write_symbols("abracadabra".chars()).unwrap();
+        let stream = encoder.finish().unwrap();
+
+        let expected = text.clone() + "abracadabra";
+
+        // The large block is decoded over several calls
+        let options = DecompressionOptions { max_symbols_per_call: NonZeroUsize::new(1000), ..DecompressionOptions::new() };
+        let mut decoder = Decoder::<char>::with_options(options);
+        let mut received: String = decoder.feed(&stream).unwrap().into_iter().collect();
+        assert_eq!(received.len(), 1000);
+        assert!(decoder.is_suspended());
+
+        let mut calls = 1;
+        while decoder.is_suspended() {
+            let symbols = decoder.feed(&[]).unwrap();
+            assert!(symbols.len() <= 1000);
+            received.extend(symbols);
+            calls += 1;
+        }
+        assert_eq!(received, expected);
+        assert_eq!(calls, expected.len().div_ceil(1000));
+        assert!(decoder.is_finished());
+
+        // An expired deadline still lets every call progress
+        let options = DecompressionOptions { deadline: Some(Duration::ZERO), ..DecompressionOptions::new() };
+        let mut decoder = Decoder::<char>::with_options(options);
+        let mut received = String::new();
+        for byte in &stream {
+            received.extend(decoder.feed(&[*byte]).unwrap());
+        }
+        while decoder.is_suspended() {
+            let symbols = decoder.feed(&[]).unwrap();
+            assert!(symbols.len() <= DecompressionOptions::DEADLINE_CHECK_INTERVAL);
+            received.extend(symbols);
+        }
+        assert_eq!(received, expected);
+        assert!(decoder.is_finished());
+
+        // Lifting the limits decodes the rest at once
+        let mut decoder = Decoder::<char>::with_options(DecompressionOptions { max_symbols_per_call: NonZeroUsize::new(1), ..DecompressionOptions::new() });
+        assert_eq!(decoder.feed(&stream).unwrap().len(), 1);
+        decoder.set_options(DecompressionOptions::new());
+        assert_eq!(decoder.feed(&[]).unwrap().len(), expected.len() - 1);
+        assert!(!decoder.is_suspended() && decoder.is_finished());
+
+        // Errors of a suspended block are found when it's resumed, after the header was dropped
+        let mut encoder = Encoder::new(Vec::new());
+        encoder.write_symbols("abcabc".chars()).unwrap();
+        let mut miscounted = encoder.finish().unwrap();
+        miscounted[HEADER_SIZE + 1] = 7;
+
+        let Err (DecompressionError::SymbolCountMismatch { offset, expected: 7, found: 6 }) = Decoder::<char>::new().feed(&miscounted) else {
+            panic!("expected a symbol count mismatch");
+        };
+
+        let mut decoder = Decoder::<char>::with_options(DecompressionOptions { max_symbols_per_call: NonZeroUsize::new(4), ..DecompressionOptions::new() });
+        assert_eq!(decoder.feed(&miscounted).unwrap().len(), 4);
+        assert_eq!(decoder.feed(&[]), Err(DecompressionError::SymbolCountMismatch { offset: offset - HEADER_SIZE, expected: 7, found: 6 }));
+    }
+
+
+    #[test]
+    fn check_carried_statistics() {
+
+        let messages: Vec<String> = (0..50)
+            .map(|i| format!("{{\"op\": \"{}\", \"id\": {}}}", if i % 3 == 0 { "get" } else { "put" }, i * 7))
+            .collect();
+
+        let session = |carry: bool| {
+
+            let mut compressor = Compressor::new();
+            compressor.carry_over_statistics(carry);
+
+            let mut decoder = Decoder::<char>::new();
+            let mut size = 0;
+
+            for message in &messages {
+                let compressed = compressor.compress(message.chars());
+                size += compressed.len();
+
+                let received: String = decoder.feed(&compressed).unwrap().into_iter().collect();
+                assert_eq!(received, *message);
+            }
+
+            assert!(decoder.feed(&compressor.finish()).unwrap().is_empty());
+            assert!(decoder.is_finished());
+
+            size
+        };
+
+        let cold = session(false);
+        let warm = session(true);
+        assert!(warm < cold, "{warm} >= {cold}");
+
+        // Statistics not seen for a while are forgotten
+        let mut carried = HashMap::new();
+        carry_frequencies(&mut carried, &[('a', 1)]);
+        assert_eq!(carried[&'a'], CARRIED_SCALE);
+        for _ in 0..8 {
+            carry_frequencies(&mut carried, &[('b', 1)]);
+        }
+        assert!(!carried.contains_key(&'a'));
+    }
+
+
+    #[test]
+    fn check_invalid_streams() {
+
+        let mut decoder = Decoder::<char>::new();
+        let compressed = crate::compress("abc".chars());
+        assert_eq!(decoder.feed(&compressed), Err(DecompressionError::NotAStream));
+
+        let mut stream = Encoder::<char, _>::new(Vec::new()).finish().unwrap();
+        stream.push(0);
+        let mut decoder = Decoder::<char>::new();
+        assert_eq!(decoder.feed(&stream), Err(DecompressionError::TrailingData { offset: HEADER_SIZE + 1, remaining: 1 }));
+
+        let stream = [crate::FORMAT_VERSION, stream[1], 0, 7];
+        let mut decoder = Decoder::<char>::new();
+        assert_eq!(decoder.feed(&stream), Err(DecompressionError::InvalidBlockKind { offset: 3, found: 7, expected: BlockKind::VALID }));
+
+        let stream = [crate::FORMAT_VERSION, stream[1], 0, BlockKind::End as u8 | REUSE_TREE];
+        let mut decoder = Decoder::<char>::new();
+        assert_eq!(decoder.feed(&stream), Err(DecompressionError::InvalidBlockKind { offset: 3, found: stream[3], expected: BlockKind::VALID }));
+
+        let stream = [crate::FORMAT_VERSION, stream[1], 0, BlockKind::Data as u8 | REUSE_TREE, 1, 0, 0, 0, 0, 0, 0, 0];
+        let mut decoder = Decoder::<char>::new();
+        assert_eq!(decoder.feed(&stream), Err(DecompressionError::NoTreeToReuse { offset: 3 }));
+    }
+
+
+    #[test]
+    fn check_decode_modes() {
+
+        let mut encoder = Encoder::new(Vec::new());
+        encoder.write_symbols("first block".chars()).unwrap();
+        encoder.flush_block().unwrap();
+        encoder.write_symbols("second block".chars()).unwrap();
+        let stream = encoder.finish().unwrap();
+
+        // The first data block starts after the header, with 1-byte count and length
+        let second_block = HEADER_SIZE + 3 + stream[HEADER_SIZE + 2] as usize;
+
+        let strict = |stream: &[u8]| Decoder::<char>::new().feed(stream).map(|symbols| symbols.into_iter().collect::<String>());
+        let lenient = |stream: &[u8]| {
+            let mut decoder = Decoder::<char>::with_options(DecompressionOptions { mode: DecodeMode::Lenient, ..DecompressionOptions::new() });
+            let symbols: String = decoder.feed(stream).unwrap().into_iter().collect();
+            (symbols, decoder.warnings().to_vec())
+        };
+
+        assert_eq!(lenient(&stream), ("first blocksecond block".to_string(), vec![]));
+
+        // One more symbol announced than the bitcode of the first block contains
+        let mut damaged = stream.clone();
+        damaged[HEADER_SIZE + 1] += 1;
+        assert!(matches!(strict(&damaged), Err(DecompressionError::SymbolCountMismatch { expected: 12, found: 11, .. })));
+        let (symbols, warnings) = lenient(&damaged);
+        assert_eq!(symbols, "first blocksecond block");
+        assert!(matches!(warnings[..], [DecompressionError::SymbolCountMismatch { expected: 12, found: 11, .. }]));
+
+        // A byte left after the bitcode of the first block
+        let mut damaged = stream.clone();
+        damaged[HEADER_SIZE + 2] += 1;
+        damaged.insert(second_block, 0);
+        assert_eq!(strict(&damaged), Err(DecompressionError::UnusedBlockBytes { offset: HEADER_SIZE, remaining: 1 }));
+        assert_eq!(lenient(&damaged), ("first blocksecond block".to_string(), vec![DecompressionError::UnusedBlockBytes { offset: HEADER_SIZE, remaining: 1 }]));
+
+        // Set the last padding bit of the bitcode of the last block, which ends before the end block
+        let mut damaged = stream.clone();
+        let last = damaged.len() - 2;
+        damaged[last] |= 1;
+        assert!(matches!(strict(&damaged), Err(DecompressionError::DirtyPadding { .. })));
+        let (symbols, warnings) = lenient(&damaged);
+        assert_eq!(symbols, "first blocksecond block");
+        assert!(matches!(warnings[..], [DecompressionError::DirtyPadding { .. }]));
+
+        // Decoding stops at a block that can't be framed, and ignores the bytes after the end
+        let mut damaged = stream.clone();
+        damaged[second_block] = 7;
+        let (symbols, warnings) = lenient(&damaged);
+        assert_eq!(symbols, "first block");
+        assert!(matches!(warnings[..], [DecompressionError::InvalidBlockKind { found: 7, .. }]));
+
+        let mut damaged = stream.clone();
+        damaged.push(0);
+        assert!(matches!(strict(&damaged), Err(DecompressionError::TrailingData { remaining: 1, .. })));
+        let (symbols, warnings) = lenient(&damaged);
+        assert_eq!(symbols, "first blocksecond block");
+        assert!(matches!(warnings[..], [DecompressionError::TrailingData { remaining: 1, .. }]));
+    }
+
+}
@@ -0,0 +1,348 @@
+use std::array;
+use std::fmt;
+use std::hash::Hash;
+
+
+/// A data unit that can be encoded in the leaves of a tree.
+/// Symbols are serialized as exactly `WIDTH` little-endian bytes, independently of the memory layout
+/// and alignment of the type, so that deserialization never has to reinterpret raw memory.
+pub trait Symbol: Clone + Eq + Hash {
+
+    /// Number of bytes of the serialized representation
+    const WIDTH: usize;
+
+    /// Type recorded in the header, so that `decompress_any` can decode the data without knowing the type.
+    /// `None` for the types that `DecodedPayload` can't hold.
+    const TYPE: Option<SymbolType> = None;
+
+    /// Write the serialized representation of `self` into `out`, which is exactly `WIDTH` bytes long
+    fn to_bytes(&self, out: &mut [u8]);
+
+    /// Read a symbol from exactly `WIDTH` bytes.
+    /// Return `None` if the bytes don't represent a valid symbol.
+    fn from_bytes(bytes: &[u8]) -> Option<Self>;
+
+}
+
+
+/// Symbol types recorded in the header, identified by their tag in the optional flags byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum SymbolType {
+
+    U8 = 1,
+    U16,
+    U32,
+    U64,
+    U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    Char,
+    Bool,
+
+}
+
+impl SymbolType {
+
+    /// All the symbol types, in order of their tags
+    pub const ALL: [Self; 12] = [
+        Self::U8, Self::U16, Self::U32, Self::U64, Self::U128,
+        Self::I8, Self::I16, Self::I32, Self::I64, Self::I128,
+        Self::Char, Self::Bool,
+    ];
+
+
+    /// Tag of the type in the header, never 0
+    pub const fn tag(self) -> u8 {
+        self as u8
+    }
+
+
+    /// Return the type with the given tag, or `None` if the tag is 0 or unknown to this version of the library
+    pub const fn from_tag(tag: u8) -> Option<Self> {
+        if tag == 0 || tag as usize > Self::ALL.len() {
+            return None;
+        }
+        Some(Self::ALL[tag as usize - 1])
+    }
+
+
+    /// Number of bytes of the serialized symbols
+    pub const fn width(self) -> usize {
+        match self {
+            Self::U8 | Self::I8 | Self::Bool => 1,
+            Self::U16 | Self::I16 => 2,
+            Self::U32 | Self::I32 | Self::Char => 4,
+            Self::U64 | Self::I64 => 8,
+            Self::U128 | Self::I128 => 16,
+        }
+    }
+
+}
+
+impl fmt::Display for SymbolType {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::U8 => "u8",
+            Self::U16 => "u16",
+            Self::U32 => "u32",
+            Self::U64 => "u64",
+            Self::U128 => "u128",
+            Self::I8 => "i8",
+            Self::I16 => "i16",
+            Self::I32 => "i32",
+            Self::I64 => "i64",
+            Self::I128 => "i128",
+            Self::Char => "char",
+            Self::Bool => "bool",
+        };
+        f.write_str(name)
+    }
+}
+
+
+macro_rules! impl_symbol_for_int {
+    ($($t:ty => $type:ident),*) => {
+        $(
+            impl Symbol for $t {
+
+                const WIDTH: usize = std::mem::size_of::<$t>();
+
+                const TYPE: Option<SymbolType> = Some(SymbolType::$type);
+
+                fn to_bytes(&self, out: &mut [u8]) {
+                    out.copy_from_slice(&self.to_le_bytes());
+                }
+
+                fn from_bytes(bytes: &[u8]) -> Option<Self> {
+                    Some(<$t>::from_le_bytes(bytes.try_into().ok()?))
+                }
+            }
+        )*
+    };
+}
+
+impl_symbol_for_int!(
+    u8 => U8, u16 => U16, u32 => U32, u64 => U64, u128 => U128,
+    i8 => I8, i16 => I16, i32 => I32, i64 => I64, i128 => I128
+);
+
+
+impl Symbol for char {
+
+    const WIDTH: usize = 4;
+
+    const TYPE: Option<SymbolType> = Some(SymbolType::Char);
+
+    fn to_bytes(&self, out: &mut [u8]) {
+        (*self as u32).to_bytes(out)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        char::from_u32(u32::from_bytes(bytes)?)
+    }
+}
+
+
+impl Symbol for bool {
+
+    const WIDTH: usize = 1;
+
+    const TYPE: Option<SymbolType> = Some(SymbolType::Bool);
+
+    fn to_bytes(&self, out: &mut [u8]) {
+        out[0] = *self as u8;
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [0] => Some(false),
+            [1] => Some(true),
+            _ => None
+        }
+    }
+}
+
+
+impl<S, const N: usize> Symbol for [S; N]
+where
+    S: Symbol
+{
+
+    const WIDTH: usize = S::WIDTH * N;
+
+    fn to_bytes(&self, out: &mut [u8]) {
+        for (symbol, chunk) in self.iter().zip(out.chunks_exact_mut(S::WIDTH)) {
+            symbol.to_bytes(chunk);
+        }
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+
+        if bytes.len() != Self::WIDTH {
+            return None;
+        }
+
+        let symbols: [Option<S>; N] = array::from_fn(
+            |i| S::from_bytes(&bytes[i * S::WIDTH..(i + 1) * S::WIDTH])
+        );
+
+        if symbols.iter().any(Option::is_none) {
+            return None;
+        }
+
+        Some(symbols.map(Option::unwrap))
+    }
+}
+
+
+/// Key of the symbols in the canonical order: by serialized bytes compared from the last one.
+/// Serialized symbols are little-endian, so integers and characters are ordered by value.
+pub(crate) fn canonical_key<U>(value: &U) -> Vec<u8>
+where
+    U: Symbol
+{
+    let mut bytes = vec![0; U::WIDTH];
+    value.to_bytes(&mut bytes);
+    bytes.reverse();
+    bytes
+}
+
+
+/// Iterator adapter that groups the symbols of `I` into arrays of `N` symbols.
+/// If the number of symbols is not a multiple of `N`, the last array is completed by repeating its last symbol.
+/// The number of meaningful symbols in the last array is given by `ChunkedSymbols::remainder()`.
+#[derive(Debug, Clone)]
+pub struct ChunkedSymbols<I, const N: usize> {
+
+    inner: I
+
+}
+
+impl<I, const N: usize> ChunkedSymbols<I, N>
+where
+    I: Iterator,
+    I::Item: Clone
+{
+
+    pub const fn new(inner: I) -> Self {
+        const { assert!(N > 0, "chunks must contain at least one symbol") };
+        Self {
+            inner
+        }
+    }
+
+
+    /// Number of meaningful symbols in the last chunk, 0 if the last chunk is complete.
+    /// Consumes a clone of the underlying iterator.
+    pub fn remainder(&self) -> usize
+    where
+        I: Clone
+    {
+        self.inner.clone().count() % N
+    }
+
+}
+
+impl<I, const N: usize> Iterator for ChunkedSymbols<I, N>
+where
+    I: Iterator,
+    I::Item: Clone
+{
+    type Item = [I::Item; N];
+
+    fn next(&mut self) -> Option<Self::Item> {
+
+        let first = self.inner.next()?;
+
+        let mut last = first.clone();
+        let mut first = Some(first);
+
+        Some(array::from_fn(|_| {
+            if let Some(symbol) = first.take() {
+                symbol
+            } else {
+                if let Some(symbol) = self.inner.next() {
+                    last = symbol;
+                }
+                last.clone()
+            }
+        }))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+
+    fn round_trip<S: Symbol + std::fmt::Debug>(symbol: S) {
+
+        let mut bytes = vec![0; S::WIDTH];
+        symbol.to_bytes(&mut bytes);
+
+        assert_eq!(S::from_bytes(&bytes), Some(symbol));
+    }
+
+
+    #[test]
+    fn check_round_trip() {
+
+        round_trip(0xAB_u8);
+        round_trip(-12345_i16);
+        round_trip(0xDEAD_BEEF_u32);
+        round_trip(u128::MAX);
+        round_trip('é');
+        round_trip(true);
+        round_trip(['a', 'b', 'c']);
+        round_trip([[1_u16, 2], [3, 4]]);
+    }
+
+
+    #[test]
+    fn check_symbol_types() {
+
+        for symbol_type in SymbolType::ALL {
+            assert_eq!(SymbolType::from_tag(symbol_type.tag()), Some(symbol_type));
+        }
+        assert_eq!(SymbolType::from_tag(0), None);
+        assert_eq!(SymbolType::from_tag(SymbolType::ALL.len() as u8 + 1), None);
+
+        assert_eq!(char::TYPE.map(SymbolType::width), Some(char::WIDTH));
+        assert_eq!(i64::TYPE.map(SymbolType::width), Some(i64::WIDTH));
+        assert_eq!(<[u8; 2]>::TYPE, None);
+    }
+
+
+    #[test]
+    fn check_invalid_bytes() {
+
+        // Surrogate code points are not valid chars
+        assert_eq!(char::from_bytes(&0xD800_u32.to_le_bytes()), None);
+        assert_eq!(bool::from_bytes(&[2]), None);
+        assert_eq!(u32::from_bytes(&[1, 2, 3]), None);
+        assert_eq!(<[char; 2]>::from_bytes(&[b'a', 0, 0, 0, 0, 0xD8, 0, 0]), None);
+    }
+
+
+    #[test]
+    fn check_chunks() {
+
+        let chunks = ChunkedSymbols::<_, 3>::new(1..=7);
+
+        assert_eq!(chunks.remainder(), 1);
+        assert_eq!(chunks.collect::<Vec<_>>(), [[1, 2, 3], [4, 5, 6], [7, 7, 7]]);
+
+        let chunks = ChunkedSymbols::<_, 2>::new("abcd".chars());
+
+        assert_eq!(chunks.remainder(), 0);
+        assert_eq!(chunks.collect::<Vec<_>>(), [['a', 'b'], ['c', 'd']]);
+    }
+
+}
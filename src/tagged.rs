@@ -0,0 +1,169 @@
+//! Decompression of data whose symbol type is only known at runtime, from the type recorded in its header.
+
+use crate::container::Header;
+use crate::symbol::SymbolType;
+use crate::{decompress, DecompressionError, Symbol};
+
+
+/// Symbols decoded by `decompress_any`, in a variant for each `SymbolType`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodedPayload {
+
+    Bytes (Vec<u8>),
+    U16s (Vec<u16>),
+    U32s (Vec<u32>),
+    U64s (Vec<u64>),
+    U128s (Vec<u128>),
+    I8s (Vec<i8>),
+    I16s (Vec<i16>),
+    I32s (Vec<i32>),
+    I64s (Vec<i64>),
+    I128s (Vec<i128>),
+    Chars (String),
+    Bools (Vec<bool>),
+
+}
+
+impl DecodedPayload {
+
+    /// Type of the decoded symbols
+    pub const fn symbol_type(&self) -> SymbolType {
+        match self {
+            DecodedPayload::Bytes (_) => SymbolType::U8,
+            DecodedPayload::U16s (_) => SymbolType::U16,
+            DecodedPayload::U32s (_) => SymbolType::U32,
+            DecodedPayload::U64s (_) => SymbolType::U64,
+            DecodedPayload::U128s (_) => SymbolType::U128,
+            DecodedPayload::I8s (_) => SymbolType::I8,
+            DecodedPayload::I16s (_) => SymbolType::I16,
+            DecodedPayload::I32s (_) => SymbolType::I32,
+            DecodedPayload::I64s (_) => SymbolType::I64,
+            DecodedPayload::I128s (_) => SymbolType::I128,
+            DecodedPayload::Chars (_) => SymbolType::Char,
+            DecodedPayload::Bools (_) => SymbolType::Bool,
+        }
+    }
+
+
+    /// Number of decoded symbols
+    pub fn len(&self) -> usize {
+        match self {
+            DecodedPayload::Bytes (symbols) => symbols.len(),
+            DecodedPayload::U16s (symbols) => symbols.len(),
+            DecodedPayload::U32s (symbols) => symbols.len(),
+            DecodedPayload::U64s (symbols) => symbols.len(),
+            DecodedPayload::U128s (symbols) => symbols.len(),
+            DecodedPayload::I8s (symbols) => symbols.len(),
+            DecodedPayload::I16s (symbols) => symbols.len(),
+            DecodedPayload::I32s (symbols) => symbols.len(),
+            DecodedPayload::I64s (symbols) => symbols.len(),
+            DecodedPayload::I128s (symbols) => symbols.len(),
+            DecodedPayload::Chars (text) => text.chars().count(),
+            DecodedPayload::Bools (symbols) => symbols.len(),
+        }
+    }
+
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+
+    /// Bytes of the decoded symbols: the UTF-8 text for characters, and the serialized symbols for the other types
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            DecodedPayload::Bytes (symbols) => symbols.clone(),
+            DecodedPayload::U16s (symbols) => serialize(symbols),
+            DecodedPayload::U32s (symbols) => serialize(symbols),
+            DecodedPayload::U64s (symbols) => serialize(symbols),
+            DecodedPayload::U128s (symbols) => serialize(symbols),
+            DecodedPayload::I8s (symbols) => serialize(symbols),
+            DecodedPayload::I16s (symbols) => serialize(symbols),
+            DecodedPayload::I32s (symbols) => serialize(symbols),
+            DecodedPayload::I64s (symbols) => serialize(symbols),
+            DecodedPayload::I128s (symbols) => serialize(symbols),
+            DecodedPayload::Chars (text) => text.as_bytes().to_vec(),
+            DecodedPayload::Bools (symbols) => serialize(symbols),
+        }
+    }
+
+}
+
+
+/// Concatenate the serialized representations of `symbols`
+fn serialize<U>(symbols: &[U]) -> Vec<u8>
+where
+    U: Symbol
+{
+    let mut bytes = vec![0; symbols.len() * U::WIDTH];
+
+    for (symbol, chunk) in symbols.iter().zip(bytes.chunks_exact_mut(U::WIDTH)) {
+        symbol.to_bytes(chunk);
+    }
+
+    bytes
+}
+
+
+/// Decompress `input` as the symbol type recorded in its header, for tools that don't know the type at compile time.
+/// Data compressed in chunks, or with symbol types that `DecodedPayload` can't hold, doesn't record its type and is
+/// rejected with `DecompressionError::UnknownSymbolType`.
+pub fn decompress_any(input: &[u8]) -> Result<DecodedPayload, DecompressionError> {
+
+    let (header, _) = Header::deserialize(input).map_err(DecompressionError::InvalidHeader)?;
+
+    let Some(symbol_type) = header.symbol_type else {
+        return Err(DecompressionError::UnknownSymbolType);
+    };
+
+    Ok(match symbol_type {
+        SymbolType::U8 => DecodedPayload::Bytes(decompress(input)?.into_vec()),
+        SymbolType::U16 => DecodedPayload::U16s(decompress(input)?.into_vec()),
+        SymbolType::U32 => DecodedPayload::U32s(decompress(input)?.into_vec()),
+        SymbolType::U64 => DecodedPayload::U64s(decompress(input)?.into_vec()),
+        SymbolType::U128 => DecodedPayload::U128s(decompress(input)?.into_vec()),
+        SymbolType::I8 => DecodedPayload::I8s(decompress(input)?.into_vec()),
+        SymbolType::I16 => DecodedPayload::I16s(decompress(input)?.into_vec()),
+        SymbolType::I32 => DecodedPayload::I32s(decompress(input)?.into_vec()),
+        SymbolType::I64 => DecodedPayload::I64s(decompress(input)?.into_vec()),
+        SymbolType::I128 => DecodedPayload::I128s(decompress(input)?.into_vec()),
+        SymbolType::Char => DecodedPayload::Chars(decompress::<char>(input)?.iter().collect()),
+        SymbolType::Bool => DecodedPayload::Bools(decompress(input)?.into_vec()),
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::stream::Encoder;
+    use crate::{compress, compress_bytes, compress_chunked, compress_with_options, CompressionOptions};
+
+
+    #[test]
+    fn check_decompress_any() {
+
+        let text = "a runtime-tagged payload";
+
+        assert_eq!(decompress_any(&compress(text.chars())), Ok(DecodedPayload::Chars(text.to_string())));
+        assert_eq!(decompress_any(&compress_bytes(text.as_bytes())), Ok(DecodedPayload::Bytes(text.as_bytes().to_vec())));
+        assert_eq!(decompress_any(&compress_bytes(&[])), Ok(DecodedPayload::Bytes(Vec::new())));
+
+        let numbers: Vec<i32> = vec![-1, 7, 7, 1 << 20, -1, 7];
+        let options = CompressionOptions { mtf: true, lz77: true, ..CompressionOptions::new() };
+        let decoded = decompress_any(&compress_with_options(numbers.iter().copied(), &options)).unwrap();
+        assert_eq!(decoded, DecodedPayload::I32s(numbers.clone()));
+        assert_eq!(decoded.symbol_type(), SymbolType::I32);
+        assert_eq!(decoded.len(), numbers.len());
+        assert_eq!(decoded.to_bytes(), numbers.iter().flat_map(|n| n.to_le_bytes()).collect::<Vec<u8>>());
+
+        let mut encoder = Encoder::new(Vec::new());
+        encoder.write_symbols([true, false, false]).unwrap();
+        assert_eq!(decompress_any(&encoder.finish().unwrap()), Ok(DecodedPayload::Bools(vec![true, false, false])));
+
+        // Chunks don't record their type
+        assert_eq!(decompress_any(&compress_chunked::<u8, 2>(text.bytes())), Err(DecompressionError::UnknownSymbolType));
+    }
+
+}
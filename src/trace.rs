@@ -0,0 +1,89 @@
+//! Instrumentation with `tracing`, which compiles to nothing unless the `tracing` feature is enabled.
+
+/// Emit a debug event, with the syntax of `tracing::debug!`
+macro_rules! trace_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+    };
+}
+
+/// Enter a debug span until the end of the enclosing block, with the syntax of `tracing::debug_span!`
+macro_rules! trace_span {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!($($arg)*).entered();
+    };
+}
+
+/// Start a timer whose elapsed time is reported by the events, only when the `tracing` feature is enabled
+macro_rules! trace_timer {
+    ($name:ident) => {
+        #[cfg(feature = "tracing")]
+        let $name = std::time::Instant::now();
+    };
+}
+
+
+/// Throughput in MiB/s of processing `bytes` bytes since `start`
+#[cfg(feature = "tracing")]
+pub(crate) fn throughput(bytes: usize, start: std::time::Instant) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0) / start.elapsed().as_secs_f64()
+}
+
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tracing::{span, subscriber, Event, Metadata, Subscriber};
+
+    use crate::{compress, decompress};
+
+
+    static SPANS: AtomicUsize = AtomicUsize::new(0);
+    static EVENTS: AtomicUsize = AtomicUsize::new(0);
+
+
+    /// Subscriber that only counts the spans and the events
+    struct Counter;
+
+    impl Subscriber for Counter {
+
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+            span::Id::from_u64(1 + SPANS.fetch_add(1, Ordering::Relaxed) as u64)
+        }
+
+        fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+        fn event(&self, _: &Event<'_>) {
+            EVENTS.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn enter(&self, _: &span::Id) {}
+
+        fn exit(&self, _: &span::Id) {}
+    }
+
+
+    #[test]
+    fn check_instrumentation() {
+
+        subscriber::with_default(Counter, || {
+            let compressed = compress("hello world".bytes());
+            decompress::<u8>(&compressed).unwrap();
+        });
+
+        // The compression and decompression spans, the model and encoding events, and the decoding event
+        assert_eq!(SPANS.load(Ordering::Relaxed), 2);
+        assert_eq!(EVENTS.load(Ordering::Relaxed), 3);
+    }
+
+}
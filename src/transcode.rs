@@ -0,0 +1,177 @@
+//! Re-compression of compressed data with different options, without decompressing it whole first.
+
+use std::cell::Cell;
+use std::error::Error;
+use std::fmt;
+use std::iter;
+
+use crate::container::Header;
+use crate::source::{self, Passes};
+use crate::writer::decoded_symbols;
+use crate::{compress_empty, compress_with_selected_codec, CompressError, CompressionOptions, DecompressionError, Symbol};
+
+
+/// Error returned by `transcode`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranscodeError {
+
+    /// The input is not valid compressed data
+    Decompression (DecompressionError),
+    /// The decoded symbols can't be compressed as specified by the options
+    Compression (CompressError),
+
+}
+
+impl fmt::Display for TranscodeError {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TranscodeError::Decompression (_)
+                => write!(f, "the input can't be decompressed"),
+            TranscodeError::Compression (_)
+                => write!(f, "the decompressed symbols can't be compressed with the new options"),
+        }
+    }
+}
+
+impl Error for TranscodeError {
+
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TranscodeError::Decompression (e) => Some(e),
+            TranscodeError::Compression (e) => Some(e),
+        }
+    }
+}
+
+impl From<DecompressionError> for TranscodeError {
+
+    fn from(e: DecompressionError) -> Self {
+        TranscodeError::Decompression(e)
+    }
+}
+
+impl From<CompressError> for TranscodeError {
+
+    fn from(e: CompressError) -> Self {
+        TranscodeError::Compression(e)
+    }
+}
+
+
+/// Compress the symbols of the compressed `input` again as specified by `options`, keeping the file metadata of its header.
+/// The layout of `input` is read from its header, so data compressed with any options, or by a streaming encoder, can be upgraded.
+///
+/// The compressor reads its input several times, and every pass decodes `input` again instead of reading decompressed symbols
+/// from memory. Stream blocks and LZ77 payloads are still decoded whole on every pass, and options that transform the
+/// whole input, such as `lz77` and `mtf`, buffer the symbols as they do with any other input.
+pub fn transcode<U>(input: &[u8], options: &CompressionOptions) -> Result<Box<[u8]>, TranscodeError>
+where
+    U: Symbol
+{
+
+    let (header, _) = Header::deserialize(input).map_err(DecompressionError::InvalidHeader)?;
+    let header = Header { metadata: header.metadata, symbol_type: U::TYPE, ..Header::new() };
+
+    // Passes stop at the first invalid symbol, which is then reported instead of what the compressor made of the partial input
+    let error = &Cell::new(None);
+
+    let source = source::from_fn(|| {
+        decoded_symbols::<U>(input, None)
+            .unwrap_or_else(|e| Box::new(iter::once(Err(e))))
+            .map_while(move |symbol| symbol.map_err(|e| error.set(Some(e))).ok())
+    });
+
+    let compressed = compress_with_selected_codec(header.clone(), Passes::new(&source), options);
+
+    if let Some(e) = error.take() {
+        return Err(e.into());
+    }
+
+    match compressed {
+        // Empty streams are written without any tree
+        Err (CompressError::EmptyInput) => Ok(compress_empty(header)),
+        compressed => Ok(compressed?)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::bits::BitOrder;
+    use crate::stream::Encoder;
+    use crate::{compress, compress_with_options, decompress, decompress_with_metadata, FileMetadata};
+
+
+    #[test]
+    fn check_transcode() {
+
+        let text = "the quick brown fox jumps over the lazy dog, the quick brown fox jumps over the lazy dog";
+
+        let all_options = [
+            CompressionOptions::new(),
+            CompressionOptions { lz77: true, compact_tree: true, ..CompressionOptions::new() },
+            CompressionOptions { mtf: true, bit_order: BitOrder::LsbFirst, ..CompressionOptions::new() },
+            CompressionOptions { canonical_tree: true, ..CompressionOptions::new() },
+        ];
+
+        for from in &all_options {
+
+            let compressed = compress_with_options(text.chars(), from);
+
+            for to in &all_options {
+                let transcoded = transcode::<char>(&compressed, to).unwrap();
+                assert_eq!(Header::deserialize(&transcoded).unwrap().0, Header::deserialize(&compress_with_options(text.chars(), to)).unwrap().0);
+                assert_eq!(decompress::<char>(&transcoded).unwrap().iter().collect::<String>(), text);
+            }
+        }
+
+        let mut encoder = Encoder::new(Vec::new());
+        encoder.write_symbols(text.bytes()).unwrap();
+        encoder.flush_block().unwrap();
+        encoder.write_symbols(text.bytes()).unwrap();
+        let stream = encoder.finish().unwrap();
+        let transcoded = transcode::<u8>(&stream, &CompressionOptions { lz77: true, ..CompressionOptions::new() }).unwrap();
+        assert_eq!(*decompress::<u8>(&transcoded).unwrap(), *text.repeat(2).as_bytes());
+        assert!(transcoded.len() < stream.len());
+
+        let seekable = crate::seek::compress_seekable(text.bytes(), std::num::NonZeroUsize::new(8).unwrap());
+        assert_eq!(*decompress::<u8>(&transcode::<u8>(&seekable, &CompressionOptions::new()).unwrap()).unwrap(), *text.as_bytes());
+
+        // Empty streams stay empty
+        let empty = Encoder::<u8, _>::new(Vec::new()).finish().unwrap();
+        assert!(decompress::<u8>(&transcode::<u8>(&empty, &CompressionOptions::new()).unwrap()).unwrap().is_empty());
+    }
+
+
+    #[test]
+    fn check_transcode_keeps_metadata() {
+
+        let metadata = FileMetadata { name: Some("notes.txt".to_string()), modified: Some(1_700_000_000), mode: None };
+        let header = Header { metadata: metadata.clone(), ..Header::new() };
+        let compressed = crate::compress_with_header(header, "abracadabra".bytes(), &crate::codecs::TreeCodec).unwrap();
+
+        let transcoded = transcode::<u8>(&compressed, &CompressionOptions { compact_tree: true, ..CompressionOptions::new() }).unwrap();
+        assert_eq!(decompress_with_metadata::<u8>(&transcoded).unwrap(), (b"abracadabra".to_vec().into_boxed_slice(), metadata));
+    }
+
+
+    #[test]
+    fn check_transcode_errors() {
+
+        let mut compressed = compress("abracadabra".bytes()).into_vec();
+        compressed.truncate(compressed.len() - 1);
+
+        let expected = decompress::<u8>(&compressed).unwrap_err();
+        assert_eq!(transcode::<u8>(&compressed, &CompressionOptions::new()), Err(TranscodeError::Decompression(expected)));
+
+        let options = CompressionOptions { max_unique_symbols: std::num::NonZeroUsize::new(2), ..CompressionOptions::new() };
+        assert_eq!(
+            transcode::<u8>(&compress("abracadabra".bytes()), &options),
+            Err(TranscodeError::Compression(CompressError::AlphabetTooLarge { max: 2 }))
+        );
+    }
+
+}
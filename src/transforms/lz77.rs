@@ -0,0 +1,317 @@
+use std::error::Error;
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+
+/// Shortest repeated sequence replaced by a match
+pub const MIN_MATCH: usize = 3;
+
+/// Longest repeated sequence replaced by a single match
+pub const MAX_MATCH: usize = 258;
+
+/// How far back the match finder looks for repeated sequences
+pub const WINDOW_SIZE: usize = 32 * 1024;
+
+/// Maximum number of candidate positions examined for each match
+const MAX_CHAIN_LENGTH: usize = 64;
+
+const HASH_BITS: u32 = 15;
+
+/// Marks the end of a hash chain
+const NO_POSITION: usize = usize::MAX;
+
+
+/// Output unit of the LZ77 match finder
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token<U> {
+
+    /// A symbol copied verbatim
+    Literal (U),
+    /// A copy of `length` symbols starting `distance` symbols before the current position
+    Match { length: u16, distance: u32 },
+
+}
+
+
+/// Error returned when a token stream can't be expanded back into symbols
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidMatch {
+
+    /// Index of the offending token in the token stream
+    pub token: usize,
+    /// Distance of the match
+    pub distance: u32,
+    /// Number of symbols decoded before the match
+    pub available: usize,
+
+}
+
+impl fmt::Display for InvalidMatch {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "match {} has distance {} but only {} symbols precede it", self.token, self.distance, self.available)
+    }
+}
+
+impl Error for InvalidMatch {}
+
+
+/// Hash chains indexing the positions of the data by the `MIN_MATCH` symbols starting at each position
+struct HashChains {
+
+    /// Most recent position with a given prefix hash
+    head: Box<[usize]>,
+    /// Previous position with the same prefix hash as the position used as index
+    prev: Box<[usize]>,
+
+}
+
+impl HashChains {
+
+    fn new(len: usize) -> Self {
+        Self {
+            head: vec![NO_POSITION; 1 << HASH_BITS].into_boxed_slice(),
+            prev: vec![NO_POSITION; len].into_boxed_slice()
+        }
+    }
+
+
+    /// Hash the `MIN_MATCH` symbols starting at `data[pos]` into a hash table index
+    fn hash<U: Hash>(data: &[U], pos: usize) -> usize {
+
+        let mut hasher = DefaultHasher::new();
+        data[pos..pos + MIN_MATCH].hash(&mut hasher);
+
+        (hasher.finish() >> (64 - HASH_BITS)) as usize
+    }
+
+
+    fn insert<U: Hash>(&mut self, data: &[U], pos: usize) {
+
+        if pos + MIN_MATCH <= data.len() {
+            let h = Self::hash(data, pos);
+            self.prev[pos] = self.head[h];
+            self.head[h] = pos;
+        }
+    }
+
+
+    /// Find the longest match for the symbols at `pos` among the previous positions.
+    /// Return the match length and distance.
+    fn longest_match<U: Hash + Eq>(&self, data: &[U], pos: usize) -> (usize, usize) {
+
+        let mut best_length = 0;
+        let mut best_distance = 0;
+
+        if pos + MIN_MATCH > data.len() {
+            return (best_length, best_distance);
+        }
+
+        let max_length = MAX_MATCH.min(data.len() - pos);
+
+        let mut candidate = self.head[Self::hash(data, pos)];
+        let mut chain = 0;
+
+        while candidate != NO_POSITION && pos - candidate <= WINDOW_SIZE && chain < MAX_CHAIN_LENGTH {
+
+            let length = data[candidate..].iter()
+                .zip(&data[pos..pos + max_length])
+                .take_while(|(a, b)| a == b)
+                .count();
+
+            if length > best_length {
+
+                best_length = length;
+                best_distance = pos - candidate;
+
+                if length == max_length {
+                    break;
+                }
+            }
+
+            candidate = self.prev[candidate];
+            chain += 1;
+        }
+
+        (best_length, best_distance)
+    }
+
+}
+
+
+/// Split a non-zero `value` into its bit length class and the bits following its most significant bit.
+/// The class is coded with a tree and the `class - 1` extra bits are stored verbatim.
+pub const fn split_magnitude(value: u32) -> (u8, u32) {
+    let class = u32::BITS - value.leading_zeros();
+    (class as u8, value - (1 << (class - 1)))
+}
+
+
+/// Reverse `split_magnitude`. Class 0 yields 0, which is not a valid magnitude.
+pub const fn join_magnitude(class: u8, extra: u32) -> u32 {
+    if class == 0 || class as u32 > u32::BITS {
+        0
+    } else {
+        (1 << (class - 1)) + extra
+    }
+}
+
+
+/// Map a match length to a non-zero magnitude
+pub const fn length_to_magnitude(length: u16) -> u32 {
+    (length as usize - MIN_MATCH + 1) as u32
+}
+
+
+/// Reverse `length_to_magnitude`. Return `None` if the magnitude is not a valid match length.
+pub const fn magnitude_to_length(magnitude: u32) -> Option<u16> {
+    if magnitude == 0 || magnitude as usize > MAX_MATCH - MIN_MATCH + 1 {
+        None
+    } else {
+        Some((magnitude as usize + MIN_MATCH - 1) as u16)
+    }
+}
+
+
+/// Replace repeated sequences of `data` with back-references to their previous occurrence.
+/// Matches may overlap the current position, so runs of a single symbol become one literal and one match.
+pub fn encode<U>(data: &[U]) -> Vec<Token<U>>
+where
+    U: Clone + Eq + Hash
+{
+
+    let mut tokens = Vec::new();
+    let mut chains = HashChains::new(data.len());
+
+    let mut pos = 0;
+
+    while pos < data.len() {
+
+        let (length, distance) = chains.longest_match(data, pos);
+
+        if length >= MIN_MATCH {
+
+            tokens.push(Token::Match { length: length as u16, distance: distance as u32 });
+
+            for p in pos..pos + length {
+                chains.insert(data, p);
+            }
+            pos += length;
+
+        } else {
+
+            tokens.push(Token::Literal(data[pos].clone()));
+
+            chains.insert(data, pos);
+            pos += 1;
+        }
+    }
+
+    tokens
+}
+
+
+/// Expand a token stream back into the original symbols
+pub fn decode<U>(tokens: impl IntoIterator<Item = Token<U>>) -> Result<Vec<U>, InvalidMatch>
+where
+    U: Clone
+{
+
+    let mut data = Vec::new();
+
+    for (i, token) in tokens.into_iter().enumerate() {
+
+        match token {
+
+            Token::Literal (value) => data.push(value),
+
+            Token::Match { length, distance } => {
+
+                if distance == 0 || distance as usize > data.len() {
+                    return Err(InvalidMatch { token: i, distance, available: data.len() });
+                }
+
+                let start = data.len() - distance as usize;
+
+                // Copy one symbol at a time because the match may overlap the symbols it produces
+                for j in start..start + length as usize {
+                    data.push(data[j].clone());
+                }
+            },
+        }
+    }
+
+    Ok(data)
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+
+    #[test]
+    fn check_round_trip() {
+
+        let texts = [
+            "",
+            "a",
+            "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            "abcabcabcabcabcabd",
+            "the quick brown fox jumps over the lazy dog, the quick brown fox",
+        ];
+
+        for text in texts {
+
+            let chars: Vec<char> = text.chars().collect();
+
+            let tokens = encode(&chars);
+
+            assert_eq!(decode(tokens).unwrap(), chars);
+        }
+    }
+
+
+    #[test]
+    fn check_matches() {
+
+        let data = b"abcabcabcx";
+
+        assert_eq!(encode(data), [
+            Token::Literal(b'a'),
+            Token::Literal(b'b'),
+            Token::Literal(b'c'),
+            Token::Match { length: 6, distance: 3 },
+            Token::Literal(b'x'),
+        ]);
+    }
+
+
+    #[test]
+    fn check_magnitudes() {
+
+        for value in [1, 2, 3, 7, 8, 255, 256, WINDOW_SIZE as u32, u32::MAX] {
+            let (class, extra) = split_magnitude(value);
+            assert_eq!(join_magnitude(class, extra), value);
+        }
+
+        assert_eq!(split_magnitude(1), (1, 0));
+        assert_eq!(split_magnitude(6), (3, 2));
+
+        for length in MIN_MATCH..=MAX_MATCH {
+            assert_eq!(magnitude_to_length(length_to_magnitude(length as u16)), Some(length as u16));
+        }
+        assert_eq!(magnitude_to_length(0), None);
+    }
+
+
+    #[test]
+    fn check_invalid_match() {
+
+        let tokens = [Token::Literal(1_u8), Token::Match { length: 3, distance: 2 }];
+
+        assert_eq!(decode(tokens), Err(InvalidMatch { token: 1, distance: 2, available: 1 }));
+    }
+
+}
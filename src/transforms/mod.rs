@@ -0,0 +1,6 @@
+//! Reversible transforms applied to the symbols before entropy coding.
+
+pub mod lz77;
+pub mod mtf;
+pub mod newlines;
+pub mod remap;
@@ -0,0 +1,176 @@
+use std::error::Error;
+use std::fmt;
+
+
+/// Error returned when an index doesn't refer to a symbol of the alphabet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidIndex {
+
+    /// Position of the offending index in the index stream
+    pub position: usize,
+    /// The invalid index
+    pub index: u32,
+    /// Number of symbols in the alphabet
+    pub alphabet_len: usize,
+
+}
+
+impl fmt::Display for InvalidIndex {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "index {} at position {} is out of an alphabet of {} symbols", self.index, self.position, self.alphabet_len)
+    }
+}
+
+impl Error for InvalidIndex {}
+
+
+/// Move-to-front transform state over an arbitrary alphabet.
+/// Each symbol is replaced by its position in a list of recently used symbols, and then moved to the front of the list,
+/// so that locally repeated symbols become runs of small indices.
+#[derive(Debug, Clone)]
+pub struct MoveToFront<U> {
+
+    /// Symbols ordered from the most to the least recently used
+    recent: Vec<U>
+
+}
+
+impl<U> MoveToFront<U>
+where
+    U: Clone + PartialEq
+{
+
+    /// Create a transform whose initial symbol order is `alphabet`
+    pub const fn new(alphabet: Vec<U>) -> Self {
+        Self {
+            recent: alphabet
+        }
+    }
+
+
+    /// Replace `symbol` with its index and move it to the front.
+    /// Return `None` if the symbol is not in the alphabet.
+    pub fn encode_symbol(&mut self, symbol: &U) -> Option<u32> {
+
+        let index = self.recent.iter().position(|s| s == symbol)?;
+
+        self.recent[..=index].rotate_right(1);
+
+        Some(index as u32)
+    }
+
+
+    /// Replace `index` with its symbol and move the symbol to the front.
+    /// Return `None` if the index is out of the alphabet.
+    pub fn decode_index(&mut self, index: u32) -> Option<U> {
+
+        let index = index as usize;
+
+        if index >= self.recent.len() {
+            return None;
+        }
+
+        self.recent[..=index].rotate_right(1);
+
+        Some(self.recent[0].clone())
+    }
+
+}
+
+
+/// Return the distinct symbols of `data` in order of first occurrence
+pub fn alphabet_of<U>(data: &[U]) -> Vec<U>
+where
+    U: Clone + PartialEq
+{
+
+    let mut alphabet: Vec<U> = Vec::new();
+
+    for symbol in data {
+        if !alphabet.contains(symbol) {
+            alphabet.push(symbol.clone());
+        }
+    }
+
+    alphabet
+}
+
+
+/// Apply the move-to-front transform to `data`.
+/// Return the initial alphabet, which is needed for decoding, and the indices.
+pub fn encode<U>(data: &[U]) -> (Vec<U>, Vec<u32>)
+where
+    U: Clone + PartialEq
+{
+
+    let alphabet = alphabet_of(data);
+
+    let mut mtf = MoveToFront::new(alphabet.clone());
+
+    let indices = data.iter()
+        // Every symbol is in the alphabet by construction
+        .map(|symbol| mtf.encode_symbol(symbol).unwrap())
+        .collect();
+
+    (alphabet, indices)
+}
+
+
+/// Reverse the move-to-front transform, starting from the initial `alphabet`
+pub fn decode<U>(alphabet: Vec<U>, indices: impl IntoIterator<Item = u32>) -> Result<Vec<U>, InvalidIndex>
+where
+    U: Clone + PartialEq
+{
+
+    let alphabet_len = alphabet.len();
+    let mut mtf = MoveToFront::new(alphabet);
+
+    indices.into_iter()
+        .enumerate()
+        .map(|(position, index)| mtf.decode_index(index)
+            .ok_or(InvalidIndex { position, index, alphabet_len })
+        )
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+
+    #[test]
+    fn check_round_trip() {
+
+        let texts = ["", "a", "banana", "aaabbbcccaaabbbccc", "the quick brown fox jumps over the lazy dog"];
+
+        for text in texts {
+
+            let chars: Vec<char> = text.chars().collect();
+
+            let (alphabet, indices) = encode(&chars);
+
+            assert_eq!(decode(alphabet, indices).unwrap(), chars);
+        }
+    }
+
+
+    #[test]
+    fn check_indices() {
+
+        let (alphabet, indices) = encode(b"bananaaa");
+
+        assert_eq!(alphabet, b"ban");
+        assert_eq!(indices, [0, 1, 2, 1, 1, 1, 0, 0]);
+    }
+
+
+    #[test]
+    fn check_invalid_index() {
+
+        assert_eq!(decode(vec!['a', 'b'], [1, 2]), Err(InvalidIndex { position: 1, index: 2, alphabet_len: 2 }));
+    }
+
+}
@@ -0,0 +1,123 @@
+//! Newline normalization of text: `\r\n` line endings are replaced with `\n` before compression and restored after decompression,
+//! so that the `\r` of text written on Windows takes no code space.
+//!
+//! The transform only applies to bytes and characters, and only to text whose every `\n` follows a `\r`,
+//! since restoring puts a `\r` back in front of every `\n`.
+
+use crate::symbol::SymbolType;
+use crate::Symbol;
+
+
+/// The carriage return and line feed symbols of `U`, if `U` holds text
+pub(crate) fn line_break_symbols<U>() -> Option<(U, U)>
+where
+    U: Symbol
+{
+
+    let mut cr = vec![0; U::WIDTH];
+    let mut lf = vec![0; U::WIDTH];
+
+    match U::TYPE {
+        Some (SymbolType::U8) => {
+            b'\r'.to_bytes(&mut cr);
+            b'\n'.to_bytes(&mut lf);
+        },
+        Some (SymbolType::Char) => {
+            '\r'.to_bytes(&mut cr);
+            '\n'.to_bytes(&mut lf);
+        },
+        _ => return None
+    }
+
+    Some((U::from_bytes(&cr)?, U::from_bytes(&lf)?))
+}
+
+
+/// Replace the `\r\n` line endings of the text with `\n`.
+/// Return `None` if the symbols aren't text, if the text has no line ending, or if a `\n` doesn't follow a `\r`,
+/// in which case `restore` couldn't give the text back.
+pub fn normalize<U>(text: &[U]) -> Option<Vec<U>>
+where
+    U: Symbol
+{
+
+    let (cr, lf) = line_break_symbols::<U>()?;
+
+    let mut normalized = Vec::with_capacity(text.len());
+    let mut line_endings = 0;
+
+    for (i, symbol) in text.iter().enumerate() {
+
+        if *symbol == lf {
+            if i == 0 || text[i - 1] != cr {
+                return None;
+            }
+            line_endings += 1;
+        }
+
+        // The `\r` of a line ending is dropped
+        if *symbol == cr && text.get(i + 1) == Some(&lf) {
+            continue;
+        }
+
+        normalized.push(symbol.clone());
+    }
+
+    (line_endings != 0).then_some(normalized)
+}
+
+
+/// Put a `\r` back in front of every `\n`, undoing `normalize`. Symbols that aren't text are returned unchanged.
+pub fn restore<U>(text: Vec<U>) -> Vec<U>
+where
+    U: Symbol
+{
+
+    let Some((cr, lf)) = line_break_symbols::<U>() else {
+        return text;
+    };
+
+    let line_endings = text.iter().filter(|symbol| **symbol == lf).count();
+    if line_endings == 0 {
+        return text;
+    }
+
+    let mut restored = Vec::with_capacity(text.len() + line_endings);
+
+    for symbol in text {
+        if symbol == lf {
+            restored.push(cr.clone());
+        }
+        restored.push(symbol);
+    }
+
+    restored
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+
+    #[test]
+    fn check_newlines() {
+
+        let text: Vec<char> = "first\r\nsecond\r\n\r\nlone \r here\r\r\n".chars().collect();
+
+        let normalized = normalize(&text).unwrap();
+        assert_eq!(normalized.iter().collect::<String>(), "first\nsecond\n\nlone \r here\r\n");
+        assert_eq!(restore(normalized), text);
+
+        assert_eq!(normalize(b"a\r\nb"), Some(b"a\nb".to_vec()));
+
+        // Bare line feeds, text without line endings, and symbols that aren't text
+        assert_eq!(normalize(b"a\r\nb\nc"), None);
+        assert_eq!(normalize(b"\n"), None);
+        assert_eq!(normalize(b"no line endings\r"), None);
+        assert_eq!(normalize(&[13_u16, 10]), None);
+        assert_eq!(restore(vec![10_u16]), [10]);
+    }
+
+}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::Symbol;
+
+
+/// Error returned when a dense identifier doesn't refer to a symbol of the alphabet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidId {
+
+    /// Position of the offending identifier in the identifier stream
+    pub position: usize,
+    /// The invalid identifier
+    pub id: u32,
+    /// Number of symbols in the alphabet
+    pub alphabet_len: usize,
+
+}
+
+impl fmt::Display for InvalidId {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "identifier {} at position {} is out of an alphabet of {} symbols", self.id, self.position, self.alphabet_len)
+    }
+}
+
+impl Error for InvalidId {}
+
+
+/// Number of bytes of the identifiers of an alphabet of `alphabet_len` symbols
+pub const fn id_width(alphabet_len: usize) -> usize {
+    if alphabet_len <= 1 << 8 {
+        1
+    } else if alphabet_len <= 1 << 16 {
+        2
+    } else {
+        4
+    }
+}
+
+
+/// Return the alphabet of `data`, in order of first occurrence, and the dense identifier of each of its symbols,
+/// to map the symbols lazily in a later pass
+pub fn index<U>(data: impl Iterator<Item = U>) -> (Vec<U>, HashMap<U, u32>)
+where
+    U: Symbol
+{
+
+    let mut alphabet = Vec::new();
+    let mut ids = HashMap::new();
+
+    for symbol in data {
+        ids.entry(symbol).or_insert_with_key(|symbol| {
+            alphabet.push(symbol.clone());
+            alphabet.len() as u32 - 1
+        });
+    }
+
+    (alphabet, ids)
+}
+
+
+/// Replace each symbol of `data` with a dense identifier, its index in the alphabet of `data`.
+/// Return the alphabet, in order of first occurrence, and the identifiers.
+pub fn encode<U>(data: impl Iterator<Item = U>) -> (Vec<U>, Vec<u32>)
+where
+    U: Symbol
+{
+
+    let mut alphabet = Vec::new();
+    let mut ids = HashMap::new();
+
+    let encoded = data
+        .map(|symbol| *ids.entry(symbol.clone()).or_insert_with(|| {
+            alphabet.push(symbol);
+            alphabet.len() as u32 - 1
+        }))
+        .collect();
+
+    (alphabet, encoded)
+}
+
+
+/// Replace each identifier with its symbol in `alphabet`
+pub fn decode<U>(alphabet: &[U], ids: impl IntoIterator<Item = u32>) -> Result<Vec<U>, InvalidId>
+where
+    U: Clone
+{
+    ids.into_iter()
+        .enumerate()
+        .map(|(position, id)| alphabet.get(id as usize).cloned()
+            .ok_or(InvalidId { position, id, alphabet_len: alphabet.len() })
+        )
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+
+    #[test]
+    fn check_round_trip() {
+
+        let texts = ["", "a", "banana", "the quick brown fox jumps over the lazy dog"];
+
+        for text in texts {
+
+            let (alphabet, ids) = encode(text.chars());
+
+            assert_eq!(decode(&alphabet, ids).unwrap(), text.chars().collect::<Vec<_>>());
+        }
+
+        let (alphabet, ids) = encode("banana".chars());
+        assert_eq!(alphabet, ['b', 'a', 'n']);
+        assert_eq!(ids, [0, 1, 2, 1, 2, 1]);
+
+        assert_eq!(decode(&['a'], [0, 1]), Err(InvalidId { position: 1, id: 1, alphabet_len: 1 }));
+
+        let (alphabet, index) = index("banana".chars());
+        assert_eq!(alphabet, ['b', 'a', 'n']);
+        assert_eq!("banana".chars().map(|c| index[&c]).collect::<Vec<_>>(), ids);
+    }
+
+
+    #[test]
+    fn check_id_width() {
+
+        assert_eq!(id_width(1), 1);
+        assert_eq!(id_width(256), 1);
+        assert_eq!(id_width(257), 2);
+        assert_eq!(id_width(1 << 16), 2);
+        assert_eq!(id_width((1 << 16) + 1), 4);
+    }
+
+}
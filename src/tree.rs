@@ -0,0 +1,3395 @@
+//! Frequency trees: the encoding tree built from the symbol frequencies, and the decoding tree serialized in the compressed data.
+//!
+//! A serialized tree lists its nodes in pre-order.
+//! Each node starts with a specifier byte: a leaf (`0`) is followed by the `Symbol::WIDTH` bytes of its value,
+//! and a parent (`1`) is followed by its left subtree and then its right subtree.
+//! The serialized size of a tree with `n` leaves is thus `n * (1 + WIDTH) + n - 1` bytes.
+//!
+//! The compact layout stores the same specifiers as single bits instead: first the `2n - 1` specifier bits in pre-order,
+//! packed from the most significant bit and padded with zeros to a whole byte, then the values of the leaves in pre-order.
+//!
+//! The front-coded layout, meant for wide symbols, starts with the same specifier bits. The serialized values of the leaves
+//! follow in increasing byte order, each one as the number of leading bytes it shares with the previous value (varint)
+//! and its remaining bytes. Last come the positions of the leaves of the pre-order in that table, `ceil(log2(n))` bits each,
+//! packed from the most significant bit and padded with zeros to a whole byte.
+//!
+//! The canonical layout doesn't depend on the shape of the tree: it stores the number of leaves (varint), then the leaves
+//! in the canonical order of their values, each as the `Symbol::WIDTH` bytes of its value and its code length in bits (1 byte).
+//! The tree is rebuilt with canonical codes: ordered by length and then by value, each code is the previous one plus one,
+//! shifted left by the difference of their lengths, like `DecodingTree::from_code_lengths` does. Encode with `DecodingTree::to_canonical`
+//! so that the codes match.
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::num::NonZeroUsize;
+use std::ops::RangeInclusive;
+use std::sync::OnceLock;
+use std::thread;
+
+use crate::bits::math::{bit_mask, byte_offset, least_bytes_repr_for_bits, padding_bits};
+use crate::bits::{BitIterator, BitVec, BitView, BitWriter};
+use crate::container::{read_varint, write_varint};
+use crate::symbol::canonical_key;
+use crate::Symbol;
+
+
+/// Type of a serialized node, written in the first byte of each node
+#[repr(u8)]
+pub(crate) enum SerialSpecifier {
+
+    Leaf,
+    Parent,
+
+}
+
+impl SerialSpecifier {
+
+    /// All the valid serialized specifier values
+    pub(crate) const VALID: &'static [u8] = &[Self::Leaf as u8, Self::Parent as u8];
+
+    /// Specifier values reserved for node kinds of later format versions, such as escape leaves and references to the trees of dictionaries
+    pub(crate) const RESERVED: RangeInclusive<u8> = 2..=15;
+
+
+    /// Error for the invalid specifier `found` at byte `offset`, telling reserved values apart from corrupted ones
+    fn error(offset: usize, found: u8) -> NodeDeserializationError {
+        if Self::RESERVED.contains(&found) {
+            NodeDeserializationError::ReservedNodeTypeSpecifier { offset, found }
+        } else {
+            NodeDeserializationError::InvalidNodeTypeSpecifier { offset, found, expected: Self::VALID }
+        }
+    }
+
+}
+
+impl TryFrom<u8> for SerialSpecifier {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Leaf),
+            1 => Ok(Self::Parent),
+            _ => Err(value)
+        }
+    }
+}
+
+
+#[derive(Debug, Clone)]
+enum Node<U> {
+
+    Parent { count: usize, left: Box<Node<U>>, right: Box<Node<U>> },
+    Leaf { count: usize, value: U },
+
+}
+
+impl<U> PartialEq for Node<U>
+where
+    U: Clone + PartialEq
+{
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+
+            (Self::Parent { left: l_left, right: l_right, .. }, Self::Parent { left: r_left, right: r_right, .. }) => l_left == r_left && l_right == r_right,
+
+            (Self::Leaf { value: l_value, .. }, Self::Leaf { value: r_value, .. }) => l_value == r_value,
+
+            _ => false,
+        }
+    }
+}
+
+impl<U> Eq for Node<U>
+where
+    U: Clone + Eq
+{}
+
+/// Hash the structure and the leaf values, ignoring the counts, consistently with `PartialEq`
+impl<U> Hash for Node<U>
+where
+    U: Hash
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+
+            Self::Parent { left, right, .. } => {
+                state.write_u8(SerialSpecifier::Parent as u8);
+                left.hash(state);
+                right.hash(state);
+            },
+
+            Self::Leaf { value, .. } => {
+                state.write_u8(SerialSpecifier::Leaf as u8);
+                value.hash(state);
+            },
+        }
+    }
+}
+
+impl<U> Node<U>
+where
+    U: fmt::Debug
+{
+
+    /// Write `value: code` for every leaf below this node, from left to right.
+    /// `code` is the code of this node, and `first` tells whether a leaf was already written.
+    fn fmt_codes(&self, code: Code, first: &mut bool, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+
+        match self {
+
+            Node::Parent { left, right, .. } => {
+                left.fmt_codes(code.step_left(), first, f)?;
+                right.fmt_codes(code.step_right(), first, f)
+            },
+
+            Node::Leaf { value, .. } => {
+                if !mem::take(first) {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{value:?}: {code}")
+            },
+        }
+    }
+}
+
+impl<U> Node<U>
+where
+    U: Symbol
+{
+
+    pub const fn count(&self) -> usize {
+        match self {
+            Node::Parent { count, .. } |
+            Node::Leaf { count, .. }
+                => *count
+        }
+    }
+
+
+    pub fn insert(&mut self, freq: usize, insert_value: U) {
+
+        match self {
+
+            Node::Parent { count, left, right } => {
+
+                if right.count() > left.count() {
+                    left.insert(freq, insert_value);
+                } else {
+                    right.insert(freq, insert_value);
+                }
+
+                *count += freq;
+            },
+
+            Node::Leaf { count, value } => {
+
+                *self = Node::Parent {
+                    count: *count + freq,
+                    left: Box::new(Node::Leaf { count: *count, value: value.clone() }),
+                    right: Box::new(Node::Leaf { count: freq, value: insert_value })
+                };
+            },
+
+        }
+    }
+
+
+    /// Insert the code of every leaf below this node into `table`.
+    /// `code` is the code of this node.
+    pub fn collect_codes(&self, code: Code, table: &mut HashMap<U, Code>) {
+
+        match self {
+
+            Node::Parent { left, right, .. } => {
+                left.collect_codes(code.step_left(), table);
+                right.collect_codes(code.step_right(), table);
+            },
+
+            Node::Leaf { value, .. } => {
+                table.insert(value.clone(), code);
+            },
+        }
+    }
+
+
+    /// Copy this node and its descendants, converting the value of every leaf with `f`
+    fn map<V>(&self, f: &impl Fn(&U) -> V) -> Node<V> {
+        match self {
+            Node::Parent { count, left, right } => Node::Parent { count: *count, left: Box::new(left.map(f)), right: Box::new(right.map(f)) },
+            Node::Leaf { count, value } => Node::Leaf { count: *count, value: f(value) },
+        }
+    }
+
+
+    /// Replace the counts of the leaves below this node with the next `counts`, in pre-order,
+    /// and set the count of each parent to the sum of its children. Return the count of this node.
+    fn set_counts(&mut self, counts: &mut impl Iterator<Item = usize>) -> usize {
+        match self {
+
+            Node::Parent { count, left, right } => {
+                *count = left.set_counts(counts).saturating_add(right.set_counts(counts));
+                *count
+            },
+
+            Node::Leaf { count, .. } => {
+                *count = counts.next().unwrap_or(0);
+                *count
+            },
+        }
+    }
+
+
+    /// Build the node at `depth` from the canonical `(code, length, value, count)` of the leaves below it, sorted by code.
+    /// The lengths must describe a complete tree.
+    fn from_canonical(leaves: &[(u128, u8, &U, usize)], depth: u8) -> Self {
+
+        if let [(_, length, value, count)] = leaves {
+            if *length == depth {
+                return Node::Leaf { count: *count, value: (*value).clone() };
+            }
+        }
+
+        // The codes are sorted, so the leaves of the left subtree come first
+        let split = leaves.partition_point(|(code, length, ..)| code >> (length - 1 - depth) & 1 == 0);
+
+        let left = Self::from_canonical(&leaves[..split], depth + 1);
+        let right = Self::from_canonical(&leaves[split..], depth + 1);
+
+        Node::Parent {
+            count: left.count().saturating_add(right.count()),
+            left: Box::new(left),
+            right: Box::new(right)
+        }
+    }
+
+
+    /// Length of the shortest code below this node, relative to it
+    fn min_depth(&self) -> usize {
+        match self {
+            Node::Parent { left, right, .. } => 1 + left.min_depth().min(right.min_depth()),
+            Node::Leaf { .. } => 0,
+        }
+    }
+
+
+    /// Push the value, depth, and count of every leaf below this node to `leaves`, from left to right.
+    /// `depth` is the depth of this node.
+    fn collect_leaves<'a>(&'a self, depth: u8, leaves: &mut Vec<(&'a U, u8, usize)>) {
+
+        match self {
+
+            Node::Parent { left, right, .. } => {
+                left.collect_leaves(depth + 1, leaves);
+                right.collect_leaves(depth + 1, leaves);
+            },
+
+            Node::Leaf { value, count } => {
+                leaves.push((value, depth, *count));
+            },
+        }
+    }
+
+
+    /// Deserialize the node starting at byte `offset` of `buf`, at `depth` in the tree.
+    /// `seen` holds the values of the leaves deserialized before, which can't appear again.
+    /// Return the node and the number of bytes read.
+    pub fn deserialize(buf: &[u8], offset: usize, depth: usize, seen: &mut HashSet<U>) -> Result<(Self, usize), NodeDeserializationError> {
+
+        let specifier = *buf.get(offset)
+            .ok_or(NodeDeserializationError::MissingNodeTypeSpecifier { offset })?;
+
+        match SerialSpecifier::try_from(specifier)
+            .map_err(|found| SerialSpecifier::error(offset, found))?
+        {
+
+            SerialSpecifier::Leaf => {
+
+                let data_offset = offset + 1;
+                let remaining = buf.len() - data_offset;
+
+                if remaining < U::WIDTH {
+                    return Err(NodeDeserializationError::MissingNodeUnitData { offset: data_offset, expected: U::WIDTH, remaining });
+                }
+
+                let value = U::from_bytes(&buf[data_offset..data_offset + U::WIDTH])
+                    .ok_or(NodeDeserializationError::InvalidNodeUnitData { offset: data_offset })?;
+
+                if !seen.insert(value.clone()) {
+                    return Err(NodeDeserializationError::DuplicateLeafValue { offset: data_offset });
+                }
+
+                Ok((
+                    Self::Leaf {
+                        count: 0, // The count won't be needed during decoding.
+                        value
+                    },
+                    1 + U::WIDTH
+                ))
+            },
+
+            SerialSpecifier::Parent => {
+
+                if depth >= Code::MAX_LEN {
+                    return Err(NodeDeserializationError::TreeTooDeep { offset, max_depth: Code::MAX_LEN });
+                }
+
+                let (left, read1) = Self::deserialize(buf, offset + 1, depth + 1, seen)?;
+                let (right, read2) = Self::deserialize(buf, offset + 1 + read1, depth + 1, seen)?;
+
+                Ok((
+                    Self::Parent {
+                        count: 0, // The count won't be needed during decoding.
+                        left: Box::new(left),
+                        right: Box::new(right)
+                    },
+                    1 + read1 + read2
+                ))
+            },
+
+        }
+
+    }
+
+
+    /// Append the specifier bits of this node and its descendants to `structure`, and the leaf values to `values`, in pre-order
+    fn serialize_compact(&self, structure: &mut BitVec, values: &mut Vec<u8>) {
+
+        match self {
+
+            Node::Parent { left, right, .. } => {
+
+                structure.append_bit(SerialSpecifier::Parent as u8 != 0);
+
+                left.serialize_compact(structure, values);
+                right.serialize_compact(structure, values);
+            },
+
+            Node::Leaf { value, .. } => {
+
+                structure.append_bit(SerialSpecifier::Leaf as u8 != 0);
+
+                let start = values.len();
+                values.resize(start + U::WIDTH, 0);
+                value.to_bytes(&mut values[start..]);
+            },
+        }
+    }
+
+
+    /// Build the node at `depth` in the tree from the specifier bits of `structure`, which must describe a complete tree,
+    /// and the leaf values starting at byte `*values_offset` of `buf`.
+    /// `structure_offset` is the byte offset of the specifier bits, for error reporting.
+    fn deserialize_compact(
+        structure: &mut BitIterator,
+        structure_offset: usize,
+        buf: &[u8],
+        values_offset: &mut usize,
+        depth: usize,
+        seen: &mut HashSet<U>
+    ) -> Result<Self, NodeDeserializationError> {
+
+        if structure.next().unwrap() {
+
+            if depth >= Code::MAX_LEN {
+                return Err(NodeDeserializationError::TreeTooDeep { offset: structure_offset, max_depth: Code::MAX_LEN });
+            }
+
+            let left = Self::deserialize_compact(structure, structure_offset, buf, values_offset, depth + 1, seen)?;
+            let right = Self::deserialize_compact(structure, structure_offset, buf, values_offset, depth + 1, seen)?;
+
+            Ok(Self::Parent {
+                count: 0, // The count won't be needed during decoding.
+                left: Box::new(left),
+                right: Box::new(right)
+            })
+
+        } else {
+
+            let offset = *values_offset;
+            let remaining = buf.len().saturating_sub(offset);
+
+            if remaining < U::WIDTH {
+                return Err(NodeDeserializationError::MissingNodeUnitData { offset, expected: U::WIDTH, remaining });
+            }
+
+            let value = U::from_bytes(&buf[offset..offset + U::WIDTH])
+                .ok_or(NodeDeserializationError::InvalidNodeUnitData { offset })?;
+
+            if !seen.insert(value.clone()) {
+                return Err(NodeDeserializationError::DuplicateLeafValue { offset });
+            }
+
+            *values_offset += U::WIDTH;
+
+            Ok(Self::Leaf {
+                count: 0, // The count won't be needed during decoding.
+                value
+            })
+        }
+    }
+
+
+    pub fn serialize(&self, buf: &mut Vec<u8>) {
+
+        // Don't include the count field in the serialized data because it won't be needed during decoding.
+        match self {
+
+            Node::Parent { left, right, .. } => {
+
+                buf.push(SerialSpecifier::Parent as u8);
+
+                left.serialize(buf);
+                right.serialize(buf);
+            },
+
+            Node::Leaf { value, .. } => {
+
+                buf.push(SerialSpecifier::Leaf as u8);
+
+                let start = buf.len();
+                buf.resize(start + U::WIDTH, 0);
+                value.to_bytes(&mut buf[start..]);
+            },
+        }
+    }
+
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodingError {
+
+    /// The bitcode contains an incomplete or invalid code that starts at bit `bit_offset`
+    InvalidEncoding { bit_offset: usize },
+    /// The bitcode claims more symbols than can be allocated
+    TooManySymbols { count: usize },
+    /// The bitcode doesn't encode the expected number of symbols
+    CountMismatch { expected: usize, found: usize },
+
+}
+
+impl fmt::Display for DecodingError {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodingError::InvalidEncoding { bit_offset }
+                => write!(f, "invalid code starting at bit {bit_offset}"),
+            DecodingError::TooManySymbols { count }
+                => write!(f, "{count} symbols can't be allocated"),
+            DecodingError::CountMismatch { expected, found }
+                => write!(f, "expected {expected} symbols, found {found}"),
+        }
+    }
+}
+
+impl Error for DecodingError {}
+
+
+/// Reserve room in `symbols` for `count` more symbols decoded with a single-leaf tree.
+/// Its only value is encoded with zero bits, so the count isn't backed by any bit and can't be trusted to fit in memory:
+/// fail with `TooManySymbols` instead of aborting if it doesn't.
+pub(crate) fn reserve_single_leaf<T>(symbols: &mut Vec<T>, count: usize) -> Result<(), DecodingError> {
+    symbols.try_reserve_exact(count).map_err(|_| DecodingError::TooManySymbols { count })
+}
+
+
+/// Code of a value in the tree: the path from the root to the value's leaf, where `0` steps left and `1` steps right.
+/// Codes are ordered like canonical codes are assigned: by length, and then by value.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Code {
+
+    /// The actual encoded value, stored as bytes in bit order: the first step is the most significant bit of the first byte
+    bits: [u8; 8],
+
+    /// How many bits have meaning
+    meaningful: u8
+
+}
+
+impl Code {
+
+    /// Maximum number of bits of a code, which is also the maximum depth of a tree
+    pub const MAX_LEN: usize = 64;
+
+
+    /// Create an empty code, which is the code of the root
+    pub const fn new() -> Self {
+        Self {
+            bits: [0; 8],
+            meaningful: 0
+        }
+    }
+
+
+    /// Create the code of the `len` least significant bits of `value`, the most significant of them first.
+    /// The other bits of `value` are ignored.
+    ///
+    /// Panics if `len` is greater than `MAX_LEN`.
+    pub const fn from_bits(value: u64, len: usize) -> Self {
+
+        assert!(len <= Self::MAX_LEN, "codes have at most 64 bits");
+
+        Self {
+            bits: if len == 0 { [0; 8] } else { (value << (Self::MAX_LEN - len)).to_be_bytes() },
+            meaningful: len as u8
+        }
+    }
+
+
+    /// The bits of the code as an integer, the first bit being the most significant one, so that `from_bits` gives the code back
+    pub const fn to_bits(&self) -> u64 {
+        if self.meaningful == 0 { 0 } else { self.as_word() >> (Self::MAX_LEN - self.meaningful as usize) }
+    }
+
+
+    /// Append a `0` bit, stepping to the left child
+    ///
+    /// Panics if the code already has `MAX_LEN` bits.
+    pub fn push_left(&mut self) {
+        assert!(self.len() < Self::MAX_LEN, "codes have at most 64 bits");
+        *self = self.step_left();
+    }
+
+
+    /// Append a `1` bit, stepping to the right child
+    ///
+    /// Panics if the code already has `MAX_LEN` bits.
+    pub fn push_right(&mut self) {
+        assert!(self.len() < Self::MAX_LEN, "codes have at most 64 bits");
+        *self = self.step_right();
+    }
+
+
+    pub(crate) const fn step_left(&self) -> Self {
+        // No operation is necessary because on a well-formed steps argument the uninitialized bits are already 0
+        Self {
+            bits: self.bits,
+            meaningful: self.meaningful + 1
+        }
+    }
+
+
+    pub(crate) const fn step_right(&self) -> Self {
+        let mut bits = self.bits;
+        bits[byte_offset(self.meaningful as usize)] |= bit_mask(self.meaningful as usize);
+        Self {
+            bits,
+            meaningful: self.meaningful + 1
+        }
+    }
+
+
+    /// Number of bits of the code
+    pub const fn len(&self) -> usize {
+        self.meaningful as usize
+    }
+
+
+    /// Whether the code has no bits, which happens for the only value of a single-leaf tree
+    pub const fn is_empty(&self) -> bool {
+        self.meaningful == 0
+    }
+
+
+    pub fn iter_bits(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.meaningful as usize)
+            .map(|i| (self.bits[byte_offset(i)] & bit_mask(i)) != 0)
+    }
+
+
+    /// The bits of the code in the most significant bits of a word, followed by zeros, as written by `BitWriter::write_bits`
+    pub(crate) const fn as_word(&self) -> u64 {
+        u64::from_be_bytes(self.bits)
+    }
+
+
+    pub fn as_bits(&self) -> BitView<'_> {
+        BitView::from_padded_bytes(
+            &self.bits[0..least_bytes_repr_for_bits(self.meaningful as usize)],
+            padding_bits(self.meaningful as usize)
+        )
+    }
+
+}
+
+impl Default for Code {
+
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ord for Code {
+
+    fn cmp(&self, other: &Self) -> Ordering {
+        // The unused bits are zero, so the words of codes of the same length compare like their values
+        (self.meaningful, self.as_word()).cmp(&(other.meaningful, other.as_word()))
+    }
+}
+
+impl PartialOrd for Code {
+
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Write the code as a string of `0` and `1`, which is empty for the only value of a single-leaf tree
+impl fmt::Display for Code {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.iter_bits().try_for_each(|bit| write!(f, "{}", bit as u8))
+    }
+}
+
+
+/// Encoder that looks up the codes of a decoding tree in a table
+#[derive(Debug, Clone)]
+pub struct Encoder<U> {
+
+    table: HashMap<U, Code>
+
+}
+
+impl<U> Encoder<U>
+where
+    U: Symbol
+{
+
+    /// Code of `value`, or `None` if the value is not in the tree
+    pub fn code(&self, value: &U) -> Option<&Code> {
+        self.table.get(value)
+    }
+
+
+    /// Encode the values of `data`.
+    /// Fail if a value is not in the tree, since it can't be encoded.
+    pub fn encode(&self, data: impl IntoIterator<Item = U>) -> Result<BitVec, UnknownValue> {
+        try_encode_with_table(&self.table, data.into_iter(), 0)
+    }
+
+}
+
+
+/// Error returned when a value to encode is not in the tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownValue {
+
+    /// Position of the value in the encoded data
+    pub position: usize,
+
+}
+
+impl fmt::Display for UnknownValue {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the value at position {} is not in the tree", self.position)
+    }
+}
+
+impl Error for UnknownValue {}
+
+
+/// Error returned when the frequencies a tree is built from list a value twice, as when the entries of two frequency tables
+/// are concatenated instead of merged. The tree would have two leaves, and two codes, for the value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DuplicateValue {
+
+    /// Position of the first entry of the value in the frequencies
+    pub first: usize,
+    /// Position of the entry that repeats it
+    pub second: usize,
+
+}
+
+impl fmt::Display for DuplicateValue {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the value at position {} of the frequencies is already at position {}", self.second, self.first)
+    }
+}
+
+impl Error for DuplicateValue {}
+
+
+/// Tree specified by hand, for fixed code tables such as the ones of existing formats, built from the leaves up.
+/// The code of a leaf is its path from the root, with `0` for a left branch and `1` for a right one.
+#[derive(Debug, Clone)]
+pub struct TreeBuilder<U> {
+
+    root: Node<U>,
+
+}
+
+impl<U> TreeBuilder<U>
+where
+    U: Symbol
+{
+
+    pub const fn leaf(value: U) -> Self {
+        Self { root: Node::Leaf { count: 0, value } }
+    }
+
+
+    pub fn parent(left: Self, right: Self) -> Self {
+        Self { root: Node::Parent { count: 0, left: Box::new(left.root), right: Box::new(right.root) } }
+    }
+
+
+    /// Check that every value has a single leaf and that every code fits in a `Code`, then build the tree.
+    pub fn build(self) -> Result<DecodingTree<U>, TreeBuildError> {
+
+        // Leaf positions from the left, and the depth of the nodes left to visit
+        let mut positions: HashMap<&U, usize> = HashMap::new();
+        let mut stack = vec![(&self.root, 0)];
+
+        while let Some((node, depth)) = stack.pop() {
+
+            if depth > Code::MAX_LEN {
+                return Err(TreeBuildError::TreeTooDeep { max_depth: Code::MAX_LEN });
+            }
+
+            match node {
+                Node::Parent { left, right, .. } => {
+                    stack.push((right, depth + 1));
+                    stack.push((left, depth + 1));
+                },
+                Node::Leaf { value, .. } => {
+                    let position = positions.len();
+                    if let Some(&first) = positions.get(value) {
+                        return Err(TreeBuildError::DuplicateValue { first, second: position });
+                    }
+                    positions.insert(value, position);
+                }
+            }
+        }
+
+        Ok(DecodingTree::from_root(self.root))
+    }
+
+}
+
+
+/// Error returned when a hand-specified tree can't be used for coding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeBuildError {
+
+    /// The leaves at positions `first` and `second`, counting from the left, have the same value
+    DuplicateValue { first: usize, second: usize },
+    /// A leaf is deeper than the longest code
+    TreeTooDeep { max_depth: usize },
+
+}
+
+impl fmt::Display for TreeBuildError {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DuplicateValue { first, second }
+                => write!(f, "the leaf at position {second} has the same value as the leaf at position {first}"),
+            Self::TreeTooDeep { max_depth }
+                => write!(f, "a leaf is deeper than the maximum depth of {max_depth}")
+        }
+    }
+}
+
+impl Error for TreeBuildError {}
+
+
+/// Error returned when code lengths don't describe a tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeLengthError {
+
+    /// There are no code lengths
+    NoValues,
+    /// The entries at positions `first` and `second` have the same value
+    DuplicateValue { first: usize, second: usize },
+    /// The length of the entry at `position` is 0 while there are other values, not 0 while there are none, or longer than the longest code
+    InvalidLength { position: usize },
+    /// The codes are too short for the number of values, so some codes would be prefixes of others
+    Oversubscribed,
+    /// The codes are longer than needed, so some bit sequences wouldn't decode to any value
+    Incomplete,
+
+}
+
+impl fmt::Display for CodeLengthError {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoValues
+                => write!(f, "there are no code lengths"),
+            Self::DuplicateValue { first, second }
+                => write!(f, "the value at position {second} of the code lengths is already at position {first}"),
+            Self::InvalidLength { position }
+                => write!(f, "invalid code length at position {position}"),
+            Self::Oversubscribed
+                => write!(f, "the code lengths are too short for the number of values"),
+            Self::Incomplete
+                => write!(f, "the code lengths don't describe a complete tree")
+        }
+    }
+}
+
+impl Error for CodeLengthError {}
+
+
+/// Read-only view of a node of a tree, for tooling that needs to inspect the tree shape
+#[derive(Debug, Clone, Copy)]
+pub struct NodeRef<'a, U>(&'a Node<U>);
+
+impl<'a, U> NodeRef<'a, U> {
+
+    /// Value of the node if it's a leaf
+    pub const fn value(&self) -> Option<&'a U> {
+        match self.0 {
+            Node::Leaf { value, .. } => Some(value),
+            Node::Parent { .. } => None
+        }
+    }
+
+
+    /// Left and right children of the node if it's a parent
+    pub fn children(&self) -> Option<(NodeRef<'a, U>, NodeRef<'a, U>)> {
+        match self.0 {
+            Node::Parent { left, right, .. } => Some((NodeRef(left), NodeRef(right))),
+            Node::Leaf { .. } => None
+        }
+    }
+
+
+    pub const fn is_leaf(&self) -> bool {
+        matches!(self.0, Node::Leaf { .. })
+    }
+
+
+    /// Total frequency of the leaves below the node.
+    /// Deserialized trees don't store frequencies, so their count is always 0.
+    pub const fn count(&self) -> usize {
+        match self.0 {
+            Node::Parent { count, .. } |
+            Node::Leaf { count, .. }
+                => *count
+        }
+    }
+
+}
+
+
+/// An iterator over the values of a `DecodingTree` and their codes, in the order of the leaves from left to right
+#[derive(Debug, Clone)]
+pub struct Codes<'a, U> {
+
+    /// Nodes left to visit, with their codes, the next one on top
+    stack: Vec<(&'a Node<U>, Code)>,
+
+}
+
+impl<'a, U> Iterator for Codes<'a, U> {
+    type Item = (&'a U, Code);
+
+    fn next(&mut self) -> Option<Self::Item> {
+
+        while let Some((node, code)) = self.stack.pop() {
+            match node {
+                Node::Parent { left, right, .. } => {
+                    self.stack.push((right, code.step_right()));
+                    self.stack.push((left, code.step_left()));
+                },
+                Node::Leaf { value, .. } => return Some((value, code))
+            }
+        }
+
+        None
+    }
+}
+
+
+/// An iterator over the values of a `DecodingTree` and their codes that consumes the tree
+#[derive(Debug)]
+pub struct IntoCodes<U> {
+
+    /// Nodes left to visit, with their codes, the next one on top
+    stack: Vec<(Node<U>, Code)>,
+
+}
+
+impl<U> Iterator for IntoCodes<U> {
+    type Item = (U, Code);
+
+    fn next(&mut self) -> Option<Self::Item> {
+
+        while let Some((node, code)) = self.stack.pop() {
+            match node {
+                Node::Parent { left, right, .. } => {
+                    self.stack.push((*right, code.step_right()));
+                    self.stack.push((*left, code.step_left()));
+                },
+                Node::Leaf { value, .. } => return Some((value, code))
+            }
+        }
+
+        None
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DecodingTree<U: Clone> {
+
+    root: Node<U>,
+
+    /// Code of every value, built on the first lookup by `code_of`
+    codes: OnceLock<HashMap<U, Code>>,
+
+    /// Flattened nodes, built on the first decoding
+    flat: OnceLock<FlatTree<U>>,
+
+}
+
+
+/// Marks a child of `FlatTree` as a leaf, whose other bits are the index of its value
+const LEAF_MARKER: usize = 1 << (usize::BITS - 1);
+
+
+/// Nodes of a decoding tree flattened into arrays, so that decoding a bit is an array lookup
+/// instead of following a pointer and matching on the node variant
+#[derive(Debug, Clone)]
+struct FlatTree<U> {
+
+    /// Children of every parent node, indexed by the bit that leads to them. The root is the first pair.
+    /// A child is either the index of a parent pair, or the index of a value with `LEAF_MARKER` set.
+    pairs: Box<[[usize; 2]]>,
+
+    /// Values of the leaves, in pre-order
+    values: Box<[U]>,
+
+}
+
+impl<U> FlatTree<U>
+where
+    U: Clone
+{
+
+    fn new(root: &Node<U>) -> Self {
+
+        let mut pairs = Vec::new();
+        let mut values = Vec::new();
+
+        Self::flatten(root, &mut pairs, &mut values);
+
+        Self {
+            pairs: pairs.into_boxed_slice(),
+            values: values.into_boxed_slice()
+        }
+    }
+
+
+    /// Append `node` and its descendants, and return the child entry that refers to it
+    fn flatten(node: &Node<U>, pairs: &mut Vec<[usize; 2]>, values: &mut Vec<U>) -> usize {
+        match node {
+
+            Node::Parent { left, right, .. } => {
+                let index = pairs.len();
+                pairs.push([0; 2]);
+                pairs[index] = [
+                    Self::flatten(left, pairs, values),
+                    Self::flatten(right, pairs, values)
+                ];
+                index
+            },
+
+            Node::Leaf { value, .. } => {
+                values.push(value.clone());
+                (values.len() - 1) | LEAF_MARKER
+            },
+        }
+    }
+
+}
+
+/// Decoding tree stored as borrowed arrays of node pairs and leaf values, which can be built and used in `const` contexts.
+///
+/// A tree embedded in the source, for example by a build script that writes the output of `DecodingTree::to_rust_static`,
+/// lives in `static` memory and decodes into a caller-provided buffer without allocating.
+///
+/// The pairs hold the children of every parent node, indexed by the bit that leads to them, and the root is the first pair.
+/// A child is either the index of a later pair, or the index of a value with the most significant bit set.
+/// A single-leaf tree has no pairs and one value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StaticDecodingTree<'a, U> {
+
+    pairs: &'a [[usize; 2]],
+
+    values: &'a [U],
+
+}
+
+impl<'a, U> StaticDecodingTree<'a, U>
+where
+    U: Copy
+{
+
+    /// Marks a child as a leaf, whose other bits are the index of its value
+    pub const LEAF_MARKER: usize = LEAF_MARKER;
+
+
+    /// Construct a tree from its node pairs and leaf values.
+    ///
+    /// Panics, or fails to compile in a `const` context, if a child is out of bounds or refers to a pair that isn't after its parent,
+    /// or if a single-leaf tree doesn't have exactly one value.
+    pub const fn new(pairs: &'a [[usize; 2]], values: &'a [U]) -> Self {
+
+        assert!(!pairs.is_empty() || values.len() == 1, "a tree without parent nodes must have exactly one value");
+
+        let mut i = 0;
+        while i < pairs.len() {
+
+            let mut bit = 0;
+            while bit < 2 {
+
+                let child = pairs[i][bit];
+                if child & LEAF_MARKER != 0 {
+                    assert!(child & !LEAF_MARKER < values.len(), "leaf value index out of bounds");
+                } else {
+                    // Children after their parent make the structure acyclic, so decoding always reaches a leaf
+                    assert!(child > i && child < pairs.len(), "parent index out of bounds or not after its parent");
+                }
+                bit += 1;
+            }
+            i += 1;
+        }
+
+        Self { pairs, values }
+    }
+
+
+    /// Children of every parent node
+    pub const fn pairs(&self) -> &'a [[usize; 2]] {
+        self.pairs
+    }
+
+
+    /// Values of the leaves
+    pub const fn values(&self) -> &'a [U] {
+        self.values
+    }
+
+
+    /// Decode `bitcode` into `out` and return the number of decoded values.
+    /// Return an error if the bitcode is invalid or decodes to more values than `out` can hold.
+    ///
+    /// The only value of a single-leaf tree is encoded with zero bits, so an empty bitcode decodes to one copy of it.
+    pub const fn decode_into(&self, bitcode: &BitView, out: &mut [U]) -> Result<usize, DecodingError> {
+
+        let (bytes, _) = bitcode.as_padded_bytes();
+        let first_bit_offset = bitcode.first_bit_offset() as usize;
+        let len = bitcode.len_bits();
+
+        if self.pairs.is_empty() {
+
+            if len != 0 {
+                return Err(DecodingError::InvalidEncoding { bit_offset: 0 });
+            }
+            if out.is_empty() {
+                return Err(DecodingError::CountMismatch { expected: 0, found: 1 });
+            }
+
+            out[0] = self.values[0];
+            return Ok(1);
+        }
+
+        let mut node = 0;
+        let mut code_start = 0;
+        let mut found = 0;
+
+        let mut i = 0;
+        while i < len {
+
+            let bit_i = i + first_bit_offset;
+            let bit = (bytes[bit_i / 8] >> (7 - bit_i % 8)) & 1;
+            let child = self.pairs[node][bit as usize];
+
+            if child & LEAF_MARKER != 0 {
+
+                // Keep counting past the end of `out` to report the number of values in the error
+                if found < out.len() {
+                    out[found] = self.values[child & !LEAF_MARKER];
+                }
+                found += 1;
+                code_start = i + 1;
+                node = 0;
+
+            } else {
+                node = child;
+            }
+            i += 1;
+        }
+
+        if node != 0 {
+            return Err(DecodingError::InvalidEncoding { bit_offset: code_start });
+        }
+        if found > out.len() {
+            return Err(DecodingError::CountMismatch { expected: out.len(), found });
+        }
+
+        Ok(found)
+    }
+
+}
+
+
+/// Trees are equal if they have the same structure and leaf values, regardless of the cached codes
+impl<U> PartialEq for DecodingTree<U>
+where
+    U: Clone + PartialEq
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.root == other.root
+    }
+}
+
+impl<U> Eq for DecodingTree<U>
+where
+    U: Clone + Eq
+{}
+
+impl<U> Hash for DecodingTree<U>
+where
+    U: Clone + Hash
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.root.hash(state);
+    }
+}
+
+/// Write the code of every value from the leftmost to the rightmost leaf, like `{'e': 00, 't': 01, 'a': 1}`
+impl<U> fmt::Display for DecodingTree<U>
+where
+    U: Clone + fmt::Debug
+{
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        self.root.fmt_codes(Code::new(), &mut true, f)?;
+        write!(f, "}}")
+    }
+}
+
+impl<'a, U> IntoIterator for &'a DecodingTree<U>
+where
+    U: Clone
+{
+    type Item = (&'a U, Code);
+    type IntoIter = Codes<'a, U>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Codes { stack: vec![(&self.root, Code::new())] }
+    }
+}
+
+
+impl<U> IntoIterator for DecodingTree<U>
+where
+    U: Clone
+{
+    type Item = (U, Code);
+    type IntoIter = IntoCodes<U>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoCodes { stack: vec![(self.root, Code::new())] }
+    }
+}
+
+
+impl<U> DecodingTree<U>
+where
+    U: Symbol
+{
+
+    const fn from_root(root: Node<U>) -> Self {
+        Self {
+            root,
+            codes: OnceLock::new(),
+            flat: OnceLock::new()
+        }
+    }
+
+
+    fn flat(&self) -> &FlatTree<U> {
+        self.flat.get_or_init(|| FlatTree::new(&self.root))
+    }
+
+
+    /// Return the only value of the tree if the root is a leaf.
+    /// Such values are encoded with zero bits, so the decoder can't count them.
+    pub(crate) fn single_value(&self) -> Option<&U> {
+        if let Node::Leaf { value, .. } = &self.root {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+
+    /// Value and count of every leaf, in pre-order.
+    /// The counts are those of the frequencies the tree was built from, or 0 if it was deserialized.
+    pub(crate) fn leaf_counts(&self) -> Vec<(&U, usize)> {
+
+        let mut leaves = Vec::new();
+        self.root.collect_leaves(0, &mut leaves);
+
+        leaves.into_iter().map(|(value, _, count)| (value, count)).collect()
+    }
+
+
+    /// Size in bits of the bitcode of the frequencies the tree was built from, the sum of their code lengths.
+    /// It's 0 if the tree was deserialized, since its leaves have no count.
+    pub(crate) fn encoded_size_bits(&self) -> u64 {
+
+        let mut leaves = Vec::new();
+        self.root.collect_leaves(0, &mut leaves);
+
+        leaves.into_iter()
+            .map(|(_, length, count)| length as u64 * count as u64)
+            .sum()
+    }
+
+
+    /// Replace the counts of the leaves with `counts`, in pre-order. Missing counts are set to 0.
+    pub(crate) fn set_leaf_counts(&mut self, counts: impl IntoIterator<Item = usize>) {
+        self.root.set_counts(&mut counts.into_iter());
+    }
+
+
+    /// Length in bits of the shortest code of the tree, 0 for a single-leaf tree
+    pub(crate) fn min_code_length(&self) -> usize {
+        self.root.min_depth()
+    }
+
+
+    /// Decode the data unit represented by the given bit code
+    pub fn decode(&self, bitcode: &BitView) -> Result<Box<[U]>, DecodingError> {
+
+        let mut decoded = Vec::new();
+
+        self.decode_each(bitcode, |value| decoded.push(value.clone()))?;
+
+        Ok(decoded.into_boxed_slice())
+    }
+
+
+    /// Decode exactly `N` symbols into an array, without allocating.
+    /// Return an error if the bitcode doesn't encode exactly `N` symbols.
+    ///
+    /// The only value of a single-leaf tree is encoded with zero bits, so an empty bitcode decodes to `N` copies of it.
+    pub fn decode_exact<const N: usize>(&self, bitcode: &BitView) -> Result<[U; N], DecodingError> {
+
+        if let Node::Leaf { value, .. } = &self.root {
+
+            if bitcode.len_bits() != 0 {
+                return Err(DecodingError::InvalidEncoding { bit_offset: 0 });
+            }
+
+            return Ok(std::array::from_fn(|_| value.clone()));
+        }
+
+        let mut decoded: [Option<U>; N] = std::array::from_fn(|_| None);
+        let mut found = 0;
+
+        self.decode_each(bitcode, |value| {
+            if let Some(slot) = decoded.get_mut(found) {
+                *slot = Some(value.clone());
+            }
+            found += 1;
+        })?;
+
+        if found != N {
+            return Err(DecodingError::CountMismatch { expected: N, found });
+        }
+
+        Ok(decoded.map(|value| value.unwrap()))
+    }
+
+
+    /// Decode the value whose code starts at bit `bit_offset` of `bitcode`.
+    /// Return the value and the bit offset of the next code.
+    pub(crate) fn decode_one(&self, bitcode: &BitView, bit_offset: usize) -> Result<(&U, usize), DecodingError> {
+
+        let flat = self.flat();
+
+        // The only value of a single-leaf tree takes no bits
+        if flat.pairs.is_empty() {
+            return Ok((&flat.values[0], bit_offset));
+        }
+
+        let mut node = 0;
+        let mut i = bit_offset;
+
+        loop {
+            let bit = bitcode.get(i)
+                .ok_or(DecodingError::InvalidEncoding { bit_offset })?;
+            let child = flat.pairs[node][bit as usize];
+            i += 1;
+
+            if child & LEAF_MARKER != 0 {
+                return Ok((&flat.values[child & !LEAF_MARKER], i));
+            }
+            node = child;
+        }
+    }
+
+
+    /// Decode `bitcode` and pass each decoded value to `emit`, in order
+    fn decode_each(&self, bitcode: &BitView, mut emit: impl FnMut(&U)) -> Result<(), DecodingError> {
+
+        if let Node::Leaf { value, .. } = &self.root {
+
+            // The only value of a single-leaf tree is encoded with zero bits, so there can't be any bit
+            if bitcode.len_bits() != 0 {
+                return Err(DecodingError::InvalidEncoding { bit_offset: 0 });
+            }
+
+            emit(value);
+            return Ok(());
+        }
+
+        let flat = self.flat();
+        let (bytes, padding) = bitcode.as_padded_bytes();
+        let first_bit_offset = bitcode.first_bit_offset() as usize;
+
+        let mut node = 0;
+
+        // Bit offset of the first bit of the code currently being decoded
+        let mut code_start = 0;
+
+        for (byte_index, byte) in bytes.iter().enumerate() {
+
+            let first = if byte_index == 0 { first_bit_offset } else { 0 };
+            let end = if byte_index + 1 == bytes.len() { 8 - padding as usize } else { 8 };
+
+            for j in first..end {
+
+                // Indexing the pair with the bit avoids a branch on the bit value
+                let bit = (byte >> (7 - j)) & 1;
+                let child = flat.pairs[node][bit as usize];
+
+                // Selecting the next node with a mask instead of this branch makes every step wait for the leaf check,
+                // while the branch lets the processor speculate on the common case of reaching a parent
+                if child & LEAF_MARKER != 0 {
+                    emit(&flat.values[child & !LEAF_MARKER]);
+                    code_start = byte_index * 8 + j + 1 - first_bit_offset;
+                    node = 0;
+                } else {
+                    node = child;
+                }
+            }
+        }
+
+        if node != 0 {
+            return Err(DecodingError::InvalidEncoding { bit_offset: code_start });
+        }
+
+        Ok(())
+    }
+
+
+    /// Root node of the tree
+    pub const fn root(&self) -> NodeRef<'_, U> {
+        NodeRef(&self.root)
+    }
+
+
+    /// Build a table mapping each value in the tree to its code
+    pub fn code_table(&self) -> HashMap<U, Code> {
+
+        let mut table = HashMap::new();
+        self.root.collect_codes(Code::new(), &mut table);
+
+        table
+    }
+
+
+    /// Iterate over the values of the tree and their codes, from the leftmost leaf to the rightmost one
+    pub fn iter(&self) -> Codes<'_, U> {
+        self.into_iter()
+    }
+
+
+    /// Borrow the tree as a `StaticDecodingTree`, flattening it on the first call
+    pub fn as_static(&self) -> StaticDecodingTree<'_, U>
+    where
+        U: Copy
+    {
+        let flat = self.flat();
+        StaticDecodingTree {
+            pairs: &flat.pairs,
+            values: &flat.values
+        }
+    }
+
+
+    /// Rust source of a `static` item named `name` holding the tree as a `StaticDecodingTree`, for build scripts that embed a tree.
+    /// The values are written with their `Debug` representation, which is a valid literal for the primitive symbols and arrays of them.
+    pub fn to_rust_static(&self, name: &str) -> String
+    where
+        U: Copy + fmt::Debug
+    {
+        let tree = self.as_static();
+
+        format!(
+            "static {name}: frequency_tree_compression::StaticDecodingTree<'static, {}> = frequency_tree_compression::StaticDecodingTree::new(&{:?}, &{:?});\n",
+            std::any::type_name::<U>(),
+            tree.pairs,
+            tree.values
+        )
+    }
+
+
+    /// Code of `symbol` in this tree, or `None` if the tree doesn't contain it.
+    /// The first call indexes the codes of all the values, so that later lookups don't walk the tree.
+    pub fn code_of(&self, symbol: &U) -> Option<BitVec> {
+
+        let code = self.cached_code_table().get(symbol)?;
+
+        let mut bits = BitVec::with_capacity(code.len());
+        bits.extend_from_bits(&code.as_bits());
+
+        Some(bits)
+    }
+
+
+    /// Code table of the tree, built on the first call and shared with `code_of`
+    pub(crate) fn cached_code_table(&self) -> &HashMap<U, Code> {
+        self.codes.get_or_init(|| self.code_table())
+    }
+
+
+    /// Build an encoder that produces bitcodes this tree decodes,
+    /// so that a single persisted tree can serve both directions of a protocol
+    pub fn encoder(&self) -> Encoder<U> {
+        Encoder {
+            table: self.codes.get().cloned().unwrap_or_else(|| self.code_table())
+        }
+    }
+
+
+    /// Append the serialized tree to `buf`, using the layout described in the module documentation.
+    /// The layout is part of the container format, so it only changes with `FORMAT_VERSION`.
+    pub fn serialize(&self, buf: &mut Vec<u8>) {
+
+        self.root.serialize(buf);
+    }
+
+
+    /// Append the serialized tree to `buf`, followed by the count of each leaf in pre-order as a varint,
+    /// so that the tree can be turned back into an `EncodingTree` with its weights by `into_encoding_tree`.
+    /// This layout is not part of the container format, which never stores counts.
+    pub fn serialize_with_counts(&self, buf: &mut Vec<u8>) {
+
+        self.serialize(buf);
+
+        for (_, count) in self.leaf_counts() {
+            write_varint(buf, count);
+        }
+    }
+
+
+    /// 64-bit FNV-1a hash of the serialized tree, so it depends only on the structure and the leaf values.
+    /// Unlike the `Hash` implementation, the result is stable across platforms and Rust versions,
+    /// so it can key persistent registries of trees, for example to deduplicate dictionaries.
+    pub fn structural_hash(&self) -> u64 {
+
+        let mut buf = Vec::new();
+        self.serialize(&mut buf);
+
+        fnv1a_64(&buf)
+    }
+
+
+    /// Deserialize the tree at the start of `input`.
+    /// Return the tree and the number of bytes read, so that the tree can be followed by other data.
+    pub fn deserialize(input: &[u8]) -> Result<(Self, usize), NodeDeserializationError> {
+        Self::deserialize_at(input, 0)
+    }
+
+
+    /// Deserialize the tree starting at byte `offset` of `input`, so that errors report offsets relative to the whole input.
+    /// Return the tree and the number of bytes read.
+    pub(crate) fn deserialize_at(input: &[u8], offset: usize) -> Result<(Self, usize), NodeDeserializationError> {
+
+        let (root, read) = Node::deserialize(input, offset, 0, &mut HashSet::new())?;
+
+        Ok((
+            Self::from_root(root),
+            read
+        ))
+    }
+
+
+    /// Deserialize the tree at the start of `input`, serialized with `serialize_with_counts`.
+    /// Return the tree and the number of bytes read.
+    pub fn deserialize_with_counts(input: &[u8]) -> Result<(Self, usize), NodeDeserializationError> {
+
+        let (mut tree, mut read) = Self::deserialize(input)?;
+
+        let mut counts = Vec::new();
+        for _ in 0..tree.leaf_counts().len() {
+
+            let (count, count_size) = read_varint(input, read)
+                .map_err(|_| NodeDeserializationError::InvalidLeafCount { offset: read })?;
+
+            counts.push(count);
+            read += count_size;
+        }
+
+        tree.set_leaf_counts(counts);
+
+        Ok((tree, read))
+    }
+
+
+    /// Turn the tree into an `EncodingTree` with the same codes.
+    /// The leaves keep their counts, which are 0 unless the tree was built from frequencies or deserialized with `deserialize_with_counts`.
+    pub fn into_encoding_tree(self) -> EncodingTree<U> {
+        EncodingTree {
+            leaf_count: self.leaf_counts().len(),
+            root: Some(self.root)
+        }
+    }
+
+
+    /// Append the serialized tree to `buf`, using the compact layout described in the module documentation
+    pub fn serialize_compact(&self, buf: &mut Vec<u8>) {
+
+        let mut structure = BitVec::new();
+        let mut values = Vec::new();
+
+        self.root.serialize_compact(&mut structure, &mut values);
+
+        buf.extend_from_slice(structure.as_padded_bytes().0);
+        buf.extend_from_slice(&values);
+    }
+
+
+    /// Deserialize the tree at the start of `input`, serialized with `serialize_compact`.
+    /// Return the tree and the number of bytes read.
+    pub fn deserialize_compact(input: &[u8]) -> Result<(Self, usize), NodeDeserializationError> {
+        Self::deserialize_compact_at(input, 0)
+    }
+
+
+    /// Deserialize the compact tree starting at byte `offset` of `input`.
+    /// Return the tree and the number of bytes read.
+    pub(crate) fn deserialize_compact_at(input: &[u8], offset: usize) -> Result<(Self, usize), NodeDeserializationError> {
+
+        let structure = read_compact_structure(input, offset)?;
+
+        let mut values_offset = offset + structure.least_len_bytes();
+        let root = Node::deserialize_compact(&mut structure.iter_bits(), offset, input, &mut values_offset, 0, &mut HashSet::new())?;
+
+        Ok((
+            Self::from_root(root),
+            values_offset - offset
+        ))
+    }
+
+
+    /// Build the tree with the canonical codes of the `(value, length, count)` leaves, whose lengths must describe a complete tree
+    fn from_canonical_leaves(mut leaves: Vec<(&U, u8, usize)>) -> Self {
+
+        leaves.sort_by_cached_key(|(value, length, _)| (*length, canonical_key(*value)));
+
+        let mut code = 0_u128;
+        let mut previous = leaves[0].1;
+
+        let leaves: Vec<_> = leaves.into_iter()
+            .map(|(value, length, count)| {
+                code <<= length - previous;
+                previous = length;
+                code += 1;
+                (code - 1, length, value, count)
+            })
+            .collect();
+
+        Self::from_root(Node::from_canonical(&leaves, 0))
+    }
+
+
+    /// Build the tree with the canonical codes of the `(value, length)` pairs, as formats that only store code lengths do:
+    /// ordered by length and then by value, each code is the previous one plus one, shifted left by the difference of their lengths.
+    /// The lengths must describe a complete tree, and only the value of a single-value tree has a length of 0.
+    pub fn from_code_lengths(lengths: &[(U, u8)]) -> Result<Self, CodeLengthError> {
+
+        if lengths.is_empty() {
+            return Err(CodeLengthError::NoValues);
+        }
+
+        let mut positions: HashMap<&U, usize> = HashMap::with_capacity(lengths.len());
+
+        // Sum of 2^-length over the leaves, in units of 2^-MAX_LEN, which is 1 for a complete tree
+        let mut kraft_sum = 0_u128;
+
+        for (position, (value, length)) in lengths.iter().enumerate() {
+
+            if let Some(&first) = positions.get(value) {
+                return Err(CodeLengthError::DuplicateValue { first, second: position });
+            }
+            positions.insert(value, position);
+
+            if (lengths.len() == 1) != (*length == 0) || *length as usize > Code::MAX_LEN {
+                return Err(CodeLengthError::InvalidLength { position });
+            }
+
+            kraft_sum += 1 << (Code::MAX_LEN - *length as usize);
+        }
+
+        match kraft_sum.cmp(&(1 << Code::MAX_LEN)) {
+            Ordering::Less => return Err(CodeLengthError::Incomplete),
+            Ordering::Greater => return Err(CodeLengthError::Oversubscribed),
+            Ordering::Equal => ()
+        }
+
+        Ok(Self::from_canonical_leaves(lengths.iter().map(|(value, length)| (value, *length, 0)).collect()))
+    }
+
+
+    /// Tree with the same code lengths and leaf counts, but with the canonical codes, which the canonical layout rebuilds.
+    /// The encoded size of any data is the same with both trees.
+    pub fn to_canonical(&self) -> Self {
+
+        let mut leaves = Vec::new();
+        self.root.collect_leaves(0, &mut leaves);
+
+        Self::from_canonical_leaves(leaves)
+    }
+
+
+    /// Append the code lengths of the tree to `buf`, using the canonical layout described in the module documentation.
+    /// The values are sorted, so a decoder can find the entry of a value by binary search,
+    /// and the serialized trees of similar data differ only where their symbols or code lengths do.
+    ///
+    /// The deserialized tree is `self.to_canonical()`, which is only equal to `self` if the tree is already canonical.
+    pub fn serialize_canonical(&self, buf: &mut Vec<u8>) {
+
+        let mut leaves = Vec::new();
+        self.root.collect_leaves(0, &mut leaves);
+
+        leaves.sort_by_cached_key(|(value, ..)| canonical_key(*value));
+
+        write_varint(buf, leaves.len());
+
+        for (value, length, _) in leaves {
+            let start = buf.len();
+            buf.resize(start + U::WIDTH, 0);
+            value.to_bytes(&mut buf[start..]);
+            buf.push(length);
+        }
+    }
+
+
+    /// Deserialize the tree at the start of `input`, serialized with `serialize_canonical`.
+    /// Return the tree and the number of bytes read.
+    pub fn deserialize_canonical(input: &[u8]) -> Result<(Self, usize), NodeDeserializationError> {
+        Self::deserialize_canonical_at(input, 0)
+    }
+
+
+    /// Deserialize the canonical tree starting at byte `offset` of `input`.
+    /// Return the tree and the number of bytes read.
+    pub(crate) fn deserialize_canonical_at(input: &[u8], offset: usize) -> Result<(Self, usize), NodeDeserializationError> {
+
+        let (leaf_count, count_size) = read_varint(input, offset)
+            .map_err(|_| NodeDeserializationError::InvalidCanonicalTable { offset })?;
+
+        if leaf_count == 0 {
+            return Err(NodeDeserializationError::InvalidCanonicalTable { offset });
+        }
+
+        let entry_size = U::WIDTH + 1;
+        let mut read = offset + count_size;
+
+        // The count is checked against the input before allocating the leaves
+        let remaining = input.len().saturating_sub(read);
+        if leaf_count > remaining / entry_size {
+            return Err(NodeDeserializationError::MissingNodeUnitData { offset: read, expected: leaf_count.saturating_mul(entry_size), remaining });
+        }
+
+        let mut leaves = Vec::with_capacity(leaf_count);
+        let mut previous_key = None;
+
+        for _ in 0..leaf_count {
+
+            let value = U::from_bytes(&input[read..read + U::WIDTH])
+                .ok_or(NodeDeserializationError::InvalidNodeUnitData { offset: read })?;
+
+            // Strictly increasing values can't repeat a leaf
+            let key = canonical_key(&value);
+            if previous_key.as_ref().is_some_and(|previous| key <= *previous) {
+                return Err(NodeDeserializationError::InvalidCanonicalTable { offset: read });
+            }
+
+            leaves.push((value, input[read + U::WIDTH]));
+            previous_key = Some(key);
+            read += entry_size;
+        }
+
+        let tree = Self::from_code_lengths(&leaves)
+            .map_err(|error| match error {
+                CodeLengthError::InvalidLength { position }
+                    => NodeDeserializationError::InvalidCanonicalTable { offset: offset + count_size + position * entry_size + U::WIDTH },
+                _ => NodeDeserializationError::InvalidCanonicalTable { offset }
+            })?;
+
+        Ok((tree, read - offset))
+    }
+
+
+    /// Append the serialized tree to `buf`, using the front-coded layout described in the module documentation.
+    /// It's smaller than the compact layout when the serialized values of many leaves start with the same bytes,
+    /// like groups of bytes or of characters.
+    pub fn serialize_front_coded(&self, buf: &mut Vec<u8>) {
+
+        let mut structure = BitVec::new();
+        let mut values = Vec::new();
+
+        self.root.serialize_compact(&mut structure, &mut values);
+
+        buf.extend_from_slice(structure.as_padded_bytes().0);
+
+        let leaves: Vec<&[u8]> = values.chunks_exact(U::WIDTH).collect();
+
+        let mut sorted: Vec<usize> = (0..leaves.len()).collect();
+        sorted.sort_unstable_by_key(|leaf| leaves[*leaf]);
+
+        let mut previous: &[u8] = &[];
+        for leaf in &sorted {
+
+            let value = leaves[*leaf];
+            let shared = previous.iter().zip(value).take_while(|(a, b)| a == b).count();
+
+            write_varint(buf, shared);
+            buf.extend_from_slice(&value[shared..]);
+
+            previous = value;
+        }
+
+        let mut positions = vec![0; leaves.len()];
+        for (position, leaf) in sorted.into_iter().enumerate() {
+            positions[leaf] = position;
+        }
+
+        let position_bits = position_width(leaves.len());
+
+        let mut writer = BitWriter::new();
+        for position in positions {
+            // The width is 0 for a single leaf, which doesn't need a position
+            if position_bits != 0 {
+                writer.write_bits((position as u64) << (u64::BITS - position_bits), position_bits);
+            }
+        }
+
+        buf.extend_from_slice(writer.finish().as_padded_bytes().0);
+    }
+
+
+    /// Deserialize the tree at the start of `input`, serialized with `serialize_front_coded`.
+    /// Return the tree and the number of bytes read.
+    pub fn deserialize_front_coded(input: &[u8]) -> Result<(Self, usize), NodeDeserializationError> {
+        Self::deserialize_front_coded_at(input, 0)
+    }
+
+
+    /// Deserialize the front-coded tree starting at byte `offset` of `input`.
+    /// Return the tree and the number of bytes read.
+    pub(crate) fn deserialize_front_coded_at(input: &[u8], offset: usize) -> Result<(Self, usize), NodeDeserializationError> {
+
+        let structure = read_compact_structure(input, offset)?;
+
+        // A complete tree has one more leaf than parents
+        let leaf_count = structure.len_bits().div_ceil(2);
+
+        let mut table: Vec<u8> = Vec::with_capacity(leaf_count * U::WIDTH);
+        let mut read = offset + structure.least_len_bytes();
+
+        for index in 0..leaf_count {
+
+            let entry_offset = read;
+
+            let (shared, shared_size) = read_varint(input, read)
+                .map_err(|_| NodeDeserializationError::InvalidLeafTable { offset: entry_offset })?;
+
+            // The first value shares nothing, and the others can't share more than a whole value
+            if (index == 0 && shared != 0) || shared > U::WIDTH {
+                return Err(NodeDeserializationError::InvalidLeafTable { offset: entry_offset });
+            }
+
+            read += shared_size;
+
+            let suffix_size = U::WIDTH - shared;
+            let remaining = input.len().saturating_sub(read);
+            if remaining < suffix_size {
+                return Err(NodeDeserializationError::MissingNodeUnitData { offset: read, expected: suffix_size, remaining });
+            }
+
+            let start = table.len();
+            table.extend_from_within(start.saturating_sub(U::WIDTH)..start.saturating_sub(U::WIDTH) + shared);
+            table.extend_from_slice(&input[read..read + suffix_size]);
+            read += suffix_size;
+
+            let value = &table[start..];
+
+            // Strictly increasing values can't repeat a leaf
+            if index != 0 && value <= &table[start - U::WIDTH..start] {
+                return Err(NodeDeserializationError::InvalidLeafTable { offset: entry_offset });
+            }
+
+            if U::from_bytes(value).is_none() {
+                return Err(NodeDeserializationError::InvalidNodeUnitData { offset: entry_offset });
+            }
+        }
+
+        let positions_offset = read;
+        let position_bits = position_width(leaf_count) as usize;
+        let positions_size = least_bytes_repr_for_bits(leaf_count * position_bits);
+
+        let positions = input.get(positions_offset..positions_offset + positions_size)
+            .ok_or(NodeDeserializationError::InvalidLeafPosition { offset: positions_offset })?;
+        let mut positions = BitView::from_padded_bytes(positions, (positions_size * 8 - leaf_count * position_bits) as u8).iter_bits();
+
+        // The values of the leaves in pre-order, as the compact layout stores them
+        let mut values = Vec::with_capacity(table.len());
+        let mut used = vec![false; leaf_count];
+
+        for _ in 0..leaf_count {
+
+            let position = (0..position_bits).fold(0, |position, _| position << 1 | positions.next().unwrap() as usize);
+
+            if position >= leaf_count || mem::replace(&mut used[position], true) {
+                return Err(NodeDeserializationError::InvalidLeafPosition { offset: positions_offset });
+            }
+
+            values.extend_from_slice(&table[position * U::WIDTH..(position + 1) * U::WIDTH]);
+        }
+
+        // The values were validated, so only the depth of the tree can be rejected
+        let root = Node::deserialize_compact(&mut structure.iter_bits(), offset, &values, &mut 0, 0, &mut HashSet::new())?;
+
+        Ok((
+            Self::from_root(root),
+            positions_offset + positions_size - offset
+        ))
+    }
+
+}
+
+
+/// Read the specifier bits of the compact layout starting at byte `offset` of `input`, up to the last node of the tree
+fn read_compact_structure(input: &[u8], offset: usize) -> Result<BitView<'_>, NodeDeserializationError> {
+
+    // Find where the specifier bits end: each parent adds two nodes to read
+    let mut pending = 1_usize;
+    let mut bit_count = 0;
+
+    while pending != 0 {
+
+        let specifier_offset = offset + byte_offset(bit_count);
+        let byte = *input.get(specifier_offset)
+            .ok_or(NodeDeserializationError::MissingNodeTypeSpecifier { offset: specifier_offset })?;
+
+        let is_parent = byte & bit_mask(bit_count) != 0;
+
+        bit_count += 1;
+        pending = pending - 1 + 2 * is_parent as usize;
+    }
+
+    let structure_size = least_bytes_repr_for_bits(bit_count);
+
+    Ok(BitView::from_padded_bytes(&input[offset..offset + structure_size], padding_bits(bit_count)))
+}
+
+
+/// Number of bits of the position of a leaf in the value table of the front-coded layout
+fn position_width(leaf_count: usize) -> u32 {
+    usize::BITS - leaf_count.saturating_sub(1).leading_zeros()
+}
+
+
+/// Serialized layout of a tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TreeLayout {
+
+    Plain,
+    Compact,
+    Canonical,
+    FrontCoded,
+
+}
+
+
+/// Decoding tree whose leaves are the serialized bytes of their values, for tools that don't know the symbol type at compile time.
+/// Every leaf has the same width, such as the width of the `SymbolType` recorded in the header.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DynDecodingTree {
+
+    width: usize,
+    root: Node<Box<[u8]>>,
+
+}
+
+impl DynDecodingTree {
+
+    /// Largest width of the leaves that can be deserialized
+    pub const MAX_WIDTH: usize = 16;
+
+
+    /// Erase the symbol type of `tree`
+    pub fn from_tree<U>(tree: &DecodingTree<U>) -> Self
+    where
+        U: Symbol
+    {
+        Self {
+            width: U::WIDTH,
+            root: tree.root.map(&|value: &U| {
+                let mut bytes = vec![0; U::WIDTH].into_boxed_slice();
+                value.to_bytes(&mut bytes);
+                bytes
+            })
+        }
+    }
+
+
+    /// Deserialize the tree at the start of `input`, whose leaves are `width` bytes wide.
+    /// Return the tree and the number of bytes read.
+    ///
+    /// Panics if `width` is 0 or larger than `MAX_WIDTH`.
+    pub fn deserialize(input: &[u8], width: usize) -> Result<(Self, usize), NodeDeserializationError> {
+        Self::deserialize_at(input, 0, width, TreeLayout::Plain)
+    }
+
+
+    /// Like `deserialize`, for a tree serialized with `DecodingTree::serialize_compact`
+    pub fn deserialize_compact(input: &[u8], width: usize) -> Result<(Self, usize), NodeDeserializationError> {
+        Self::deserialize_at(input, 0, width, TreeLayout::Compact)
+    }
+
+
+    /// Like `deserialize`, for a tree serialized with `DecodingTree::serialize_canonical`
+    pub fn deserialize_canonical(input: &[u8], width: usize) -> Result<(Self, usize), NodeDeserializationError> {
+        Self::deserialize_at(input, 0, width, TreeLayout::Canonical)
+    }
+
+
+    /// Like `deserialize`, for a tree serialized with `DecodingTree::serialize_front_coded`
+    pub fn deserialize_front_coded(input: &[u8], width: usize) -> Result<(Self, usize), NodeDeserializationError> {
+        Self::deserialize_at(input, 0, width, TreeLayout::FrontCoded)
+    }
+
+
+    /// Deserialize the tree in `layout` starting at byte `offset` of `input`, with a typed tree of byte arrays of the same width.
+    /// Return the tree and the number of bytes read.
+    pub(crate) fn deserialize_at(input: &[u8], offset: usize, width: usize, layout: TreeLayout) -> Result<(Self, usize), NodeDeserializationError> {
+
+        macro_rules! deserialize_width {
+            ($($n:literal)*) => {
+                match width {
+                    $( $n => Self::deserialize_array::<$n>(input, offset, layout), )*
+                    _ => panic!("leaves of {width} bytes are not supported, the width must be between 1 and {}", Self::MAX_WIDTH)
+                }
+            };
+        }
+
+        deserialize_width!(1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16)
+    }
+
+
+    fn deserialize_array<const N: usize>(input: &[u8], offset: usize, layout: TreeLayout) -> Result<(Self, usize), NodeDeserializationError> {
+
+        let (tree, size) = match layout {
+            TreeLayout::Plain => DecodingTree::<[u8; N]>::deserialize_at(input, offset),
+            TreeLayout::Compact => DecodingTree::deserialize_compact_at(input, offset),
+            TreeLayout::Canonical => DecodingTree::deserialize_canonical_at(input, offset),
+            TreeLayout::FrontCoded => DecodingTree::deserialize_front_coded_at(input, offset),
+        }?;
+
+        Ok((Self::from_tree(&tree), size))
+    }
+
+
+    /// Number of bytes of every leaf value
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+
+    /// Root node of the tree
+    pub const fn root(&self) -> NodeRef<'_, Box<[u8]>> {
+        NodeRef(&self.root)
+    }
+
+
+    /// Serialized value and code of every leaf, in pre-order
+    pub fn leaves(&self) -> Vec<(&[u8], Code)> {
+
+        fn collect<'a>(node: &'a Node<Box<[u8]>>, code: Code, leaves: &mut Vec<(&'a [u8], Code)>) {
+            match node {
+                Node::Parent { left, right, .. } => {
+                    collect(left, code.step_left(), leaves);
+                    collect(right, code.step_right(), leaves);
+                },
+                Node::Leaf { value, .. } => leaves.push((value, code)),
+            }
+        }
+
+        let mut leaves = Vec::new();
+        collect(&self.root, Code::new(), &mut leaves);
+        leaves
+    }
+
+}
+
+/// Write the values as hexadecimal bytes in serialized order, like `{6100: 0, 6200: 1}`
+impl fmt::Display for DynDecodingTree {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+
+        write!(f, "{{")?;
+
+        for (i, (value, code)) in self.leaves().into_iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            for byte in value {
+                write!(f, "{byte:02x}")?;
+            }
+            write!(f, ": {code}")?;
+        }
+
+        write!(f, "}}")
+    }
+}
+
+
+/// Error returned when a serialized decoding tree is malformed.
+/// Offsets are byte offsets from the start of the deserialized input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeDeserializationError {
+
+    /// The input ended where a node type specifier was expected
+    MissingNodeTypeSpecifier { offset: usize },
+    /// The node type specifier is not one of the `expected` values
+    InvalidNodeTypeSpecifier { offset: usize, found: u8, expected: &'static [u8] },
+    /// The node type specifier is reserved for a node kind of a later format version
+    ReservedNodeTypeSpecifier { offset: usize, found: u8 },
+    /// The input ended before the `expected` bytes of a leaf's unit data
+    MissingNodeUnitData { offset: usize, expected: usize, remaining: usize },
+    /// The leaf's unit data doesn't represent a valid unit
+    InvalidNodeUnitData { offset: usize },
+    /// The parent node at `offset` is deeper than the longest code allows
+    TreeTooDeep { offset: usize, max_depth: usize },
+    /// The count of a leaf serialized with `DecodingTree::serialize_with_counts` is truncated or doesn't fit in a `usize`
+    InvalidLeafCount { offset: usize },
+    /// The entry of the front-coded value table at `offset` is truncated, shares too many bytes,
+    /// or doesn't come after the previous value
+    InvalidLeafTable { offset: usize },
+    /// The leaf positions of the front-coded layout at `offset` are truncated, out of range, or repeated
+    InvalidLeafPosition { offset: usize },
+    /// The canonical layout at `offset` has no leaves, values out of order, or code lengths that don't describe a complete tree
+    InvalidCanonicalTable { offset: usize },
+    /// The value of the leaf at `offset` is already the value of a previous leaf, so the tree would have two codes for it
+    DuplicateLeafValue { offset: usize },
+
+}
+
+impl NodeDeserializationError {
+
+    /// Byte offset at which the error was detected
+    pub const fn offset(&self) -> usize {
+        match self {
+            NodeDeserializationError::MissingNodeTypeSpecifier { offset } |
+            NodeDeserializationError::InvalidNodeTypeSpecifier { offset, .. } |
+            NodeDeserializationError::ReservedNodeTypeSpecifier { offset, .. } |
+            NodeDeserializationError::MissingNodeUnitData { offset, .. } |
+            NodeDeserializationError::InvalidNodeUnitData { offset } |
+            NodeDeserializationError::TreeTooDeep { offset, .. } |
+            NodeDeserializationError::InvalidLeafCount { offset } |
+            NodeDeserializationError::InvalidLeafTable { offset } |
+            NodeDeserializationError::InvalidLeafPosition { offset } |
+            NodeDeserializationError::InvalidCanonicalTable { offset } |
+            NodeDeserializationError::DuplicateLeafValue { offset }
+                => *offset
+        }
+    }
+
+}
+
+impl fmt::Display for NodeDeserializationError {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeDeserializationError::MissingNodeTypeSpecifier { offset }
+                => write!(f, "missing node type specifier at byte {offset}"),
+            NodeDeserializationError::InvalidNodeTypeSpecifier { offset, found, expected }
+                => write!(f, "invalid node type specifier {found} at byte {offset}, expected one of {expected:?}"),
+            NodeDeserializationError::ReservedNodeTypeSpecifier { offset, found }
+                => write!(f, "the node type specifier {found} at byte {offset} is reserved for a later format version"),
+            NodeDeserializationError::MissingNodeUnitData { offset, expected, remaining }
+                => write!(f, "missing leaf unit data at byte {offset}: expected {expected} bytes, {remaining} remaining"),
+            NodeDeserializationError::InvalidNodeUnitData { offset }
+                => write!(f, "invalid leaf unit data at byte {offset}"),
+            NodeDeserializationError::TreeTooDeep { offset, max_depth }
+                => write!(f, "the parent node at byte {offset} is deeper than the maximum depth of {max_depth}"),
+            NodeDeserializationError::InvalidLeafCount { offset }
+                => write!(f, "invalid leaf count at byte {offset}"),
+            NodeDeserializationError::InvalidLeafTable { offset }
+                => write!(f, "invalid entry of the leaf value table at byte {offset}"),
+            NodeDeserializationError::InvalidLeafPosition { offset }
+                => write!(f, "invalid leaf positions at byte {offset}"),
+            NodeDeserializationError::InvalidCanonicalTable { offset }
+                => write!(f, "invalid canonical leaf table at byte {offset}"),
+            NodeDeserializationError::DuplicateLeafValue { offset }
+                => write!(f, "the leaf value at byte {offset} is already in the tree"),
+        }
+    }
+}
+
+impl Error for NodeDeserializationError {}
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EncodingTree<U: Clone> {
+
+    /// Root node of the binary tree
+    root: Option<Node<U>>,
+
+    /// Total number of leaf nodes in the tree
+    leaf_count: usize,
+
+}
+
+/// Write the code of every value from the leftmost to the rightmost leaf, like `{'e': 00, 't': 01, 'a': 1}`
+impl<U> fmt::Display for EncodingTree<U>
+where
+    U: Clone + fmt::Debug
+{
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        if let Some(root) = &self.root {
+            root.fmt_codes(Code::new(), &mut true, f)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl<U> EncodingTree<U>
+where
+    U: Symbol
+{
+
+    const fn new() -> Self {
+        Self {
+            root: None,
+            leaf_count: 0
+        }
+    }
+
+
+    pub const fn leaf_node_count(&self) -> usize {
+        self.leaf_count
+    }
+
+
+    pub const fn parent_node_count(&self) -> usize {
+        self.leaf_count - (self.leaf_count > 1) as usize
+    }
+
+
+    pub const fn total_node_count(&self) -> usize {
+        self.leaf_node_count() + self.parent_node_count()
+    }
+
+
+    fn add_value(&mut self, freq: usize, value: U) {
+
+        if let Some(root) = &mut self.root {
+            root.insert(freq, value);
+        } else {
+            self.root = Some(Node::Leaf { count: freq, value });
+        }
+
+        self.leaf_count += 1;
+    }
+
+
+    /// Root node of the tree, or `None` if the tree is empty
+    pub fn root(&self) -> Option<NodeRef<'_, U>> {
+        self.root.as_ref().map(NodeRef)
+    }
+
+
+    /// Build a table mapping each value in the tree to its code, so that values don't need to be searched in the tree
+    pub fn code_table(&self) -> HashMap<U, Code> {
+
+        let mut table = HashMap::with_capacity(self.leaf_count);
+
+        if let Some(root) = &self.root {
+            root.collect_codes(Code::new(), &mut table);
+        }
+
+        table
+    }
+
+
+    /// Value, code length, and frequency of every leaf, from left to right
+    fn leaves(&self) -> Vec<(&U, u8, usize)> {
+
+        let mut leaves = Vec::with_capacity(self.leaf_count);
+
+        if let Some(root) = &self.root {
+            root.collect_leaves(0, &mut leaves);
+        }
+
+        leaves
+    }
+
+
+    /// Code length in bits of every value in the tree, ordered from the leftmost to the rightmost leaf
+    pub fn code_lengths(&self) -> Vec<(U, u8)> {
+        self.leaves().into_iter()
+            .map(|(value, length, _)| (value.clone(), length))
+            .collect()
+    }
+
+
+    /// Summary of the code lengths, weighted by the value frequencies.
+    /// Return `None` if the tree is empty.
+    pub fn code_length_stats(&self) -> Option<CodeLengthStats> {
+
+        let leaves = self.leaves();
+
+        let min = leaves.iter().map(|(_, length, _)| *length).min()?;
+        let max = leaves.iter().map(|(_, length, _)| *length).max()?;
+
+        let total = leaves.iter().map(|(_, _, count)| *count).sum::<usize>() as f64;
+
+        let weighted_average = leaves.iter()
+            .map(|(_, length, count)| *length as f64 * *count as f64)
+            .sum::<f64>() / total;
+
+        let entropy = leaves.iter()
+            .map(|(_, _, count)| {
+                let p = *count as f64 / total;
+                -p * p.log2()
+            })
+            .sum();
+
+        Some(CodeLengthStats { min, max, weighted_average, entropy })
+    }
+
+
+    /// Exact size in bits of the bitcode of values with the given frequencies, the sum of their code lengths.
+    /// Together with `serialized_size`, it predicts the size of the compressed data before encoding it.
+    ///
+    /// Panics if a value of `frequencies` is not in the tree.
+    pub fn encoded_size_bits(&self, frequencies: &[(U, usize)]) -> u64 {
+
+        let lengths: HashMap<&U, u8> = self.leaves().into_iter()
+            .map(|(value, length, _)| (value, length))
+            .collect();
+
+        frequencies.iter()
+            .map(|(value, count)| lengths[value] as u64 * *count as u64)
+            .sum()
+    }
+
+
+    /// Build the tree from value-frequency pairs, such as the entries of a `FrequencyTable`.
+    /// Fail if a value is listed twice.
+    pub fn try_from_frequencies(frequencies: &[(U, usize)]) -> Result<Self, DuplicateValue> {
+
+        let mut positions = HashMap::with_capacity(frequencies.len());
+
+        for (second, (value, _)) in frequencies.iter().enumerate() {
+            if let Some(&first) = positions.get(value) {
+                return Err(DuplicateValue { first, second });
+            }
+            positions.insert(value, second);
+        }
+
+        Ok(Self::from_frequencies(frequencies))
+    }
+
+
+    /// Build the tree from value-frequency pairs whose values are distinct, as counted by `value_frequencies`
+    pub(crate) fn from_frequencies(frequencies: &[(U, usize)]) -> Self {
+
+        let mut frequencies = frequencies.to_vec();
+        sort_frequencies(&mut frequencies);
+
+        let mut encoder = Self::new();
+
+        for (value, freq) in frequencies {
+            encoder.add_value(freq, value);
+        }
+
+        encoder
+    }
+
+
+    /// Number of bytes needed to serialize the tree
+    pub(crate) const fn serialized_size(&self) -> usize {
+        (1 + U::WIDTH) * self.leaf_node_count() + self.parent_node_count()
+    }
+
+
+    pub fn encode(data: impl Iterator<Item = U> + Clone) -> (Self, BitVec) {
+
+        let frequencies = value_frequencies(data.clone());
+        let encoder = Self::from_frequencies(&frequencies);
+
+        let encoded = encode_with_table(&encoder.code_table(), data, encoder.encoded_size_bits(&frequencies) as usize);
+
+        (encoder, encoded)
+    }
+
+
+    /// Build the tree of `data`, counting the frequencies of `threads` chunks of the slice in parallel
+    pub fn from_slice_parallel(data: &[U], threads: NonZeroUsize) -> Self
+    where
+        U: Send + Sync
+    {
+        Self::from_frequencies(&value_frequencies_parallel(data, threads))
+    }
+
+
+    /// Like `encode`, with the frequencies of `threads` chunks of the slice counted in parallel
+    pub fn encode_parallel(data: &[U], threads: NonZeroUsize) -> (Self, BitVec)
+    where
+        U: Send + Sync
+    {
+
+        let frequencies = value_frequencies_parallel(data, threads);
+        let encoder = Self::from_frequencies(&frequencies);
+
+        let encoded = encode_with_table(&encoder.code_table(), data.iter().cloned(), encoder.encoded_size_bits(&frequencies) as usize);
+
+        (encoder, encoded)
+    }
+
+
+    /// Convert the `EncodingTree` into a `DecodingTree`
+    /// Return `None` if the tree is not initialized
+    pub fn into_decoder(self) -> Option<DecodingTree<U>> {
+        Some(DecodingTree::from_root(self.root?))
+    }
+
+
+    /// Build a `DecodingTree` from a clone of the tree, so that the `EncodingTree` can keep encoding data.
+    /// Return `None` if the tree is not initialized
+    pub fn as_decoder(&self) -> Option<DecodingTree<U>> {
+        self.root.clone().map(DecodingTree::from_root)
+    }
+
+}
+
+
+impl EncodingTree<u8> {
+
+    /// Build the tree of a byte slice, counting the frequencies with `byte_histogram` instead of a hash table
+    pub fn from_bytes(data: &[u8]) -> Self {
+        Self::from_frequencies(&byte_frequencies(data))
+    }
+
+
+    /// Like `encode`, with the frequencies of the bytes counted by `byte_histogram`
+    /// and the codes looked up in an array indexed by the bytes instead of a hash table
+    pub fn encode_bytes(data: &[u8]) -> (Self, BitVec) {
+
+        let frequencies = byte_frequencies(data);
+        let encoder = Self::from_frequencies(&frequencies);
+
+        let encoded = encode_bytes_with_table(&encoder.byte_code_table(), data, encoder.encoded_size_bits(&frequencies) as usize);
+
+        (encoder, encoded)
+    }
+
+
+    /// The code of each byte as a word and a length in bits, as taken by `BitWriter::write_bits`.
+    /// The bytes that are not in the tree have an empty code.
+    pub(crate) fn byte_code_table(&self) -> [(u64, u32); 256] {
+
+        let mut table = [(0, 0); 256];
+
+        for (byte, code) in self.code_table() {
+            table[byte as usize] = (code.as_word(), code.len() as u32);
+        }
+
+        table
+    }
+
+}
+
+
+/// Code length statistics of an encoding tree.
+/// The gap between `weighted_average` and `entropy` is the number of bits per symbol lost to the tree shape,
+/// compared to an ideal entropy coder.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CodeLengthStats {
+
+    /// Shortest code length in bits
+    pub min: u8,
+    /// Longest code length in bits
+    pub max: u8,
+    /// Average code length in bits, weighted by the value frequencies
+    pub weighted_average: f64,
+    /// Shannon entropy of the value frequencies in bits per symbol, the lower bound of `weighted_average`
+    pub entropy: f64,
+
+}
+
+
+/// Concatenate the codes of the values of `data`.
+/// Every value must be in the table. `capacity_bits` is the size of the result if it's known in advance, or 0.
+pub(crate) fn encode_with_table<U>(table: &HashMap<U, Code>, data: impl Iterator<Item = U>, capacity_bits: usize) -> BitVec
+where
+    U: Eq + Hash
+{
+
+    let mut encoded = BitWriter::with_capacity(capacity_bits);
+
+    for value in data {
+        let code = &table[&value];
+        encoded.write_bits(code.as_word(), code.len() as u32);
+    }
+
+    encoded.finish()
+}
+
+
+/// Like `encode_with_table`, for bytes whose codes are in an array built by `EncodingTree::byte_code_table`.
+/// Every byte of `data` must have a code.
+pub(crate) fn encode_bytes_with_table(table: &[(u64, u32); 256], data: &[u8], capacity_bits: usize) -> BitVec {
+
+    let mut encoded = BitWriter::with_capacity(capacity_bits);
+
+    for byte in data {
+        let (word, len) = table[*byte as usize];
+        encoded.write_bits(word, len);
+    }
+
+    encoded.finish()
+}
+
+
+/// Like `encode_with_table`, but fail instead of panicking if a value is not in the table
+pub(crate) fn try_encode_with_table<U>(table: &HashMap<U, Code>, data: impl Iterator<Item = U>, capacity_bits: usize) -> Result<BitVec, UnknownValue>
+where
+    U: Eq + Hash
+{
+
+    let mut encoded = BitWriter::with_capacity(capacity_bits);
+
+    for (position, value) in data.enumerate() {
+        let code = table.get(&value).ok_or(UnknownValue { position })?;
+        encoded.write_bits(code.as_word(), code.len() as u32);
+    }
+
+    Ok(encoded.finish())
+}
+
+
+/// Sort the value-frequency pairs by increasing frequency, and the values with the same frequency by their bytes,
+/// so that the tree of some data doesn't depend on the order the values were counted in, which for a hash table changes
+/// from one run to the next.
+///
+/// Values are inserted into the lighter subtree, and when both subtrees have the same count the choice doesn't change
+/// any code length, so the order of the values is the only tie to break.
+fn sort_frequencies<U>(frequencies: &mut [(U, usize)])
+where
+    U: Symbol
+{
+    frequencies.sort_by_cached_key(|(value, count)| (*count, canonical_key(value)))
+}
+
+
+/// Count the occurrences of each value of `data`
+pub(crate) fn value_frequencies<U, I>(data: I) -> Box<[(U, usize)]>
+where
+    U: Eq + Hash,
+    I: Iterator<Item = U>
+{
+
+    let mut frequencies: HashMap<U, usize> = HashMap::new();
+
+    for unit in data {
+
+        frequencies.entry(unit)
+            .and_modify(|counter| *counter += 1)
+            .or_insert(1);
+    }
+
+    frequencies.drain().collect()
+}
+
+
+/// Inputs shorter than this are counted with a single table, whose setup is cheaper
+const MULTI_TABLE_THRESHOLD: usize = 1 << 12;
+
+
+/// Count the occurrences of every byte value of `data`.
+///
+/// Runs of the same byte make a single table stall on the store of one counter before the next increment.
+/// Long inputs are counted into 4 tables in turn, so that consecutive bytes update different counters, and the tables are summed at the end.
+pub(crate) fn byte_histogram(data: &[u8]) -> [usize; 256] {
+
+    if data.len() < MULTI_TABLE_THRESHOLD {
+
+        let mut counts = [0; 256];
+        for byte in data {
+            counts[*byte as usize] += 1;
+        }
+        return counts;
+    }
+
+    let mut tables = [[0_usize; 256]; 4];
+
+    let mut quads = data.chunks_exact(4);
+    for quad in quads.by_ref() {
+        tables[0][quad[0] as usize] += 1;
+        tables[1][quad[1] as usize] += 1;
+        tables[2][quad[2] as usize] += 1;
+        tables[3][quad[3] as usize] += 1;
+    }
+
+    for byte in quads.remainder() {
+        tables[0][*byte as usize] += 1;
+    }
+
+    let mut counts = tables[0];
+    for table in &tables[1..] {
+        for (count, other) in counts.iter_mut().zip(table) {
+            *count += other;
+        }
+    }
+
+    counts
+}
+
+
+/// Count the occurrences of each byte of `data` with `byte_histogram`, leaving out the bytes that don't occur
+pub(crate) fn byte_frequencies(data: &[u8]) -> Box<[(u8, usize)]> {
+    (0..=u8::MAX).zip(byte_histogram(data))
+        .filter(|(_, count)| *count != 0)
+        .collect()
+}
+
+
+/// Count the occurrences of each value of `data`, splitting it in `threads` chunks counted by scoped threads.
+/// The tables of the chunks are merged at the end, so the result is the same as `value_frequencies`.
+pub(crate) fn value_frequencies_parallel<U>(data: &[U], threads: NonZeroUsize) -> Box<[(U, usize)]>
+where
+    U: Eq + Hash + Clone + Send + Sync
+{
+
+    let chunk_len = data.len().div_ceil(threads.get()).max(1);
+
+    let tables: Vec<HashMap<U, usize>> = thread::scope(|scope| {
+
+        let workers: Vec<_> = data.chunks(chunk_len)
+            .map(|chunk| scope.spawn(move || {
+                let mut table = HashMap::new();
+                for unit in chunk {
+                    *table.entry(unit.clone()).or_insert(0) += 1;
+                }
+                table
+            }))
+            .collect();
+
+        workers.into_iter()
+            .map(|worker| worker.join().unwrap())
+            .collect()
+    });
+
+    let mut tables = tables.into_iter();
+    let mut frequencies = tables.next().unwrap_or_default();
+
+    for table in tables {
+        for (unit, count) in table {
+            *frequencies.entry(unit).or_insert(0) += count;
+        }
+    }
+
+    frequencies.drain().collect()
+}
+
+
+/// 64-bit FNV-1a hash
+const fn fnv1a_64(bytes: &[u8]) -> u64 {
+
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        i += 1;
+    }
+
+    hash
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+
+    #[test]
+    fn check_code() {
+
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for _ in 0..100 {
+
+            let mut code = Code::new();
+
+            for _ in 0..8 {
+                if rng.gen_bool(0.5) {
+                    code = code.step_left();
+                } else {
+                    code = code.step_right()
+                }
+            }
+
+            let v = code.as_bits();
+            let expected = code.iter_bits().collect::<Vec<bool>>();
+
+            assert_eq!(code.len(), 8);
+            assert_eq!(*v.to_bool_slice(), expected);
+            assert_eq!(Code::from_bits(code.to_bits(), 8), code);
+        }
+
+        let mut code = Code::new();
+        code.push_right();
+        code.push_left();
+        code.push_right();
+        assert_eq!(code, Code::from_bits(0b101, 3));
+        assert_eq!(code.to_string(), "101");
+        assert_eq!(Code::from_bits(0xff05, 3), code);
+        assert_eq!(Code::from_bits(u64::MAX, 64).to_bits(), u64::MAX);
+        assert_eq!(Code::from_bits(7, 0), Code::new());
+        assert_eq!(Code::new().to_bits(), 0);
+
+        // Canonical order: by length, then by value
+        let mut codes = ["110", "0", "111", "10"].map(|bits| Code::from_bits(u64::from_str_radix(bits, 2).unwrap(), bits.len()));
+        codes.sort();
+        assert_eq!(codes.map(|code| code.to_string()), ["0", "10", "110", "111"]);
+        assert!(Code::from_bits(0b11, 2) < Code::from_bits(0b000, 3));
+    }
+
+
+    #[test]
+    fn small_coherency() {
+
+        let text = "He";
+
+        let (encoder, compressed) = EncodingTree::encode(text.chars());
+
+        let decoded = encoder.into_decoder().unwrap().decode(&compressed.as_bit_view())
+            .unwrap()
+            .iter()
+            .collect::<String>();
+
+        assert_eq!(text, decoded);
+
+    }
+
+
+    #[test]
+    fn check_compact_serde() {
+
+        for text in ["a", "He", "the quick brown fox jumps over the lazy dog"] {
+
+            let (encoder, _) = EncodingTree::encode(text.bytes());
+            let leaves = encoder.leaf_node_count();
+            let tree = encoder.into_decoder().unwrap();
+
+            let mut buf = Vec::new();
+            tree.serialize_compact(&mut buf);
+            buf.push(0xff);
+
+            assert_eq!(buf.len() - 1, least_bytes_repr_for_bits(2 * leaves - 1) + leaves);
+            assert_eq!(DecodingTree::deserialize_compact(&buf), Ok((tree, buf.len() - 1)));
+        }
+
+        // Parents whose children are missing, and a leaf without its value
+        assert_eq!(DecodingTree::<u8>::deserialize_compact(&[0xff]), Err(NodeDeserializationError::MissingNodeTypeSpecifier { offset: 1 }));
+        assert_eq!(DecodingTree::<u8>::deserialize_compact(&[0x80, 0]), Err(NodeDeserializationError::MissingNodeUnitData { offset: 2, expected: 1, remaining: 0 }));
+        assert_eq!(DecodingTree::<char>::deserialize_compact(&[0, 0, 0xd8, 0, 0]), Err(NodeDeserializationError::InvalidNodeUnitData { offset: 1 }));
+    }
+
+
+    #[test]
+    fn check_counts_serde() {
+
+        let (encoder, _) = EncodingTree::encode("aaaabbc".chars());
+        let tree = encoder.as_decoder().unwrap();
+
+        let mut buf = Vec::new();
+        tree.serialize_with_counts(&mut buf);
+        buf.push(0xff);
+
+        // The counts follow the plain layout
+        let mut plain = Vec::new();
+        tree.serialize(&mut plain);
+        assert_eq!(buf[..plain.len()], plain);
+        assert_eq!(buf.len() - 1, plain.len() + 3);
+
+        let (deserialized, read) = DecodingTree::<char>::deserialize_with_counts(&buf).unwrap();
+        assert_eq!(read, buf.len() - 1);
+
+        // The weights survive the round trip, unlike with the plain layout
+        let restored = deserialized.into_encoding_tree();
+        assert_eq!(restored, encoder);
+        assert_eq!(restored.leaves(), encoder.leaves());
+        assert_eq!(restored.code_length_stats(), encoder.code_length_stats());
+        assert!(DecodingTree::<char>::deserialize(&plain).unwrap().0.into_encoding_tree().leaves().iter().all(|(_, _, count)| *count == 0));
+
+        assert_eq!(DecodingTree::<char>::deserialize_with_counts(&buf[..plain.len() + 2]), Err(NodeDeserializationError::InvalidLeafCount { offset: plain.len() + 2 }));
+    }
+
+
+    #[test]
+    fn check_front_coded_serde() {
+
+        let (tree, _) = EncodingTree::encode([*b"abcd", *b"abcd", *b"abcd", *b"abcd", *b"abce", *b"abce", *b"abxy"].into_iter());
+        let tree = tree.into_decoder().unwrap();
+
+        let mut buf = Vec::new();
+        tree.serialize_front_coded(&mut buf);
+
+        // 5 specifier bits, the sorted values without their shared bytes, and 3 positions of 2 bits
+        assert_eq!(buf[1..11], [0, b'a', b'b', b'c', b'd', 3, b'e', 2, b'x', b'y']);
+        assert_eq!(buf.len(), 12);
+
+        let mut compact = Vec::new();
+        tree.serialize_compact(&mut compact);
+        assert!(buf.len() < compact.len());
+
+        buf.push(0xff);
+        assert_eq!(DecodingTree::<[u8; 4]>::deserialize_front_coded(&buf), Ok((tree.clone(), 12)));
+
+        // A single leaf needs no position
+        let single = DecodingTree::from_root(Node::Leaf { count: 0, value: 7_u32 });
+        let mut single_buf = Vec::new();
+        single.serialize_front_coded(&mut single_buf);
+        assert_eq!(single_buf, [0, 0, 7, 0, 0, 0]);
+        assert_eq!(DecodingTree::<u32>::deserialize_front_coded(&single_buf), Ok((single, 6)));
+
+        let mut corrupted = buf.clone();
+        corrupted[6] = 5;
+        assert_eq!(DecodingTree::<[u8; 4]>::deserialize_front_coded(&corrupted), Err(NodeDeserializationError::InvalidLeafTable { offset: 6 }));
+
+        // The second value shares all the bytes of the first one
+        let mut corrupted = buf.clone();
+        corrupted.splice(6..8, [4]);
+        assert_eq!(DecodingTree::<[u8; 4]>::deserialize_front_coded(&corrupted), Err(NodeDeserializationError::InvalidLeafTable { offset: 6 }));
+
+        // Every leaf at the first position
+        let mut corrupted = buf.clone();
+        corrupted[11] = 0;
+        assert_eq!(DecodingTree::<[u8; 4]>::deserialize_front_coded(&corrupted), Err(NodeDeserializationError::InvalidLeafPosition { offset: 11 }));
+
+        assert_eq!(DecodingTree::<[u8; 4]>::deserialize_front_coded(&buf[..11]), Err(NodeDeserializationError::InvalidLeafPosition { offset: 11 }));
+    }
+
+
+    #[test]
+    fn check_canonical_serde() {
+
+        let (tree, _) = EncodingTree::encode("abbccccdddddddde".chars());
+        let tree = tree.into_decoder().unwrap();
+        let canonical = tree.to_canonical();
+
+        // Same code lengths, canonical codes: ordered by length then by value, counting up
+        let lengths = |tree: &DecodingTree<char>| {
+            let mut lengths: Vec<(char, usize)> = tree.code_table().into_iter().map(|(value, code)| (value, code.len())).collect();
+            lengths.sort();
+            lengths
+        };
+        assert_eq!(lengths(&canonical), lengths(&tree));
+        assert_eq!(canonical.to_canonical(), canonical);
+        assert_eq!(canonical.leaf_counts().iter().map(|(_, count)| count).sum::<usize>(), 16);
+
+        let mut by_code: Vec<(String, char)> = canonical.code_table().into_iter().map(|(value, code)| (code.to_string(), value)).collect();
+        by_code.sort_by_key(|(code, value)| (code.len(), *value));
+        let codes: Vec<&str> = by_code.iter().map(|(code, _)| code.as_str()).collect();
+        let mut expected = codes.clone();
+        expected.sort();
+        assert_eq!(codes, expected);
+
+        let mut buf = Vec::new();
+        tree.serialize_canonical(&mut buf);
+
+        // The values are sorted, each followed by its code length
+        assert_eq!(buf[0], 5);
+        let values: Vec<u8> = buf[1..].chunks_exact(5).map(|entry| entry[0]).collect();
+        assert_eq!(values, b"abcde");
+
+        buf.push(0xff);
+        assert_eq!(DecodingTree::<char>::deserialize_canonical(&buf), Ok((canonical.clone(), buf.len() - 1)));
+
+        // A single leaf has an empty code
+        let single = DecodingTree::from_root(Node::Leaf { count: 0, value: b'z' });
+        let mut single_buf = Vec::new();
+        single.serialize_canonical(&mut single_buf);
+        assert_eq!(single_buf, [1, b'z', 0]);
+        assert_eq!(DecodingTree::<u8>::deserialize_canonical(&single_buf), Ok((single, 3)));
+
+        // Unsorted values, incomplete trees, and oversubscribed trees
+        assert_eq!(DecodingTree::<u8>::deserialize_canonical(&[2, b'b', 1, b'a', 1]), Err(NodeDeserializationError::InvalidCanonicalTable { offset: 3 }));
+        assert_eq!(DecodingTree::<u8>::deserialize_canonical(&[2, b'a', 1, b'b', 2]), Err(NodeDeserializationError::InvalidCanonicalTable { offset: 0 }));
+        assert_eq!(DecodingTree::<u8>::deserialize_canonical(&[3, b'a', 1, b'b', 1, b'c', 1]), Err(NodeDeserializationError::InvalidCanonicalTable { offset: 0 }));
+        assert_eq!(DecodingTree::<u8>::deserialize_canonical(&[2, b'a', 0, b'b', 1]), Err(NodeDeserializationError::InvalidCanonicalTable { offset: 2 }));
+        assert_eq!(DecodingTree::<u8>::deserialize_canonical(&[0]), Err(NodeDeserializationError::InvalidCanonicalTable { offset: 0 }));
+        assert!(matches!(DecodingTree::<u8>::deserialize_canonical(&[200, b'a', 1]), Err(NodeDeserializationError::MissingNodeUnitData { .. })));
+    }
+
+
+    #[test]
+    fn check_from_code_lengths() {
+
+        // The code lengths of DEFLATE's example, in section 3.2.2 of RFC 1951
+        let lengths = [(b'A', 3), (b'B', 3), (b'C', 3), (b'D', 3), (b'E', 3), (b'F', 2), (b'G', 4), (b'H', 4)];
+        let tree = DecodingTree::from_code_lengths(&lengths).unwrap();
+
+        let codes: HashMap<u8, String> = tree.code_table().into_iter().map(|(value, code)| (value, code.to_string())).collect();
+        assert_eq!(codes[&b'F'], "00");
+        assert_eq!(codes[&b'A'], "010");
+        assert_eq!(codes[&b'E'], "110");
+        assert_eq!(codes[&b'G'], "1110");
+        assert_eq!(codes[&b'H'], "1111");
+
+        // The decode side of the canonical layout
+        let mut buf = Vec::new();
+        tree.serialize_canonical(&mut buf);
+        assert_eq!(DecodingTree::<u8>::deserialize_canonical(&buf), Ok((tree.clone(), buf.len())));
+
+        // The order of the entries doesn't matter
+        let mut reversed = lengths;
+        reversed.reverse();
+        assert_eq!(DecodingTree::from_code_lengths(&reversed), Ok(tree));
+
+        let single = DecodingTree::from_code_lengths(&[(b'z', 0)]).unwrap();
+        assert_eq!(single.code_table()[&b'z'].len(), 0);
+
+        assert_eq!(DecodingTree::<u8>::from_code_lengths(&[]), Err(CodeLengthError::NoValues));
+        assert_eq!(DecodingTree::from_code_lengths(&[(b'a', 1), (b'b', 2), (b'a', 2)]), Err(CodeLengthError::DuplicateValue { first: 0, second: 2 }));
+        assert_eq!(DecodingTree::from_code_lengths(&[(b'a', 1), (b'b', 0)]), Err(CodeLengthError::InvalidLength { position: 1 }));
+        assert_eq!(DecodingTree::from_code_lengths(&[(b'a', 65), (b'b', 1)]), Err(CodeLengthError::InvalidLength { position: 0 }));
+        assert_eq!(DecodingTree::from_code_lengths(&[(b'a', 1), (b'b', 1), (b'c', 1)]), Err(CodeLengthError::Oversubscribed));
+        assert_eq!(DecodingTree::from_code_lengths(&[(b'a', 1), (b'b', 2)]), Err(CodeLengthError::Incomplete));
+    }
+
+
+    #[test]
+    fn check_iter_codes() {
+
+        let (encoder, _) = EncodingTree::encode("abbcccddddeeeeeffffff".chars());
+        let tree = encoder.into_decoder().unwrap();
+
+        // Leaves from left to right, so the codes count up like the paths of the tree
+        let codes: Vec<(char, Code)> = tree.iter().map(|(value, code)| (*value, code)).collect();
+        assert_eq!(codes.len(), 6);
+        assert!(codes.windows(2).all(|pair| pair[0].1.to_string() < pair[1].1.to_string()));
+        assert_eq!(codes.iter().cloned().collect::<HashMap<_, _>>(), tree.code_table());
+
+        for (value, code) in &tree {
+            assert_eq!(*tree.decode(&tree.code_of(value).unwrap().as_bit_view()).unwrap(), [*value]);
+            assert_eq!(tree.code_of(value).unwrap().to_bool_slice().as_ref(), code.iter_bits().collect::<Vec<_>>());
+        }
+
+        assert_eq!(tree.clone().into_iter().collect::<Vec<_>>(), codes);
+
+        let single = DecodingTree::from_root(Node::Leaf { count: 0, value: 'z' });
+        assert_eq!(single.into_iter().collect::<Vec<_>>(), [('z', Code::new())]);
+    }
+
+    #[test]
+    fn check_tree_depth_limit() {
+
+        // A degenerate tree whose last parent is at the maximum depth
+        let mut buf = vec![SerialSpecifier::Parent as u8; Code::MAX_LEN + 1];
+        for _ in 0..=Code::MAX_LEN + 1 {
+            buf.extend([SerialSpecifier::Leaf as u8, 0]);
+        }
+        assert_eq!(DecodingTree::<u8>::deserialize(&buf), Err(NodeDeserializationError::TreeTooDeep { offset: Code::MAX_LEN, max_depth: Code::MAX_LEN }));
+
+        // Deep enough to overflow the stack if the depth weren't limited
+        let buf = vec![SerialSpecifier::Parent as u8; 1 << 20];
+        assert_eq!(DecodingTree::<u8>::deserialize(&buf), Err(NodeDeserializationError::TreeTooDeep { offset: Code::MAX_LEN, max_depth: Code::MAX_LEN }));
+
+        // The same degenerate tree in the compact layout: 65 parent bits and 66 leaf bits, then the values
+        let mut compact = vec![0xff; 8];
+        compact.extend([0x80, 0, 0, 0, 0, 0, 0, 0, 0]);
+        compact.extend([0; 66]);
+        assert_eq!(DecodingTree::<u8>::deserialize_compact(&compact), Err(NodeDeserializationError::TreeTooDeep { offset: 0, max_depth: Code::MAX_LEN }));
+    }
+
+
+    #[test]
+    fn check_decoding_tree_encoder() {
+
+        let (encoder, _) = EncodingTree::encode("the quick brown fox jumps over the lazy dog".chars());
+
+        let mut serialized = Vec::new();
+        encoder.into_decoder().unwrap().serialize(&mut serialized);
+
+        // Only the serialized tree is shared by both sides
+        let (tree, _) = DecodingTree::<char>::deserialize(&serialized).unwrap();
+        let encoder = tree.encoder();
+
+        let message = "a quick brown dog";
+        let bitcode = encoder.encode(message.chars()).unwrap();
+        assert_eq!(tree.decode(&bitcode.as_bit_view()).unwrap().iter().collect::<String>(), message);
+
+        assert_eq!(encoder.code(&'q').map(Code::len), tree.code_table().get(&'q').map(Code::len));
+        assert_eq!(encoder.code(&'!'), None);
+        assert_eq!(encoder.encode("dog!".chars()), Err(UnknownValue { position: 3 }));
+    }
+
+
+    #[test]
+    fn check_flat_tree() {
+
+        // The root is a parent of the leaf 'a' and of a parent of the leaves 'b' and 'c'
+        let tree = DecodingTree::from_root(Node::Parent {
+            count: 0,
+            left: Box::new(Node::Leaf { count: 0, value: 'a' }),
+            right: Box::new(Node::Parent {
+                count: 0,
+                left: Box::new(Node::Leaf { count: 0, value: 'b' }),
+                right: Box::new(Node::Leaf { count: 0, value: 'c' }),
+            }),
+        });
+
+        let flat = tree.flat();
+        assert_eq!(*flat.pairs, [[LEAF_MARKER, 1], [1 | LEAF_MARKER, 2 | LEAF_MARKER]]);
+        assert_eq!(*flat.values, ['a', 'b', 'c']);
+
+        let bitcode = BitVec::from_bool_slice(&[true, true, false, true, false, false]);
+        assert_eq!(*tree.decode(&bitcode.as_bit_view()).unwrap(), ['c', 'a', 'b', 'a']);
+        assert_eq!(tree.decode_one(&bitcode.as_bit_view(), 2), Ok((&'a', 3)));
+
+        // A code cut in the middle is reported from its first bit
+        let truncated = BitVec::from_bool_slice(&[false, true]);
+        assert_eq!(tree.decode(&truncated.as_bit_view()), Err(DecodingError::InvalidEncoding { bit_offset: 1 }));
+        assert_eq!(tree.decode_one(&truncated.as_bit_view(), 1), Err(DecodingError::InvalidEncoding { bit_offset: 1 }));
+
+        // Views that start in the middle of a byte, with errors reported from the start of the view
+        let bits = bitcode.as_bit_view();
+        assert_eq!(*tree.decode(&bits.slice(2, 4)).unwrap(), ['a', 'b', 'a']);
+        assert_eq!(tree.as_static().decode_into(&bits.slice(2, 4), &mut ['\0'; 3]), Ok(3));
+        assert_eq!(tree.decode(&bits.slice(2, 2)), Err(DecodingError::InvalidEncoding { bit_offset: 1 }));
+        assert_eq!(tree.as_static().decode_into(&bits.slice(2, 2), &mut ['\0'; 3]), Err(DecodingError::InvalidEncoding { bit_offset: 1 }));
+    }
+
+
+    static EMBEDDED: StaticDecodingTree<'static, char> = StaticDecodingTree::new(
+        &[[StaticDecodingTree::<char>::LEAF_MARKER, 1], [1 | StaticDecodingTree::<char>::LEAF_MARKER, 2 | StaticDecodingTree::<char>::LEAF_MARKER]],
+        &['a', 'b', 'c']
+    );
+
+    /// Decoded when compiling: `11 0 10 0` is "caba"
+    const EMBEDDED_DECODED: [char; 4] = {
+        let mut out = ['\0'; 4];
+        match EMBEDDED.decode_into(&BitView::from_padded_bytes(&[0b1101_0000], 2), &mut out) {
+            Ok(4) => out,
+            _ => panic!()
+        }
+    };
+
+
+    #[test]
+    fn check_static_tree() {
+
+        assert_eq!(EMBEDDED_DECODED, ['c', 'a', 'b', 'a']);
+
+        let (encoder, bitcode) = EncodingTree::encode("the quick brown fox jumps over the lazy dog".chars());
+        let tree = encoder.into_decoder().unwrap();
+
+        let mut out = ['\0'; 64];
+        let found = tree.as_static().decode_into(&bitcode.as_bit_view(), &mut out).unwrap();
+        assert_eq!(out[..found], *tree.decode(&bitcode.as_bit_view()).unwrap());
+
+        let mut short = ['\0'; 3];
+        assert_eq!(tree.as_static().decode_into(&bitcode.as_bit_view(), &mut short), Err(DecodingError::CountMismatch { expected: 3, found }));
+
+        let source = tree.to_rust_static("TREE");
+        assert!(source.starts_with("static TREE: frequency_tree_compression::StaticDecodingTree<'static, char> = "));
+        assert!(source.contains(&format!("{:?}", tree.as_static().values())));
+
+        let single = StaticDecodingTree::new(&[], &[7_u8]);
+        assert_eq!(single.decode_into(&BitView::from_padded_bytes(&[], 0), &mut [0; 2]), Ok(1));
+        assert_eq!(single.decode_into(&BitView::from_padded_bytes(&[0], 7), &mut [0; 2]), Err(DecodingError::InvalidEncoding { bit_offset: 0 }));
+    }
+
+
+    #[test]
+    #[should_panic]
+    fn check_static_tree_cycle() {
+        StaticDecodingTree::new(&[[1, 0], [0, 0]], &['a']);
+    }
+
+
+    #[test]
+    fn check_decode_exact() {
+
+        let (encoder, bitcode) = EncodingTree::encode("frame".bytes());
+        let tree = encoder.into_decoder().unwrap();
+
+        assert_eq!(tree.decode_exact::<5>(&bitcode.as_bit_view()), Ok(*b"frame"));
+        assert_eq!(tree.decode_exact::<4>(&bitcode.as_bit_view()), Err(DecodingError::CountMismatch { expected: 4, found: 5 }));
+        assert_eq!(tree.decode_exact::<6>(&bitcode.as_bit_view()), Err(DecodingError::CountMismatch { expected: 6, found: 5 }));
+        assert_eq!(tree.decode_exact::<0>(&BitVec::new().as_bit_view()), Ok([]));
+
+        let (single, _) = EncodingTree::encode("aaa".chars());
+        let single = single.into_decoder().unwrap();
+        assert_eq!(single.decode_exact::<3>(&BitVec::new().as_bit_view()), Ok(['a'; 3]));
+        assert_eq!(single.decode_exact::<3>(&BitVec::from_bool_slice(&[true]).as_bit_view()), Err(DecodingError::InvalidEncoding { bit_offset: 0 }));
+    }
+
+
+    #[test]
+    fn check_code_of() {
+
+        let (encoder, _) = EncodingTree::encode("the quick brown fox jumps over the lazy dog".chars());
+        let table = encoder.code_table();
+        let tree = encoder.into_decoder().unwrap();
+
+        for (symbol, code) in &table {
+            let bits = tree.code_of(symbol).unwrap();
+            assert_eq!(*bits.to_bool_slice(), *code.iter_bits().collect::<Vec<_>>());
+            assert_eq!(*tree.decode(&bits.as_bit_view()).unwrap(), [*symbol]);
+        }
+        assert_eq!(tree.code_of(&'!'), None);
+
+        // The cached index doesn't take part in the identity of the tree
+        let mut serialized = Vec::new();
+        tree.serialize(&mut serialized);
+        assert_eq!(DecodingTree::deserialize(&serialized).unwrap().0, tree);
+
+        let (single, _) = EncodingTree::encode("aaa".chars());
+        assert_eq!(single.into_decoder().unwrap().code_of(&'a').map(|bits| bits.len_bits()), Some(0));
+    }
+
+
+    #[test]
+    fn check_tree_identity() {
+
+        use std::collections::HashSet;
+
+        let text = "the quick brown fox jumps over the lazy dog";
+        let tree = EncodingTree::encode(text.chars()).0.into_decoder().unwrap();
+
+        let mut serialized = Vec::new();
+        tree.serialize(&mut serialized);
+        let (deserialized, _) = DecodingTree::<char>::deserialize(&serialized).unwrap();
+
+        // The counts are lost in serialization, but the identity only depends on the structure and the values
+        assert_eq!(tree, deserialized);
+        assert_eq!(tree.structural_hash(), deserialized.structural_hash());
+        assert_eq!(HashSet::from([tree, deserialized]).len(), 1);
+
+        let other = EncodingTree::encode("the lazy dog".chars()).0.into_decoder().unwrap();
+        let same_shape = EncodingTree::encode("He".chars()).0.into_decoder().unwrap();
+        let swapped = EncodingTree::encode("Ha".chars()).0.into_decoder().unwrap();
+        assert_ne!(other.structural_hash(), same_shape.structural_hash());
+        assert_ne!(same_shape, swapped);
+        assert_ne!(same_shape.structural_hash(), swapped.structural_hash());
+
+        // Pinned, since persistent registries rely on it
+        assert_eq!(fnv1a_64(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a_64(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+
+    #[test]
+    fn check_clone_and_display() {
+
+        let (encoder, _) = EncodingTree::encode("aaab".chars());
+        let decoder = encoder.as_decoder().unwrap();
+
+        assert_eq!(encoder.to_string(), decoder.to_string());
+        assert_eq!(encoder.clone().into_decoder(), Some(decoder.clone()));
+        assert_eq!(EncodingTree::<char>::new().as_decoder(), None);
+
+        // The encoder is still usable after building a decoder
+        let bits = encode_with_table(&encoder.code_table(), "baa".chars(), 0);
+        assert_eq!(*decoder.decode(&bits.as_bit_view()).unwrap(), ['b', 'a', 'a']);
+        assert!(["{'a': 0, 'b': 1}", "{'b': 0, 'a': 1}"].contains(&decoder.to_string().as_str()));
+
+        // Clones decode independently, for example from several threads
+        let bitcode = decoder.encoder().encode("abba".chars()).unwrap();
+        std::thread::scope(|scope| {
+            for tree in [decoder.clone(), decoder.clone()] {
+                let bitcode = &bitcode;
+                scope.spawn(move || assert_eq!(*tree.decode(&bitcode.as_bit_view()).unwrap(), ['a', 'b', 'b', 'a']));
+            }
+        });
+
+        let (single, _) = EncodingTree::encode("aaa".bytes());
+        assert_eq!(single.to_string(), "{97: }");
+        assert_eq!(EncodingTree::<u8>::new().to_string(), "{}");
+
+        let (tree, _) = EncodingTree::encode("aaaabbc".chars());
+        let codes = tree.to_string();
+        assert!(codes.contains("'a': 0") || codes.contains("'a': 1"));
+        assert_eq!(codes.matches(": ").count(), 3);
+    }
+
+
+    #[test]
+    fn check_node_ref() {
+
+        let (encoder, _) = EncodingTree::encode("aaab".chars());
+
+        let root = encoder.root().unwrap();
+        assert_eq!(root.count(), 4);
+        assert_eq!(root.value(), None);
+
+        let (left, right) = root.children().unwrap();
+        let mut leaves = [(*left.value().unwrap(), left.count()), (*right.value().unwrap(), right.count())];
+        leaves.sort();
+        assert_eq!(leaves, [('a', 3), ('b', 1)]);
+
+        let table = encoder.code_table();
+        assert!(table.values().all(|code| code.len() == 1));
+
+        let decoder = encoder.into_decoder().unwrap();
+        assert!(decoder.root().children().is_some_and(|(left, right)| left.is_leaf() && right.is_leaf()));
+        assert_eq!(decoder.code_table(), table);
+    }
+
+
+    #[test]
+    fn check_dyn_tree() {
+
+        let (tree, _) = EncodingTree::encode("abbccccdddddddde".chars());
+        let tree = tree.into_decoder().unwrap();
+        let erased = DynDecodingTree::from_tree(&tree);
+
+        assert_eq!(erased.width(), 4);
+
+        let table = tree.code_table();
+        assert_eq!(erased.leaves().len(), table.len());
+        for (value, code) in erased.leaves() {
+            let value = char::from_bytes(value).unwrap();
+            assert_eq!(table[&value], code);
+        }
+
+        // Every layout reads the same tree without knowing the symbol type
+        let mut buf = Vec::new();
+        tree.serialize(&mut buf);
+        assert_eq!(DynDecodingTree::deserialize(&buf, 4), Ok((erased.clone(), buf.len())));
+
+        let mut buf = Vec::new();
+        tree.serialize_compact(&mut buf);
+        assert_eq!(DynDecodingTree::deserialize_compact(&buf, 4), Ok((erased.clone(), buf.len())));
+
+        let mut buf = Vec::new();
+        tree.serialize_front_coded(&mut buf);
+        assert_eq!(DynDecodingTree::deserialize_front_coded(&buf, 4), Ok((erased, buf.len())));
+
+        let mut buf = Vec::new();
+        tree.serialize_canonical(&mut buf);
+        assert_eq!(DynDecodingTree::deserialize_canonical(&buf, 4), Ok((DynDecodingTree::from_tree(&tree.to_canonical()), buf.len())));
+
+        // A wrong width shows up as a malformed tree
+        assert!(DynDecodingTree::deserialize(&[SerialSpecifier::Leaf as u8, b'a'], 2).is_err());
+
+        let single = DynDecodingTree::deserialize(&[SerialSpecifier::Leaf as u8, b'a', 0], 2).unwrap().0;
+        assert_eq!(single.to_string(), "{6100: }");
+    }
+
+
+    #[test]
+    fn check_encoded_size_bits() {
+
+        for text in ["a", "aaaa", "He", "aaaabbc", "the quick brown fox jumps over the lazy dog"] {
+
+            let frequencies = value_frequencies(text.chars());
+            let (encoder, bitcode) = EncodingTree::encode(text.chars());
+
+            assert_eq!(encoder.encoded_size_bits(&frequencies), bitcode.len_bits() as u64);
+
+            // The decoder keeps the counts, also through the canonical reshaping, but not through serialization
+            let decoder = encoder.as_decoder().unwrap();
+            assert_eq!(decoder.encoded_size_bits(), bitcode.len_bits() as u64);
+            assert_eq!(decoder.to_canonical().encoded_size_bits(), bitcode.len_bits() as u64);
+            let mut buf = Vec::new();
+            decoder.serialize(&mut buf);
+            assert_eq!(DecodingTree::<char>::deserialize(&buf).unwrap().0.encoded_size_bits(), 0);
+        }
+
+        // Counts beyond the range of the encoded data
+        let (encoder, _) = EncodingTree::encode("aaaabbc".chars());
+        let lengths: HashMap<char, u8> = encoder.code_lengths().into_iter().collect();
+        assert_eq!(encoder.encoded_size_bits(&[('a', 1 << 40), ('c', 1)]), lengths[&'a'] as u64 * (1 << 40) + lengths[&'c'] as u64);
+    }
+
+
+    #[test]
+    fn check_parallel_frequencies() {
+
+        let text: Vec<char> = "the quick brown fox jumps over the lazy dog".chars().collect();
+
+        let mut expected = value_frequencies(text.iter().copied()).into_vec();
+        expected.sort();
+
+        for threads in [1, 2, 3, 8, 100] {
+
+            let mut frequencies = value_frequencies_parallel(&text, NonZeroUsize::new(threads).unwrap()).into_vec();
+            frequencies.sort();
+            assert_eq!(frequencies, expected);
+
+            let (encoder, bitcode) = EncodingTree::encode_parallel(&text, NonZeroUsize::new(threads).unwrap());
+            assert_eq!(*encoder.into_decoder().unwrap().decode(&bitcode.as_bit_view()).unwrap(), *text);
+        }
+
+        assert!(value_frequencies_parallel::<u8>(&[], NonZeroUsize::new(4).unwrap()).is_empty());
+    }
+
+
+    #[test]
+    fn check_byte_histogram() {
+
+        let mut rng = rand::thread_rng();
+
+        // Below and above the multi-table threshold, with a remainder that doesn't fill a group of 4
+        for len in [0, 1, 100, MULTI_TABLE_THRESHOLD + 3, 50_001] {
+
+            let data: Vec<u8> = (0..len).map(|_| rng.gen_range(0..=u8::MAX)).collect();
+
+            let mut expected = [0; 256];
+            for byte in &data {
+                expected[*byte as usize] += 1;
+            }
+            assert_eq!(byte_histogram(&data), expected);
+
+            let mut frequencies = byte_frequencies(&data).into_vec();
+            let mut reference = value_frequencies(data.iter().copied()).into_vec();
+            frequencies.sort();
+            reference.sort();
+            assert_eq!(frequencies, reference);
+
+            if !data.is_empty() {
+                let (encoder, bitcode) = EncodingTree::encode_bytes(&data);
+                assert_eq!(*encoder.into_decoder().unwrap().decode(&bitcode.as_bit_view()).unwrap(), *data);
+            }
+        }
+
+        assert_eq!(byte_histogram(&[7; 10_000])[7], 10_000);
+    }
+
+
+    #[test]
+    fn check_tie_order() {
+
+        use rand::seq::SliceRandom;
+
+        let mut rng = StdRng::seed_from_u64(1679);
+
+        // Many values with the same frequency, counted in different orders
+        let mut frequencies: Vec<(u16, usize)> = (0..300).map(|value| (value, 1 + value as usize % 3)).collect();
+        let reference = EncodingTree::from_frequencies(&frequencies);
+
+        for _ in 0..10 {
+            frequencies.shuffle(&mut rng);
+            assert_eq!(EncodingTree::from_frequencies(&frequencies), reference);
+        }
+
+        let text = "abcdefgh".repeat(10);
+        assert_eq!(crate::compress(text.chars()), crate::compress(text.chars()));
+    }
+
+
+    #[test]
+    fn check_duplicate_values() {
+
+        let frequencies = [('a', 3), ('b', 2), ('c', 1)];
+        assert_eq!(EncodingTree::try_from_frequencies(&frequencies), Ok(EncodingTree::from_frequencies(&frequencies)));
+
+        // Concatenated entries of two frequency tables
+        let merged = [('a', 3), ('b', 2), ('b', 5), ('a', 1)];
+        assert_eq!(EncodingTree::try_from_frequencies(&merged), Err(DuplicateValue { first: 1, second: 2 }));
+
+        let leaf = SerialSpecifier::Leaf as u8;
+        let parent = SerialSpecifier::Parent as u8;
+
+        let plain = [parent, leaf, b'a', parent, leaf, b'b', leaf, b'a'];
+        assert_eq!(DecodingTree::<u8>::deserialize(&plain), Err(NodeDeserializationError::DuplicateLeafValue { offset: 7 }));
+
+        // Structure bits 1, 0, 0, then the values of the two leaves
+        let compact = [0x80, b'a', b'a'];
+        assert_eq!(DecodingTree::<u8>::deserialize_compact(&compact), Err(NodeDeserializationError::DuplicateLeafValue { offset: 2 }));
+    }
+
+
+    #[test]
+    fn check_tree_builder() {
+
+        let tree = TreeBuilder::parent(
+            TreeBuilder::leaf('a'),
+            TreeBuilder::parent(TreeBuilder::leaf('b'), TreeBuilder::leaf('c'))
+        ).build().unwrap();
+
+        assert_eq!(*tree.code_of(&'a').unwrap().to_bool_slice(), [false]);
+        assert_eq!(*tree.code_of(&'b').unwrap().to_bool_slice(), [true, false]);
+        assert_eq!(*tree.code_of(&'c').unwrap().to_bool_slice(), [true, true]);
+
+        let bitcode = tree.encoder().encode("abcab".chars()).unwrap();
+        assert_eq!(bitcode.len_bits(), 8);
+        assert_eq!(tree.decode(&bitcode.as_bit_view()).unwrap().iter().collect::<String>(), "abcab");
+
+        let duplicate = TreeBuilder::parent(
+            TreeBuilder::leaf('a'),
+            TreeBuilder::parent(TreeBuilder::leaf('b'), TreeBuilder::leaf('a'))
+        );
+        assert_eq!(duplicate.build().err(), Some(TreeBuildError::DuplicateValue { first: 0, second: 2 }));
+
+        // Leaf at depth MAX_LEN is the longest code, one more level is too deep
+        let chain = |depth: u8| (0..depth).fold(TreeBuilder::leaf(0_u8), |tree, value| TreeBuilder::parent(TreeBuilder::leaf(value + 1), tree));
+        assert!(chain(Code::MAX_LEN as u8).build().is_ok());
+        assert_eq!(chain(Code::MAX_LEN as u8 + 1).build().err(), Some(TreeBuildError::TreeTooDeep { max_depth: Code::MAX_LEN }));
+    }
+
+    #[test]
+    fn check_code_lengths() {
+
+        let (encoder, _) = EncodingTree::encode("aaaabbc".chars());
+
+        let lengths: HashMap<char, u8> = encoder.code_lengths().into_iter().collect();
+        assert_eq!(lengths.len(), 3);
+        assert_eq!(lengths.values().map(|length| *length as usize).sum::<usize>(), 5);
+
+        let stats = encoder.code_length_stats().unwrap();
+        assert_eq!((stats.min, stats.max), (1, 2));
+
+        let total_bits = 4 * lengths[&'a'] as usize + 2 * lengths[&'b'] as usize + lengths[&'c'] as usize;
+        assert!((stats.weighted_average - total_bits as f64 / 7.0).abs() < 1e-9);
+        assert!(stats.entropy <= stats.weighted_average);
+
+        let (single, _) = EncodingTree::encode("aaa".chars());
+        assert_eq!(single.code_lengths(), [('a', 0)]);
+        assert_eq!(EncodingTree::<char>::new().code_length_stats(), None);
+    }
+
+}
@@ -0,0 +1,400 @@
+//! Spanned output: compressed data split into fixed-size volumes, for backups on media of limited size.
+//!
+//! Every volume starts with a continuation header, the magic bytes `FTCV` followed by the index of the volume
+//! (4 bytes, little-endian), so that missing volumes and volumes given out of order are detected when reading them back.
+//! The volumes hold consecutive slices of the compressed data, and every volume but the last one is exactly `volume_size` bytes long.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::{decompress, DecompressionError, Symbol};
+
+
+/// Magic bytes at the start of each volume
+const MAGIC: &[u8; 4] = b"FTCV";
+
+/// Size of the continuation header at the start of each volume
+pub const VOLUME_HEADER_SIZE: u64 = MAGIC.len() as u64 + 4;
+
+
+/// Writer that splits the data written to it into volumes of `volume_size` bytes, continuation headers included.
+/// A new volume is opened by calling `open_volume` with its index, starting from 0, when the previous one is full.
+pub struct SplitWriter<W, F>
+where
+    W: Write,
+    F: FnMut(u32) -> io::Result<W>
+{
+
+    open_volume: F,
+    volume_size: u64,
+    /// The volume being written and the number of bytes written to it
+    current: Option<(W, u64)>,
+    /// Number of volumes opened so far
+    volumes: u32,
+
+}
+
+impl<W, F> SplitWriter<W, F>
+where
+    W: Write,
+    F: FnMut(u32) -> io::Result<W>
+{
+
+    /// Panics if `volume_size` can't hold the continuation header and at least one byte of data.
+    pub fn new(volume_size: u64, open_volume: F) -> Self {
+
+        assert!(volume_size > VOLUME_HEADER_SIZE, "the volume size must be larger than the {VOLUME_HEADER_SIZE} bytes of the continuation header");
+
+        Self {
+            open_volume,
+            volume_size,
+            current: None,
+            volumes: 0
+        }
+    }
+
+
+    /// Flush the last volume and return the number of volumes written.
+    /// A single volume holding only its header is written if no data was written.
+    pub fn finish(mut self) -> io::Result<u32> {
+
+        if self.current.is_none() {
+            self.open_next_volume()?;
+        }
+
+        if let Some((volume, _)) = &mut self.current {
+            volume.flush()?;
+        }
+
+        Ok(self.volumes)
+    }
+
+
+    /// Flush the current volume, if any, open the next one, and write its continuation header
+    fn open_next_volume(&mut self) -> io::Result<()> {
+
+        if let Some((mut previous, _)) = self.current.take() {
+            previous.flush()?;
+        }
+
+        let mut volume = (self.open_volume)(self.volumes)?;
+
+        volume.write_all(MAGIC)?;
+        volume.write_all(&self.volumes.to_le_bytes())?;
+
+        self.volumes = self.volumes.checked_add(1)
+            .ok_or(io::Error::new(io::ErrorKind::InvalidInput, "too many volumes"))?;
+
+        self.current = Some((volume, VOLUME_HEADER_SIZE));
+
+        Ok(())
+    }
+
+}
+
+impl<W, F> Write for SplitWriter<W, F>
+where
+    W: Write,
+    F: FnMut(u32) -> io::Result<W>
+{
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.current.as_ref().is_none_or(|(_, written)| *written == self.volume_size) {
+            self.open_next_volume()?;
+        }
+
+        let (volume, written) = self.current.as_mut().unwrap();
+
+        let len = buf.len().min((self.volume_size - *written) as usize);
+        let len = volume.write(&buf[..len])?;
+        *written += len as u64;
+
+        Ok(len)
+    }
+
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.current {
+            Some ((volume, _)) => volume.flush(),
+            None => Ok(())
+        }
+    }
+
+}
+
+
+/// Reader of the data split by a `SplitWriter`, from the volumes in order.
+/// The continuation header of each volume is checked and skipped.
+/// Reading fails with `io::ErrorKind::InvalidData` and a `VolumeError` if a volume is missing or out of order.
+pub struct SplitReader<I, R>
+where
+    I: Iterator<Item = R>,
+    R: Read
+{
+
+    volumes: I,
+    current: Option<R>,
+    /// Index of the next volume to open
+    next_index: u32,
+
+}
+
+impl<I, R> SplitReader<I, R>
+where
+    I: Iterator<Item = R>,
+    R: Read
+{
+
+    pub fn new(volumes: impl IntoIterator<IntoIter = I>) -> Self {
+        Self {
+            volumes: volumes.into_iter(),
+            current: None,
+            next_index: 0
+        }
+    }
+
+
+    /// Open the next volume and check its continuation header.
+    /// Return `false` if there are no more volumes.
+    fn open_next_volume(&mut self) -> io::Result<bool> {
+
+        let Some(mut volume) = self.volumes.next() else {
+            self.current = None;
+            return Ok(false);
+        };
+
+        let mut header = [0; VOLUME_HEADER_SIZE as usize];
+        volume.read_exact(&mut header)
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::UnexpectedEof => VolumeError::NotAVolume { volume: self.next_index }.into(),
+                _ => e
+            })?;
+
+        if header[..MAGIC.len()] != *MAGIC {
+            return Err(VolumeError::NotAVolume { volume: self.next_index }.into());
+        }
+
+        let found = u32::from_le_bytes(header[MAGIC.len()..].try_into().unwrap());
+        if found != self.next_index {
+            return Err(VolumeError::OutOfOrder { expected: self.next_index, found }.into());
+        }
+
+        self.next_index += 1;
+        self.current = Some(volume);
+
+        Ok(true)
+    }
+
+}
+
+impl<I, R> Read for SplitReader<I, R>
+where
+    I: Iterator<Item = R>,
+    R: Read
+{
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+
+            if let Some(volume) = &mut self.current {
+                let read = volume.read(buf)?;
+                if read != 0 {
+                    return Ok(read);
+                }
+            }
+
+            // The current volume is exhausted, or none was opened yet
+            if !self.open_next_volume()? {
+                return Ok(0);
+            }
+        }
+    }
+
+}
+
+
+/// Read the compressed data from the volumes written by a `SplitWriter`, in order, and decompress it
+pub fn decompress_volumes<U, R>(volumes: impl IntoIterator<Item = R>) -> Result<Box<[U]>, VolumeError>
+where
+    U: Symbol,
+    R: Read
+{
+
+    let mut compressed = Vec::new();
+
+    SplitReader::new(volumes).read_to_end(&mut compressed)
+        .map_err(VolumeError::from_io)?;
+
+    decompress(&compressed).map_err(VolumeError::InvalidData)
+}
+
+
+/// Error returned when split volumes can't be read back
+#[derive(Debug)]
+pub enum VolumeError {
+
+    /// Reading a volume failed
+    Io (io::Error),
+    /// The `volume` doesn't start with a continuation header
+    NotAVolume { volume: u32 },
+    /// The volume at position `expected` has the index `found`: volumes are missing or out of order
+    OutOfOrder { expected: u32, found: u32 },
+    /// The data reassembled from the volumes can't be decompressed
+    InvalidData (DecompressionError),
+
+}
+
+impl VolumeError {
+
+    /// Unwrap the volume errors reported through `io::Error` by `SplitReader`
+    fn from_io(e: io::Error) -> Self {
+        if e.get_ref().is_some_and(|inner| inner.is::<VolumeError>()) {
+            *e.into_inner().unwrap().downcast::<VolumeError>().unwrap()
+        } else {
+            VolumeError::Io(e)
+        }
+    }
+
+}
+
+impl fmt::Display for VolumeError {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VolumeError::Io (_)
+                => write!(f, "could not read a volume"),
+            VolumeError::NotAVolume { volume }
+                => write!(f, "volume {volume} doesn't start with a continuation header"),
+            VolumeError::OutOfOrder { expected, found }
+                => write!(f, "expected volume {expected}, found volume {found}"),
+            VolumeError::InvalidData (_)
+                => write!(f, "could not decompress the data of the volumes"),
+        }
+    }
+}
+
+impl Error for VolumeError {
+
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            VolumeError::Io (e) => Some(e),
+            VolumeError::NotAVolume { .. } |
+            VolumeError::OutOfOrder { .. } => None,
+            VolumeError::InvalidData (e) => Some(e),
+        }
+    }
+}
+
+impl From<VolumeError> for io::Error {
+
+    fn from(e: VolumeError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::compress;
+
+
+    /// Volume kept in memory, in a list shared with the test
+    struct MemoryVolume {
+        volumes: Rc<RefCell<Vec<Vec<u8>>>>,
+        index: usize,
+    }
+
+    impl Write for MemoryVolume {
+
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.volumes.borrow_mut()[self.index].extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+
+    /// Split `data` in volumes of `volume_size` bytes kept in memory
+    fn split(data: &[u8], volume_size: u64) -> Vec<Vec<u8>> {
+
+        let volumes = Rc::new(RefCell::new(Vec::new()));
+
+        let mut writer = SplitWriter::new(volume_size, |index| {
+            let mut list = volumes.borrow_mut();
+            assert_eq!(index as usize, list.len());
+            list.push(Vec::new());
+            Ok(MemoryVolume { volumes: Rc::clone(&volumes), index: list.len() - 1 })
+        });
+
+        writer.write_all(data).unwrap();
+        let count = writer.finish().unwrap();
+
+        let volumes = volumes.take();
+        assert_eq!(count as usize, volumes.len());
+
+        volumes
+    }
+
+
+    #[test]
+    fn check_split_round_trip() {
+
+        let text = "the quick brown fox jumps over the lazy dog ".repeat(20);
+        let compressed = compress(text.bytes());
+
+        for volume_size in [VOLUME_HEADER_SIZE + 1, 16, 100, compressed.len() as u64 + VOLUME_HEADER_SIZE, 1 << 20] {
+
+            let volumes = split(&compressed, volume_size);
+
+            let data_size = (volume_size - VOLUME_HEADER_SIZE) as usize;
+            assert_eq!(volumes.len(), compressed.len().div_ceil(data_size));
+            assert!(volumes[..volumes.len() - 1].iter().all(|volume| volume.len() as u64 == volume_size));
+
+            let decompressed = decompress_volumes::<u8, _>(volumes.iter().map(|volume| &volume[..])).unwrap();
+            assert_eq!(*decompressed, *text.as_bytes());
+        }
+
+        // Nothing written still makes a volume with its header
+        assert_eq!(split(&[], 16), [[b'F', b'T', b'C', b'V', 0, 0, 0, 0]]);
+    }
+
+
+    #[test]
+    fn check_invalid_volumes() {
+
+        let compressed = compress("abracadabra alakazam".bytes());
+        let volumes = split(&compressed, 16);
+        let volumes: Vec<&[u8]> = volumes.iter().map(|volume| &volume[..]).collect();
+
+        let swapped = [volumes[1], volumes[0]].into_iter().chain(volumes[2..].iter().copied());
+        assert!(matches!(decompress_volumes::<u8, _>(swapped), Err(VolumeError::OutOfOrder { expected: 0, found: 1 })));
+
+        let missing = volumes.iter().copied().enumerate().filter(|(i, _)| *i != 1).map(|(_, volume)| volume);
+        assert!(matches!(decompress_volumes::<u8, _>(missing), Err(VolumeError::OutOfOrder { expected: 1, found: 2 })));
+
+        let foreign = [volumes[0], &compressed[..]];
+        assert!(matches!(decompress_volumes::<u8, _>(foreign), Err(VolumeError::NotAVolume { volume: 1 })));
+
+        assert!(matches!(decompress_volumes::<u8, _>(volumes[..1].iter().copied()), Err(VolumeError::InvalidData (_))));
+    }
+
+}
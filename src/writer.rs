@@ -0,0 +1,426 @@
+//! Decompression straight into a writer, without holding all the decompressed symbols in memory.
+
+use std::io::{self, Write};
+use std::iter;
+
+use crate::bits::{BitOrder, BitVec};
+use crate::codecs::{CanonicalTreeCodec, CompactTreeCodec, TreeCodec};
+use crate::container::{read_alphabet, read_compact_alphabet, read_payload, read_symbol_count, read_tree, Header};
+use crate::seek::read_checkpoints;
+use crate::stream::for_each_block;
+use crate::transforms::mtf::{self, MoveToFront};
+use crate::transforms::newlines;
+use crate::transforms::remap;
+use crate::tree::{DecodingError, DecodingTree};
+use crate::{DecompressionError, Symbol};
+
+
+/// Number of serialized bytes buffered before they're written
+const BUFFER_SIZE: usize = 64 * 1024;
+
+
+/// Symbols decoded one at a time. Invalid data yields a single error after the symbols decoded before it
+pub(crate) type DecodedSymbols<'a, U> = Box<dyn Iterator<Item = Result<U, DecompressionError>> + 'a>;
+
+
+/// Reservation of the collection that receives salvaged symbols, called by the decoders of single-leaf trees
+/// like `reserve_single_leaf`, since their symbol count can't be trusted to fit in memory
+pub(crate) type Salvage<'r> = &'r mut dyn FnMut(usize) -> Result<(), DecodingError>;
+
+
+/// Decompress `input` and write the serialized representation of each symbol to `writer`, which for bytes is the bytes themselves.
+/// Return the number of symbols written.
+///
+/// The symbols are written as soon as they're decoded, so only the compressed input and a stream block at a time are held in memory,
+/// except for LZ77 payloads: their matches can refer to any previous symbol, so they're decoded whole before writing,
+/// and take as much memory as `decompress`.
+///
+/// Invalid data fails with `io::ErrorKind::InvalidData` wrapping a `DecompressionError`, after the symbols decoded before the error were written.
+pub fn decompress_into_writer<U, W>(input: &[u8], writer: W) -> io::Result<usize>
+where
+    U: Symbol,
+    W: Write
+{
+
+    let mut out = SymbolWriter { writer, buffer: Vec::with_capacity(BUFFER_SIZE), written: 0 };
+
+    for_each_symbol(input, |symbol: &U| out.push(symbol))?;
+
+    out.finish()
+}
+
+
+/// Pass the symbols of `input` to `emit` as they're decoded, like `decompress_into_writer` writes them
+pub(crate) fn for_each_symbol<U, E>(input: &[u8], mut emit: impl FnMut(&U) -> Result<(), E>) -> Result<(), E>
+where
+    U: Symbol,
+    E: From<DecompressionError>
+{
+
+    let (header, header_size) = Header::deserialize(input).map_err(DecompressionError::InvalidHeader)?;
+
+    if header.blocks && !header.transformed_blocks && header.chunk_size == 1 && header.dictionary.is_none() {
+        for_each_block(input, header_size, &header.settings, |symbols: Vec<U>| symbols.iter().try_for_each(&mut emit))
+    } else {
+        decoded_symbols::<U>(input, None)?.try_for_each(|symbol| emit(&symbol?))
+    }
+}
+
+
+/// Decode the symbols of `input` one at a time.
+/// Stream blocks and LZ77 payloads are decoded whole when the iterator is created, the other layouts as the iterator advances.
+///
+/// To `salvage` damaged data, bitcodes too short for their symbol count are decoded until they end,
+/// and the symbols of the stream blocks before an invalid block are yielded before its error.
+pub(crate) fn decoded_symbols<'a, U>(input: &'a [u8], salvage: Option<Salvage>) -> Result<DecodedSymbols<'a, U>, DecompressionError>
+where
+    U: Symbol + 'a
+{
+
+    let (header, header_size) = Header::deserialize(input).map_err(DecompressionError::InvalidHeader)?;
+
+    let symbols = layout_symbols(input, &header, header_size, salvage)?;
+
+    match newlines::line_break_symbols::<U>() {
+
+        // A `\r` is put back in front of every `\n`
+        Some ((cr, lf)) if header.settings.normalized_newlines => Ok(Box::new(symbols.flat_map(move |symbol| {
+            let restored = matches!(&symbol, Ok (symbol) if *symbol == lf).then(|| Ok(cr.clone()));
+            restored.into_iter().chain(iter::once(symbol))
+        }))),
+
+        _ => Ok(symbols)
+    }
+}
+
+
+/// Decode the symbols of the layout of `header`, which is `header_size` bytes long, one at a time
+fn layout_symbols<'a, U>(input: &'a [u8], header: &Header, header_size: usize, salvage: Option<Salvage>) -> Result<DecodedSymbols<'a, U>, DecompressionError>
+where
+    U: Symbol + 'a
+{
+
+    if header.chunk_size != 1 {
+        return Err(DecompressionError::ChunkSizeMismatch { expected: 1, found: header.chunk_size as usize });
+    }
+
+    if let Some(id) = header.dictionary {
+        return Err(DecompressionError::MissingDictionary { id });
+    }
+
+    if header.blocks {
+
+        if header.transformed_blocks {
+            return Err(DecompressionError::MissingTransform);
+        }
+
+        let mut symbols = Vec::new();
+        let result = for_each_block(input, header_size, &header.settings, |block: Vec<U>| {
+            symbols.extend(block);
+            Ok::<_, DecompressionError>(())
+        });
+
+        if let (Err (e), None) = (result, &salvage) {
+            return Err(e);
+        }
+
+        return Ok(Box::new(symbols.into_iter().map(Ok).chain(result.err().map(Err))));
+    }
+
+    if header.mtf {
+
+        let (alphabet, alphabet_size) = read_alphabet::<U>(input, header_size)?;
+        let alphabet_len = alphabet.len();
+        let mut mtf = MoveToFront::new(alphabet);
+
+        let indices = payload_symbols::<u32>(input, header_size + alphabet_size, header, salvage)?;
+
+        return Ok(Box::new(indices.enumerate().map(move |(position, index)| {
+            let index = index?;
+            mtf.decode_index(index)
+                .ok_or(DecompressionError::InvalidMtfIndex(mtf::InvalidIndex { position, index, alphabet_len }))
+        })));
+    }
+
+    if header.checkpoints {
+
+        let (tree, tree_size) = read_tree::<U>(input, header_size, header)?;
+        let (_, _, table_size) = read_checkpoints(input, header_size + tree_size)?;
+
+        return Ok(Box::new(CountedSymbols::new(tree, input, header_size + tree_size + table_size, BitOrder::MsbFirst, salvage)?));
+    }
+
+    if header.remap {
+
+        let (alphabet, alphabet_size) = read_compact_alphabet::<U>(input, header_size)?;
+        let offset = header_size + alphabet_size;
+
+        let ids: DecodedSymbols<'a, u32> = match remap::id_width(alphabet.len()) {
+            1 => Box::new(payload_symbols::<u8>(input, offset, header, salvage)?.map(|id| id.map(u32::from))),
+            2 => Box::new(payload_symbols::<u16>(input, offset, header, salvage)?.map(|id| id.map(u32::from))),
+            _ => payload_symbols::<u32>(input, offset, header, salvage)?,
+        };
+
+        return Ok(Box::new(ids.enumerate().map(move |(position, id)| {
+            let id = id?;
+            alphabet.get(id as usize)
+                .cloned()
+                .ok_or(DecompressionError::InvalidRemappedId(remap::InvalidId { position, id, alphabet_len: alphabet.len() }))
+        })));
+    }
+
+    payload_symbols(input, header_size, header, salvage)
+}
+
+
+/// Buffer of serialized symbols in front of a writer
+struct SymbolWriter<W> {
+
+    writer: W,
+    buffer: Vec<u8>,
+    /// Number of symbols pushed so far
+    written: usize,
+
+}
+
+impl<W> SymbolWriter<W>
+where
+    W: Write
+{
+
+    fn push<U>(&mut self, symbol: &U) -> io::Result<()>
+    where
+        U: Symbol
+    {
+
+        let start = self.buffer.len();
+        self.buffer.resize(start + U::WIDTH, 0);
+        symbol.to_bytes(&mut self.buffer[start..]);
+
+        self.written += 1;
+
+        if self.buffer.len() >= BUFFER_SIZE {
+            self.writer.write_all(&self.buffer)?;
+            self.buffer.clear();
+        }
+
+        Ok(())
+    }
+
+
+    /// Write the buffered symbols, flush the writer, and return the number of symbols written
+    fn finish(mut self) -> io::Result<usize> {
+
+        self.writer.write_all(&self.buffer)?;
+        self.writer.flush()?;
+
+        Ok(self.written)
+    }
+
+}
+
+
+/// Decode the payload written by `write_payload` at byte `offset` of `input` one symbol at a time
+fn payload_symbols<'a, V>(input: &[u8], offset: usize, header: &Header, salvage: Option<Salvage>) -> Result<DecodedSymbols<'a, V>, DecompressionError>
+where
+    V: Symbol + 'a
+{
+
+    if header.lz77 {
+
+        let symbols = if header.compact_tree {
+            read_payload::<V, _>(input, offset, true, header.bit_order, &CompactTreeCodec)?
+        } else if header.canonical_tree {
+            read_payload::<V, _>(input, offset, true, header.bit_order, &CanonicalTreeCodec)?
+        } else {
+            read_payload::<V, _>(input, offset, true, header.bit_order, &TreeCodec)?
+        };
+
+        return Ok(Box::new(symbols.into_vec().into_iter().map(Ok)));
+    }
+
+    let (tree, tree_size) = read_tree(input, offset, header)?;
+
+    Ok(Box::new(CountedSymbols::new(tree, input, offset + tree_size, header.bit_order, salvage)?))
+}
+
+
+/// Iterator over the symbol count and the bitcode written by `write_counted_bitcode`, decoding one symbol per step
+struct CountedSymbols<V: Clone> {
+
+    tree: DecodingTree<V>,
+    bitcode: BitVec,
+    /// Byte offset of the bitcode, for the errors
+    offset: usize,
+    /// Number of symbols recorded before the bitcode
+    count: usize,
+    /// Number of symbols decoded so far
+    found: usize,
+    bit_offset: usize,
+    /// Whether the end of the bitcode or an error was reached
+    done: bool,
+
+}
+
+impl<V> CountedSymbols<V>
+where
+    V: Symbol
+{
+
+    /// Read the symbol count and the bitcode from byte `offset` to the end of `input`.
+    /// To `salvage` truncated data, the bitcode isn't required to be long enough for the count.
+    fn new(tree: DecodingTree<V>, input: &[u8], offset: usize, bit_order: BitOrder, salvage: Option<Salvage>) -> Result<Self, DecompressionError> {
+
+        let min_code_bits = if salvage.is_some() { 0 } else { tree.min_code_length() };
+        let (count, count_size) = read_symbol_count(input, offset, min_code_bits)?;
+
+        let offset = offset + count_size;
+
+        if let (Some (reserve), Some (_)) = (salvage, tree.single_value()) {
+            reserve(count).map_err(|source| DecompressionError::BitCodeDecodingError { offset, source })?;
+        }
+
+        let bitcode = BitVec::deserialize_with_order(&input[offset..], bit_order)
+            .map_err(|_| DecompressionError::InvalidBitCode { offset, remaining: input.len() - offset })?;
+
+        Ok(Self { tree, bitcode, offset, count, found: 0, bit_offset: 0, done: false })
+    }
+
+}
+
+impl<V> Iterator for CountedSymbols<V>
+where
+    V: Symbol
+{
+    type Item = Result<V, DecompressionError>;
+
+
+    fn next(&mut self) -> Option<Self::Item> {
+
+        if self.done {
+            return None;
+        }
+
+        // The only value of a single-leaf tree is encoded with zero bits
+        if let Some(value) = self.tree.single_value() {
+
+            if self.found == self.count {
+                self.done = true;
+                return None;
+            }
+
+            self.found += 1;
+            return Some(Ok(value.clone()));
+        }
+
+        let bits = self.bitcode.as_bit_view();
+
+        while self.bit_offset < bits.len_bits() {
+
+            let (value, next) = match self.tree.decode_one(&bits, self.bit_offset) {
+                Ok (decoded) => decoded,
+                Err (source) => {
+                    self.done = true;
+                    return Some(Err(DecompressionError::BitCodeDecodingError { offset: self.offset, source }));
+                }
+            };
+
+            self.found += 1;
+            self.bit_offset = next;
+
+            // Symbols past the count are still decoded to report how many there are
+            if self.found <= self.count {
+                return Some(Ok(value.clone()));
+            }
+        }
+
+        self.done = true;
+
+        if self.found != self.count {
+            return Some(Err(DecompressionError::SymbolCountMismatch { offset: self.offset, expected: self.count, found: self.found }));
+        }
+
+        None
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::stream::Encoder;
+    use crate::{compress, compress_with_options, decompress, CompressionOptions};
+
+
+    #[test]
+    fn check_decompress_into_writer() {
+
+        let text = "the quick brown fox jumps over the lazy dog, the quick brown fox jumps over the lazy dog";
+
+        for options in [
+            CompressionOptions::new(),
+            CompressionOptions { lz77: true, compact_tree: true, ..CompressionOptions::new() },
+            CompressionOptions { mtf: true, bit_order: BitOrder::LsbFirst, ..CompressionOptions::new() },
+        ] {
+
+            let compressed = compress_with_options(text.bytes(), &options);
+            let mut out = Vec::new();
+            assert_eq!(decompress_into_writer::<u8, _>(&compressed, &mut out).unwrap(), text.len());
+            assert_eq!(out, text.as_bytes());
+
+            // Wide symbols are written in their serialized representation
+            let compressed = compress_with_options(text.chars(), &options);
+            let mut out = Vec::new();
+            assert_eq!(decompress_into_writer::<char, _>(&compressed, &mut out).unwrap(), text.len());
+            assert_eq!(out, text.chars().flat_map(|c| (c as u32).to_le_bytes()).collect::<Vec<u8>>());
+        }
+
+        let seekable = crate::seek::compress_seekable(text.bytes(), std::num::NonZeroUsize::new(8).unwrap());
+        let mut out = Vec::new();
+        decompress_into_writer::<u8, _>(&seekable, &mut out).unwrap();
+        assert_eq!(out, text.as_bytes());
+
+        let mut encoder = Encoder::new(Vec::new());
+        encoder.write_symbols(text.bytes()).unwrap();
+        encoder.flush_block().unwrap();
+        encoder.write_symbols(text.bytes()).unwrap();
+        let stream = encoder.finish().unwrap();
+        let mut out = Vec::new();
+        assert_eq!(decompress_into_writer::<u8, _>(&stream, &mut out).unwrap(), 2 * text.len());
+
+        // Larger than the buffer
+        let large: Vec<u8> = text.bytes().cycle().take(3 * BUFFER_SIZE + 7).collect();
+        let mut out = Vec::new();
+        decompress_into_writer::<u8, _>(&compress(large.iter().copied()), &mut out).unwrap();
+        assert_eq!(out, large);
+    }
+
+
+    #[test]
+    fn check_writer_errors() {
+
+        let mut compressed = compress("abracadabra".bytes()).into_vec();
+        compressed.truncate(compressed.len() - 1);
+
+        let expected = decompress::<u8>(&compressed).unwrap_err();
+        let e = decompress_into_writer::<u8, _>(&compressed, io::sink()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(e.into_inner().unwrap().downcast::<DecompressionError>().unwrap().as_ref(), &expected);
+
+        // Errors of the writer are returned as is
+        struct Full;
+        impl Write for Full {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::StorageFull.into())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let e = decompress_into_writer::<u8, _>(&compress("abracadabra".bytes()), Full).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::StorageFull);
+    }
+
+}